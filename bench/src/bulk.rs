@@ -60,7 +60,7 @@ fn main() {
 }
 
 async fn server(mut incoming: quinn::Incoming, opt: Opt) -> Result<()> {
-    let handshake = incoming.next().await.unwrap();
+    let handshake = incoming.next().await.unwrap().accept()?;
     let quinn::NewConnection {
         mut uni_streams,
         connection,
@@ -117,11 +117,15 @@ async fn client(server_addr: SocketAddr, server_cert: quinn::Certificate, opt: O
         .bind(&SocketAddr::new(IpAddr::V6(Ipv6Addr::LOCALHOST), 0))
         .unwrap();
 
+    let crypto_config = rustls::ClientConfig::builder()
+        .with_cipher_suites(&[opt.cipher.as_rustls()])
+        .with_safe_default_kx_groups()
+        .with_protocol_versions(&[&rustls::version::TLS13])
+        .unwrap()
+        .with_root_certificates(rustls::RootCertStore::empty())
+        .with_no_client_auth();
     let mut config = quinn::ClientConfig::default();
-    let crypto_config = Arc::get_mut(&mut config.crypto).unwrap();
-
-    crypto_config.ciphersuites.clear();
-    crypto_config.ciphersuites.push(opt.cipher.as_rustls());
+    config.crypto = Arc::new(crypto_config);
 
     let mut client_config = quinn::ClientConfigBuilder::new(config);
     client_config
@@ -310,11 +314,11 @@ enum CipherSuite {
 }
 
 impl CipherSuite {
-    fn as_rustls(self) -> &'static rustls::SupportedCipherSuite {
+    fn as_rustls(self) -> rustls::SupportedCipherSuite {
         match self {
-            CipherSuite::Aes128 => &rustls::ciphersuite::TLS13_AES_128_GCM_SHA256,
-            CipherSuite::Aes256 => &rustls::ciphersuite::TLS13_AES_256_GCM_SHA384,
-            CipherSuite::Chacha20 => &rustls::ciphersuite::TLS13_CHACHA20_POLY1305_SHA256,
+            CipherSuite::Aes128 => rustls::cipher_suite::TLS13_AES_128_GCM_SHA256,
+            CipherSuite::Aes256 => rustls::cipher_suite::TLS13_AES_256_GCM_SHA384,
+            CipherSuite::Chacha20 => rustls::cipher_suite::TLS13_CHACHA20_POLY1305_SHA256,
         }
     }
 }
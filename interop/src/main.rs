@@ -2,6 +2,7 @@
 #![type_length_limit = "2121396"]
 
 use std::{
+    convert::TryFrom,
     env,
     net::{SocketAddr, ToSocketAddrs},
     sync::{Arc, Mutex},
@@ -257,19 +258,23 @@ impl State {
             .to_socket_addrs()?
             .next()
             .ok_or_else(|| anyhow!("couldn't resolve to an address"))?;
-        let host = if webpki::DNSNameRef::try_from_ascii_str(&peer.host).is_ok() {
+        let host = if rustls::ServerName::try_from(peer.host.as_str()).is_ok() {
             &peer.host
         } else {
             warn!("invalid hostname, using \"example.com\"");
             "example.com"
         };
 
-        let mut tls_config = rustls::ClientConfig::new();
-        tls_config.versions = vec![rustls::ProtocolVersion::TLSv1_3];
+        let mut tls_config = rustls::ClientConfig::builder()
+            .with_safe_default_cipher_suites()
+            .with_safe_default_kx_groups()
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .unwrap()
+            .with_custom_certificate_verifier(Arc::new(InteropVerifier(Arc::new(Mutex::new(
+                false,
+            )))))
+            .with_no_client_auth();
         tls_config.enable_early_data = true;
-        tls_config
-            .dangerous()
-            .set_certificate_verifier(Arc::new(InteropVerifier(Arc::new(Mutex::new(false)))));
         tls_config.alpn_protocols = (&peer.alpn).into();
         if keylog {
             tls_config.key_log = Arc::new(rustls::KeyLogFile::new());
@@ -280,10 +285,9 @@ impl State {
         transport
             .max_idle_timeout(Some(Duration::from_secs(1)))
             .unwrap();
-        let client_config = quinn::ClientConfig {
-            crypto: Arc::new(tls_config),
-            transport: Arc::new(transport),
-        };
+        let mut client_config = quinn::ClientConfig::default();
+        client_config.crypto = Arc::new(tls_config);
+        client_config.transport = Arc::new(transport);
 
         let mut endpoint = quinn::Endpoint::builder();
         endpoint.default_client_config(client_config.clone());
@@ -319,16 +323,11 @@ impl State {
         new_conn.connection.close(0u32.into(), b"done");
 
         let saw_cert = Arc::new(Mutex::new(false));
-        let quinn::ClientConfig {
-            mut crypto,
-            transport,
-        } = self.client_config.clone();
-        Arc::make_mut(&mut crypto)
+        let mut client_config = self.client_config.clone();
+        Arc::make_mut(&mut client_config.crypto)
             .dangerous()
             .set_certificate_verifier(Arc::new(InteropVerifier(saw_cert.clone())));
 
-        let client_config = quinn::ClientConfig { transport, crypto };
-
         let conn = match self
             .endpoint
             .connect_with(client_config, &self.remote, &self.host)?
@@ -603,15 +602,17 @@ async fn hq_get(stream: (quinn::SendStream, quinn::RecvStream), path: &str) -> R
 }
 
 struct InteropVerifier(Arc<Mutex<bool>>);
-impl rustls::ServerCertVerifier for InteropVerifier {
+impl rustls::client::ServerCertVerifier for InteropVerifier {
     fn verify_server_cert(
         &self,
-        _roots: &rustls::RootCertStore,
-        _presented_certs: &[rustls::Certificate],
-        _dns_name: webpki::DNSNameRef,
+        _end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _server_name: &rustls::ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
         _ocsp_response: &[u8],
-    ) -> std::result::Result<rustls::ServerCertVerified, rustls::TLSError> {
+        _now: std::time::SystemTime,
+    ) -> std::result::Result<rustls::client::ServerCertVerified, rustls::Error> {
         *self.0.lock().unwrap() = true;
-        Ok(rustls::ServerCertVerified::assertion())
+        Ok(rustls::client::ServerCertVerified::assertion())
     }
 }
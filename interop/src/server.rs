@@ -101,7 +101,11 @@ async fn server(server_config: quinn::ServerConfigBuilder, addr: SocketAddr) ->
     let (_, mut incoming) = endpoint_builder.bind(&addr)?;
 
     println!("server listening on {}", addr);
-    while let Some(mut connecting) = incoming.next().await {
+    while let Some(incoming) = incoming.next().await {
+        let mut connecting = match incoming.accept() {
+            Ok(x) => x,
+            Err(_) => continue,
+        };
         tokio::spawn(async move {
             let proto = match connecting.handshake_data().await {
                 Err(_) => return,
@@ -282,7 +286,7 @@ async fn h2_handle(request: hyper::Request<hyper::Body>) -> Result<hyper::Respon
 
 async fn h2_server(server_config: quinn::ServerConfigBuilder) -> Result<()> {
     let mut tls_cfg = (*server_config.build().crypto).clone();
-    tls_cfg.set_protocols(&[b"h2".to_vec(), b"http/1.1".to_vec()]);
+    tls_cfg.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
     let tls_acceptor = TlsAcceptor::from(sync::Arc::new(tls_cfg));
 
     let tcp = TcpListener::bind(&SocketAddr::new([0, 0, 0, 0].into(), 443)).await?;
@@ -68,20 +68,7 @@ async fn run(opt: Opt) -> Result<()> {
     server_config.certificate(cert, key).unwrap();
     server_config.protocols(&[b"perf"]);
 
-    let mut server_config = server_config.build();
-
-    // Configure cipher suites for efficiency
-    let tls_config = Arc::get_mut(&mut server_config.crypto).unwrap();
-    tls_config.ciphersuites.clear();
-    tls_config
-        .ciphersuites
-        .push(&rustls::ciphersuite::TLS13_AES_128_GCM_SHA256);
-    tls_config
-        .ciphersuites
-        .push(&rustls::ciphersuite::TLS13_AES_256_GCM_SHA384);
-    tls_config
-        .ciphersuites
-        .push(&rustls::ciphersuite::TLS13_CHACHA20_POLY1305_SHA256);
+    let server_config = server_config.build();
 
     let mut endpoint = quinn::EndpointBuilder::default();
     endpoint.listen(server_config);
@@ -94,7 +81,14 @@ async fn run(opt: Opt) -> Result<()> {
 
     let opt = Arc::new(opt);
 
-    while let Some(handshake) = incoming.next().await {
+    while let Some(incoming) = incoming.next().await {
+        let handshake = match incoming.accept() {
+            Ok(x) => x,
+            Err(e) => {
+                error!("accepting connection failed: {:#}", e);
+                continue;
+            }
+        };
         let opt = opt.clone();
         tokio::spawn(async move {
             if let Err(e) = handle(handshake, opt).await {
@@ -20,6 +20,22 @@ pub trait ConnectionIdGenerator: Send {
     ///
     /// Connection IDs will be retired after the returned `Duration`, if any. Assumed to be constant.
     fn cid_lifetime(&self) -> Option<Duration>;
+    /// Generates a new CID that records `reset_key_id`
+    ///
+    /// The ID identifies the key in the endpoint's reset [`KeyRing`](crate::KeyRing) that the
+    /// CID's stateless reset token is derived from, so that the endpoint, or a later instance of
+    /// it, can send a stateless reset with the right key. Generators must only record it where
+    /// observers can't read it, e.g. encrypted along with other data. The default implementation
+    /// ignores it, in which case resets are always sent with the current key.
+    fn generate_cid_for_reset_key(&mut self, reset_key_id: u8) -> ConnectionId {
+        let _ = reset_key_id;
+        self.generate_cid()
+    }
+    /// Recovers the ID recorded by `generate_cid_for_reset_key()`, if `cid` has one
+    fn reset_key_id(&self, cid: &ConnectionId) -> Option<u8> {
+        let _ = cid;
+        None
+    }
 }
 
 /// Generates purely random connection IDs of a certain length
@@ -76,6 +76,11 @@ impl CidQueue {
         Some((cid_data.1.unwrap(), orig_offset..sequence))
     }
 
+    /// The CID `next` would switch to, if any
+    pub fn peek_next(&self) -> Option<ConnectionId> {
+        self.iter().next().map(|(_, (cid, _))| cid)
+    }
+
     /// Iterate inactive CIDs in CidQueue that are not `None`
     fn iter(&self) -> impl Iterator<Item = (usize, CidData)> + '_ {
         (1..Self::LEN).filter_map(move |step| {
@@ -94,6 +99,15 @@ impl CidQueue {
         self.buffer[self.cursor].unwrap().0
     }
 
+    /// Look up the CID with sequence number `sequence`, if it's known and not retired
+    pub fn get(&self, sequence: u64) -> Option<ConnectionId> {
+        let index = sequence.checked_sub(self.offset)?;
+        if index >= Self::LEN as u64 {
+            return None;
+        }
+        self.buffer[(self.cursor + index as usize) % Self::LEN].map(|x| x.0)
+    }
+
     /// Check whether self.offset points to a valid CID in CidQueue
     pub fn is_active_retired(&mut self) -> bool {
         self.buffer[self.cursor].is_none()
@@ -207,6 +221,18 @@ mod tests {
         assert!(!q.is_active_retired());
     }
 
+    #[test]
+    fn get() {
+        let mut q = CidQueue::new(initial_cid());
+        q.insert(cid(2)).unwrap();
+        assert_eq!(q.get(0), Some(initial_cid()));
+        assert_eq!(q.get(1), None);
+        assert_eq!(q.get(2), Some(cid(2).id));
+        q.retire_prior_to(1);
+        assert_eq!(q.get(0), None, "retired CIDs can't be looked up");
+        assert_eq!(q.get(CidQueue::LEN as u64 + 1), None);
+    }
+
     #[test]
     fn insert_limit() {
         let mut q = CidQueue::new(initial_cid());
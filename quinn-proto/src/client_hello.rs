@@ -0,0 +1,258 @@
+//! Best-effort inspection of a TLS ClientHello before a connection is created
+//!
+//! QUIC always carries a TLS 1.3 handshake, so this is independent of the crypto implementation.
+//! Anything unexpected, such as a ClientHello split across several Initial packets, yields `None`
+//! rather than an error; the crypto implementation remains responsible for validating it.
+
+use bytes::Bytes;
+
+use crate::frame::Frame;
+
+/// Fields of interest from a ClientHello
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub(crate) struct ClientHello {
+    /// Host name from the server_name extension
+    pub(crate) server_name: Option<String>,
+    /// Protocols from the application_layer_protocol_negotiation extension, in preference order
+    pub(crate) alpn_protocols: Option<Vec<Vec<u8>>>,
+    /// Whether the early_data extension is present, i.e. the client is attempting 0-RTT
+    pub(crate) early_data: bool,
+    /// First identity from the pre_shared_key extension, which is the ticket 0-RTT data is
+    /// encrypted with
+    pub(crate) psk_identity: Option<Vec<u8>>,
+}
+
+impl ClientHello {
+    /// Extract the ClientHello from the CRYPTO frames of a decrypted Initial packet payload
+    pub(crate) fn from_initial_payload(payload: Bytes) -> Option<Self> {
+        let mut frames = crate::frame::Iter::new(payload)
+            .filter_map(|frame| match frame {
+                Frame::Crypto(x) => Some(x),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        frames.sort_unstable_by_key(|x| x.offset);
+        let mut data = Vec::new();
+        for frame in frames {
+            let start = frame.offset as usize;
+            let end = start + frame.data.len();
+            if start > data.len() {
+                break;
+            }
+            if end > data.len() {
+                data.extend_from_slice(&frame.data[data.len() - start..]);
+            }
+        }
+        Self::decode(&data)
+    }
+
+    fn decode(mut buf: &[u8]) -> Option<Self> {
+        const CLIENT_HELLO: u8 = 1;
+        const SERVER_NAME: u16 = 0;
+        const ALPN: u16 = 16;
+        const PRE_SHARED_KEY: u16 = 41;
+        const EARLY_DATA: u16 = 42;
+
+        if read_u8(&mut buf)? != CLIENT_HELLO {
+            return None;
+        }
+        let len = read_u24(&mut buf)?;
+        let mut body = take(&mut buf, len)?;
+        // legacy_version and random
+        skip(&mut body, 2 + 32)?;
+        let session_id_len = read_u8(&mut body)? as usize;
+        skip(&mut body, session_id_len)?;
+        let cipher_suites_len = read_u16(&mut body)? as usize;
+        skip(&mut body, cipher_suites_len)?;
+        let compression_len = read_u8(&mut body)? as usize;
+        skip(&mut body, compression_len)?;
+        let extensions_len = read_u16(&mut body)? as usize;
+        let mut extensions = take(&mut body, extensions_len)?;
+
+        let mut hello = Self::default();
+        while !extensions.is_empty() {
+            let ty = read_u16(&mut extensions)?;
+            let len = read_u16(&mut extensions)? as usize;
+            let mut data = take(&mut extensions, len)?;
+            match ty {
+                SERVER_NAME => {
+                    let len = read_u16(&mut data)? as usize;
+                    let mut names = take(&mut data, len)?;
+                    while !names.is_empty() {
+                        let name_type = read_u8(&mut names)?;
+                        let len = read_u16(&mut names)? as usize;
+                        let name = take(&mut names, len)?;
+                        // Only host_name is defined
+                        if name_type == 0 {
+                            hello.server_name = Some(String::from_utf8(name.to_vec()).ok()?);
+                        }
+                    }
+                }
+                ALPN => {
+                    let len = read_u16(&mut data)? as usize;
+                    let mut list = take(&mut data, len)?;
+                    let mut protocols = Vec::new();
+                    while !list.is_empty() {
+                        let len = read_u8(&mut list)? as usize;
+                        protocols.push(take(&mut list, len)?.to_vec());
+                    }
+                    hello.alpn_protocols = Some(protocols);
+                }
+                PRE_SHARED_KEY => {
+                    let len = read_u16(&mut data)? as usize;
+                    let mut identities = take(&mut data, len)?;
+                    let len = read_u16(&mut identities)? as usize;
+                    hello.psk_identity = Some(take(&mut identities, len)?.to_vec());
+                }
+                EARLY_DATA => {
+                    hello.early_data = true;
+                }
+                _ => {}
+            }
+        }
+        Some(hello)
+    }
+}
+
+fn read_u8(buf: &mut &[u8]) -> Option<u8> {
+    Some(take(buf, 1)?[0])
+}
+
+fn read_u16(buf: &mut &[u8]) -> Option<u16> {
+    let bytes = take(buf, 2)?;
+    Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u24(buf: &mut &[u8]) -> Option<usize> {
+    let bytes = take(buf, 3)?;
+    Some((bytes[0] as usize) << 16 | (bytes[1] as usize) << 8 | bytes[2] as usize)
+}
+
+fn take<'a>(buf: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if buf.len() < len {
+        return None;
+    }
+    let (head, tail) = buf.split_at(len);
+    *buf = tail;
+    Some(head)
+}
+
+fn skip(buf: &mut &[u8], len: usize) -> Option<()> {
+    take(buf, len).map(|_| ())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::frame;
+    use bytes::BytesMut;
+
+    fn client_hello(extensions: &[u8]) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&[0x03, 0x03]);
+        body.extend_from_slice(&[0xAA; 32]);
+        // Session ID
+        body.extend_from_slice(&[1, 0xBB]);
+        // Cipher suites
+        body.extend_from_slice(&[0, 2, 0x13, 0x01]);
+        // Compression methods
+        body.extend_from_slice(&[1, 0]);
+        body.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+        body.extend_from_slice(extensions);
+
+        let mut msg = vec![1];
+        msg.extend_from_slice(&(body.len() as u32).to_be_bytes()[1..]);
+        msg.extend_from_slice(&body);
+        msg
+    }
+
+    fn extensions() -> Vec<u8> {
+        let mut buf = Vec::new();
+        // server_name: "example.com"
+        buf.extend_from_slice(&[0, 0, 0, 16, 0, 14, 0, 0, 11]);
+        buf.extend_from_slice(b"example.com");
+        // supported_versions, ignored
+        buf.extend_from_slice(&[0, 43, 0, 3, 2, 0x03, 0x04]);
+        // ALPN: "h3", "hq-29"
+        buf.extend_from_slice(&[0, 16, 0, 11, 0, 9, 2]);
+        buf.extend_from_slice(b"h3");
+        buf.push(5);
+        buf.extend_from_slice(b"hq-29");
+        buf
+    }
+
+    fn resumption_extensions() -> Vec<u8> {
+        let mut buf = extensions();
+        // early_data
+        buf.extend_from_slice(&[0, 42, 0, 0]);
+        // pre_shared_key: one 4-byte identity with its obfuscated age, and a dummy binder
+        buf.extend_from_slice(&[0, 41, 0, 16, 0, 10, 0, 4]);
+        buf.extend_from_slice(b"tckt");
+        buf.extend_from_slice(&[0, 0, 0, 1]);
+        buf.extend_from_slice(&[0, 2, 1, 0xCC]);
+        buf
+    }
+
+    #[test]
+    fn decode() {
+        let hello = ClientHello::decode(&client_hello(&extensions())).unwrap();
+        assert_eq!(hello.server_name.as_deref(), Some("example.com"));
+        assert_eq!(
+            hello.alpn_protocols,
+            Some(vec![b"h3".to_vec(), b"hq-29".to_vec()])
+        );
+    }
+
+    #[test]
+    fn decode_resumption() {
+        let hello = ClientHello::decode(&client_hello(&resumption_extensions())).unwrap();
+        assert!(hello.early_data);
+        assert_eq!(hello.psk_identity.as_deref(), Some(&b"tckt"[..]));
+
+        let hello = ClientHello::decode(&client_hello(&extensions())).unwrap();
+        assert!(!hello.early_data);
+        assert_eq!(hello.psk_identity, None);
+    }
+
+    #[test]
+    fn decode_without_extensions() {
+        let hello = ClientHello::decode(&client_hello(&[])).unwrap();
+        assert_eq!(hello, ClientHello::default());
+    }
+
+    #[test]
+    fn decode_truncated() {
+        let msg = client_hello(&extensions());
+        assert_eq!(ClientHello::decode(&msg[..msg.len() - 1]), None);
+    }
+
+    #[test]
+    fn reordered_crypto_frames() {
+        let msg = Bytes::from(client_hello(&extensions()));
+        let mut payload = BytesMut::new();
+        frame::Crypto {
+            offset: 20,
+            data: msg.slice(20..),
+        }
+        .encode(&mut payload);
+        frame::Crypto {
+            offset: 0,
+            data: msg.slice(..20),
+        }
+        .encode(&mut payload);
+        let hello = ClientHello::from_initial_payload(payload.freeze()).unwrap();
+        assert_eq!(hello.server_name.as_deref(), Some("example.com"));
+    }
+
+    #[test]
+    fn missing_crypto_data() {
+        let msg = Bytes::from(client_hello(&extensions()));
+        let mut payload = BytesMut::new();
+        frame::Crypto {
+            offset: 0,
+            data: msg.slice(..msg.len() / 2),
+        }
+        .encode(&mut payload);
+        assert_eq!(ClientHello::from_initial_payload(payload.freeze()), None);
+    }
+}
@@ -1,15 +1,27 @@
-use std::{convert::TryInto, fmt, num::TryFromIntError, sync::Arc, time::Duration};
+use std::{
+    convert::TryInto,
+    fmt,
+    net::{IpAddr, Ipv4Addr, SocketAddrV4, SocketAddrV6},
+    num::TryFromIntError,
+    sync::Arc,
+    time::Duration,
+};
 
 use rand::RngCore;
 use thiserror::Error;
 
-#[cfg(feature = "rustls")]
-use crate::crypto::types::{Certificate, CertificateChain, PrivateKey};
 use crate::{
     cid_generator::{ConnectionIdGenerator, RandomConnectionIdGenerator},
     congestion,
     crypto::{self, ClientConfig as _, HandshakeTokenKey as _, HmacKey as _, ServerConfig as _},
-    VarInt, VarIntBoundsExceeded, DEFAULT_SUPPORTED_VERSIONS,
+    multipath::{MinRtt, PathScheduler},
+    KeyRing, TokenStore, VarInt, VarIntBoundsExceeded, ZeroRttPolicy, DEFAULT_SUPPORTED_VERSIONS,
+    INITIAL_MAX_UDP_PAYLOAD_SIZE,
+};
+#[cfg(feature = "rustls")]
+use crate::{
+    crypto::types::{Certificate, CertificateChain, PrivateKey},
+    SessionStore,
 };
 
 /// Parameters governing the core QUIC state machine
@@ -43,8 +55,13 @@ pub struct TransportConfig {
     pub(crate) allow_spin: bool,
     pub(crate) datagram_receive_buffer_size: Option<usize>,
     pub(crate) datagram_send_buffer_size: usize,
+    pub(crate) min_mtu: u16,
+    pub(crate) mtu_discovery_config: Option<MtuDiscoveryConfig>,
 
     pub(crate) congestion_controller_factory: Box<dyn congestion::ControllerFactory + Send + Sync>,
+
+    pub(crate) enable_multipath: bool,
+    pub(crate) path_scheduler_factory: Box<dyn Fn() -> Box<dyn PathScheduler> + Send + Sync>,
 }
 
 impl TransportConfig {
@@ -196,6 +213,30 @@ impl TransportConfig {
         self
     }
 
+    /// The UDP payload size every path is assumed to support, in bytes
+    ///
+    /// Used as the maximum size of outgoing datagrams until MTU discovery finds a larger one, and
+    /// as the fallback when a path stops delivering larger datagrams. Must be at least 1200, the
+    /// minimum required by QUIC, which is also the default.
+    pub fn min_mtu(&mut self, value: u16) -> Result<&mut Self, ConfigError> {
+        if value < INITIAL_MAX_UDP_PAYLOAD_SIZE {
+            return Err(ConfigError::OutOfBounds);
+        }
+        self.min_mtu = value;
+        Ok(self)
+    }
+
+    /// How to search each path for the largest UDP payload size it supports, or `None` to always
+    /// use `min_mtu`
+    ///
+    /// Disabled by default. Probes only tell the path MTU apart from IP fragmentation when the
+    /// socket sets the don't-fragment bit on outgoing datagrams, so only enable this for sockets
+    /// that do, such as quinn's on Linux.
+    pub fn mtu_discovery_config(&mut self, value: Option<MtuDiscoveryConfig>) -> &mut Self {
+        self.mtu_discovery_config = value;
+        self
+    }
+
     /// How to construct new `congestion::Controller`s
     ///
     /// Typically the refcounted configuration of a `congestion::Controller`,
@@ -214,6 +255,42 @@ impl TransportConfig {
         self.congestion_controller_factory = Box::new(factory);
         self
     }
+
+    /// Whether to negotiate the multipath extension, letting clients send over several paths at
+    /// once
+    ///
+    /// Takes effect only if both peers enable it and use non-empty connection IDs, and the
+    /// session's packet keys support multipath (see `crypto::PacketKey::MULTIPATH`), which those of
+    /// rustls don't. Every path uses its own pair of connection IDs, so at most
+    /// `active_connection_id_limit - 1` paths can be opened in addition to the initial one over
+    /// the lifetime of a connection. Connection IDs are not rotated on multipath connections,
+    /// servers' preferred addresses are ignored, and migration attempts fail with
+    /// [`MigrationError::Multipath`]. Defaults to `false`.
+    ///
+    /// [`MigrationError::Multipath`]: crate::MigrationError::Multipath
+    pub fn enable_multipath(&mut self, value: bool) -> &mut Self {
+        self.enable_multipath = value;
+        self
+    }
+
+    /// How to construct the `PathScheduler` that spreads application data across the paths of a
+    /// multipath connection
+    ///
+    /// Defaults to `multipath::MinRtt`.
+    ///
+    /// # Example
+    /// ```
+    /// # use quinn_proto::*;
+    /// let mut config = TransportConfig::default();
+    /// config.path_scheduler(|| Box::new(multipath::RoundRobin::default()));
+    /// ```
+    pub fn path_scheduler<F: Fn() -> Box<dyn PathScheduler> + Send + Sync + 'static>(
+        &mut self,
+        factory: F,
+    ) -> &mut Self {
+        self.path_scheduler_factory = Box::new(factory);
+        self
+    }
 }
 
 impl Default for TransportConfig {
@@ -243,8 +320,13 @@ impl Default for TransportConfig {
             allow_spin: true,
             datagram_receive_buffer_size: Some(STREAM_RWND as usize),
             datagram_send_buffer_size: 1024 * 1024,
+            min_mtu: INITIAL_MAX_UDP_PAYLOAD_SIZE,
+            mtu_discovery_config: None,
 
             congestion_controller_factory: Box::new(Arc::new(congestion::CubicConfig::default())),
+
+            enable_multipath: false,
+            path_scheduler_factory: Box::new(|| Box::new(MinRtt)),
         }
     }
 }
@@ -280,11 +362,242 @@ impl fmt::Debug for TransportConfig {
                 &self.datagram_receive_buffer_size,
             )
             .field("datagram_send_buffer_size", &self.datagram_send_buffer_size)
+            .field("min_mtu", &self.min_mtu)
+            .field("mtu_discovery_config", &self.mtu_discovery_config)
             .field("congestion_controller_factory", &"[ opaque ]")
+            .field("enable_multipath", &self.enable_multipath)
+            .field("path_scheduler_factory", &"[ opaque ]")
             .finish()
     }
 }
 
+/// Parameters governing MTU discovery
+///
+/// Implements Datagram Packetization Layer Path MTU Discovery (RFC 8899): once a connection is
+/// established, each path is probed with PING packets padded to increasingly accurate sizes until
+/// the largest UDP payload size it delivers is found, bounded by the peer's `max_udp_payload_size`
+/// transport parameter. Lost probes are not treated as congestion. If packets larger than
+/// `TransportConfig::min_mtu` are repeatedly lost afterwards, the path is assumed to have become a
+/// black hole and the MTU falls back to the minimum.
+#[derive(Debug, Clone)]
+pub struct MtuDiscoveryConfig {
+    pub(crate) interval: Duration,
+    pub(crate) upper_bound: u16,
+    pub(crate) minimum_change: u16,
+    pub(crate) black_hole_cooldown: Duration,
+}
+
+impl MtuDiscoveryConfig {
+    /// Time to wait after a completed search before searching for a larger MTU again
+    ///
+    /// Paths can change over the lifetime of a connection, so the search is periodically
+    /// repeated while the MTU is below `upper_bound`. Defaults to 600 seconds, as recommended by
+    /// RFC 8899.
+    pub fn interval(&mut self, value: Duration) -> &mut Self {
+        self.interval = value;
+        self
+    }
+
+    /// The largest UDP payload size to probe for, in bytes
+    ///
+    /// Probes are never larger than the peer's `max_udp_payload_size` either. Defaults to 1452,
+    /// which fits common 1500-byte links with IPv6 headers; raise it for networks supporting jumbo
+    /// frames.
+    pub fn upper_bound(&mut self, value: u16) -> &mut Self {
+        self.upper_bound = value;
+        self
+    }
+
+    /// Smallest increase in MTU, in bytes, considered worth probing for
+    ///
+    /// The search ends once the range of untested sizes is smaller than this. Defaults to 20.
+    pub fn minimum_change(&mut self, value: u16) -> &mut Self {
+        self.minimum_change = value;
+        self
+    }
+
+    /// Time to wait before searching again after a black hole was detected
+    ///
+    /// Defaults to 60 seconds.
+    pub fn black_hole_cooldown(&mut self, value: Duration) -> &mut Self {
+        self.black_hole_cooldown = value;
+        self
+    }
+}
+
+impl Default for MtuDiscoveryConfig {
+    fn default() -> Self {
+        MtuDiscoveryConfig {
+            interval: Duration::from_secs(600),
+            upper_bound: 1452,
+            minimum_change: 20,
+            black_hole_cooldown: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Parameters governing per-source rate limiting of incoming connection attempts
+///
+/// Each source address prefix is given a token bucket holding up to `burst` connection attempts,
+/// refilled by one attempt every `interval`. Attempts from a prefix whose bucket is empty are
+/// refused or dropped before any connection state is created. Attempts that a Retry is sent for
+/// instead don't count against the limit, since they cost the server nothing to track.
+#[derive(Debug, Clone)]
+pub struct HandshakeLimitConfig {
+    pub(crate) burst: u32,
+    pub(crate) interval: Duration,
+    pub(crate) ipv4_prefix_len: u8,
+    pub(crate) ipv6_prefix_len: u8,
+    pub(crate) max_sources: usize,
+    pub(crate) refuse: bool,
+}
+
+impl HandshakeLimitConfig {
+    /// Number of connection attempts a source may make in quick succession
+    ///
+    /// Defaults to 10.
+    pub fn burst(&mut self, value: u32) -> &mut Self {
+        self.burst = value;
+        self
+    }
+
+    /// Time it takes a source to earn one more connection attempt
+    ///
+    /// Defaults to 100 milliseconds, i.e. a sustained rate of 10 attempts per second.
+    pub fn interval(&mut self, value: Duration) -> &mut Self {
+        self.interval = value;
+        self
+    }
+
+    /// Number of leading bits of an IPv4 address that identify a source
+    ///
+    /// Defaults to 32, limiting each address separately. Must be at most 32.
+    pub fn ipv4_prefix_len(&mut self, value: u8) -> Result<&mut Self, ConfigError> {
+        if value > 32 {
+            return Err(ConfigError::OutOfBounds);
+        }
+        self.ipv4_prefix_len = value;
+        Ok(self)
+    }
+
+    /// Number of leading bits of an IPv6 address that identify a source
+    ///
+    /// Defaults to 64, since hosts are commonly assigned a whole /64. Must be at most 128.
+    pub fn ipv6_prefix_len(&mut self, value: u8) -> Result<&mut Self, ConfigError> {
+        if value > 128 {
+            return Err(ConfigError::OutOfBounds);
+        }
+        self.ipv6_prefix_len = value;
+        Ok(self)
+    }
+
+    /// Maximum number of sources to track at once
+    ///
+    /// Bounds the memory used by the limiter. While this many others have recently made attempts,
+    /// sources that aren't already tracked share a single bucket, so spreading attempts over many
+    /// prefixes doesn't evade the limit. Defaults to 65536.
+    pub fn max_sources(&mut self, value: usize) -> &mut Self {
+        self.max_sources = value;
+        self
+    }
+
+    /// Whether to tell over-limit sources that their connection was refused
+    ///
+    /// If `false`, over-limit connection attempts are silently dropped, which is cheaper under a
+    /// flood but leaves legitimate clients waiting for their handshake to time out. Defaults to
+    /// `false`.
+    pub fn refuse(&mut self, value: bool) -> &mut Self {
+        self.refuse = value;
+        self
+    }
+
+    /// The prefix `addr` belongs to, with all other bits cleared
+    ///
+    /// IPv4-mapped IPv6 addresses, as seen by dual-stack sockets, are treated as the IPv4 address
+    /// they represent so they are subject to the IPv4 prefix length.
+    pub(crate) fn prefix(&self, addr: IpAddr) -> IpAddr {
+        let addr = match addr {
+            IpAddr::V6(x) => match x.segments() {
+                [0, 0, 0, 0, 0, 0xffff, ..] => {
+                    let [.., a, b, c, d] = x.octets();
+                    IpAddr::V4(Ipv4Addr::new(a, b, c, d))
+                }
+                _ => addr,
+            },
+            _ => addr,
+        };
+        match addr {
+            IpAddr::V4(x) => {
+                let mask = u32::MAX.checked_shl(32 - u32::from(self.ipv4_prefix_len));
+                IpAddr::V4((u32::from(x) & mask.unwrap_or(0)).into())
+            }
+            IpAddr::V6(x) => {
+                let mask = u128::MAX.checked_shl(128 - u32::from(self.ipv6_prefix_len));
+                IpAddr::V6((u128::from(x) & mask.unwrap_or(0)).into())
+            }
+        }
+    }
+}
+
+/// Parameters governing protection of 0-RTT data against replay
+///
+/// Data sent in 0-RTT packets isn't protected against replay by TLS: an attacker can capture a
+/// client's first flight and send it again, causing the server to act on it twice. With this
+/// protection, each session ticket may be used for 0-RTT only once; later attempts with the same
+/// ticket still resume the session, but have their 0-RTT data rejected, so the client sends it
+/// again once the handshake completes.
+///
+/// Tickets are only tracked by the endpoint that saw them, so servers sharing ticket keys across
+/// several endpoints or hosts aren't protected against replay between them.
+#[derive(Debug, Clone)]
+pub struct AntiReplayConfig {
+    pub(crate) window: Duration,
+    pub(crate) max_tickets: usize,
+}
+
+impl AntiReplayConfig {
+    /// How long to remember that a ticket was used
+    ///
+    /// Must be at least as long as tickets are accepted by the crypto layer, since a ticket that
+    /// was forgotten may be replayed. Defaults to 12 hours, the longest a `rustls::Ticketer`
+    /// accepts its tickets for.
+    pub fn window(&mut self, value: Duration) -> &mut Self {
+        self.window = value;
+        self
+    }
+
+    /// Maximum number of tickets to remember
+    ///
+    /// Once this many tickets have been used within the window, 0-RTT is rejected for new ones
+    /// until old ones can be forgotten. Defaults to 1 million, using tens of megabytes.
+    pub fn max_tickets(&mut self, value: usize) -> &mut Self {
+        self.max_tickets = value;
+        self
+    }
+}
+
+impl Default for AntiReplayConfig {
+    fn default() -> Self {
+        Self {
+            window: Duration::from_secs(12 * 60 * 60),
+            max_tickets: 1_000_000,
+        }
+    }
+}
+
+impl Default for HandshakeLimitConfig {
+    fn default() -> Self {
+        Self {
+            burst: 10,
+            interval: Duration::from_millis(100),
+            ipv4_prefix_len: 32,
+            ipv6_prefix_len: 64,
+            max_sources: 65536,
+            refuse: false,
+        }
+    }
+}
+
 /// Global configuration for the endpoint, affecting all connections
 ///
 /// Default values should be suitable for most internet applications.
@@ -292,7 +605,7 @@ pub struct EndpointConfig<S>
 where
     S: crypto::Session,
 {
-    pub(crate) reset_key: Arc<S::HmacKey>,
+    pub(crate) reset_keys: Arc<KeyRing<S::HmacKey>>,
     pub(crate) max_udp_payload_size: VarInt,
     /// CID generator factory
     ///
@@ -312,7 +625,7 @@ where
         let cid_factory: fn() -> Box<dyn ConnectionIdGenerator> =
             || Box::new(RandomConnectionIdGenerator::default());
         Self {
-            reset_key: Arc::new(reset_key),
+            reset_keys: Arc::new(KeyRing::new(0, reset_key)),
             max_udp_payload_size: 1480u32.into(), // Typical internet MTU minus IPv4 and UDP overhead, rounded up to a multiple of 8
             connection_id_generator_factory: Arc::new(cid_factory),
             initial_version: DEFAULT_SUPPORTED_VERSIONS[0],
@@ -340,11 +653,28 @@ where
 
     /// Private key used to send authenticated connection resets to peers who were
     /// communicating with a previous instance of this endpoint.
+    ///
+    /// Replaces the configured key ring with one holding only this key, identified by 0.
     pub fn reset_key(&mut self, value: &[u8]) -> Result<&mut Self, ConfigError> {
-        self.reset_key = Arc::new(S::HmacKey::new(value)?);
+        self.reset_keys = Arc::new(KeyRing::new(0, S::HmacKey::new(value)?));
         Ok(self)
     }
 
+    /// Keys used to send authenticated connection resets, which may be rotated at runtime
+    ///
+    /// Reset tokens for new connection IDs are derived from the current key, and stateless resets
+    /// are sent using the current key too. Connections whose reset tokens were derived from an
+    /// earlier key, e.g. by a previous instance of this endpoint, therefore can't be reset after
+    /// a rotation, unless the connection ID generator records the key's ID in the connection
+    /// IDs it creates, as QUIC-LB generators can with
+    /// [`quic_lb::Config::record_reset_key_id()`](crate::quic_lb::Config::record_reset_key_id).
+    /// Resets for those are sent using the recorded key, or not at all if it has been removed
+    /// from the ring.
+    pub fn reset_keys(&mut self, value: Arc<KeyRing<S::HmacKey>>) -> &mut Self {
+        self.reset_keys = value;
+        self
+    }
+
     /// Maximum UDP payload size accepted from peers. Excludes UDP and IP overhead.
     ///
     /// The default is suitable for typical internet applications. Applications which expect to run
@@ -368,12 +698,21 @@ where
     }
 
     /// Override supported QUIC versions
+    ///
+    /// `initial_version` is used for outgoing connections and must be contained in
+    /// `supported_versions`. Defaults to `DEFAULT_SUPPORTED_VERSIONS`; draft versions from
+    /// `LEGACY_DRAFT_VERSIONS` may be added here to interoperate with older peers. Versions the
+    /// crypto session can't derive keys for are refused, e.g. `QUIC_VERSION_2` with rustls.
     pub fn supported_versions(
         &mut self,
         supported_versions: Vec<u32>,
         initial_version: u32,
     ) -> Result<&mut Self, ConfigError> {
-        if !supported_versions.contains(&initial_version) {
+        if !supported_versions.contains(&initial_version)
+            || !supported_versions
+                .iter()
+                .all(|&x| S::is_supported_version(x))
+        {
             return Err(ConfigError::OutOfBounds);
         }
         self.supported_versions = supported_versions;
@@ -385,7 +724,7 @@ where
 impl<S: crypto::Session> fmt::Debug for EndpointConfig<S> {
     fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
         fmt.debug_struct("EndpointConfig")
-            .field("reset_key", &"[ elided ]")
+            .field("reset_keys", &self.reset_keys)
             .field("max_udp_payload_size", &self.max_udp_payload_size)
             .field("cid_generator_factory", &"[ elided ]")
             .field("supported_versions", &self.supported_versions)
@@ -408,7 +747,7 @@ impl<S: crypto::Session> Default for EndpointConfig<S> {
 impl<S: crypto::Session> Clone for EndpointConfig<S> {
     fn clone(&self) -> Self {
         Self {
-            reset_key: self.reset_key.clone(),
+            reset_keys: self.reset_keys.clone(),
             max_udp_payload_size: self.max_udp_payload_size,
             connection_id_generator_factory: self.connection_id_generator_factory.clone(),
             supported_versions: self.supported_versions.clone(),
@@ -433,7 +772,7 @@ where
     pub crypto: S::ServerConfig,
 
     /// Used to generate one-time AEAD keys to protect handshake tokens
-    pub(crate) token_key: Arc<S::HandshakeTokenKey>,
+    pub(crate) token_keys: Arc<KeyRing<S::HandshakeTokenKey>>,
 
     /// Whether to require clients to prove ownership of an address before committing resources.
     ///
@@ -441,15 +780,33 @@ where
     pub(crate) use_stateless_retry: bool,
     /// Microseconds after a stateless retry token was issued for which it's considered valid.
     pub(crate) retry_token_lifetime: Duration,
+    /// Duration after a NEW_TOKEN address validation token was issued for which it's considered
+    /// valid
+    pub(crate) validation_token_lifetime: Duration,
+    /// Number of NEW_TOKEN frames sent to a client once the handshake is confirmed
+    pub(crate) new_token_count: u32,
 
     /// Maximum number of concurrent connections
     pub(crate) concurrent_connections: u32,
+    /// Number of handshakes in progress beyond which unvalidated clients are sent a Retry
+    pub(crate) retry_threshold: Option<u32>,
+    /// Per-source rate limiting of connection attempts
+    pub(crate) handshake_limit: Option<HandshakeLimitConfig>,
+    /// Protection of 0-RTT data against replay
+    pub(crate) anti_replay: Option<AntiReplayConfig>,
+    /// Application decision on whether to accept 0-RTT data
+    pub(crate) zero_rtt_policy: Option<Arc<dyn ZeroRttPolicy>>,
 
     /// Whether to allow clients to migrate to new addresses
     ///
     /// Improves behavior for clients that move between different internet connections or suffer NAT
     /// rebinding. Enabled by default.
     pub(crate) migration: bool,
+
+    /// IPv4 address clients are asked to migrate to once the handshake is confirmed
+    pub(crate) preferred_address_v4: Option<SocketAddrV4>,
+    /// IPv6 address clients are asked to migrate to once the handshake is confirmed
+    pub(crate) preferred_address_v6: Option<SocketAddrV6>,
 }
 
 impl<S> ServerConfig<S>
@@ -462,22 +819,45 @@ where
             transport: Arc::new(TransportConfig::default()),
             crypto: S::ServerConfig::new(),
 
-            token_key: Arc::new(prk),
+            token_keys: Arc::new(KeyRing::new(0, prk)),
             use_stateless_retry: false,
             retry_token_lifetime: Duration::from_secs(15),
+            validation_token_lifetime: Duration::from_secs(2 * 7 * 24 * 60 * 60),
+            new_token_count: 2,
 
             concurrent_connections: 100_000,
+            retry_threshold: None,
+            handshake_limit: None,
+            anti_replay: None,
+            zero_rtt_policy: None,
 
             migration: true,
+
+            preferred_address_v4: None,
+            preferred_address_v6: None,
         }
     }
 
     /// Private key used to authenticate data included in handshake tokens.
+    ///
+    /// Replaces the configured key ring with one holding only this key, identified by 0.
     pub fn token_key(&mut self, master_key: &[u8]) -> Result<&mut Self, ConfigError> {
-        self.token_key = Arc::new(S::HandshakeTokenKey::from_secret(master_key));
+        self.token_keys = Arc::new(KeyRing::new(
+            0,
+            S::HandshakeTokenKey::from_secret(master_key),
+        ));
         Ok(self)
     }
 
+    /// Keys used to protect Retry and NEW_TOKEN tokens, which may be rotated at runtime
+    ///
+    /// Tokens are sealed with the current key and name it, so those sealed with any key still in
+    /// the ring remain valid. Servers sharing a ring's keys accept each other's tokens.
+    pub fn token_keys(&mut self, value: Arc<KeyRing<S::HandshakeTokenKey>>) -> &mut Self {
+        self.token_keys = value;
+        self
+    }
+
     /// Whether to require clients to prove ownership of an address before committing resources.
     ///
     /// Introduces an additional round-trip to the handshake to make denial of service attacks more difficult.
@@ -492,6 +872,26 @@ where
         self
     }
 
+    /// Duration after an address validation token was issued in a NEW_TOKEN frame for which it's
+    /// considered valid.
+    ///
+    /// A client presenting a valid token when reconnecting from the same IP address skips the
+    /// stateless retry and isn't subject to the anti-amplification limit. Defaults to two weeks.
+    pub fn validation_token_lifetime(&mut self, value: Duration) -> &mut Self {
+        self.validation_token_lifetime = value;
+        self
+    }
+
+    /// Number of address validation tokens to send to a client in NEW_TOKEN frames once the
+    /// handshake is confirmed.
+    ///
+    /// Clients use each token for at most one future connection. Set to 0 to not send any tokens.
+    /// Defaults to 2.
+    pub fn new_token_count(&mut self, value: u32) -> &mut Self {
+        self.new_token_count = value;
+        self
+    }
+
     /// Maximum number of simultaneous connections to accept.
     ///
     /// New incoming connections are only accepted if the total number of incoming or outgoing
@@ -501,6 +901,48 @@ where
         self
     }
 
+    /// Number of handshakes in progress beyond which clients must prove ownership of their
+    /// address
+    ///
+    /// Once this many incoming connections are awaiting a decision from the application or
+    /// still handshaking, new connection attempts without a valid token are sent a Retry, just
+    /// as if `use_stateless_retry` were set, until the backlog drains. This protects against
+    /// floods of Initial packets from spoofed addresses without the extra round-trip in the
+    /// common case. Defaults to `None`, never requiring a Retry based on load.
+    pub fn retry_threshold(&mut self, value: Option<u32>) -> &mut Self {
+        self.retry_threshold = value;
+        self
+    }
+
+    /// How to limit the rate of connection attempts from each source, or `None` to not limit
+    /// them
+    ///
+    /// Defaults to `None`.
+    pub fn handshake_limit(&mut self, value: Option<HandshakeLimitConfig>) -> &mut Self {
+        self.handshake_limit = value;
+        self
+    }
+
+    /// How to protect 0-RTT data against replay, or `None` to accept it whenever the crypto
+    /// layer does
+    ///
+    /// Whether protection was in effect for a connection is reported by
+    /// `Connection::is_0rtt_replay_protected()`. Defaults to `None`.
+    pub fn anti_replay(&mut self, value: Option<AntiReplayConfig>) -> &mut Self {
+        self.anti_replay = value;
+        self
+    }
+
+    /// Application hook deciding whether to accept 0-RTT data on each resumed connection, or
+    /// `None` to accept it whenever the crypto layer does
+    ///
+    /// Data to base the decision on can be associated with session tickets with
+    /// `Connection::set_resumption_data()`. Defaults to `None`.
+    pub fn zero_rtt_policy(&mut self, value: Option<Arc<dyn ZeroRttPolicy>>) -> &mut Self {
+        self.zero_rtt_policy = value;
+        self
+    }
+
     /// Whether to allow clients to migrate to new addresses
     ///
     /// Improves behavior for clients that move between different internet connections or suffer NAT
@@ -509,6 +951,26 @@ where
         self.migration = value;
         self
     }
+
+    /// IPv4 address to advertise to clients in the `preferred_address` transport parameter
+    ///
+    /// Clients connected over IPv4 validate this address once the handshake is confirmed and move
+    /// the connection to it, e.g. to hand off from an anycast address to a unicast one. The
+    /// connection stays on the original address if validation fails. Clients on multipath
+    /// connections ignore the preferred address, though they may open an additional path to it.
+    /// The endpoint must be reachable at this address. Defaults to `None`.
+    pub fn preferred_address_v4(&mut self, address: Option<SocketAddrV4>) -> &mut Self {
+        self.preferred_address_v4 = address;
+        self
+    }
+
+    /// IPv6 address to advertise to clients in the `preferred_address` transport parameter
+    ///
+    /// See [`preferred_address_v4`](Self::preferred_address_v4). Defaults to `None`.
+    pub fn preferred_address_v6(&mut self, address: Option<SocketAddrV6>) -> &mut Self {
+        self.preferred_address_v6 = address;
+        self
+    }
 }
 
 #[cfg(feature = "rustls")]
@@ -518,8 +980,13 @@ impl ServerConfig<crypto::rustls::TlsSession> {
         &mut self,
         cert_chain: CertificateChain,
         key: PrivateKey,
-    ) -> Result<&mut Self, rustls::TLSError> {
-        Arc::make_mut(&mut self.crypto).set_single_cert(cert_chain.certs, key.inner)?;
+    ) -> Result<&mut Self, rustls::Error> {
+        let key = rustls::sign::any_supported_type(&key.inner)
+            .map_err(|_| rustls::Error::General("invalid private key".into()))?;
+        Arc::make_mut(&mut self.crypto).cert_resolver =
+            Arc::new(crypto::rustls::SingleCertResolver(Arc::new(
+                rustls::sign::CertifiedKey::new(cert_chain.certs, key),
+            )));
         Ok(self)
     }
 }
@@ -532,11 +999,19 @@ where
         fmt.debug_struct("ServerConfig<T>")
             .field("transport", &self.transport)
             .field("crypto", &"ServerConfig { elided }")
-            .field("token_key", &"[ elided ]")
+            .field("token_keys", &self.token_keys)
             .field("use_stateless_retry", &self.use_stateless_retry)
             .field("retry_token_lifetime", &self.retry_token_lifetime)
+            .field("validation_token_lifetime", &self.validation_token_lifetime)
+            .field("new_token_count", &self.new_token_count)
             .field("concurrent_connections", &self.concurrent_connections)
+            .field("retry_threshold", &self.retry_threshold)
+            .field("handshake_limit", &self.handshake_limit)
+            .field("anti_replay", &self.anti_replay)
+            .field("zero_rtt_policy", &self.zero_rtt_policy.is_some())
             .field("migration", &self.migration)
+            .field("preferred_address_v4", &self.preferred_address_v4)
+            .field("preferred_address_v6", &self.preferred_address_v6)
             .finish()
     }
 }
@@ -564,11 +1039,19 @@ where
         Self {
             transport: self.transport.clone(),
             crypto: self.crypto.clone(),
-            token_key: self.token_key.clone(),
+            token_keys: self.token_keys.clone(),
             use_stateless_retry: self.use_stateless_retry,
             retry_token_lifetime: self.retry_token_lifetime,
+            validation_token_lifetime: self.validation_token_lifetime,
+            new_token_count: self.new_token_count,
             concurrent_connections: self.concurrent_connections,
+            retry_threshold: self.retry_threshold,
+            handshake_limit: self.handshake_limit.clone(),
+            anti_replay: self.anti_replay.clone(),
+            zero_rtt_policy: self.zero_rtt_policy.clone(),
             migration: self.migration,
+            preferred_address_v4: self.preferred_address_v4,
+            preferred_address_v6: self.preferred_address_v6,
         }
     }
 }
@@ -585,21 +1068,60 @@ where
 
     /// Cryptographic configuration to use
     pub crypto: S::ClientConfig,
+
+    /// QUIC protocol version to use, overriding the endpoint's initial version
+    pub(crate) version: Option<u32>,
+
+    /// Where to keep address validation tokens received from servers, keyed by server name
+    ///
+    /// Tokens are presented when reconnecting to the same server to skip address validation.
+    /// Tokens aren't stored or used if `None`, the default.
+    pub token_store: Option<Arc<dyn TokenStore>>,
+
+    /// Trust anchors installed by `add_certificate_authority`, once it has been called
+    #[cfg(feature = "rustls")]
+    pub(crate) roots: Option<rustls::RootCertStore>,
+}
+
+impl<S> ClientConfig<S>
+where
+    S: crypto::Session,
+{
+    /// QUIC protocol version to use, overriding the endpoint's initial version
+    ///
+    /// Must be one of the versions supported by the endpoint.
+    pub fn version(&mut self, version: u32) -> &mut Self {
+        self.version = Some(version);
+        self
+    }
 }
 
 #[cfg(feature = "rustls")]
 impl ClientConfig<crypto::rustls::TlsSession> {
     /// Add a trusted certificate authority
+    ///
+    /// The authority is trusted in addition to the default roots and any authorities added
+    /// previously. Replaces the certificate verifier `crypto` was otherwise configured with.
     pub fn add_certificate_authority(
         &mut self,
         cert: Certificate,
     ) -> Result<&mut Self, webpki::Error> {
-        let anchor = webpki::trust_anchor_util::cert_der_as_trust_anchor(&cert.inner.0)?;
+        let roots = self.roots.get_or_insert_with(crypto::rustls::default_roots);
+        roots.add(&cert.inner)?;
         Arc::make_mut(&mut self.crypto)
-            .root_store
-            .add_server_trust_anchors(&webpki::TLSServerTrustAnchors(&[anchor]));
+            .dangerous()
+            .set_certificate_verifier(crypto::rustls::verifier(roots.clone()));
         Ok(self)
     }
+
+    /// Keep TLS session tickets in `store` rather than rustls' default in-memory cache
+    ///
+    /// Use a persistent store to resume sessions and send 0-RTT data after the process restarts.
+    pub fn session_store(&mut self, store: Arc<dyn SessionStore>) -> &mut Self {
+        Arc::make_mut(&mut self.crypto).session_storage =
+            Arc::new(crypto::rustls::SessionStoreAdapter(store));
+        self
+    }
 }
 
 impl<S> Default for ClientConfig<S>
@@ -610,6 +1132,10 @@ where
         Self {
             transport: Default::default(),
             crypto: S::ClientConfig::new(),
+            version: None,
+            token_store: None,
+            #[cfg(feature = "rustls")]
+            roots: None,
         }
     }
 }
@@ -623,6 +1149,10 @@ where
         Self {
             transport: self.transport.clone(),
             crypto: self.crypto.clone(),
+            version: self.version,
+            token_store: self.token_store.clone(),
+            #[cfg(feature = "rustls")]
+            roots: self.roots.clone(),
         }
     }
 }
@@ -635,6 +1165,11 @@ where
         fmt.debug_struct("ClientConfig<T>")
             .field("transport", &self.transport)
             .field("crypto", &"ClientConfig { elided }")
+            .field("version", &self.version)
+            .field(
+                "token_store",
+                &self.token_store.as_ref().map(|_| "[ elided ]"),
+            )
             .finish()
     }
 }
@@ -1,31 +1,121 @@
 //! Logic for controlling the rate at which data is sent
 
-use std::time::{Duration, Instant};
+use std::time::Instant;
 
+use crate::connection::RttEstimator;
+
+mod bbr;
 mod cubic;
+mod hystart;
+mod ledbat;
 mod new_reno;
 
+pub use bbr::{Bbr, BbrConfig};
 pub use cubic::{Cubic, CubicConfig};
+pub use hystart::HyStart;
+pub use ledbat::{Ledbat, LedbatConfig};
 pub use new_reno::{NewReno, NewRenoConfig};
 
 /// Common interface for different congestion controllers
 pub trait Controller: Send {
-    /// Packet deliveries were confirmed
+    /// One of a batch of packet deliveries was confirmed
     ///
     /// `app_limited` indicates whether the connection was blocked on outgoing
-    /// application data prior to receiving these acknowledgements.
-    fn on_ack(&mut self, now: Instant, sent: Instant, bytes: u64, app_limited: bool, rtt: Duration);
+    /// application data prior to receiving these acknowledgements. `rtt` reflects the round-trip
+    /// time measured from the ACK being processed.
+    fn on_ack(
+        &mut self,
+        now: Instant,
+        sent: Instant,
+        bytes: u64,
+        app_limited: bool,
+        rtt: &RttEstimator,
+    );
+
+    /// An ack-eliciting packet of `bytes` bytes was sent
+    ///
+    /// `packet_number` is its number if it was sent in the application data packet number space,
+    /// and `in_flight` is the number of bytes in flight, including the new packet.
+    fn on_sent(
+        &mut self,
+        _now: Instant,
+        _bytes: u64,
+        _packet_number: Option<u64>,
+        _in_flight: u64,
+    ) {
+    }
+
+    /// All packets newly acknowledged by an ACK frame have been passed to [`on_ack`], and any
+    /// packets it revealed to be lost to [`on_packet_lost`]
+    ///
+    /// `in_flight` is the number of bytes left in flight, and `largest_packet_number_acked` the
+    /// largest packet number acknowledged so far if the ACK was for the application data packet
+    /// number space, whose numbers [`on_sent`] is given.
+    ///
+    /// [`on_ack`]: Controller::on_ack
+    /// [`on_packet_lost`]: Controller::on_packet_lost
+    /// [`on_sent`]: Controller::on_sent
+    fn on_end_acks(
+        &mut self,
+        _now: Instant,
+        _in_flight: u64,
+        _app_limited: bool,
+        _largest_packet_number_acked: Option<u64>,
+    ) {
+    }
+
+    /// An ack-eliciting packet of `bytes` bytes sent at `sent` was deemed lost
+    ///
+    /// Called for each lost packet before [`on_congestion_event`] is called for all of them.
+    ///
+    /// [`on_congestion_event`]: Controller::on_congestion_event
+    fn on_packet_lost(&mut self, _now: Instant, _sent: Instant, _bytes: u64) {}
 
     /// Packets were deemed lost or marked congested
     ///
+    /// `sent` is when the most recent of them was sent, and `lost_bytes` the number of
+    /// ack-eliciting bytes lost, which is 0 if they were marked congested.
     /// `in_persistent_congestion` indicates whether all packets sent within the persistent
     /// congestion threshold period ending when the most recent packet in this batch was sent were
     /// lost.
-    fn on_congestion_event(&mut self, now: Instant, sent: Instant, is_persistent_congestion: bool);
+    fn on_congestion_event(
+        &mut self,
+        now: Instant,
+        sent: Instant,
+        is_persistent_congestion: bool,
+        lost_bytes: u64,
+    );
+
+    /// The peer reported `ce_count` more packets marked with ECN Congestion Experienced in an
+    /// ACK whose largest acknowledged packet was sent at `sent`
+    ///
+    /// Treated as a congestion event by default.
+    fn on_ecn_ce(&mut self, now: Instant, sent: Instant, _ce_count: u64) {
+        self.on_congestion_event(now, sent, false, 0);
+    }
+
+    /// Careful resumption of a previous connection's congestion window settled on `window`
+    ///
+    /// If `congested`, the resumed window caused congestion and `window` is the reduced one to
+    /// continue from without slow start. Otherwise the path was found to carry `window` and slow
+    /// start may go on from there. Ignored by default, so that the controller's own window applies
+    /// again once resumption ends.
+    fn on_resume(&mut self, _now: Instant, _window: u64, _congested: bool) {}
+
+    /// The maximum UDP payload size of the path changed, e.g. due to MTU discovery
+    fn on_mtu_update(&mut self, _new_mtu: u16) {}
 
     /// Number of ack-eliciting bytes that may be in flight
     fn window(&self) -> u64;
 
+    /// Rate at which to pace outgoing packets, in bytes per second
+    ///
+    /// If `None`, which is the default, a window's worth of data is paced out over slightly less
+    /// than a smoothed round-trip time.
+    fn pacing_rate(&self) -> Option<u64> {
+        None
+    }
+
     /// Duplicate the controller's state
     fn clone_box(&self) -> Box<dyn Controller>;
 
@@ -0,0 +1,782 @@
+use std::cmp;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rand::Rng;
+
+use super::{Controller, ControllerFactory};
+use crate::connection::RttEstimator;
+
+/// Experimental! Use at your own risk.
+///
+/// A model-based congestion controller following version 3 of Google's BBR algorithm, as described
+/// in draft-ietf-ccwg-bbr
+///
+/// Rather than treating every loss as a sign of congestion, BBR estimates the path's bottleneck
+/// bandwidth and minimum round-trip time, paces packets at the estimated bandwidth and keeps
+/// roughly one bandwidth-delay product in flight. Loss only reduces its estimates once it exceeds
+/// a small fraction of the data sent, so it sustains much higher throughput than loss-based
+/// controllers such as [`Cubic`](super::Cubic) on paths with random loss.
+#[derive(Debug, Clone)]
+pub struct Bbr {
+    config: Arc<BbrConfig>,
+    /// The current maximum UDP payload size of the path
+    current_mtu: u64,
+    state: State,
+    pacing_gain: f64,
+    cwnd_gain: f64,
+    /// Maximum number of bytes in flight that may be sent
+    window: u64,
+    /// Bytes per second, once the round-trip time is known
+    pacing_rate: Option<u64>,
+
+    // Delivery rate sampling
+    /// Number of bytes acknowledged so far
+    delivered: u64,
+    /// When `delivered` last increased
+    delivered_time: Instant,
+    /// When the most recently acknowledged packet was sent
+    first_sent_time: Instant,
+    /// Delivery state at the times packets that may still be in flight were sent, oldest first
+    sent: VecDeque<SendState>,
+    /// Bytes in flight, as last reported by the connection and adjusted since
+    in_flight: u64,
+    /// Whether the connection was last reported to be application-limited
+    app_limited: bool,
+    /// Taken from the most recently sent packet acknowledged by the ACK being processed
+    sample: Option<RateSample>,
+    /// Bytes acknowledged by the ACK being processed
+    newly_acked: u64,
+    /// Smallest round-trip time measured from the ACK being processed
+    ack_rtt: Option<Duration>,
+
+    // Round counting, where a round ends once a packet sent after it began is acknowledged
+    round_start: bool,
+    next_round_delivered: u64,
+    rounds_since_bw_probe: u64,
+
+    // Network path model
+    /// Windowed maximum of the delivery rate over the last two bandwidth probing cycles
+    max_bw: MaxBwFilter,
+    /// Largest delivery rate sampled in the current round
+    bw_latest: u64,
+    /// Largest volume of data delivered in a single sample in the current round
+    inflight_latest: u64,
+    /// Short-term bandwidth bound, lowered in rounds with loss
+    bw_lo: Option<u64>,
+    /// Short-term bound on data in flight, lowered in rounds with loss
+    inflight_lo: Option<u64>,
+    /// Long-term bound on data in flight, above which loss is excessive
+    inflight_hi: Option<u64>,
+    min_rtt: Option<Duration>,
+    min_rtt_stamp: Instant,
+    /// Minimum round-trip time over the shorter window between ProbeRTT phases
+    probe_rtt_min_delay: Option<Duration>,
+    probe_rtt_min_stamp: Instant,
+
+    // Loss in the current round
+    lost_in_round: u64,
+    lost_packets_in_round: u64,
+    /// Largest amount of data that was in flight when a packet lost this round was sent
+    lost_tx_in_flight: u64,
+    /// Whether the previous round saw any loss
+    loss_in_round: bool,
+
+    // Startup
+    full_bw: u64,
+    full_bw_count: u32,
+    full_bw_reached: bool,
+
+    // ProbeBW
+    cycle_stamp: Instant,
+    bw_probe_wait: Duration,
+    probe_up_rounds: u32,
+
+    // ProbeRTT
+    probe_rtt_done_stamp: Option<Instant>,
+    probe_rtt_round_done: bool,
+    prior_window: u64,
+}
+
+impl Bbr {
+    /// Construct a state using the given `config` and current time `now`
+    pub fn new(config: Arc<BbrConfig>, now: Instant) -> Self {
+        Self {
+            current_mtu: config.max_datagram_size,
+            state: State::Startup,
+            pacing_gain: STARTUP_PACING_GAIN,
+            cwnd_gain: STARTUP_CWND_GAIN,
+            window: config.initial_window,
+            pacing_rate: None,
+            delivered: 0,
+            delivered_time: now,
+            first_sent_time: now,
+            sent: VecDeque::new(),
+            in_flight: 0,
+            app_limited: false,
+            sample: None,
+            newly_acked: 0,
+            ack_rtt: None,
+            round_start: false,
+            next_round_delivered: 0,
+            rounds_since_bw_probe: 0,
+            max_bw: MaxBwFilter::default(),
+            bw_latest: 0,
+            inflight_latest: 0,
+            bw_lo: None,
+            inflight_lo: None,
+            inflight_hi: None,
+            min_rtt: None,
+            min_rtt_stamp: now,
+            probe_rtt_min_delay: None,
+            probe_rtt_min_stamp: now,
+            lost_in_round: 0,
+            lost_packets_in_round: 0,
+            lost_tx_in_flight: 0,
+            loss_in_round: false,
+            full_bw: 0,
+            full_bw_count: 0,
+            full_bw_reached: false,
+            cycle_stamp: now,
+            bw_probe_wait: Duration::from_secs(0),
+            probe_up_rounds: 0,
+            probe_rtt_done_stamp: None,
+            probe_rtt_round_done: false,
+            prior_window: 0,
+            config,
+        }
+    }
+
+    fn minimum_window(&self) -> u64 {
+        cmp::max(self.config.minimum_window, 4 * self.current_mtu)
+    }
+
+    /// The bandwidth estimate used to control sending, in bytes per second
+    fn bw(&self) -> u64 {
+        cmp::min(self.max_bw.get(), self.bw_lo.unwrap_or(u64::MAX))
+    }
+
+    /// `gain` times the estimated bandwidth-delay product
+    fn bdp(&self, gain: f64) -> u64 {
+        match self.min_rtt {
+            Some(min_rtt) => {
+                let bdp = u128::from(self.bw()) * min_rtt.as_nanos() / 1_000_000_000;
+                (bdp as f64 * gain) as u64
+            }
+            None => (self.config.initial_window as f64 * gain) as u64,
+        }
+    }
+
+    /// Data that may be in flight for a pacing or window gain of `gain`, allowing for batching
+    fn inflight(&self, gain: f64) -> u64 {
+        let send_quantum = match self.pacing_rate {
+            Some(rate) => (rate / 1000).min(64 * 1024).max(2 * self.current_mtu),
+            None => 2 * self.current_mtu,
+        };
+        self.bdp(gain) + 3 * send_quantum
+    }
+
+    /// The bound on data in flight that leaves other flows room in the bottleneck's buffer
+    fn inflight_with_headroom(&self) -> u64 {
+        match self.inflight_hi {
+            Some(hi) => cmp::max((hi as f64 * (1.0 - HEADROOM)) as u64, self.minimum_window()),
+            None => u64::MAX,
+        }
+    }
+
+    fn probe_rtt_window(&self) -> u64 {
+        cmp::max(self.bdp(PROBE_RTT_CWND_GAIN), self.minimum_window())
+    }
+
+    /// Whether the loss in the current round is excessive
+    fn inflight_too_high(&self) -> bool {
+        self.lost_in_round as f64 > LOSS_THRESH * self.lost_tx_in_flight as f64
+    }
+
+    fn is_probing_bw(&self) -> bool {
+        matches!(
+            self.state,
+            State::Startup
+                | State::ProbeBw(ProbeBwPhase::Refill)
+                | State::ProbeBw(ProbeBwPhase::Up)
+        )
+    }
+
+    fn set_state(&mut self, state: State) {
+        self.state = state;
+        let (pacing_gain, cwnd_gain) = match state {
+            State::Startup => (STARTUP_PACING_GAIN, STARTUP_CWND_GAIN),
+            State::Drain => (DRAIN_PACING_GAIN, STARTUP_CWND_GAIN),
+            State::ProbeBw(ProbeBwPhase::Down) => (0.9, 2.0),
+            State::ProbeBw(ProbeBwPhase::Cruise) | State::ProbeBw(ProbeBwPhase::Refill) => {
+                (1.0, 2.0)
+            }
+            State::ProbeBw(ProbeBwPhase::Up) => (1.25, 2.25),
+            State::ProbeRtt => (1.0, PROBE_RTT_CWND_GAIN),
+        };
+        self.pacing_gain = pacing_gain;
+        self.cwnd_gain = cwnd_gain;
+    }
+
+    /// Begin a new round with the next packet sent
+    fn start_round(&mut self) {
+        self.next_round_delivered = self.delivered;
+    }
+
+    fn update_round(&mut self, sample: Option<&RateSample>) {
+        self.round_start = false;
+        if let Some(sample) = sample {
+            if sample.prior_delivered >= self.next_round_delivered {
+                self.start_round();
+                self.round_start = true;
+                self.rounds_since_bw_probe += 1;
+            }
+        }
+    }
+
+    /// Update the round-trip time estimates, returning whether it's time for ProbeRTT
+    fn update_min_rtt(&mut self, now: Instant, rtt: Option<Duration>) -> bool {
+        let probe_rtt_expired = now > self.probe_rtt_min_stamp + PROBE_RTT_INTERVAL;
+        if let Some(rtt) = rtt {
+            if probe_rtt_expired || !matches!(self.probe_rtt_min_delay, Some(x) if x <= rtt) {
+                self.probe_rtt_min_delay = Some(rtt);
+                self.probe_rtt_min_stamp = now;
+            }
+        }
+        let min_rtt_expired = now > self.min_rtt_stamp + MIN_RTT_FILTER_LEN;
+        let lower = match (self.probe_rtt_min_delay, self.min_rtt) {
+            (Some(x), Some(y)) => x < y,
+            (x, y) => x.is_some() && y.is_none(),
+        };
+        if lower || min_rtt_expired {
+            self.min_rtt = self.probe_rtt_min_delay;
+            self.min_rtt_stamp = self.probe_rtt_min_stamp;
+        }
+        probe_rtt_expired && self.probe_rtt_min_delay.is_some()
+    }
+
+    fn check_startup_done(&mut self, app_limited: bool) {
+        if self.full_bw_reached || self.state != State::Startup {
+            return;
+        }
+        if self.round_start && !app_limited {
+            let bw = self.max_bw.get();
+            if bw as f64 >= self.full_bw as f64 * FULL_BW_GROWTH {
+                self.full_bw = bw;
+                self.full_bw_count = 0;
+            } else {
+                self.full_bw_count += 1;
+                self.full_bw_reached = self.full_bw_count >= STARTUP_FULL_BW_ROUNDS;
+            }
+        }
+        if self.lost_packets_in_round >= STARTUP_FULL_LOSS_COUNT && self.inflight_too_high() {
+            self.full_bw_reached = true;
+            self.inflight_hi = Some(cmp::max(self.bdp(1.0), self.inflight_latest));
+        }
+        if self.full_bw_reached {
+            self.set_state(State::Drain);
+        }
+    }
+
+    fn start_probe_bw_down(&mut self, now: Instant) {
+        self.max_bw.advance();
+        self.rounds_since_bw_probe = rand::thread_rng().gen_range(0..2);
+        self.bw_probe_wait = Duration::from_secs(2)
+            + Duration::from_micros(rand::thread_rng().gen_range(0..1_000_000));
+        self.cycle_stamp = now;
+        self.start_round();
+        self.set_state(State::ProbeBw(ProbeBwPhase::Down));
+    }
+
+    fn is_time_to_probe_bw(&self, now: Instant) -> bool {
+        // Probe at least as often as Reno would grow to fill the pipe, so as to compete fairly
+        let reno_rounds = cmp::min(self.bdp(1.0) / self.current_mtu, MAX_RENO_ROUNDS);
+        now > self.cycle_stamp + self.bw_probe_wait || self.rounds_since_bw_probe >= reno_rounds
+    }
+
+    fn update_probe_bw_phase(&mut self, now: Instant) {
+        let phase = match self.state {
+            State::Drain => {
+                if self.in_flight <= self.inflight(1.0) {
+                    self.start_probe_bw_down(now);
+                }
+                return;
+            }
+            State::ProbeBw(phase) => phase,
+            _ => return,
+        };
+        match phase {
+            ProbeBwPhase::Down | ProbeBwPhase::Cruise if self.is_time_to_probe_bw(now) => {
+                // Probing starts afresh, without the bounds imposed by losses since the last probe
+                self.bw_lo = None;
+                self.inflight_lo = None;
+                self.probe_up_rounds = 0;
+                self.start_round();
+                self.set_state(State::ProbeBw(ProbeBwPhase::Refill));
+            }
+            ProbeBwPhase::Down => {
+                if self.in_flight <= cmp::min(self.inflight_with_headroom(), self.inflight(1.0)) {
+                    self.set_state(State::ProbeBw(ProbeBwPhase::Cruise));
+                }
+            }
+            ProbeBwPhase::Cruise => {}
+            ProbeBwPhase::Refill => {
+                if self.round_start {
+                    self.cycle_stamp = now;
+                    self.start_round();
+                    self.set_state(State::ProbeBw(ProbeBwPhase::Up));
+                }
+            }
+            ProbeBwPhase::Up => {
+                if self.round_start {
+                    // Raise the bound on data in flight exponentially while it's not exceeded
+                    if let Some(ref mut hi) = self.inflight_hi {
+                        if self.in_flight + self.current_mtu >= *hi {
+                            *hi += self.current_mtu << self.probe_up_rounds;
+                        }
+                    }
+                    self.probe_up_rounds = cmp::min(self.probe_up_rounds + 1, 30);
+                }
+                let elapsed = now.saturating_duration_since(self.cycle_stamp);
+                if elapsed > self.min_rtt.unwrap_or_default()
+                    && self.in_flight >= self.inflight(1.25)
+                {
+                    self.start_probe_bw_down(now);
+                }
+            }
+        }
+    }
+
+    /// React to excessive loss while probing for bandwidth
+    fn check_inflight_too_high(&mut self, now: Instant, app_limited: bool) {
+        if !matches!(
+            self.state,
+            State::ProbeBw(ProbeBwPhase::Refill) | State::ProbeBw(ProbeBwPhase::Up)
+        ) || !self.inflight_too_high()
+        {
+            return;
+        }
+        if !app_limited {
+            self.inflight_hi = Some(cmp::max(
+                self.lost_tx_in_flight,
+                (self.bdp(1.0) as f64 * BETA) as u64,
+            ));
+        }
+        if self.state == State::ProbeBw(ProbeBwPhase::Up) {
+            self.start_probe_bw_down(now);
+        }
+    }
+
+    fn check_probe_rtt(&mut self, now: Instant, probe_rtt_expired: bool) {
+        if self.state != State::ProbeRtt && probe_rtt_expired {
+            self.prior_window = self.window;
+            self.probe_rtt_done_stamp = None;
+            self.set_state(State::ProbeRtt);
+        }
+        if self.state != State::ProbeRtt {
+            return;
+        }
+        match self.probe_rtt_done_stamp {
+            None if self.in_flight <= self.probe_rtt_window() => {
+                // Hold the reduced window for a while, and at least a round
+                self.probe_rtt_done_stamp = Some(now + PROBE_RTT_DURATION);
+                self.probe_rtt_round_done = false;
+                self.start_round();
+            }
+            None => {}
+            Some(done) => {
+                self.probe_rtt_round_done |= self.round_start;
+                if self.probe_rtt_round_done && now > done {
+                    self.probe_rtt_min_stamp = now;
+                    self.window = cmp::max(self.window, self.prior_window);
+                    self.bw_lo = None;
+                    self.inflight_lo = None;
+                    if self.full_bw_reached {
+                        self.start_probe_bw_down(now);
+                        self.set_state(State::ProbeBw(ProbeBwPhase::Cruise));
+                    } else {
+                        self.set_state(State::Startup);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Lower the short-term bounds after a round with loss, unless probing for bandwidth
+    fn adapt_lower_bounds(&mut self) {
+        if !self.round_start || !self.loss_in_round || self.is_probing_bw() {
+            return;
+        }
+        let bw_lo = self.bw_lo.unwrap_or_else(|| self.max_bw.get());
+        self.bw_lo = Some(cmp::max(self.bw_latest, (bw_lo as f64 * BETA) as u64));
+        let inflight_lo = self.inflight_lo.unwrap_or(self.window);
+        self.inflight_lo = Some(cmp::max(
+            self.inflight_latest,
+            (inflight_lo as f64 * BETA) as u64,
+        ));
+    }
+
+    fn set_pacing_rate(&mut self) {
+        let bw = self.bw();
+        if bw == 0 {
+            return;
+        }
+        let rate = (self.pacing_gain * bw as f64 * (1.0 - PACING_MARGIN)) as u64;
+        if self.full_bw_reached || !matches!(self.pacing_rate, Some(x) if x >= rate) {
+            self.pacing_rate = Some(rate);
+        }
+    }
+
+    fn set_window(&mut self, newly_acked: u64) {
+        let target = self.inflight(self.cwnd_gain);
+        if self.full_bw_reached {
+            self.window = cmp::min(self.window + newly_acked, target);
+        } else if self.window < target || self.delivered < self.config.initial_window {
+            self.window += newly_acked;
+        }
+        self.window = cmp::max(self.window, self.minimum_window());
+
+        let mut cap = match self.state {
+            State::ProbeBw(ProbeBwPhase::Cruise) | State::ProbeRtt => self.inflight_with_headroom(),
+            State::ProbeBw(_) => self.inflight_hi.unwrap_or(u64::MAX),
+            _ => u64::MAX,
+        };
+        cap = cmp::min(cap, self.inflight_lo.unwrap_or(u64::MAX));
+        cap = cmp::max(cap, self.minimum_window());
+        self.window = cmp::min(self.window, cap);
+        if self.state == State::ProbeRtt {
+            self.window = cmp::min(self.window, self.probe_rtt_window());
+        }
+    }
+
+    /// Forget the delivery state of packets sent before `time`, which are no longer in flight
+    fn discard_sent_before(&mut self, time: Instant) -> Option<SendState> {
+        while let Some(x) = self.sent.front() {
+            if x.time >= time {
+                break;
+            }
+            self.sent.pop_front();
+        }
+        self.sent.front().filter(|x| x.time == time).cloned()
+    }
+}
+
+impl Controller for Bbr {
+    fn on_sent(&mut self, now: Instant, bytes: u64, _packet_number: Option<u64>, in_flight: u64) {
+        if in_flight == bytes {
+            // Don't count idle time towards the next sample
+            self.first_sent_time = now;
+            self.delivered_time = now;
+        }
+        self.in_flight = in_flight;
+        match self.sent.back_mut() {
+            // Packets sent together share their delivery state
+            Some(x) if x.time == now && x.delivered == self.delivered => {
+                x.in_flight = self.in_flight;
+            }
+            _ => self.sent.push_back(SendState {
+                time: now,
+                delivered: self.delivered,
+                delivered_time: self.delivered_time,
+                first_sent_time: self.first_sent_time,
+                in_flight: self.in_flight,
+                app_limited: self.app_limited,
+            }),
+        }
+    }
+
+    fn on_ack(
+        &mut self,
+        now: Instant,
+        sent: Instant,
+        bytes: u64,
+        app_limited: bool,
+        rtt: &RttEstimator,
+    ) {
+        if self.pacing_rate.is_none() {
+            // Until the bandwidth is measured, pace out the initial window quickly
+            let rtt = cmp::max(rtt.get(), Duration::from_millis(1));
+            let nominal_bw =
+                u128::from(self.config.initial_window) * 1_000_000_000 / rtt.as_nanos();
+            self.pacing_rate = Some((STARTUP_PACING_GAIN * nominal_bw as f64) as u64);
+        }
+
+        self.in_flight = self.in_flight.saturating_sub(bytes);
+        self.delivered += bytes;
+        self.delivered_time = now;
+        self.newly_acked += bytes;
+        let rtt = now.saturating_duration_since(sent);
+        self.ack_rtt = Some(self.ack_rtt.map_or(rtt, |x| cmp::min(x, rtt)));
+
+        let packet = match self.discard_sent_before(sent) {
+            Some(x) => x,
+            None => return,
+        };
+        if !matches!(self.sample, Some(ref x) if packet.delivered < x.prior_delivered) {
+            self.sample = Some(RateSample {
+                prior_delivered: packet.delivered,
+                prior_time: packet.delivered_time,
+                send_elapsed: sent.saturating_duration_since(packet.first_sent_time),
+                app_limited: packet.app_limited || app_limited,
+            });
+        }
+        self.first_sent_time = cmp::max(self.first_sent_time, sent);
+    }
+
+    fn on_packet_lost(&mut self, _now: Instant, sent: Instant, bytes: u64) {
+        self.lost_in_round += bytes;
+        self.lost_packets_in_round += 1;
+        let tx_in_flight = match self.discard_sent_before(sent) {
+            Some(packet) => packet.in_flight,
+            // Without a record of the delivery state, at least what is in flight now was then
+            None => self.in_flight + bytes,
+        };
+        self.lost_tx_in_flight = cmp::max(self.lost_tx_in_flight, tx_in_flight);
+        self.in_flight = self.in_flight.saturating_sub(bytes);
+    }
+
+    fn on_end_acks(
+        &mut self,
+        now: Instant,
+        in_flight: u64,
+        app_limited: bool,
+        _largest_packet_number_acked: Option<u64>,
+    ) {
+        self.in_flight = in_flight;
+        self.app_limited = app_limited;
+        let newly_acked = std::mem::replace(&mut self.newly_acked, 0);
+        let sample = self.sample.take();
+        let rtt = self.ack_rtt.take();
+        if newly_acked == 0 {
+            return;
+        }
+
+        self.update_round(sample.as_ref());
+        let probe_rtt_expired = self.update_min_rtt(now, rtt);
+        let sample_app_limited = sample.as_ref().map(|x| x.app_limited).unwrap_or(true);
+        if let Some(sample) = sample {
+            let delivered = self.delivered - sample.prior_delivered;
+            let ack_elapsed = self
+                .delivered_time
+                .saturating_duration_since(sample.prior_time);
+            let interval = cmp::max(sample.send_elapsed, ack_elapsed);
+            // Samples covering less than a round trip overestimate bandwidth due to ACK compression
+            if interval >= self.min_rtt.unwrap_or_default() && interval.as_nanos() > 0 {
+                let rate = (u128::from(delivered) * 1_000_000_000 / interval.as_nanos()) as u64;
+                if rate >= self.max_bw.get() || !sample.app_limited {
+                    self.max_bw.update(rate);
+                }
+                self.bw_latest = cmp::max(self.bw_latest, rate);
+            }
+            self.inflight_latest = cmp::max(self.inflight_latest, delivered);
+        }
+
+        self.adapt_lower_bounds();
+        self.check_startup_done(sample_app_limited);
+        self.check_inflight_too_high(now, sample_app_limited);
+        self.update_probe_bw_phase(now);
+        self.check_probe_rtt(now, probe_rtt_expired);
+
+        if self.round_start {
+            self.loss_in_round = self.lost_in_round > 0;
+            self.lost_in_round = 0;
+            self.lost_packets_in_round = 0;
+            self.lost_tx_in_flight = 0;
+            self.bw_latest = 0;
+            self.inflight_latest = 0;
+        }
+
+        self.set_pacing_rate();
+        self.set_window(newly_acked);
+    }
+
+    fn on_congestion_event(
+        &mut self,
+        _now: Instant,
+        _sent: Instant,
+        is_persistent_congestion: bool,
+        _lost_bytes: u64,
+    ) {
+        // Losses are accounted for by `on_packet_lost`
+        if is_persistent_congestion {
+            self.prior_window = self.window;
+            self.window = self.minimum_window();
+        }
+    }
+
+    fn on_resume(&mut self, _now: Instant, window: u64, congested: bool) {
+        if !congested {
+            // Startup goes on from the window the path was found to carry
+            self.window = cmp::max(self.window, window);
+            return;
+        }
+        // The jump overshot, so treat it like excessive loss in startup
+        self.full_bw_reached = true;
+        self.inflight_hi = Some(cmp::max(window, self.minimum_window()));
+        self.window = cmp::max(window, self.minimum_window());
+        if self.state == State::Startup {
+            self.set_state(State::Drain);
+        }
+    }
+
+    fn on_mtu_update(&mut self, new_mtu: u16) {
+        self.current_mtu = new_mtu as u64;
+        self.window = self.window.max(self.minimum_window());
+    }
+
+    fn window(&self) -> u64 {
+        self.window
+    }
+
+    fn pacing_rate(&self) -> Option<u64> {
+        self.pacing_rate
+    }
+
+    fn clone_box(&self) -> Box<dyn Controller> {
+        Box::new(self.clone())
+    }
+
+    fn initial_window(&self) -> u64 {
+        self.config.initial_window
+    }
+}
+
+/// Configuration for the [`Bbr`] congestion controller
+#[derive(Debug, Clone)]
+pub struct BbrConfig {
+    max_datagram_size: u64,
+    initial_window: u64,
+    minimum_window: u64,
+}
+
+impl BbrConfig {
+    /// The sender’s maximum UDP payload size. Does not include UDP or IP overhead.
+    ///
+    /// Used for calculating initial and minimum congestion windows.
+    pub fn max_datagram_size(&mut self, value: u64) -> &mut Self {
+        self.max_datagram_size = value;
+        self
+    }
+
+    /// Default limit on the amount of outstanding data in bytes.
+    ///
+    /// Recommended value: `min(10 * max_datagram_size, max(2 * max_datagram_size, 14720))`
+    pub fn initial_window(&mut self, value: u64) -> &mut Self {
+        self.initial_window = value;
+        self
+    }
+
+    /// Default minimum congestion window.
+    ///
+    /// Recommended value: `4 * max_datagram_size`.
+    pub fn minimum_window(&mut self, value: u64) -> &mut Self {
+        self.minimum_window = value;
+        self
+    }
+}
+
+impl Default for BbrConfig {
+    fn default() -> Self {
+        const MAX_DATAGRAM_SIZE: u64 = 1232;
+        Self {
+            max_datagram_size: MAX_DATAGRAM_SIZE,
+            initial_window: 14720.clamp(2 * MAX_DATAGRAM_SIZE, 10 * MAX_DATAGRAM_SIZE),
+            minimum_window: 4 * MAX_DATAGRAM_SIZE,
+        }
+    }
+}
+
+impl ControllerFactory for Arc<BbrConfig> {
+    fn build(&self, now: Instant) -> Box<dyn Controller> {
+        Box::new(Bbr::new(self.clone(), now))
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum State {
+    /// Grow the sending rate exponentially until the bandwidth stops increasing
+    Startup,
+    /// Drain the queue built up during startup
+    Drain,
+    /// Cycle between probing for more bandwidth and leaving room for other flows
+    ProbeBw(ProbeBwPhase),
+    /// Briefly reduce data in flight to measure the path's minimum round-trip time
+    ProbeRtt,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum ProbeBwPhase {
+    /// Drain any queue built up by probing
+    Down,
+    /// Send at the estimated bandwidth
+    Cruise,
+    /// Refill the pipe for a round before probing
+    Refill,
+    /// Send faster than the estimated bandwidth
+    Up,
+}
+
+/// Delivery state when a packet was sent
+#[derive(Debug, Clone)]
+struct SendState {
+    time: Instant,
+    delivered: u64,
+    delivered_time: Instant,
+    first_sent_time: Instant,
+    in_flight: u64,
+    app_limited: bool,
+}
+
+#[derive(Debug, Clone)]
+struct RateSample {
+    prior_delivered: u64,
+    prior_time: Instant,
+    send_elapsed: Duration,
+    app_limited: bool,
+}
+
+/// Tracks the maximum bandwidth sampled over the current and previous bandwidth probing cycles
+#[derive(Debug, Clone, Default)]
+struct MaxBwFilter {
+    current: u64,
+    previous: u64,
+}
+
+impl MaxBwFilter {
+    fn get(&self) -> u64 {
+        cmp::max(self.current, self.previous)
+    }
+
+    fn update(&mut self, bw: u64) {
+        self.current = cmp::max(self.current, bw);
+    }
+
+    /// Start a new cycle, forgetting samples from before the previous one
+    fn advance(&mut self) {
+        self.previous = self.current;
+        self.current = 0;
+    }
+}
+
+/// 4 * ln(2), the smallest gain that doubles the sending rate each round trip
+const STARTUP_PACING_GAIN: f64 = 2.77;
+const STARTUP_CWND_GAIN: f64 = 2.0;
+const DRAIN_PACING_GAIN: f64 = 0.35;
+const PROBE_RTT_CWND_GAIN: f64 = 0.5;
+/// Pace slightly below the estimated bandwidth to avoid building a queue
+const PACING_MARGIN: f64 = 0.01;
+/// Fraction of data in flight that may be lost before the amount in flight is deemed excessive
+const LOSS_THRESH: f64 = 0.02;
+/// Multiplicative decrease applied to the short-term bounds in response to loss
+const BETA: f64 = 0.7;
+/// Fraction of `inflight_hi` left unused when not probing, for other flows
+const HEADROOM: f64 = 0.15;
+/// Bandwidth growth per round below which startup is deemed to have filled the pipe
+const FULL_BW_GROWTH: f64 = 1.25;
+const STARTUP_FULL_BW_ROUNDS: u32 = 3;
+const STARTUP_FULL_LOSS_COUNT: u64 = 6;
+const MAX_RENO_ROUNDS: u64 = 63;
+const MIN_RTT_FILTER_LEN: Duration = Duration::from_secs(10);
+const PROBE_RTT_INTERVAL: Duration = Duration::from_secs(5);
+const PROBE_RTT_DURATION: Duration = Duration::from_millis(200);
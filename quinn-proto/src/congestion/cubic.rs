@@ -1,7 +1,8 @@
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
-use super::{Controller, ControllerFactory};
+use super::{Controller, ControllerFactory, HyStart};
+use crate::connection::RttEstimator;
 use std::cmp;
 
 /// CUBIC Constants.
@@ -72,6 +73,10 @@ pub struct Cubic {
     /// after this time is acknowledged, QUIC exits recovery.
     recovery_start_time: Option<Instant>,
     cubic_state: State,
+    /// The current maximum UDP payload size of the path
+    current_mtu: u64,
+    /// Decides when to leave slow start, if enabled
+    hystart: Option<HyStart>,
 }
 
 impl Cubic {
@@ -81,10 +86,18 @@ impl Cubic {
             window: config.initial_window,
             ssthresh: u64::MAX,
             recovery_start_time: None,
+            current_mtu: config.max_datagram_size,
+            hystart: match config.hystart {
+                true => Some(HyStart::new()),
+                false => None,
+            },
             config,
             cubic_state: Default::default(),
         }
     }
+    fn minimum_window(&self) -> u64 {
+        cmp::max(self.config.minimum_window, 2 * self.current_mtu)
+    }
 }
 
 impl Controller for Cubic {
@@ -94,7 +107,7 @@ impl Controller for Cubic {
         sent: Instant,
         bytes: u64,
         app_limited: bool,
-        rtt: Duration,
+        rtt: &RttEstimator,
     ) {
         if app_limited
             || self
@@ -107,7 +120,13 @@ impl Controller for Cubic {
 
         if self.window < self.ssthresh {
             // Slow start
-            self.window += bytes;
+            match self.hystart {
+                Some(ref mut hystart) => {
+                    hystart.on_ack(rtt);
+                    self.window += bytes / hystart.growth_divisor();
+                }
+                None => self.window += bytes,
+            }
         } else {
             // Congestion avoidance.
             let ca_start_time;
@@ -126,16 +145,13 @@ impl Controller for Cubic {
             }
 
             let t = now - ca_start_time;
+            let rtt = rtt.get();
 
             // w_cubic(t + rtt)
-            let w_cubic = self
-                .cubic_state
-                .w_cubic(t + rtt, self.config.max_datagram_size);
+            let w_cubic = self.cubic_state.w_cubic(t + rtt, self.current_mtu);
 
             // w_est(t)
-            let w_est = self
-                .cubic_state
-                .w_est(t, rtt, self.config.max_datagram_size);
+            let w_est = self.cubic_state.w_est(t, rtt, self.current_mtu);
 
             let mut cubic_cwnd = self.window;
 
@@ -144,8 +160,8 @@ impl Controller for Cubic {
                 cubic_cwnd = cmp::max(cubic_cwnd, w_est as u64);
             } else if cubic_cwnd < w_cubic as u64 {
                 // Concave region or convex region use same increment.
-                let cubic_inc = (w_cubic - cubic_cwnd as f64) / cubic_cwnd as f64
-                    * self.config.max_datagram_size as f64;
+                let cubic_inc =
+                    (w_cubic - cubic_cwnd as f64) / cubic_cwnd as f64 * self.current_mtu as f64;
 
                 cubic_cwnd += cubic_inc as u64;
             }
@@ -156,18 +172,42 @@ impl Controller for Cubic {
             // cwnd_inc can be more than 1 MSS in the late stage of max probing.
             // however RFC9002 §7.3.3 (Congestion Avoidance) limits
             // the increase of cwnd to 1 max_datagram_size per cwnd acknowledged.
-            if self.cubic_state.cwnd_inc as u64 >= self.config.max_datagram_size {
-                self.window += self.config.max_datagram_size;
+            if self.cubic_state.cwnd_inc as u64 >= self.current_mtu {
+                self.window += self.current_mtu;
                 self.cubic_state.cwnd_inc = 0;
             }
         }
     }
 
+    fn on_sent(&mut self, _now: Instant, _bytes: u64, packet_number: Option<u64>, _in_flight: u64) {
+        if let (Some(hystart), Some(pn)) = (self.hystart.as_mut(), packet_number) {
+            hystart.on_sent(pn);
+        }
+    }
+
+    fn on_end_acks(
+        &mut self,
+        _now: Instant,
+        _in_flight: u64,
+        _app_limited: bool,
+        largest_packet_number_acked: Option<u64>,
+    ) {
+        if self.window >= self.ssthresh {
+            return;
+        }
+        if let Some(ref mut hystart) = self.hystart {
+            if hystart.on_end_acks(largest_packet_number_acked) {
+                self.ssthresh = self.window;
+            }
+        }
+    }
+
     fn on_congestion_event(
         &mut self,
         now: Instant,
         sent: Instant,
         _is_persistent_congestion: bool,
+        _lost_bytes: u64,
     ) {
         if self
             .recovery_start_time
@@ -191,11 +231,35 @@ impl Controller for Cubic {
 
         self.cubic_state.w_max = self.window as f64;
         self.ssthresh = (self.cubic_state.w_max * BETA_CUBIC) as u64;
-        self.ssthresh = cmp::max(self.ssthresh, self.config.minimum_window);
+        self.ssthresh = cmp::max(self.ssthresh, self.minimum_window());
         self.window = self.ssthresh;
-        self.cubic_state.k = self.cubic_state.cubic_k(self.config.max_datagram_size);
+        self.cubic_state.k = self.cubic_state.cubic_k(self.current_mtu);
 
         self.cubic_state.cwnd_inc = (self.cubic_state.cwnd_inc as f64 * BETA_CUBIC) as u64;
+
+        if let Some(ref mut hystart) = self.hystart {
+            // Any later slow start begins afresh
+            *hystart = HyStart::new();
+        }
+    }
+
+    fn on_resume(&mut self, now: Instant, window: u64, congested: bool) {
+        if !congested {
+            self.window = cmp::max(self.window, window);
+            return;
+        }
+        self.recovery_start_time = Some(now);
+        self.window = cmp::max(window, self.minimum_window());
+        self.ssthresh = self.window;
+        // Grow from the reduced window as if it were the last maximum
+        self.cubic_state.w_max = self.window as f64;
+        self.cubic_state.k = 0.0;
+        self.cubic_state.cwnd_inc = 0;
+    }
+
+    fn on_mtu_update(&mut self, new_mtu: u16) {
+        self.current_mtu = new_mtu as u64;
+        self.window = self.window.max(self.minimum_window());
     }
 
     fn window(&self) -> u64 {
@@ -217,6 +281,7 @@ pub struct CubicConfig {
     max_datagram_size: u64,
     initial_window: u64,
     minimum_window: u64,
+    hystart: bool,
 }
 
 impl CubicConfig {
@@ -243,6 +308,15 @@ impl CubicConfig {
         self.minimum_window = value;
         self
     }
+
+    /// Whether to leave slow start once round-trip times rise, using HyStart++ (RFC 9406), rather
+    /// than only after a loss
+    ///
+    /// Avoids the burst losses that slow start otherwise causes on paths with deep buffers.
+    pub fn hystart(&mut self, value: bool) -> &mut Self {
+        self.hystart = value;
+        self
+    }
 }
 
 impl Default for CubicConfig {
@@ -252,6 +326,7 @@ impl Default for CubicConfig {
             max_datagram_size: MAX_DATAGRAM_SIZE,
             initial_window: 14720.max(2 * MAX_DATAGRAM_SIZE).min(10 * MAX_DATAGRAM_SIZE),
             minimum_window: 2 * MAX_DATAGRAM_SIZE,
+            hystart: false,
         }
     }
 }
@@ -0,0 +1,129 @@
+use std::cmp;
+use std::time::Duration;
+
+use crate::connection::RttEstimator;
+
+/// HyStart++ (RFC 9406), which ends slow start when round-trip times rise rather than waiting for
+/// losses
+///
+/// Meant to be embedded in a loss-based [`Controller`](super::Controller), which feeds it the
+/// packets it sends and the ACKs it receives, divides its slow start window growth by
+/// [`growth_divisor()`](Self::growth_divisor), and leaves slow start once
+/// [`on_end_acks()`](Self::on_end_acks) returns `true`.
+///
+/// Once RTT samples taken over a round trip rise by a fraction of the previous round's minimum, it
+/// enters Conservative Slow Start, in which the window grows more slowly. If the RTT falls back
+/// the increase is deemed spurious and slow start resumes, but if it persists for several rounds,
+/// slow start ends. Since quinn paces its packets, the limit on the number of packets sent per
+/// ACK that the RFC recommends for unpaced senders is omitted.
+#[derive(Debug, Clone, Default)]
+pub struct HyStart {
+    /// Largest packet number sent so far
+    largest_sent: Option<u64>,
+    /// Packet number whose acknowledgement ends the current round
+    window_end: Option<u64>,
+    last_round_min_rtt: Option<Duration>,
+    current_round_min_rtt: Option<Duration>,
+    rtt_sample_count: u32,
+    /// Latest RTT measured from the ACK being processed
+    ack_rtt: Option<Duration>,
+    /// The minimum RTT of the round in which Conservative Slow Start began, if it's in progress
+    css_baseline_min_rtt: Option<Duration>,
+    css_rounds: u32,
+}
+
+impl HyStart {
+    /// Construct the state for a connection in slow start
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether Conservative Slow Start is in progress
+    pub fn in_css(&self) -> bool {
+        self.css_baseline_min_rtt.is_some()
+    }
+
+    /// Factor by which slow start window growth is divided
+    pub fn growth_divisor(&self) -> u64 {
+        match self.in_css() {
+            true => CSS_GROWTH_DIVISOR,
+            false => 1,
+        }
+    }
+
+    /// An ack-eliciting packet numbered `packet_number` was sent
+    pub fn on_sent(&mut self, packet_number: u64) {
+        self.largest_sent = Some(cmp::max(self.largest_sent.unwrap_or(0), packet_number));
+        if self.window_end.is_none() {
+            self.window_end = self.largest_sent;
+        }
+    }
+
+    /// A packet was acknowledged, with `rtt` reflecting the ACK that acknowledged it
+    pub fn on_ack(&mut self, rtt: &RttEstimator) {
+        self.ack_rtt = Some(rtt.latest());
+    }
+
+    /// All packets acknowledged by an ACK have been passed to [`on_ack()`](Self::on_ack)
+    ///
+    /// Returns whether slow start should end.
+    pub fn on_end_acks(&mut self, largest_packet_number_acked: Option<u64>) -> bool {
+        if let Some(rtt) = self.ack_rtt.take() {
+            self.current_round_min_rtt = Some(match self.current_round_min_rtt {
+                Some(x) => cmp::min(x, rtt),
+                None => rtt,
+            });
+            self.rtt_sample_count += 1;
+        }
+
+        if self.rtt_sample_count >= N_RTT_SAMPLE {
+            if let (Some(current), Some(last)) =
+                (self.current_round_min_rtt, self.last_round_min_rtt)
+            {
+                match self.css_baseline_min_rtt {
+                    None => {
+                        let threshold = cmp::min(
+                            cmp::max(last / MIN_RTT_DIVISOR, MIN_RTT_THRESH),
+                            MAX_RTT_THRESH,
+                        );
+                        if current >= last + threshold {
+                            self.css_baseline_min_rtt = Some(current);
+                            self.css_rounds = 0;
+                        }
+                    }
+                    Some(baseline) => {
+                        if current < baseline {
+                            // The RTT increase was spurious
+                            self.css_baseline_min_rtt = None;
+                        }
+                    }
+                }
+            }
+        }
+
+        match (largest_packet_number_acked, self.window_end) {
+            (Some(acked), Some(end)) if acked >= end => {}
+            _ => return false,
+        }
+        // Start a new round
+        self.window_end = self.largest_sent;
+        self.last_round_min_rtt = self.current_round_min_rtt.take();
+        self.rtt_sample_count = 0;
+        if self.in_css() {
+            self.css_rounds += 1;
+            return self.css_rounds >= CSS_ROUNDS;
+        }
+        false
+    }
+}
+
+/// Bounds on the RTT increase deemed to indicate a growing queue
+const MIN_RTT_THRESH: Duration = Duration::from_millis(4);
+const MAX_RTT_THRESH: Duration = Duration::from_millis(16);
+/// Fraction of the previous round's minimum RTT that the RTT must rise by to enter CSS
+const MIN_RTT_DIVISOR: u32 = 8;
+/// RTT samples needed in a round before deciding whether the RTT has risen
+const N_RTT_SAMPLE: u32 = 8;
+const CSS_GROWTH_DIVISOR: u64 = 4;
+/// Rounds spent in CSS before leaving slow start
+const CSS_ROUNDS: u32 = 5;
@@ -0,0 +1,320 @@
+use std::cmp;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::{Controller, ControllerFactory};
+use crate::connection::RttEstimator;
+
+/// Experimental! Use at your own risk.
+///
+/// A scavenger congestion controller for background transfers, following LEDBAT++
+/// (draft-irtf-iccrg-ledbat-plus-plus)
+///
+/// Estimates the queueing delay at the bottleneck as the amount by which round-trip times exceed
+/// the smallest observed recently, and reduces its window whenever that delay exceeds a target.
+/// Competing traffic that fills the queue, such as connections using loss-based controllers,
+/// therefore gets most of the path's capacity, while the spare capacity is used when the path is
+/// otherwise idle. The window is periodically cut to a minimum so that the queue drains and
+/// the base delay can be remeasured.
+#[derive(Debug, Clone)]
+pub struct Ledbat {
+    config: Arc<LedbatConfig>,
+    /// Maximum number of bytes in flight that may be sent
+    window: u64,
+    /// Slow start threshold in bytes
+    ssthresh: u64,
+    /// The time when QUIC first detects a loss, causing it to enter recovery. When a packet sent
+    /// after this time is acknowledged, QUIC exits recovery.
+    recovery_start_time: Instant,
+    /// The current maximum UDP payload size of the path
+    current_mtu: u64,
+    /// Minimum RTT measured in each of the last few minutes, oldest first
+    base_delays: VecDeque<(Instant, Duration)>,
+    /// The most recent RTT samples, oldest first
+    current_delays: VecDeque<Duration>,
+    smoothed_rtt: Duration,
+    /// Latest RTT measured from the ACK being processed
+    ack_rtt: Option<Duration>,
+    /// Bytes acknowledged by the ACK being processed, excluding those sent before recovery
+    newly_acked: u64,
+    /// When the next periodic slowdown is due, once slow start has ended
+    next_slowdown: Option<Instant>,
+    /// When the slowdown in progress began, until slow start has restored the window
+    slowdown_start: Option<Instant>,
+    /// When the window may grow again after being cut by the slowdown in progress
+    slowdown_frozen_until: Option<Instant>,
+}
+
+impl Ledbat {
+    /// Construct a state using the given `config` and current time `now`
+    pub fn new(config: Arc<LedbatConfig>, now: Instant) -> Self {
+        Self {
+            window: config.initial_window,
+            ssthresh: u64::MAX,
+            recovery_start_time: now,
+            current_mtu: config.max_datagram_size,
+            base_delays: VecDeque::new(),
+            current_delays: VecDeque::new(),
+            smoothed_rtt: Duration::from_secs(0),
+            ack_rtt: None,
+            newly_acked: 0,
+            next_slowdown: None,
+            slowdown_start: None,
+            slowdown_frozen_until: None,
+            config,
+        }
+    }
+
+    fn minimum_window(&self) -> u64 {
+        cmp::max(self.config.minimum_window, 2 * self.current_mtu)
+    }
+
+    fn base_delay(&self) -> Option<Duration> {
+        self.base_delays.iter().map(|&(_, x)| x).min()
+    }
+
+    fn queueing_delay(&self) -> Option<Duration> {
+        let current = self.current_delays.iter().min()?;
+        Some(current.saturating_sub(self.base_delay()?))
+    }
+
+    /// Divisor applied to the growth a standard controller would make, which is larger on paths
+    /// with short base delays so as to ramp up less aggressively than competing traffic
+    fn gain_divisor(&self) -> u64 {
+        let base = match self.base_delay() {
+            Some(x) if x.as_nanos() > 0 => x,
+            _ => return MAX_GAIN_DIVISOR,
+        };
+        let divisor = (2.0 * self.config.target_delay.as_secs_f64() / base.as_secs_f64()).ceil();
+        (divisor as u64).clamp(1, MAX_GAIN_DIVISOR)
+    }
+
+    fn record_delay(&mut self, now: Instant, rtt: Duration) {
+        match self.base_delays.back_mut() {
+            Some(&mut (start, ref mut min)) if now < start + BASE_DELAY_INTERVAL => {
+                *min = cmp::min(*min, rtt);
+            }
+            _ => {
+                self.base_delays.push_back((now, rtt));
+                if self.base_delays.len() > BASE_HISTORY {
+                    self.base_delays.pop_front();
+                }
+            }
+        }
+        self.current_delays.push_back(rtt);
+        if self.current_delays.len() > CURRENT_FILTER {
+            self.current_delays.pop_front();
+        }
+    }
+
+    /// Slow start ended, either by reaching the threshold or due to rising delay
+    fn on_slow_start_end(&mut self, now: Instant) {
+        self.next_slowdown = Some(match self.slowdown_start.take() {
+            Some(start) => now + (now - start) * SLOWDOWN_INTERVAL_FACTOR,
+            // The first slowdown measures the base delay soon after the initial slow start
+            None => now + 2 * self.smoothed_rtt,
+        });
+    }
+}
+
+impl Controller for Ledbat {
+    fn on_ack(
+        &mut self,
+        _now: Instant,
+        sent: Instant,
+        bytes: u64,
+        app_limited: bool,
+        rtt: &RttEstimator,
+    ) {
+        self.smoothed_rtt = rtt.get();
+        self.ack_rtt = Some(rtt.latest());
+        if app_limited || sent <= self.recovery_start_time {
+            return;
+        }
+        self.newly_acked += bytes;
+    }
+
+    fn on_end_acks(
+        &mut self,
+        now: Instant,
+        _in_flight: u64,
+        _app_limited: bool,
+        _largest_packet_number_acked: Option<u64>,
+    ) {
+        if let Some(rtt) = self.ack_rtt.take() {
+            self.record_delay(now, rtt);
+        }
+        let bytes = std::mem::replace(&mut self.newly_acked, 0);
+
+        if let Some(until) = self.slowdown_frozen_until {
+            if now < until {
+                return;
+            }
+            self.slowdown_frozen_until = None;
+        }
+        if matches!(self.next_slowdown, Some(x) if now >= x) {
+            // Let the queue drain, so that the base delay can be remeasured, then slow start back
+            // to the current window
+            self.next_slowdown = None;
+            self.slowdown_start = Some(now);
+            self.slowdown_frozen_until = Some(now + 2 * self.smoothed_rtt);
+            self.ssthresh = self.window;
+            self.window = self.minimum_window();
+            return;
+        }
+        if bytes == 0 {
+            return;
+        }
+
+        let target = self.config.target_delay;
+        let queueing_delay = self.queueing_delay().unwrap_or_default();
+        if self.window < self.ssthresh {
+            // Slow start
+            self.window += bytes / self.gain_divisor();
+            if self.window >= self.ssthresh || queueing_delay > target * 3 / 4 {
+                self.ssthresh = self.window;
+                self.on_slow_start_end(now);
+            }
+            return;
+        }
+
+        // Congestion avoidance, growing by a fraction of a datagram per round trip while the
+        // queueing delay is below target, and shrinking in proportion to the excess above it
+        let window = self.window as f64;
+        let increase = (self.current_mtu * bytes) as f64 / window / self.gain_divisor() as f64;
+        let excess = queueing_delay.as_secs_f64() / target.as_secs_f64() - 1.0;
+        let decrease = match excess > 0.0 {
+            true => (DECREASE_CONSTANT * excess * bytes as f64).min(bytes as f64 / 2.0),
+            false => 0.0,
+        };
+        let window = (window + increase - decrease) as u64;
+        self.window = cmp::max(window, self.minimum_window());
+    }
+
+    fn on_congestion_event(
+        &mut self,
+        now: Instant,
+        sent: Instant,
+        is_persistent_congestion: bool,
+        _lost_bytes: u64,
+    ) {
+        if sent <= self.recovery_start_time {
+            return;
+        }
+
+        let in_slow_start = self.window < self.ssthresh;
+        self.recovery_start_time = now;
+        self.window = cmp::max(self.window / 2, self.minimum_window());
+        self.ssthresh = self.window;
+        if in_slow_start {
+            self.on_slow_start_end(now);
+        }
+
+        if is_persistent_congestion {
+            self.window = self.minimum_window();
+        }
+    }
+
+    fn on_resume(&mut self, now: Instant, window: u64, congested: bool) {
+        if !congested {
+            self.window = cmp::max(self.window, window);
+            return;
+        }
+        let in_slow_start = self.window < self.ssthresh;
+        self.recovery_start_time = now;
+        self.window = cmp::max(window, self.minimum_window());
+        self.ssthresh = self.window;
+        if in_slow_start {
+            self.on_slow_start_end(now);
+        }
+    }
+
+    fn on_mtu_update(&mut self, new_mtu: u16) {
+        self.current_mtu = new_mtu as u64;
+        self.window = self.window.max(self.minimum_window());
+    }
+
+    fn window(&self) -> u64 {
+        self.window
+    }
+
+    fn clone_box(&self) -> Box<dyn Controller> {
+        Box::new(self.clone())
+    }
+
+    fn initial_window(&self) -> u64 {
+        self.config.initial_window
+    }
+}
+
+/// Configuration for the [`Ledbat`] congestion controller
+#[derive(Debug, Clone)]
+pub struct LedbatConfig {
+    max_datagram_size: u64,
+    initial_window: u64,
+    minimum_window: u64,
+    target_delay: Duration,
+}
+
+impl LedbatConfig {
+    /// The sender’s maximum UDP payload size. Does not include UDP or IP overhead.
+    ///
+    /// Used for calculating initial and minimum congestion windows.
+    pub fn max_datagram_size(&mut self, value: u64) -> &mut Self {
+        self.max_datagram_size = value;
+        self
+    }
+
+    /// Default limit on the amount of outstanding data in bytes.
+    ///
+    /// Recommended value: `min(10 * max_datagram_size, max(2 * max_datagram_size, 14720))`
+    pub fn initial_window(&mut self, value: u64) -> &mut Self {
+        self.initial_window = value;
+        self
+    }
+
+    /// Default minimum congestion window.
+    ///
+    /// Recommended value: `2 * max_datagram_size`.
+    pub fn minimum_window(&mut self, value: u64) -> &mut Self {
+        self.minimum_window = value;
+        self
+    }
+
+    /// Queueing delay above which the window is reduced
+    ///
+    /// Recommended value: 60ms.
+    pub fn target_delay(&mut self, value: Duration) -> &mut Self {
+        self.target_delay = value;
+        self
+    }
+}
+
+impl Default for LedbatConfig {
+    fn default() -> Self {
+        const MAX_DATAGRAM_SIZE: u64 = 1232;
+        Self {
+            max_datagram_size: MAX_DATAGRAM_SIZE,
+            initial_window: 14720.clamp(2 * MAX_DATAGRAM_SIZE, 10 * MAX_DATAGRAM_SIZE),
+            minimum_window: 2 * MAX_DATAGRAM_SIZE,
+            target_delay: Duration::from_millis(60),
+        }
+    }
+}
+
+impl ControllerFactory for Arc<LedbatConfig> {
+    fn build(&self, now: Instant) -> Box<dyn Controller> {
+        Box::new(Ledbat::new(self.clone(), now))
+    }
+}
+
+/// Number of intervals over which the base delay is the minimum
+const BASE_HISTORY: usize = 10;
+const BASE_DELAY_INTERVAL: Duration = Duration::from_secs(60);
+/// Number of RTT samples over which the current delay is the minimum, filtering out noise
+const CURRENT_FILTER: usize = 4;
+const MAX_GAIN_DIVISOR: u64 = 16;
+const DECREASE_CONSTANT: f64 = 1.0;
+/// Ratio of the time between slowdowns to the time the previous one took
+const SLOWDOWN_INTERVAL_FACTOR: u32 = 9;
@@ -1,7 +1,9 @@
+use std::cmp;
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::Instant;
 
-use super::{Controller, ControllerFactory};
+use super::{Controller, ControllerFactory, HyStart};
+use crate::connection::RttEstimator;
 
 /// A simple, standard congestion controller
 #[derive(Debug, Clone)]
@@ -17,6 +19,10 @@ pub struct NewReno {
     recovery_start_time: Instant,
     /// Bytes which had been acked by the peer since leaving slow start
     bytes_acked: u64,
+    /// The current maximum UDP payload size of the path
+    current_mtu: u64,
+    /// Decides when to leave slow start, if enabled
+    hystart: Option<HyStart>,
 }
 
 impl NewReno {
@@ -26,10 +32,18 @@ impl NewReno {
             window: config.initial_window,
             ssthresh: u64::max_value(),
             recovery_start_time: now,
+            current_mtu: config.max_datagram_size,
+            hystart: match config.hystart {
+                true => Some(HyStart::new()),
+                false => None,
+            },
             config,
             bytes_acked: 0,
         }
     }
+    fn minimum_window(&self) -> u64 {
+        cmp::max(self.config.minimum_window, 2 * self.current_mtu)
+    }
 }
 
 impl Controller for NewReno {
@@ -39,7 +53,7 @@ impl Controller for NewReno {
         sent: Instant,
         bytes: u64,
         app_limited: bool,
-        _rtt: Duration,
+        rtt: &RttEstimator,
     ) {
         if app_limited || sent <= self.recovery_start_time {
             return;
@@ -47,7 +61,13 @@ impl Controller for NewReno {
 
         if self.window < self.ssthresh {
             // Slow start
-            self.window += bytes;
+            match self.hystart {
+                Some(ref mut hystart) => {
+                    hystart.on_ack(rtt);
+                    self.window += bytes / hystart.growth_divisor();
+                }
+                None => self.window += bytes,
+            }
 
             if self.window >= self.ssthresh {
                 // Exiting slow start
@@ -69,26 +89,76 @@ impl Controller for NewReno {
 
             if self.bytes_acked >= self.window {
                 self.bytes_acked -= self.window;
-                self.window += self.config.max_datagram_size;
+                self.window += self.current_mtu;
             }
         }
     }
 
-    fn on_congestion_event(&mut self, now: Instant, sent: Instant, is_persistent_congestion: bool) {
+    fn on_sent(&mut self, _now: Instant, _bytes: u64, packet_number: Option<u64>, _in_flight: u64) {
+        if let (Some(hystart), Some(pn)) = (self.hystart.as_mut(), packet_number) {
+            hystart.on_sent(pn);
+        }
+    }
+
+    fn on_end_acks(
+        &mut self,
+        _now: Instant,
+        _in_flight: u64,
+        _app_limited: bool,
+        largest_packet_number_acked: Option<u64>,
+    ) {
+        if self.window >= self.ssthresh {
+            return;
+        }
+        if let Some(ref mut hystart) = self.hystart {
+            if hystart.on_end_acks(largest_packet_number_acked) {
+                self.ssthresh = self.window;
+                self.bytes_acked = 0;
+            }
+        }
+    }
+
+    fn on_congestion_event(
+        &mut self,
+        now: Instant,
+        sent: Instant,
+        is_persistent_congestion: bool,
+        _lost_bytes: u64,
+    ) {
         if sent <= self.recovery_start_time {
             return;
         }
 
         self.recovery_start_time = now;
         self.window = (self.window as f32 * self.config.loss_reduction_factor) as u64;
-        self.window = self.window.max(self.config.minimum_window);
+        self.window = self.window.max(self.minimum_window());
         self.ssthresh = self.window;
 
         if is_persistent_congestion {
-            self.window = self.config.minimum_window;
+            self.window = self.minimum_window();
+        }
+        if let Some(ref mut hystart) = self.hystart {
+            // Any later slow start begins afresh
+            *hystart = HyStart::new();
         }
     }
 
+    fn on_resume(&mut self, now: Instant, window: u64, congested: bool) {
+        if !congested {
+            self.window = cmp::max(self.window, window);
+            return;
+        }
+        self.recovery_start_time = now;
+        self.window = cmp::max(window, self.minimum_window());
+        self.ssthresh = self.window;
+        self.bytes_acked = 0;
+    }
+
+    fn on_mtu_update(&mut self, new_mtu: u16) {
+        self.current_mtu = new_mtu as u64;
+        self.window = self.window.max(self.minimum_window());
+    }
+
     fn window(&self) -> u64 {
         self.window
     }
@@ -109,6 +179,7 @@ pub struct NewRenoConfig {
     initial_window: u64,
     minimum_window: u64,
     loss_reduction_factor: f32,
+    hystart: bool,
 }
 
 impl NewRenoConfig {
@@ -141,6 +212,15 @@ impl NewRenoConfig {
         self.loss_reduction_factor = value;
         self
     }
+
+    /// Whether to leave slow start once round-trip times rise, using HyStart++ (RFC 9406), rather
+    /// than only after a loss
+    ///
+    /// Avoids the burst losses that slow start otherwise causes on paths with deep buffers.
+    pub fn hystart(&mut self, value: bool) -> &mut Self {
+        self.hystart = value;
+        self
+    }
 }
 
 impl Default for NewRenoConfig {
@@ -151,6 +231,7 @@ impl Default for NewRenoConfig {
             initial_window: 14720.max(2 * MAX_DATAGRAM_SIZE).min(10 * MAX_DATAGRAM_SIZE),
             minimum_window: 2 * MAX_DATAGRAM_SIZE,
             loss_reduction_factor: 0.5,
+            hystart: false,
         }
     }
 }
@@ -0,0 +1,237 @@
+//! Careful resumption of congestion control state (draft-ietf-tsvwg-careful-resume)
+
+use std::{
+    cmp,
+    net::SocketAddr,
+    time::{Duration, Instant, SystemTime},
+};
+
+use tracing::debug;
+
+use super::RttEstimator;
+use crate::congestion::Controller;
+
+/// Congestion control state of a connection's path, saved to speed up later connections to the
+/// same peer
+///
+/// Obtained from `Connection::congestion_snapshot()` and passed to
+/// `Connection::resume_congestion()` of a later connection. Snapshots describe the network between
+/// two particular hosts, and grow stale as conditions change, so they're only used for the same
+/// peer address and for [`MAX_AGE`](Self::MAX_AGE) after they were taken.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct CongestionSnapshot {
+    /// Congestion window in bytes
+    pub window: u64,
+    /// Minimum round-trip time
+    pub rtt: Duration,
+    /// Address of the peer the path led to
+    pub remote: SocketAddr,
+    /// When the snapshot was taken
+    pub taken: SystemTime,
+}
+
+impl CongestionSnapshot {
+    /// How long after being taken a snapshot may be resumed
+    pub const MAX_AGE: Duration = Duration::from_secs(3600);
+
+    /// Whether the snapshot still describes the path to `remote`
+    pub(crate) fn applies_to(&self, remote: SocketAddr) -> bool {
+        // A snapshot from the future is as suspect as an old one
+        let fresh = matches!(
+            SystemTime::now().duration_since(self.taken),
+            Ok(age) if age <= Self::MAX_AGE
+        );
+        self.remote == remote && fresh
+    }
+}
+
+/// Tracks careful resumption of a [`CongestionSnapshot`] on a path
+///
+/// Starts in reconnaissance, checking that the path's RTT is consistent with the saved one. Once
+/// the congestion window limits sending, the window jumps to half the saved one. When data sent
+/// after the jump is first acknowledged, sending is limited to the amount the path has proven to
+/// deliver until all of it is acknowledged, after which the congestion controller continues from
+/// that window. If congestion is detected after the jump, the window retreats to half of what was
+/// delivered instead.
+#[derive(Debug, Clone)]
+pub struct CarefulResume {
+    snapshot: CongestionSnapshot,
+    phase: Phase,
+}
+
+impl CarefulResume {
+    pub fn new(snapshot: CongestionSnapshot) -> Self {
+        Self {
+            snapshot,
+            phase: Phase::Reconnaissance {
+                rtt_confirmed: false,
+            },
+        }
+    }
+
+    /// Congestion window imposed by resumption, which applies if larger than the controller's
+    pub fn window(&self) -> u64 {
+        match self.phase {
+            Phase::Reconnaissance { .. } => 0,
+            Phase::Unvalidated { window, .. } => window,
+            Phase::Validating { pipe_size, .. } => pipe_size,
+        }
+    }
+
+    /// An ack-eliciting application data packet was sent
+    ///
+    /// `in_flight` includes the new packet. Returns whether resumption continues.
+    pub fn on_sent(
+        &mut self,
+        packet_number: u64,
+        in_flight: u64,
+        mtu: u16,
+        path_validated: bool,
+        congestion: &dyn Controller,
+    ) -> bool {
+        match self.phase {
+            Phase::Reconnaissance { rtt_confirmed } => {
+                let window = congestion.window();
+                // Only jump once the window is what limits sending
+                if !rtt_confirmed || !path_validated || in_flight + u64::from(mtu) < window {
+                    return true;
+                }
+                let jump_window = self.snapshot.window / 2;
+                if jump_window <= window {
+                    debug!("careful resumption unnecessary");
+                    return false;
+                }
+                debug!(jump_window, "careful resumption jumping");
+                self.phase = Phase::Unvalidated {
+                    window: jump_window,
+                    pipe_size: in_flight,
+                    first_packet: None,
+                    last_packet: packet_number,
+                };
+            }
+            Phase::Unvalidated {
+                ref mut first_packet,
+                ref mut last_packet,
+                ..
+            } => {
+                first_packet.get_or_insert(packet_number);
+                *last_packet = packet_number;
+            }
+            Phase::Validating { .. } => {}
+        }
+        true
+    }
+
+    /// An application data packet was acknowledged
+    ///
+    /// Returns whether resumption continues.
+    pub fn on_ack(&mut self, packet_number: u64, bytes: u64, rtt: &RttEstimator) -> bool {
+        match self.phase {
+            Phase::Reconnaissance {
+                ref mut rtt_confirmed,
+            } => {
+                let saved = self.snapshot.rtt;
+                let rtt = rtt.min();
+                if rtt < saved / 2 || rtt >= saved * 10 {
+                    debug!(
+                        ?rtt,
+                        ?saved,
+                        "careful resumption abandoned due to RTT change"
+                    );
+                    return false;
+                }
+                *rtt_confirmed = true;
+            }
+            Phase::Unvalidated {
+                first_packet: Some(first_packet),
+                ref mut pipe_size,
+                ..
+            }
+            | Phase::Validating {
+                first_packet,
+                ref mut pipe_size,
+                ..
+            } if packet_number >= first_packet => {
+                *pipe_size += bytes;
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /// An ACK frame for application data was processed
+    ///
+    /// Returns whether resumption continues.
+    pub fn on_end_acks(
+        &mut self,
+        now: Instant,
+        largest_packet_number_acked: Option<u64>,
+        congestion: &mut dyn Controller,
+    ) -> bool {
+        let largest_acked = match largest_packet_number_acked {
+            Some(x) => x,
+            None => return true,
+        };
+        match self.phase {
+            Phase::Unvalidated {
+                pipe_size,
+                first_packet: Some(first_packet),
+                last_packet,
+                ..
+            } if largest_acked >= first_packet => {
+                // Stop sending more unvalidated data, waiting to see how much of it arrives
+                self.phase = Phase::Validating {
+                    pipe_size,
+                    first_packet,
+                    last_packet,
+                };
+            }
+            Phase::Validating {
+                pipe_size,
+                last_packet,
+                ..
+            } if largest_acked >= last_packet => {
+                debug!(window = pipe_size, "careful resumption validated");
+                congestion.on_resume(now, pipe_size, false);
+                return false;
+            }
+            _ => {}
+        }
+        true
+    }
+
+    /// Packets were deemed lost or marked congested, ending resumption
+    pub fn on_congestion_event(self, now: Instant, congestion: &mut dyn Controller) {
+        match self.phase {
+            Phase::Reconnaissance { .. } => {
+                debug!("careful resumption abandoned due to congestion");
+            }
+            Phase::Unvalidated { pipe_size, .. } | Phase::Validating { pipe_size, .. } => {
+                let window = cmp::max(pipe_size / 2, congestion.initial_window());
+                debug!(window, "careful resumption retreating");
+                congestion.on_resume(now, window, true);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Phase {
+    /// Checking that the path resembles the saved one, and waiting for the window to fill
+    Reconnaissance { rtt_confirmed: bool },
+    /// Sending up to `window` without knowing whether the path can carry it
+    Unvalidated {
+        window: u64,
+        /// Bytes in flight at the jump plus those acknowledged from packets sent since
+        pipe_size: u64,
+        /// First and last packets sent since the jump
+        first_packet: Option<u64>,
+        last_packet: u64,
+    },
+    /// Waiting for the packets sent in the unvalidated phase to be acknowledged
+    Validating {
+        pipe_size: u64,
+        first_packet: u64,
+        last_packet: u64,
+    },
+}
@@ -57,7 +57,7 @@ impl<'a, S: Session> Datagrams<'a, S> {
     /// Not necessarily the maximum size of received datagrams.
     pub fn max_size(&self) -> Option<usize> {
         // This is usually 1162 bytes, but we shouldn't document that without a doctest.
-        let max_size = self.conn.path.max_udp_payload_size as usize
+        let max_size = self.conn.path_data(self.conn.primary_path()).current_mtu() as usize
             - 1                 // flags byte
             - self.conn.rem_cids.active().len()
             - 4                 // worst-case packet number size
@@ -1,15 +1,16 @@
 use std::{
     cmp,
-    collections::VecDeque,
+    collections::{BTreeMap, VecDeque},
     convert::TryFrom,
     fmt, io, mem,
     net::{IpAddr, SocketAddr},
     sync::Arc,
-    time::{Duration, Instant},
+    time::{Duration, Instant, SystemTime},
 };
 
 use bytes::{Bytes, BytesMut};
 use frame::StreamMetaVec;
+use fxhash::{FxHashMap, FxHashSet};
 use rand::{rngs::StdRng, Rng, SeedableRng};
 use thiserror::Error;
 use tracing::{debug, error, trace, trace_span, warn};
@@ -19,9 +20,11 @@ use crate::{
     cid_queue::CidQueue,
     coding::BufMutExt,
     config::{ServerConfig, TransportConfig},
-    crypto::{self, KeyPair, Keys, PacketKey},
+    crypto::{self, ClientConfig as _, KeyPair, Keys, PacketKey, ServerConfig as _},
     frame,
     frame::{Close, Datagram, FrameStruct},
+    is_compatible_version,
+    multipath::{PathId, PathInfo, PathScheduler, PathStatus},
     packet::{Header, LongType, Packet, PartialDecode, SpaceId},
     range_set::ArrayRangeSet,
     shared::{
@@ -29,13 +32,18 @@ use crate::{
         EndpointEventInner, IssuedCid,
     },
     transport_parameters::TransportParameters,
-    Dir, Frame, Side, StreamId, Transmit, TransportError, TransportErrorCode, VarInt,
-    MAX_STREAM_COUNT, MIN_INITIAL_SIZE, RESET_TOKEN_SIZE, TIMER_GRANULARITY,
+    Dir, Frame, Side, StreamId, TokenStore, Transmit, TransportError, TransportErrorCode,
+    ValidationToken, VarInt, MAX_STREAM_COUNT, MIN_INITIAL_SIZE, RESET_TOKEN_SIZE,
+    TIMER_GRANULARITY,
 };
 
 mod assembler;
 pub use assembler::Chunk;
 
+mod careful_resume;
+use careful_resume::CarefulResume;
+pub use careful_resume::CongestionSnapshot;
+
 mod cid_state;
 use cid_state::CidState;
 
@@ -43,13 +51,16 @@ mod datagrams;
 use datagrams::DatagramState;
 pub use datagrams::{Datagrams, SendDatagramError};
 
+mod mtud;
+
 mod pacing;
 
 mod packet_builder;
 use packet_builder::PacketBuilder;
 
 mod paths;
-use paths::PathData;
+pub use paths::RttEstimator;
+use paths::{MigrationProbe, PathData, PathState};
 
 mod send_buffer;
 
@@ -58,7 +69,7 @@ mod spaces;
 pub use spaces::Retransmits;
 #[cfg(not(fuzzing))]
 use spaces::Retransmits;
-use spaces::{PacketSpace, SendableFrames, SentPacket, ThinRetransmits};
+use spaces::{PacketNumberSpace, PacketSpace, SendableFrames, SentPacket, ThinRetransmits};
 
 mod stats;
 pub use stats::ConnectionStats;
@@ -128,12 +139,9 @@ where
     handshake_cid: ConnectionId,
     /// The CID the peer initially chose, for use during the handshake
     rem_handshake_cid: ConnectionId,
-    /// The "real" local IP address which was was used to receive the initial packet.
-    /// This is only populated for the server case, and if known
-    local_ip: Option<IpAddr>,
-
-    path: PathData,
-    prev_path: Option<PathData>,
+    /// New path being validated for a migration started by `initiate_migration` or towards the
+    /// server's preferred address
+    migration: Option<MigrationProbe>,
     state: State,
     side: Side,
     /// Whether or not 0-RTT was enabled during the handshake. Does not imply acceptance.
@@ -143,6 +151,14 @@ where
     key_phase: bool,
     /// Transport parameters set by the peer
     peer_params: TransportParameters,
+    /// Transport parameters sent to the peer
+    local_params: TransportParameters,
+    /// Needed by clients to restart the handshake after a Version Negotiation packet
+    ///
+    /// Consumed by the first restart, as at most one Version Negotiation packet is acted upon.
+    client_restart: Option<ClientRestart<S>>,
+    /// Where clients save the tokens received in NEW_TOKEN frames
+    token_store: Option<ClientTokenStore>,
     /// Source ConnectionId of the first packet received from the peer
     orig_rem_cid: ConnectionId,
     /// Destination ConnectionId sent by the client on the first Initial
@@ -170,6 +186,8 @@ where
     /// spoofing key updates.
     next_crypto: Option<KeyPair<S::PacketKey>>,
     accepted_0rtt: bool,
+    /// Whether 0-RTT data was checked not to be a replay
+    zero_rtt_replay_protected: bool,
     /// Whether the idle timer should be reset the next time an ack-eliciting packet is transmitted.
     permit_idle_reset: bool,
     /// Negotiated idle timeout
@@ -180,30 +198,10 @@ where
     /// Why the connection was lost, if it has been
     error: Option<ConnectionError>,
 
-    //
-    // Queued non-retransmittable 1-RTT data
-    //
-    path_response: Option<PathResponse>,
     close: bool,
 
-    //
-    // Loss Detection
-    //
-    /// The number of times a PTO has been sent without receiving an ack.
-    pto_count: u32,
-
-    //
-    // Congestion Control
-    //
-    /// Summary statistics of packets that have been sent, but not yet acked or deemed lost
-    in_flight: InFlight,
-    /// Whether the most recently received packet had an ECN codepoint set
-    receiving_ecn: bool,
     /// Number of packets authenticated
     total_authed_packets: u64,
-    /// Whether the last `poll_transmit` call yielded no data because there was
-    /// no outgoing application data.
-    app_limited: bool,
 
     streams: StreamsState,
     /// Surplus remote CIDs for future use on new paths
@@ -216,6 +214,31 @@ where
     stats: ConnectionStats,
     /// QUIC version used for the connection.
     version: u32,
+
+    //
+    // Paths
+    //
+    // Each path is identified by the sequence number of the CIDs it uses in both directions, so
+    // CIDs are never rotated on multipath connections; rotating them would move a path's traffic
+    // to another path's packet number space. Moving to a new network is done by opening a new
+    // path and closing the old one instead.
+    //
+    /// Every path of the connection that hasn't been discarded
+    ///
+    /// Only ever holds `PathId::INITIAL` unless the multipath extension was negotiated. State that
+    /// isn't tied to a path, such as the connection's idle timeout, follows the primary path,
+    /// which is the lowest-numbered one that hasn't been abandoned.
+    paths: BTreeMap<PathId, PathState>,
+    /// Paths that were abandoned and discarded, whose identifiers must not be reused
+    discarded_paths: FxHashSet<PathId>,
+    /// Whether both peers enabled the multipath extension
+    multipath: bool,
+    path_scheduler: Box<dyn PathScheduler>,
+    /// Sequence numbers of the CIDs we issued that the peer hasn't retired, which identify the
+    /// path incoming packets belong to
+    local_cids: FxHashMap<ConnectionId, u64>,
+    /// Sequence number of the last PATH_STANDBY or PATH_AVAILABLE frame we queued
+    path_status_seq: u64,
 }
 
 impl<S> Connection<S>
@@ -231,6 +254,10 @@ where
         remote: SocketAddr,
         local_ip: Option<IpAddr>,
         crypto: S,
+        local_params: TransportParameters,
+        client_restart: Option<ClientRestart<S>>,
+        token_store: Option<ClientTokenStore>,
+        path_validated: bool,
         cid_gen: &dyn ConnectionIdGenerator,
         now: Instant,
         version: u32,
@@ -241,39 +268,49 @@ where
             Side::Client
         };
         let initial_space = PacketSpace {
-            crypto: Some(S::initial_keys(&init_cid, side)),
+            crypto: Some(S::initial_keys(version, &init_cid, side)),
             ..PacketSpace::new(now)
         };
         let state = State::Handshake(state::Handshake {
             rem_cid_set: side.is_server(),
-            token: None,
-            client_hello: None,
+            token: token_store
+                .as_ref()
+                .and_then(|x| x.store.take(&x.server_name)),
+            client_hello: if side.is_server() {
+                Some(Bytes::new())
+            } else {
+                None
+            },
         });
         let mut rng = StdRng::from_entropy();
-        let path_validated = server_config
-            .as_ref()
-            .map_or(true, |c| c.use_stateless_retry);
+        let mut paths = BTreeMap::new();
+        paths.insert(
+            PathId::INITIAL,
+            PathState::new(
+                PathData::new(remote, &config, now, path_validated),
+                local_ip,
+                None,
+                true,
+            ),
+        );
+        let mut local_cids = FxHashMap::default();
+        local_cids.insert(loc_cid, 0);
         let mut this = Self {
             server_config,
             crypto,
             handshake_cid: loc_cid,
             rem_handshake_cid: rem_cid,
             local_cid_state: CidState::new(cid_gen.cid_len(), cid_gen.cid_lifetime(), now),
-            path: PathData::new(
-                remote,
-                config.initial_rtt,
-                config.congestion_controller_factory.build(now),
-                now,
-                path_validated,
-            ),
-            local_ip,
-            prev_path: None,
+            migration: None,
             side,
             state,
             zero_rtt_enabled: false,
             zero_rtt_crypto: None,
             key_phase: false,
             peer_params: TransportParameters::default(),
+            local_params,
+            client_restart,
+            token_store,
             orig_rem_cid: rem_cid,
             initial_dst_cid: init_cid,
             retry_src_cid: None,
@@ -287,20 +324,15 @@ where
             prev_crypto: None,
             next_crypto: None,
             accepted_0rtt: false,
+            zero_rtt_replay_protected: false,
             permit_idle_reset: true,
             idle_timeout: config.max_idle_timeout,
             timers: TimerTable::default(),
             authentication_failures: 0,
             error: None,
 
-            path_response: None,
             close: false,
 
-            pto_count: 0,
-
-            app_limited: false,
-            in_flight: InFlight::new(),
-            receiving_ecn: false,
             total_authed_packets: 0,
 
             streams: StreamsState::new(
@@ -312,12 +344,31 @@ where
                 config.stream_receive_window,
             ),
             datagrams: DatagramState::default(),
-            config,
             rem_cids: CidQueue::new(rem_cid),
             rng,
             stats: ConnectionStats::default(),
             version,
+
+            paths,
+            discarded_paths: FxHashSet::default(),
+            multipath: false,
+            path_scheduler: (config.path_scheduler_factory)(),
+            local_cids,
+            path_status_seq: 0,
+            config,
         };
+        if let Some(ref info) = this.local_params.preferred_address {
+            this.local_cids.insert(info.connection_id, 1);
+            // The endpoint has already registered the preferred address CID as sequence number 1
+            this.local_cid_state.new_cids(
+                &[IssuedCid {
+                    sequence: 1,
+                    id: info.connection_id,
+                    reset_token: info.stateless_reset_token,
+                }],
+                now,
+            );
+        }
         if side.is_client() {
             // Kick off the connection
             this.write_crypto();
@@ -335,7 +386,11 @@ where
     /// - a call was made to `handle_timeout`
     #[must_use]
     pub fn poll_timeout(&mut self) -> Option<Instant> {
-        self.timers.next_timeout()
+        self.paths
+            .values()
+            .filter_map(|x| x.timers.next_timeout())
+            .chain(self.timers.next_timeout())
+            .min()
     }
 
     /// Returns application-facing events
@@ -411,76 +466,150 @@ where
     pub fn poll_transmit(&mut self, now: Instant, max_datagrams: usize) -> Option<Transmit> {
         assert!(max_datagrams != 0);
         let max_datagrams = max_datagrams.min(MAX_TRANSMIT_SEGMENTS);
+        if self.paths.len() == 1 {
+            let id = self.primary_path();
+            return self.poll_transmit_on_path(now, max_datagrams, id, true);
+        }
+
+        let mut transmit = None;
+        for (id, app_data) in self.transmit_order() {
+            transmit = self.poll_transmit_on_path(now, max_datagrams, id, app_data);
+            if transmit.is_some() {
+                break;
+            }
+        }
+        self.discard_abandoned_paths();
+        transmit
+    }
+
+    /// The order in which paths are polled for packets to send, and whether each may carry new
+    /// application data
+    ///
+    /// Usable paths are ordered by the path scheduler, and followed by all others so that their
+    /// acknowledgements, probes and path validation still get sent.
+    fn transmit_order(&mut self) -> Vec<(PathId, bool)> {
+        let usable = |entry: &PathState, standby: bool| {
+            entry.validated
+                && !entry.abandoned
+                && (standby
+                    || (entry.status == PathStatus::Available
+                        && entry.peer_status == PathStatus::Available))
+        };
+        let mut candidates = self
+            .paths
+            .iter()
+            .filter(|&(_, x)| usable(x, false))
+            .map(|(&id, x)| self.path_info(id, x))
+            .collect::<Vec<_>>();
+        if candidates.is_empty() {
+            // Fall back on standby paths rather than stalling
+            candidates = self
+                .paths
+                .iter()
+                .filter(|&(_, x)| usable(x, true))
+                .map(|(&id, x)| self.path_info(id, x))
+                .collect();
+        }
+        self.path_scheduler.prioritize(&mut candidates);
 
+        let mut order = candidates.iter().map(|x| (x.id, true)).collect::<Vec<_>>();
+        for (&id, entry) in &self.paths {
+            if !entry.abandoned && !order.iter().any(|&(x, _)| x == id) {
+                order.push((id, false));
+            }
+        }
+        order
+    }
+
+    /// Build a packet for path `path_id`, only including new application data if `app_data`
+    fn poll_transmit_on_path(
+        &mut self,
+        now: Instant,
+        max_datagrams: usize,
+        path_id: PathId,
+        app_data: bool,
+    ) -> Option<Transmit> {
         let mut num_datagrams = 0;
 
         // Send PATH_CHALLENGE for a previous path if necessary
-        if let Some(ref mut prev_path) = self.prev_path {
+        let path = self.paths.get_mut(&path_id).unwrap();
+        if let Some(ref mut prev_path) = path.prev {
             if prev_path.challenge_pending {
                 prev_path.challenge_pending = false;
                 let token = prev_path
                     .challenge
                     .expect("previous path challenge pending without token");
                 let destination = prev_path.remote;
-                debug_assert_eq!(
-                    self.highest_space,
-                    SpaceId::Data,
-                    "PATH_CHALLENGE queued without 1-RTT keys"
+                let local_ip = path.local_ip;
+                trace!("validating previous path with PATH_CHALLENGE {:08x}", token);
+                return self.send_path_frame(
+                    now,
+                    path_id,
+                    frame::Type::PATH_CHALLENGE,
+                    token,
+                    self.rem_cid(path_id),
+                    destination,
+                    local_ip,
                 );
-                let mut buf = Vec::with_capacity(self.path.max_udp_payload_size as usize);
-                let buf_capacity = self.path.max_udp_payload_size as usize;
+            }
+        }
 
-                let mut builder = PacketBuilder::new(
+        // Send PATH_CHALLENGE on a path we're migrating to
+        if let Some(ref mut migration) = self.migration {
+            if migration.challenge_pending {
+                migration.challenge_pending = false;
+                let token = migration.challenge;
+                let (remote, local_ip) = (migration.remote, migration.local_ip);
+                let rem_cid = migration.rem_cid;
+                trace!(%remote, ?local_ip, "validating new path with PATH_CHALLENGE {:08x}", token);
+                let local_ip = local_ip.or(self.paths[&path_id].local_ip);
+                return self.send_path_frame(
                     now,
-                    SpaceId::Data,
-                    &mut buf,
-                    buf_capacity,
-                    0,
-                    false,
-                    self,
-                    self.version,
-                )?;
-                trace!("validating previous path with PATH_CHALLENGE {:08x}", token);
-                buf.write(frame::Type::PATH_CHALLENGE);
-                buf.write(token);
-                self.stats.frame_tx.path_challenge += 1;
-
-                // An endpoint MUST expand datagrams that contain a PATH_CHALLENGE frame
-                // to at least the smallest allowed maximum datagram size of 1200 bytes,
-                // unless the anti-amplification limit for the path does not permit
-                // sending a datagram of this size
-                builder.pad_to(MIN_INITIAL_SIZE);
+                    path_id,
+                    frame::Type::PATH_CHALLENGE,
+                    token,
+                    rem_cid,
+                    remote,
+                    local_ip,
+                );
+            }
+        }
 
-                builder.finish(self, &mut buf);
-                self.stats.udp_tx.datagrams += 1;
-                self.stats.udp_tx.transmits += 1;
-                self.stats.udp_tx.bytes += buf.len() as u64;
-                return Some(Transmit {
-                    destination,
-                    contents: buf,
-                    ecn: None,
-                    segment_size: None,
-                    src_ip: self.local_ip,
-                });
+        // Respond to a PATH_CHALLENGE on the path it was received on
+        let path = self.paths.get_mut(&path_id).unwrap();
+        if let Some(ref response) = path.path_response {
+            if response.remote != path.data.remote {
+                let response = path.path_response.take().unwrap();
+                let local_ip = path.local_ip;
+                trace!(remote = %response.remote, "PATH_RESPONSE {:08x}", response.token);
+                return self.send_path_frame(
+                    now,
+                    path_id,
+                    frame::Type::PATH_RESPONSE,
+                    response.token,
+                    self.rem_cid(path_id),
+                    response.remote,
+                    local_ip,
+                );
             }
         }
 
         // If we need to send a probe, make sure we have something to send.
         for space in SpaceId::iter() {
-            self.spaces[space].maybe_queue_probe();
+            self.spaces[space].maybe_queue_probe(path_id);
         }
 
         // Check whether we need to send a close message
         let close = match self.state {
             State::Drained => {
-                self.app_limited = true;
+                self.path_mut(path_id).app_limited = true;
                 return None;
             }
             State::Draining | State::Closed(_) => {
                 // self.close is only reset once the associated packet had been
                 // encoded successfully
                 if !self.close {
-                    self.app_limited = true;
+                    self.path_mut(path_id).app_limited = true;
                     return None;
                 }
                 true
@@ -488,6 +617,15 @@ where
             _ => false,
         };
 
+        // Loss probes are limited to the minimum MTU, so that they get through even if the path has
+        // stopped delivering larger packets. Their acknowledgement then lets loss detection declare
+        // the larger packets lost, which is what black hole detection relies on.
+        let datagram_size = if self.spaces[SpaceId::Data].for_path(path_id).loss_probes != 0 {
+            self.config.min_mtu
+        } else {
+            self.path_data(path_id).current_mtu()
+        };
+
         let mut buf = Vec::new();
         // Reserving capacity can provide more capacity than we asked for.
         // However we are not allowed to write more than MTU size. Therefore
@@ -516,16 +654,16 @@ where
             }
 
             // Is there data or a close message to send in this space?
-            let can_send = self.space_can_send(space_id);
+            let can_send = self.space_can_send(space_id, path_id, app_data);
             if can_send.is_empty() && !close {
                 space_idx += 1;
                 continue;
             }
 
-            let mut ack_eliciting =
-                !self.spaces[space_id].pending.is_empty() || self.spaces[space_id].ping_pending;
+            let mut ack_eliciting = !self.spaces[space_id].pending.is_empty()
+                || self.spaces[space_id].for_path(path_id).ping_pending;
             if space_id == SpaceId::Data {
-                ack_eliciting |= self.can_send_1rtt();
+                ack_eliciting |= self.can_send_1rtt(path_id, app_data);
             }
 
             // Can we append more data into the current buffer?
@@ -541,7 +679,7 @@ where
                 // We need to send 1 more datagram and extend the buffer for that.
 
                 // Is 1 more datagram allowed?
-                if buf_capacity >= self.path.max_udp_payload_size as usize * max_datagrams {
+                if buf_capacity >= datagram_size as usize * max_datagrams {
                     // No more datagrams allowed
                     break;
                 }
@@ -552,26 +690,30 @@ where
                 // for starting another datagram. If there is any anti-amplification
                 // budget left, we always allow a full MTU to be sent
                 // (see https://github.com/quinn-rs/quinn/issues/1082)
-                if self.path.anti_amplification_blocked(
-                    self.path.max_udp_payload_size as u64 * num_datagrams as u64 + 1,
-                ) {
+                if self
+                    .path_data(path_id)
+                    .anti_amplification_blocked(datagram_size as u64 * num_datagrams as u64 + 1)
+                {
                     trace!("blocked by anti-amplification");
                     break;
                 }
 
                 // Congestion control and pacing checks
                 // Tail loss probes must not be blocked by congestion, or a deadlock could arise
-                if ack_eliciting && self.spaces[space_id].loss_probes == 0 {
+                if ack_eliciting && self.spaces[space_id].for_path(path_id).loss_probes == 0 {
                     // Assume the current packet will get padded to fill the full MTU
                     let untracked_bytes = if let Some(builder) = &builder {
                         buf_capacity - builder.partial_encode.start
                     } else {
                         0
                     } as u64;
-                    debug_assert!(untracked_bytes <= self.path.max_udp_payload_size as u64);
+                    debug_assert!(untracked_bytes <= self.path_data(path_id).current_mtu() as u64);
 
-                    let bytes_to_send = u64::from(self.path.max_udp_payload_size) + untracked_bytes;
-                    if self.in_flight.bytes + bytes_to_send >= self.path.congestion.window() {
+                    let bytes_to_send =
+                        u64::from(self.path_data(path_id).current_mtu()) + untracked_bytes;
+                    if self.paths[&path_id].in_flight.bytes + bytes_to_send
+                        >= self.path_data(path_id).congestion_window()
+                    {
                         space_idx += 1;
                         congestion_blocked = true;
                         // We continue instead of breaking here in order to avoid
@@ -580,15 +722,19 @@ where
                     }
 
                     // Check whether the next datagram is blocked by pacing
-                    let smoothed_rtt = self.path.rtt.get();
-                    if let Some(delay) = self.path.pacing.delay(
+                    let path = self.path_mut(path_id);
+                    let smoothed_rtt = path.data.rtt.get();
+                    let (mtu, window) = (path.data.current_mtu(), path.data.congestion_window());
+                    let pacing_rate = path.data.pacing_rate();
+                    if let Some(delay) = path.data.pacing.delay(
                         smoothed_rtt,
                         bytes_to_send,
-                        self.path.max_udp_payload_size,
-                        self.path.congestion.window(),
+                        mtu,
+                        window,
+                        pacing_rate,
                         now,
                     ) {
-                        self.timers.set(Timer::Pacing, delay);
+                        path.timers.set(Timer::Pacing, delay);
                         congestion_blocked = true;
                         // Loss probes should be subject to pacing, even though
                         // they are not congestion controlled.
@@ -600,7 +746,7 @@ where
                 if let Some(mut builder) = builder.take() {
                     // Pad the packet to make it suitable for sending with GSO
                     // which will always send the maximum PDU.
-                    builder.pad_to(self.path.max_udp_payload_size);
+                    builder.pad_to(datagram_size);
 
                     builder.finish_and_track(now, self, sent_frames.take(), &mut buf);
 
@@ -608,7 +754,7 @@ where
                 }
 
                 // Allocate space for another datagram
-                buf_capacity += self.path.max_udp_payload_size as usize;
+                buf_capacity += datagram_size as usize;
                 if buf.capacity() < buf_capacity {
                     // We reserve the maximum space for sending `max_datagrams` upfront
                     // to avoid any reallocations if more datagrams have to be appended later on.
@@ -618,9 +764,7 @@ where
                     // (e.g. purely containing ACKs), modern memory allocators
                     // (e.g. mimalloc and jemalloc) will pool certain allocation sizes
                     // and therefore this is still rather efficient.
-                    buf.reserve(
-                        max_datagrams * self.path.max_udp_payload_size as usize - buf.capacity(),
-                    );
+                    buf.reserve(max_datagrams * datagram_size as usize - buf.capacity());
                 }
                 num_datagrams += 1;
                 coalesce = true;
@@ -663,10 +807,12 @@ where
             let builder = builder.get_or_insert(PacketBuilder::new(
                 now,
                 space_id,
+                path_id,
                 &mut buf,
                 buf_capacity,
-                (num_datagrams - 1) * (self.path.max_udp_payload_size as usize),
+                (num_datagrams - 1) * (datagram_size as usize),
                 ack_eliciting,
+                self.rem_cid(path_id),
                 self,
                 self.version,
             )?);
@@ -682,11 +828,18 @@ where
                 // a better approximate on what data has been processed. This is
                 // especially important with ack delay, since the peer might not
                 // have gotten any other ACK for the data earlier on.
-                if !self.spaces[space_id].pending_acks.ranges().is_empty() {
+                if !self.spaces[space_id]
+                    .for_path_mut(path_id)
+                    .pending_acks
+                    .ranges()
+                    .is_empty()
+                {
                     Self::populate_acks(
-                        self.receiving_ecn,
+                        self.paths[&path_id].receiving_ecn,
+                        self.ack_path(space_id, path_id),
                         &mut SentFrames::default(),
                         &mut self.spaces[space_id],
+                        path_id,
                         &mut buf,
                         &mut self.stats,
                     );
@@ -730,7 +883,13 @@ where
                 break;
             }
 
-            let sent = self.populate_packet(space_id, &mut buf, buf_capacity - builder.tag_len);
+            let sent = self.populate_packet(
+                space_id,
+                path_id,
+                &mut buf,
+                buf_capacity - builder.tag_len,
+                app_data,
+            );
 
             // ACK-only packets should only be sent when explicitly allowed. If we write them due
             // to any other reason, there is a bug which leads to one component announcing write
@@ -741,15 +900,17 @@ where
                 !(sent.is_ack_only()
                     && !can_send.acks
                     && can_send.other
-                    && (buf_capacity - builder.datagram_start)
-                        == self.path.max_udp_payload_size as _),
+                    && (buf_capacity - builder.datagram_start) == datagram_size as _),
                 "SendableFrames was {:?}, but only ACKs have been written",
                 can_send
             );
             pad_datagram |= sent.requires_padding;
 
             if !sent.acks.is_empty() {
-                self.spaces[space_id].pending_acks.acks_sent();
+                self.spaces[space_id]
+                    .for_path_mut(path_id)
+                    .pending_acks
+                    .acks_sent();
             }
 
             // Keep information about the packet around until it gets finalized
@@ -767,39 +928,139 @@ where
             builder.finish_and_track(now, self, sent_frames, &mut buf);
         }
 
-        self.app_limited = buf.is_empty() && !congestion_blocked;
+        self.path_mut(path_id).app_limited = buf.is_empty() && !congestion_blocked;
+
+        // Send an MTU probe if there's nothing else to send
+        if buf.is_empty() && self.state.is_established() && !congestion_blocked {
+            let probe_size = self.path_data_mut(path_id).mtud.poll_transmit(now)?;
+            if self.paths[&path_id].in_flight.bytes + u64::from(probe_size)
+                >= self.path_data(path_id).congestion_window()
+            {
+                return None;
+            }
+            buf.reserve(probe_size as usize);
+            let mut builder = PacketBuilder::new(
+                now,
+                SpaceId::Data,
+                path_id,
+                &mut buf,
+                probe_size as usize,
+                0,
+                true,
+                self.rem_cid(path_id),
+                self,
+                self.version,
+            )?;
+            // Probes are PING frames padded to the size being tested
+            trace!("PING");
+            buf.write(frame::Type::PING);
+            self.stats.frame_tx.ping += 1;
+            builder.pad_to(probe_size);
+            self.path_data_mut(path_id)
+                .mtud
+                .on_probe_sent(builder.exact_number, probe_size);
+            let sent_frames = SentFrames {
+                non_retransmits: true,
+                ..SentFrames::default()
+            };
+            builder.finish_and_track(now, self, Some(sent_frames), &mut buf);
+            self.stats.path.sent_plpmtud_probes += 1;
+            num_datagrams = 1;
+        }
 
         if buf.is_empty() {
             return None;
         }
 
         trace!("sending {} bytes in {} datagrams", buf.len(), num_datagrams);
-        self.path.total_sent = self.path.total_sent.saturating_add(buf.len() as u64);
+        let path = self.path_data_mut(path_id);
+        path.total_sent = path.total_sent.saturating_add(buf.len() as u64);
 
         self.stats.udp_tx.datagrams += num_datagrams as u64;
         self.stats.udp_tx.bytes += buf.len() as u64;
         self.stats.udp_tx.transmits += 1;
 
         Some(Transmit {
-            destination: self.path.remote,
+            destination: self.path_data(path_id).remote,
             contents: buf,
-            ecn: if self.path.sending_ecn {
+            ecn: if self.path_data(path_id).sending_ecn {
                 Some(EcnCodepoint::Ect0)
             } else {
                 None
             },
             segment_size: match num_datagrams {
                 1 => None,
-                _ => Some(self.path.max_udp_payload_size as usize),
+                _ => Some(datagram_size as usize),
             },
-            src_ip: self.local_ip,
+            src_ip: self.paths[&path_id].local_ip,
+        })
+    }
+
+    /// Build a datagram holding only a PATH_CHALLENGE or PATH_RESPONSE frame, numbered as a
+    /// packet of path `path_id`
+    ///
+    /// Used for network paths other than the one currently carrying the path's traffic.
+    fn send_path_frame(
+        &mut self,
+        now: Instant,
+        path_id: PathId,
+        ty: frame::Type,
+        token: u64,
+        dst_cid: ConnectionId,
+        destination: SocketAddr,
+        src_ip: Option<IpAddr>,
+    ) -> Option<Transmit> {
+        debug_assert_eq!(
+            self.highest_space,
+            SpaceId::Data,
+            "path validation frame queued without 1-RTT keys"
+        );
+        let mut buf = Vec::with_capacity(self.path_data(path_id).current_mtu() as usize);
+        let buf_capacity = self.path_data(path_id).current_mtu() as usize;
+
+        let mut builder = PacketBuilder::new(
+            now,
+            SpaceId::Data,
+            path_id,
+            &mut buf,
+            buf_capacity,
+            0,
+            false,
+            dst_cid,
+            self,
+            self.version,
+        )?;
+        buf.write(ty);
+        buf.write(token);
+        if ty == frame::Type::PATH_CHALLENGE {
+            self.stats.frame_tx.path_challenge += 1;
+        } else {
+            self.stats.frame_tx.path_response += 1;
+        }
+
+        // An endpoint MUST expand datagrams that contain a PATH_CHALLENGE frame
+        // to at least the smallest allowed maximum datagram size of 1200 bytes,
+        // unless the anti-amplification limit for the path does not permit
+        // sending a datagram of this size
+        builder.pad_to(MIN_INITIAL_SIZE);
+
+        builder.finish(self, &mut buf);
+        self.stats.udp_tx.datagrams += 1;
+        self.stats.udp_tx.transmits += 1;
+        self.stats.udp_tx.bytes += buf.len() as u64;
+        Some(Transmit {
+            destination,
+            contents: buf,
+            ecn: None,
+            segment_size: None,
+            src_ip,
         })
     }
 
-    /// Indicate what types of frames are ready to send for the given space
-    fn space_can_send(&self, space_id: SpaceId) -> SendableFrames {
+    /// Indicate what types of frames are ready to send for the given space on path `path_id`
+    fn space_can_send(&self, space_id: SpaceId, path_id: PathId, app_data: bool) -> SendableFrames {
         if self.spaces[space_id].crypto.is_some() {
-            let can_send = self.spaces[space_id].can_send();
+            let can_send = self.spaces[space_id].can_send(path_id);
             if !can_send.is_empty() {
                 return can_send;
             }
@@ -809,7 +1070,7 @@ where
             return SendableFrames::empty();
         }
 
-        if self.spaces[space_id].crypto.is_some() && self.can_send_1rtt() {
+        if self.spaces[space_id].crypto.is_some() && self.can_send_1rtt(path_id, app_data) {
             return SendableFrames {
                 other: true,
                 acks: false,
@@ -817,8 +1078,8 @@ where
         }
 
         if self.zero_rtt_crypto.is_some() && self.side.is_client() {
-            let mut can_send = self.spaces[space_id].can_send();
-            can_send.other |= self.can_send_1rtt();
+            let mut can_send = self.spaces[space_id].can_send(path_id);
+            can_send.other |= self.can_send_1rtt(path_id, app_data);
             if !can_send.is_empty() {
                 return can_send;
             }
@@ -838,46 +1099,31 @@ where
             Datagram {
                 now,
                 remote,
+                local_ip,
                 ecn,
                 first_decode,
                 remaining,
             } => {
-                // If this packet could initiate a migration and we're a client or a server that
-                // forbids migration, drop the datagram. This could be relaxed to heuristically
-                // permit NAT-rebinding-like migration.
-                if remote != self.path.remote
-                    && self.server_config.as_ref().map_or(true, |x| !x.migration)
-                {
-                    trace!("discarding packet from unrecognized peer {}", remote);
-                    return;
-                }
-
-                let was_anti_amplification_blocked = self.path.anti_amplification_blocked(1);
-
-                self.stats.udp_rx.datagrams += 1;
-                self.stats.udp_rx.bytes += first_decode.len() as u64;
-                let data_len = first_decode.len();
-
-                self.handle_decode(now, remote, ecn, first_decode);
-                // The current `path` might have changed inside `handle_decode`,
-                // since the packet could have triggered a migration. Make sure
-                // the data received is accounted for the most recent path by accessing
-                // `path` after `handle_decode`.
-                self.path.total_recvd = self.path.total_recvd.saturating_add(data_len as u64);
-
-                if let Some(data) = remaining {
-                    self.stats.udp_rx.bytes += data.len() as u64;
-                    self.handle_coalesced(now, remote, ecn, data);
-                }
-
-                if was_anti_amplification_blocked {
-                    // A prior attempt to set the loss detection timer may have failed due to
-                    // anti-amplification, so ensure it's set now. Prevents a handshake deadlock if
-                    // the server's first flight is lost.
-                    self.set_loss_detection_timer(now);
+                let (path_id, new_path) =
+                    match self.recv_path(now, remote, local_ip, &first_decode.dst_cid()) {
+                        Some(x) => x,
+                        None => {
+                            trace!("discarding packet for unknown path");
+                            return;
+                        }
+                    };
+                let prev_authed_packets = self.total_authed_packets;
+                self.handle_datagram(now, path_id, remote, local_ip, ecn, first_decode, remaining);
+                if new_path && self.total_authed_packets == prev_authed_packets {
+                    // Don't keep state around for paths nothing authentic arrived on
+                    self.remove_path(path_id);
                 }
+                self.discard_abandoned_paths();
             }
             NewIdentifiers(ids, now) => {
+                for issued in &ids {
+                    self.local_cids.insert(issued.id, issued.sequence);
+                }
                 self.local_cid_state.new_cids(&ids, now);
                 ids.into_iter().rev().for_each(|frame| {
                     self.spaces[SpaceId::Data].pending.new_cids.push(frame);
@@ -894,6 +1140,132 @@ where
         }
     }
 
+    /// Find the path a datagram addressed to `dst_cid` belongs to, creating it if the peer is
+    /// opening a new one
+    ///
+    /// Returns the path and whether it was just created, or `None` if the datagram should be
+    /// dropped.
+    fn recv_path(
+        &mut self,
+        now: Instant,
+        remote: SocketAddr,
+        local_ip: Option<IpAddr>,
+        dst_cid: &ConnectionId,
+    ) -> Option<(PathId, bool)> {
+        if !self.multipath {
+            return Some((PathId::INITIAL, false));
+        }
+        let seq = match self.local_cids.get(dst_cid) {
+            Some(&x) => x,
+            // Only possible for long header packets, which belong to the initial path
+            None => 0,
+        };
+        let id = PathId(u32::try_from(seq).ok()?);
+        if let Some(entry) = self.paths.get(&id) {
+            return match entry.abandoned {
+                true => None,
+                false => Some((id, false)),
+            };
+        }
+        if self.side.is_client() || self.discarded_paths.contains(&id) {
+            return None;
+        }
+        // Paths use the CIDs with the same sequence number in both directions
+        let rem_cid = self.rem_cids.get(seq)?;
+
+        trace!(%remote, "peer opening {}", id);
+        let mut data = PathData::new(remote, &self.config, now, false);
+        data.mtud
+            .on_peer_max_udp_payload_size_received(self.peer_params.max_udp_payload_size.0);
+        data.challenge = Some(self.rng.gen());
+        data.challenge_pending = true;
+        self.insert_path(
+            now,
+            id,
+            PathState::new(data, local_ip, Some(rem_cid), false),
+        );
+        Some((id, true))
+    }
+
+    /// Start tracking path `id`, giving the peer until the path validation timeout to prove
+    /// reachable over it
+    fn insert_path(&mut self, now: Instant, id: PathId, mut path: PathState) {
+        path.timers.set(
+            Timer::PathValidation,
+            now + self.new_path_validation_timeout(),
+        );
+        self.paths.insert(id, path);
+        self.spaces[SpaceId::Data]
+            .number_spaces
+            .insert(id, PacketNumberSpace::new(now));
+    }
+
+    /// Forget all state of path `id`
+    fn remove_path(&mut self, id: PathId) -> Option<PacketNumberSpace> {
+        self.paths.remove(&id);
+        self.spaces[SpaceId::Data].number_spaces.remove(&id)
+    }
+
+    /// Process a datagram received on path `path_id`
+    fn handle_datagram(
+        &mut self,
+        now: Instant,
+        path_id: PathId,
+        remote: SocketAddr,
+        local_ip: Option<IpAddr>,
+        ecn: Option<EcnCodepoint>,
+        first_decode: PartialDecode,
+        remaining: Option<BytesMut>,
+    ) {
+        // If this packet could initiate a migration and we're a client or a server that
+        // forbids migration, drop the datagram. This could be relaxed to heuristically
+        // permit NAT-rebinding-like migration. Packets still arriving over the path we
+        // just moved away from, or over a path we're validating, are accepted.
+        let path = &self.paths[&path_id];
+        let known_remote = remote == path.data.remote
+            || matches!(path.prev, Some(ref x) if x.remote == remote)
+            || matches!(self.migration, Some(ref x) if x.remote == remote);
+        if !known_remote && self.server_config.as_ref().map_or(true, |x| !x.migration) {
+            trace!("discarding packet from unrecognized peer {}", remote);
+            return;
+        }
+
+        let was_anti_amplification_blocked = path.data.anti_amplification_blocked(1);
+        let prev_authed_packets = self.total_authed_packets;
+
+        self.stats.udp_rx.datagrams += 1;
+        self.stats.udp_rx.bytes += first_decode.len() as u64;
+        let data_len = first_decode.len();
+
+        self.handle_decode(now, path_id, remote, ecn, first_decode);
+        // The current `path` might have changed inside `handle_decode`,
+        // since the packet could have triggered a migration. Make sure
+        // the data received is accounted for the most recent path by accessing
+        // `path` after `handle_decode`.
+        let path = self.path_data_mut(path_id);
+        path.total_recvd = path.total_recvd.saturating_add(data_len as u64);
+
+        if let Some(data) = remaining {
+            self.stats.udp_rx.bytes += data.len() as u64;
+            self.handle_coalesced(now, path_id, remote, ecn, data);
+        }
+
+        // A client that moved to our preferred address expects responses to come from it
+        if self.side.is_server()
+            && local_ip.is_some()
+            && self.total_authed_packets > prev_authed_packets
+        {
+            self.path_mut(path_id).local_ip = local_ip;
+        }
+
+        if was_anti_amplification_blocked {
+            // A prior attempt to set the loss detection timer may have failed due to
+            // anti-amplification, so ensure it's set now. Prevents a handshake deadlock if
+            // the server's first flight is lost.
+            self.set_loss_detection_timer(now, path_id);
+        }
+    }
+
     /// Process timer expirations
     ///
     /// Executes protocol logic, potentially preparing signals (including application `Event`s,
@@ -909,48 +1281,94 @@ where
                 continue;
             }
             self.timers.stop(timer);
-            trace!(timer = ?timer, "timeout");
-            match timer {
-                Timer::Close => {
-                    self.state = State::Drained;
-                    self.endpoint_events.push_back(EndpointEventInner::Drained);
-                }
-                Timer::Idle => {
-                    self.kill(ConnectionError::TimedOut);
-                }
-                Timer::KeepAlive => {
-                    trace!("sending keep-alive");
-                    self.ping();
-                }
-                Timer::LossDetection => {
-                    self.on_loss_detection_timeout(now);
-                }
-                Timer::KeyDiscard => {
-                    self.zero_rtt_crypto = None;
-                    self.prev_crypto = None;
-                }
-                Timer::PathValidation => {
-                    debug!("path validation failed");
-                    if let Some(prev) = self.prev_path.take() {
-                        self.path = prev;
+            self.on_timeout(now, timer);
+        }
+
+        let expired = self
+            .paths
+            .iter()
+            .filter(|&(_, x)| matches!(x.timers.next_timeout(), Some(t) if t <= now))
+            .map(|(&id, _)| id)
+            .collect::<Vec<_>>();
+        for id in expired {
+            for &timer in &Timer::PATH_VALUES {
+                match self.paths.get_mut(&id) {
+                    Some(path) if path.timers.is_expired(timer, now) => {
+                        path.timers.stop(timer);
+                        self.on_path_timeout(now, id, timer);
                     }
-                    self.path.challenge = None;
-                    self.path.challenge_pending = false;
+                    _ => {}
                 }
-                Timer::Pacing => trace!("pacing timer expired"),
-                Timer::PushNewCid => {
-                    // Update `retire_prior_to` field in NEW_CONNECTION_ID frame
-                    let num_new_cid = self.local_cid_state.on_cid_timeout().into();
-                    if !self.state.is_closed() {
-                        trace!(
-                            "push a new cid to peer RETIRE_PRIOR_TO field {}",
-                            self.local_cid_state.retire_prior_to()
-                        );
-                        self.endpoint_events
-                            .push_back(EndpointEventInner::NeedIdentifiers(now, num_new_cid));
+            }
+        }
+        self.discard_abandoned_paths();
+    }
+
+    fn on_timeout(&mut self, now: Instant, timer: Timer) {
+        trace!(timer = ?timer, "timeout");
+        match timer {
+            Timer::Close => {
+                self.state = State::Drained;
+                self.endpoint_events.push_back(EndpointEventInner::Drained);
+            }
+            Timer::Idle => {
+                self.kill(ConnectionError::TimedOut);
+            }
+            Timer::KeepAlive => {
+                trace!("sending keep-alive");
+                self.ping();
+            }
+            Timer::KeyDiscard => {
+                self.zero_rtt_crypto = None;
+                self.prev_crypto = None;
+            }
+            Timer::LossDetection | Timer::PathValidation | Timer::Pacing => {
+                unreachable!("path timer in the connection's timer table")
+            }
+            Timer::PushNewCid => {
+                // Update `retire_prior_to` field in NEW_CONNECTION_ID frame
+                let num_new_cid = self.local_cid_state.on_cid_timeout().into();
+                if !self.state.is_closed() {
+                    trace!(
+                        "push a new cid to peer RETIRE_PRIOR_TO field {}",
+                        self.local_cid_state.retire_prior_to()
+                    );
+                    self.endpoint_events
+                        .push_back(EndpointEventInner::NeedIdentifiers(now, num_new_cid));
+                }
+            }
+        }
+    }
+
+    fn on_path_timeout(&mut self, now: Instant, path_id: PathId, timer: Timer) {
+        trace!(timer = ?timer, path = %path_id, "timeout");
+        match timer {
+            Timer::LossDetection => {
+                self.on_loss_detection_timeout(now, path_id);
+            }
+            Timer::PathValidation => {
+                debug!("path validation failed");
+                let path = self.paths.get_mut(&path_id).unwrap();
+                if !path.validated {
+                    // A new path of a multipath connection never became usable
+                    let _ = self.abandon_path(path_id, VarInt(0), PathError::ValidationFailed);
+                } else if let Some(migration) = self.migration.take() {
+                    // The current path was never abandoned
+                    if migration.local_ip.is_some() {
+                        self.events.push_back(Event::MigrationFailed {
+                            reason: MigrationError::ValidationFailed,
+                        });
                     }
+                } else {
+                    if let Some(prev) = path.prev.take() {
+                        path.data = prev;
+                    }
+                    path.data.challenge = None;
+                    path.data.challenge_pending = false;
                 }
             }
+            Timer::Pacing => trace!("pacing timer expired"),
+            _ => unreachable!("connection timer in a path's timer table"),
         }
     }
 
@@ -990,8 +1408,10 @@ where
     /// Returns connection statistics
     pub fn stats(&self) -> ConnectionStats {
         let mut stats = self.stats;
-        stats.path.rtt = self.path.rtt.get();
-        stats.path.cwnd = self.path.congestion.window();
+        let path = self.path_data(self.primary_path());
+        stats.path.rtt = path.rtt.get();
+        stats.path.cwnd = path.congestion_window();
+        stats.path.current_mtu = path.current_mtu();
 
         stats
     }
@@ -1000,7 +1420,164 @@ where
     ///
     /// Causes an ACK-eliciting packet to be transmitted.
     pub fn ping(&mut self) {
-        self.spaces[self.highest_space].ping_pending = true;
+        let path_id = self.primary_path();
+        self.spaces[self.highest_space]
+            .for_path_mut(path_id)
+            .ping_pending = true;
+    }
+
+    /// Begin moving the connection to a new local IP address
+    ///
+    /// Only clients can migrate. A PATH_CHALLENGE is sent from `local_ip` while traffic continues
+    /// over the current path, which is replaced once the peer responds. Packets on the new path
+    /// use a fresh connection ID, making it harder to link to the old one, while the current path
+    /// keeps its own. Completion is reported by [`Event::Migrated`] or
+    /// [`Event::MigrationFailed`].
+    pub fn initiate_migration(
+        &mut self,
+        now: Instant,
+        local_ip: IpAddr,
+    ) -> Result<(), MigrationError> {
+        if self.side.is_server() {
+            return Err(MigrationError::NotClient);
+        }
+        // Clients can't tell the handshake is confirmed until they see HANDSHAKE_DONE
+        if !self.state.is_established() || self.spaces[SpaceId::Handshake].crypto.is_some() {
+            return Err(MigrationError::HandshakeNotConfirmed);
+        }
+        if self.multipath {
+            return Err(MigrationError::Multipath);
+        }
+        if self.peer_params.disable_active_migration {
+            return Err(MigrationError::DisabledByPeer);
+        }
+        let path_id = self.primary_path();
+        if self.migration.is_some() || self.path_data(path_id).challenge.is_some() {
+            return Err(MigrationError::InProgress);
+        }
+        let rem_cid = self
+            .rem_cids
+            .peek_next()
+            .ok_or(MigrationError::NoConnectionIds)?;
+
+        trace!(%local_ip, "migration initiated");
+        self.migration = Some(MigrationProbe {
+            local_ip: Some(local_ip),
+            remote: self.path_data(path_id).remote,
+            rem_cid,
+            challenge: self.rng.gen(),
+            challenge_pending: true,
+        });
+        let timeout = now + self.new_path_validation_timeout();
+        self.path_mut(path_id)
+            .timers
+            .set(Timer::PathValidation, timeout);
+        Ok(())
+    }
+
+    /// Open a new path to `remote`, sending from `local_ip` if specified
+    ///
+    /// Requires the multipath extension to have been negotiated, see
+    /// [`TransportConfig::enable_multipath()`]. Only clients can open paths, and only once the
+    /// handshake is confirmed. The path is validated before it carries application data, with the
+    /// outcome reported by [`Event::PathOpened`] or [`Event::PathClosed`].
+    ///
+    /// [`TransportConfig::enable_multipath()`]: crate::TransportConfig::enable_multipath
+    pub fn open_path(
+        &mut self,
+        now: Instant,
+        remote: SocketAddr,
+        local_ip: Option<IpAddr>,
+    ) -> Result<PathId, PathError> {
+        if !self.multipath {
+            return Err(PathError::MultipathNotNegotiated);
+        }
+        if self.side.is_server() {
+            return Err(PathError::NotClient);
+        }
+        if !self.state.is_established() || self.spaces[SpaceId::Handshake].crypto.is_some() {
+            return Err(PathError::HandshakeNotConfirmed);
+        }
+        // A path uses the CIDs with the same sequence number in both directions
+        let mut seqs = self.local_cids.values().cloned().collect::<Vec<_>>();
+        seqs.sort_unstable();
+        let (id, rem_cid) = seqs
+            .into_iter()
+            .filter_map(|seq| {
+                let id = PathId(u32::try_from(seq).ok()?);
+                if self.paths.contains_key(&id) || self.discarded_paths.contains(&id) {
+                    return None;
+                }
+                Some((id, self.rem_cids.get(seq)?))
+            })
+            .next()
+            .ok_or(PathError::NoConnectionIds)?;
+
+        trace!(%remote, "opening {}", id);
+        // Servers don't apply an anti-amplification limit to our own address, so treat the path as
+        // validated for that purpose.
+        let mut data = PathData::new(remote, &self.config, now, true);
+        data.mtud
+            .on_peer_max_udp_payload_size_received(self.peer_params.max_udp_payload_size.0);
+        data.challenge = Some(self.rng.gen());
+        data.challenge_pending = true;
+        self.insert_path(
+            now,
+            id,
+            PathState::new(data, local_ip, Some(rem_cid), false),
+        );
+        Ok(id)
+    }
+
+    /// Stop using path `id`, telling the peer why with `error_code`
+    ///
+    /// Anything still in flight on the path is retransmitted on the remaining paths. The last
+    /// open path can't be closed; close the connection instead.
+    pub fn close_path(&mut self, id: PathId, error_code: VarInt) -> Result<(), PathError> {
+        if !self.multipath {
+            return Err(PathError::MultipathNotNegotiated);
+        }
+        self.abandon_path(id, error_code, PathError::LocallyClosed)?;
+        self.discard_abandoned_paths();
+        Ok(())
+    }
+
+    /// Ask the peer to only use path `id` for application data if it has no better option, or to
+    /// use it freely again
+    ///
+    /// Only affects what the peer sends. Which paths we send on is decided by the
+    /// [`PathScheduler`] and the status the peer requested for each path.
+    pub fn set_path_status(&mut self, id: PathId, status: PathStatus) -> Result<(), PathError> {
+        if !self.multipath {
+            return Err(PathError::MultipathNotNegotiated);
+        }
+        let entry = match self.paths.get_mut(&id) {
+            Some(x) if !x.abandoned => x,
+            _ => return Err(PathError::UnknownPath),
+        };
+        if entry.status == status {
+            return Ok(());
+        }
+        entry.status = status;
+        self.path_status_seq += 1;
+        self.spaces[SpaceId::Data]
+            .pending
+            .path_status
+            .push(frame::PathStatus {
+                path_id: id.0.into(),
+                seq: self.path_status_seq,
+                available: status == PathStatus::Available,
+            });
+        Ok(())
+    }
+
+    /// The paths of the connection that haven't been closed
+    pub fn paths(&self) -> Vec<PathInfo> {
+        self.paths
+            .iter()
+            .filter(|&(_, x)| !x.abandoned)
+            .map(|(&id, x)| self.path_info(id, x))
+            .collect()
     }
 
     #[doc(hidden)]
@@ -1052,6 +1629,26 @@ where
         self.zero_rtt_enabled
     }
 
+    /// For servers, whether 0-RTT data was checked not to be a replay
+    ///
+    /// Only `true` if the server is configured with
+    /// [`ServerConfig::anti_replay`](crate::ServerConfig::anti_replay) and the client's session
+    /// ticket hadn't been used for 0-RTT before. Any 0-RTT data received when this is `false` may
+    /// have been replayed by an attacker, so it shouldn't be acted on in non-idempotent ways.
+    pub fn is_0rtt_replay_protected(&self) -> bool {
+        self.zero_rtt_replay_protected
+    }
+
+    /// For servers, set application data to embed in the session tickets issued to the client
+    ///
+    /// When the client later resumes the session and attempts 0-RTT, the data is passed to the
+    /// server's [`ZeroRttPolicy`](crate::ZeroRttPolicy). With rustls it's kept in the server's
+    /// session storage, out of the client's reach, and should be set before the handshake
+    /// completes so that every session stored for the client carries it.
+    pub fn set_resumption_data(&mut self, data: &[u8]) {
+        self.crypto.set_resumption_data(data);
+    }
+
     /// Whether there are any pending retransmits
     pub fn has_pending_retransmits(&self) -> bool {
         !self.spaces[SpaceId::Data].pending.is_empty()
@@ -1064,7 +1661,7 @@ where
 
     /// The latest socket address for this connection's peer
     pub fn remote_address(&self) -> SocketAddr {
-        self.path.remote
+        self.path_data(self.primary_path()).remote
     }
 
     /// The local IP address which was used when the peer established
@@ -1082,25 +1679,72 @@ where
     /// On all non-supported platforms the local IP address will not be available,
     /// and the method will return `None`.
     pub fn local_ip(&self) -> Option<IpAddr> {
-        self.local_ip
+        self.paths[&self.primary_path()].local_ip
     }
 
     /// Current best estimate of this connection's latency (round-trip-time)
     pub fn rtt(&self) -> Duration {
-        self.path.rtt.get()
+        self.path_data(self.primary_path()).rtt.get()
+    }
+
+    /// Congestion control state of the current path, to speed up later connections to the peer
+    ///
+    /// Typically taken once the connection is closed, and passed to
+    /// [`resume_congestion()`](Self::resume_congestion) on a later connection to the same peer
+    /// address. `None` if no RTT has been measured yet, or while a resumption is in progress.
+    pub fn congestion_snapshot(&self) -> Option<CongestionSnapshot> {
+        let path = self.path_data(self.primary_path());
+        if !path.rtt.has_samples() || path.resume.is_some() {
+            return None;
+        }
+        Some(CongestionSnapshot {
+            window: path.congestion.window(),
+            rtt: path.rtt.min(),
+            remote: path.remote,
+            taken: SystemTime::now(),
+        })
+    }
+
+    /// Carefully resume the congestion control state saved from an earlier connection to the peer
+    ///
+    /// Rather than slow starting from the initial window, the current path's congestion window
+    /// jumps to half of `snapshot`'s once it fills, provided the path has been validated and its
+    /// RTT is consistent with the saved one. The jump is then validated by checking that the
+    /// data sent is acknowledged, and the window is reduced if that causes congestion. Has no
+    /// effect if the window has already grown beyond the one that would be resumed.
+    ///
+    /// Replaces any resumption in progress. Ends upon migration to a new path. Returns `false`,
+    /// leaving congestion control unaffected, if `snapshot` was taken on a path to another peer
+    /// address or is older than [`CongestionSnapshot::MAX_AGE`].
+    pub fn resume_congestion(&mut self, snapshot: CongestionSnapshot) -> bool {
+        let path = self.path_data_mut(self.primary_path());
+        if !snapshot.applies_to(path.remote) {
+            debug!(?snapshot, "congestion snapshot not applicable");
+            return false;
+        }
+        path.resume = Some(CarefulResume::new(snapshot));
+        true
+    }
+
+    /// The QUIC version in use
+    ///
+    /// May change while the handshake is in progress as a result of version negotiation.
+    pub fn version(&self) -> u32 {
+        self.version
     }
 
     fn on_ack_received(
         &mut self,
         now: Instant,
         space: SpaceId,
+        path_id: PathId,
         ack: frame::Ack,
     ) -> Result<(), TransportError> {
-        if ack.largest >= self.spaces[space].next_packet_number {
+        if ack.largest >= self.spaces[space].for_path(path_id).next_packet_number {
             return Err(TransportError::PROTOCOL_VIOLATION("unsent packet acked"));
         }
         let new_largest = {
-            let space = &mut self.spaces[space];
+            let space = self.spaces[space].for_path_mut(path_id);
             if space
                 .largest_acked_packet
                 .map_or(true, |pn| ack.largest > pn)
@@ -1121,7 +1765,11 @@ where
         // Avoid DoS from unreasonably huge ack ranges by filtering out just the new acks.
         let mut newly_acked = ArrayRangeSet::new();
         for range in ack.iter() {
-            for (&pn, _) in self.spaces[space].sent_packets.range(range) {
+            for (&pn, _) in self.spaces[space]
+                .for_path(path_id)
+                .sent_packets
+                .range(range)
+            {
                 newly_acked.insert_one(pn);
             }
         }
@@ -1130,15 +1778,15 @@ where
             return Ok(());
         }
 
-        let mut ack_eliciting_acked = false;
-        for packet in newly_acked.elts() {
-            if let Some(info) = self.spaces[space].sent_packets.remove(&packet) {
-                self.spaces[space].pending_acks.subtract(&info.acks);
-                ack_eliciting_acked |= info.ack_eliciting;
-                self.on_packet_acked(now, space, info);
-            }
-        }
-
+        // Take the RTT sample first, so that it's reflected in what the congestion controller is
+        // told about each packet
+        let ack_eliciting_acked = newly_acked.elts().any(|pn| {
+            self.spaces[space]
+                .for_path(path_id)
+                .sent_packets
+                .get(&pn)
+                .map_or(false, |info| info.ack_eliciting)
+        });
         if new_largest && ack_eliciting_acked {
             let ack_delay = if space != SpaceId::Data {
                 Duration::from_micros(0)
@@ -1148,36 +1796,69 @@ where
                     Duration::from_micros(ack.delay << self.peer_params.ack_delay_exponent.0),
                 )
             };
-            let rtt = instant_saturating_sub(now, self.spaces[space].largest_acked_packet_sent);
-            self.path.rtt.update(ack_delay, rtt);
+            let sent = self.spaces[space]
+                .for_path(path_id)
+                .largest_acked_packet_sent;
+            let rtt = instant_saturating_sub(now, sent);
+            self.path_data_mut(path_id).rtt.update(ack_delay, rtt);
+        }
+
+        for packet in newly_acked.elts() {
+            let number_space = self.spaces[space].for_path_mut(path_id);
+            if let Some(info) = number_space.sent_packets.remove(&packet) {
+                number_space.pending_acks.subtract(&info.acks);
+                self.on_packet_acked(now, space, path_id, packet, info);
+            }
         }
 
         // Must be called before crypto/pto_count are clobbered
-        self.detect_lost_packets(now, space);
+        self.detect_lost_packets(now, space, path_id);
+        let largest_acked = self.spaces[space].for_path(path_id).largest_acked_packet;
+        let largest_packet_number_acked = match space {
+            SpaceId::Data => largest_acked,
+            _ => None,
+        };
+        let path = self.paths.get_mut(&path_id).unwrap();
+        path.data.congestion.on_end_acks(
+            now,
+            path.in_flight.bytes,
+            path.app_limited,
+            largest_packet_number_acked,
+        );
+        let path = &mut path.data;
+        if let Some(ref mut resume) = path.resume {
+            if space == SpaceId::Data
+                && !resume.on_end_acks(now, largest_acked, &mut *path.congestion)
+            {
+                path.resume = None;
+            }
+        }
 
         if self.peer_completed_address_validation() {
-            self.pto_count = 0;
+            self.path_mut(path_id).pto_count = 0;
         }
 
         // Explicit congestion notification
-        if self.path.sending_ecn {
+        if self.path_data(path_id).sending_ecn {
             if let Some(ecn) = ack.ecn {
                 // We only examine ECN counters from ACKs that we are certain we received in transmit
                 // order, allowing us to compute an increase in ECN counts to compare against the number
                 // of newly acked packets that remains well-defined in the presence of arbitrary packet
                 // reordering.
                 if new_largest {
-                    let sent = self.spaces[space].largest_acked_packet_sent;
-                    self.process_ecn(now, space, newly_acked.len() as u64, ecn, sent);
+                    let sent = self.spaces[space]
+                        .for_path(path_id)
+                        .largest_acked_packet_sent;
+                    self.process_ecn(now, space, path_id, newly_acked.len() as u64, ecn, sent);
                 }
             } else {
                 // We always start out sending ECN, so any ack that doesn't acknowledge it disables it.
                 debug!("ECN not acknowledged by peer");
-                self.path.sending_ecn = false;
+                self.path_data_mut(path_id).sending_ecn = false;
             }
         }
 
-        self.set_loss_detection_timer(now);
+        self.set_loss_detection_timer(now, path_id);
         Ok(())
     }
 
@@ -1186,42 +1867,65 @@ where
         &mut self,
         now: Instant,
         space: SpaceId,
+        path_id: PathId,
         newly_acked: u64,
         ecn: frame::EcnCounts,
         largest_sent_time: Instant,
     ) {
-        match self.spaces[space].detect_ecn(newly_acked, ecn) {
+        match self.spaces[space]
+            .for_path_mut(path_id)
+            .detect_ecn(newly_acked, ecn)
+        {
             Err(e) => {
                 debug!("halting ECN due to verification failure: {}", e);
-                self.path.sending_ecn = false;
+                self.path_data_mut(path_id).sending_ecn = false;
                 // Wipe out the existing value because it might be garbage and could interfere with
                 // future attempts to use ECN on new paths.
-                self.spaces[space].ecn_feedback = frame::EcnCounts::ZERO;
+                self.spaces[space].for_path_mut(path_id).ecn_feedback = frame::EcnCounts::ZERO;
             }
-            Ok(false) => {}
-            Ok(true) => {
+            Ok(0) => {}
+            Ok(ce_count) => {
                 self.stats.path.congestion_events += 1;
-                self.path
+                self.path_data_mut(path_id)
                     .congestion
-                    .on_congestion_event(now, largest_sent_time, false);
+                    .on_ecn_ce(now, largest_sent_time, ce_count);
+                self.resume_on_congestion_event(now, path_id);
             }
         }
     }
 
     // Not timing-aware, so it's safe to call this for inferred acks, such as arise from
     // high-latency handshakes
-    fn on_packet_acked(&mut self, now: Instant, space: SpaceId, info: SentPacket) {
-        self.remove_in_flight(space, &info);
-        if info.ack_eliciting && self.path.challenge.is_none() {
+    fn on_packet_acked(
+        &mut self,
+        now: Instant,
+        space: SpaceId,
+        path_id: PathId,
+        pn: u64,
+        info: SentPacket,
+    ) {
+        self.remove_in_flight(space, path_id, &info);
+        let path = self.paths.get_mut(&path_id).unwrap();
+        let app_limited = path.app_limited;
+        let path = &mut path.data;
+        if info.ack_eliciting && path.challenge.is_none() {
             // Only pass ACKs to the congestion controller if we are not validating the current
             // path, so as to ignore any ACKs from older paths still coming in.
-            self.path.congestion.on_ack(
+            path.congestion.on_ack(
                 now,
                 info.time_sent,
                 info.size.into(),
-                self.app_limited,
-                self.path.rtt.get(),
+                app_limited,
+                &path.rtt,
             );
+            if let Some(ref mut resume) = path.resume {
+                if space == SpaceId::Data && !resume.on_ack(pn, info.size.into(), &path.rtt) {
+                    path.resume = None;
+                }
+            }
+        }
+        if space == SpaceId::Data && path.mtud.on_acked(pn, info.size) {
+            path.congestion.on_mtu_update(path.current_mtu());
         }
 
         // Update state for confirmed delivery of frames
@@ -1248,32 +1952,34 @@ where
                 .expect("update not acknowledged yet")
                 .1
         };
-        self.timers.set(Timer::KeyDiscard, start + self.pto() * 3);
+        let pto = self.pto(self.primary_path());
+        self.timers.set(Timer::KeyDiscard, start + pto * 3);
     }
 
-    fn on_loss_detection_timeout(&mut self, now: Instant) {
-        if let Some((_, pn_space)) = self.loss_time_and_space() {
+    fn on_loss_detection_timeout(&mut self, now: Instant, path_id: PathId) {
+        if let Some((_, pn_space)) = self.loss_time_and_space(path_id) {
             // Time threshold loss Detection
-            self.detect_lost_packets(now, pn_space);
-            self.set_loss_detection_timer(now);
+            self.detect_lost_packets(now, pn_space, path_id);
+            self.set_loss_detection_timer(now, path_id);
             return;
         }
 
-        let (_, space) = match self.pto_time_and_space(now) {
+        let (_, space) = match self.pto_time_and_space(now, path_id) {
             Some(x) => x,
             None => {
                 error!("PTO expired while unset");
                 return;
             }
         };
+        let path = &self.paths[&path_id];
         trace!(
-            in_flight = self.in_flight.bytes,
-            count = self.pto_count,
+            in_flight = path.in_flight.bytes,
+            count = path.pto_count,
             ?space,
             "PTO fired"
         );
 
-        let count = match self.in_flight.ack_eliciting {
+        let count = match path.in_flight.ack_eliciting {
             // A PTO when we're not expecting any ACKs must be due to handshake anti-amplification
             // deadlock preventions
             0 => {
@@ -1283,22 +1989,24 @@ where
             // Conventional loss probe
             _ => 2,
         };
-        self.spaces[space].loss_probes = self.spaces[space].loss_probes.saturating_add(count);
-        self.pto_count = self.pto_count.saturating_add(1);
-        self.set_loss_detection_timer(now);
+        let number_space = self.spaces[space].for_path_mut(path_id);
+        number_space.loss_probes = number_space.loss_probes.saturating_add(count);
+        let path = self.path_mut(path_id);
+        path.pto_count = path.pto_count.saturating_add(1);
+        self.set_loss_detection_timer(now, path_id);
     }
 
-    fn detect_lost_packets(&mut self, now: Instant, pn_space: SpaceId) {
+    fn detect_lost_packets(&mut self, now: Instant, pn_space: SpaceId, path_id: PathId) {
         let mut lost_packets = Vec::<u64>::new();
-        let rtt = self.path.rtt.conservative();
+        let rtt = self.path_data(path_id).rtt.conservative();
         let loss_delay = cmp::max(rtt.mul_f32(self.config.time_threshold), TIMER_GRANULARITY);
 
         // Packets sent before this time are deemed lost.
         let lost_send_time = now - loss_delay;
-        let largest_acked_packet = self.spaces[pn_space].largest_acked_packet.unwrap();
         let packet_threshold = self.config.packet_threshold as u64;
 
-        let space = &mut self.spaces[pn_space];
+        let space = self.spaces[pn_space].for_path_mut(path_id);
+        let largest_acked_packet = space.largest_acked_packet.unwrap();
         space.loss_time = None;
         for (&packet, info) in space.sent_packets.range(0..largest_acked_packet) {
             if info.time_sent <= lost_send_time || largest_acked_packet >= packet + packet_threshold
@@ -1316,49 +2024,96 @@ where
 
         // OnPacketsLost
         if let Some(largest_lost) = lost_packets.last().cloned() {
-            let old_bytes_in_flight = self.in_flight.bytes;
-            let largest_lost_sent = self.spaces[pn_space].sent_packets[&largest_lost].time_sent;
+            let old_bytes_in_flight = self.paths[&path_id].in_flight.bytes;
+            let largest_lost_sent =
+                self.spaces[pn_space].for_path(path_id).sent_packets[&largest_lost].time_sent;
             self.lost_packets += lost_packets.len() as u64;
             trace!("packets lost: {:?}", lost_packets);
+            let mut lost_probe_bytes = 0;
             for packet in &lost_packets {
-                let info = self.spaces[pn_space].sent_packets.remove(packet).unwrap(); // safe: lost_packets is populated just above
-                self.remove_in_flight(pn_space, &info);
+                let info = self.spaces[pn_space]
+                    .for_path_mut(path_id)
+                    .sent_packets
+                    .remove(packet)
+                    .unwrap(); // safe: lost_packets is populated just above
+                self.remove_in_flight(pn_space, path_id, &info);
+                let path = self.path_data_mut(path_id);
+                let mut is_probe = false;
+                if pn_space == SpaceId::Data {
+                    if path.mtud.in_flight_probe() == Some(*packet) {
+                        path.mtud.on_probe_lost();
+                        self.stats.path.lost_plpmtud_probes += 1;
+                        lost_probe_bytes += u64::from(info.size);
+                        is_probe = true;
+                    } else {
+                        path.mtud.on_non_probe_lost(info.size);
+                    }
+                }
+                if info.ack_eliciting && !is_probe {
+                    self.path_data_mut(path_id).congestion.on_packet_lost(
+                        now,
+                        info.time_sent,
+                        info.size.into(),
+                    );
+                }
                 for frame in info.stream_frames {
                     self.streams.retransmit(frame);
                 }
                 self.spaces[pn_space].pending |= info.retransmits;
             }
-            // Don't apply congestion penalty for lost ack-only packets
-            let lost_ack_eliciting = old_bytes_in_flight != self.in_flight.bytes;
+            let path = self.path_data_mut(path_id);
+            if path.mtud.detect_black_hole(now) {
+                path.congestion.on_mtu_update(path.current_mtu());
+                self.stats.path.black_holes_detected += 1;
+            }
+            // Don't apply congestion penalty for lost ack-only packets or MTU probes, which don't
+            // indicate congestion
+            let lost_bytes =
+                old_bytes_in_flight - self.paths[&path_id].in_flight.bytes - lost_probe_bytes;
 
             // InPersistentCongestion: Determine if all packets in the time period before the newest
             // lost packet, including the edges, are marked lost
-            let congestion_period = self.pto() * self.config.persistent_congestion_threshold;
-            let in_persistent_congestion = self.spaces[pn_space].largest_acked_packet_sent
+            let congestion_period = self.pto(path_id) * self.config.persistent_congestion_threshold;
+            let in_persistent_congestion = self.spaces[pn_space]
+                .for_path(path_id)
+                .largest_acked_packet_sent
                 < largest_lost_sent - congestion_period;
 
-            if lost_ack_eliciting {
+            if lost_bytes != 0 {
                 self.stats.path.congestion_events += 1;
-                self.path.congestion.on_congestion_event(
+                self.path_data_mut(path_id).congestion.on_congestion_event(
                     now,
                     largest_lost_sent,
                     in_persistent_congestion,
+                    lost_bytes,
                 );
+                self.resume_on_congestion_event(now, path_id);
             }
         }
     }
 
-    fn loss_time_and_space(&self) -> Option<(Instant, SpaceId)> {
+    fn resume_on_congestion_event(&mut self, now: Instant, path_id: PathId) {
+        let path = self.path_data_mut(path_id);
+        if let Some(resume) = path.resume.take() {
+            resume.on_congestion_event(now, &mut *path.congestion);
+        }
+    }
+
+    fn loss_time_and_space(&self, path_id: PathId) -> Option<(Instant, SpaceId)> {
         SpaceId::iter()
-            .filter_map(|id| Some((self.spaces[id].loss_time?, id)))
+            .filter_map(|id| {
+                let number_space = self.spaces[id].number_spaces.get(&path_id)?;
+                Some((number_space.loss_time?, id))
+            })
             .min_by_key(|&(time, _)| time)
     }
 
-    fn pto_time_and_space(&self, now: Instant) -> Option<(Instant, SpaceId)> {
-        let backoff = 2u32.pow(self.pto_count.min(MAX_BACKOFF_EXPONENT));
-        let mut duration = self.path.rtt.pto_base() * backoff;
+    fn pto_time_and_space(&self, now: Instant, path_id: PathId) -> Option<(Instant, SpaceId)> {
+        let path = &self.paths[&path_id];
+        let backoff = 2u32.pow(path.pto_count.min(MAX_BACKOFF_EXPONENT));
+        let mut duration = path.data.rtt.pto_base() * backoff;
 
-        if self.in_flight.ack_eliciting == 0 {
+        if path.in_flight.ack_eliciting == 0 {
             debug_assert!(!self.peer_completed_address_validation());
             let space = match self.highest_space {
                 SpaceId::Handshake => SpaceId::Handshake,
@@ -1369,9 +2124,10 @@ where
 
         let mut result = None;
         for space in SpaceId::iter() {
-            if self.spaces[space].in_flight == 0 {
-                continue;
-            }
+            let number_space = match self.spaces[space].number_spaces.get(&path_id) {
+                Some(x) if x.in_flight != 0 => x,
+                _ => continue,
+            };
             if space == SpaceId::Data {
                 // Skip ApplicationData until handshake completes.
                 if self.is_handshaking() {
@@ -1380,7 +2136,7 @@ where
                 // Include max_ack_delay and backoff for ApplicationData.
                 duration += self.max_ack_delay() * backoff;
             }
-            let last_ack_eliciting = match self.spaces[space].time_of_last_ack_eliciting_packet {
+            let last_ack_eliciting = match number_space.time_of_last_ack_eliciting_packet {
                 Some(time) => time,
                 None => continue,
             };
@@ -1399,52 +2155,66 @@ where
         }
         // The server is guaranteed to have validated our address if any of our handshake or 1-RTT
         // packets are acknowledged or we've seen HANDSHAKE_DONE and discarded handshake keys.
-        self.spaces[SpaceId::Handshake]
-            .largest_acked_packet
-            .is_some()
-            || self.spaces[SpaceId::Data].largest_acked_packet.is_some()
+        let any_acked = |space: SpaceId| {
+            self.spaces[space]
+                .number_spaces
+                .values()
+                .any(|x| x.largest_acked_packet.is_some())
+        };
+        any_acked(SpaceId::Handshake)
+            || any_acked(SpaceId::Data)
             || (self.spaces[SpaceId::Data].crypto.is_some()
                 && self.spaces[SpaceId::Handshake].crypto.is_none())
     }
 
-    fn set_loss_detection_timer(&mut self, now: Instant) {
-        if let Some((loss_time, _)) = self.loss_time_and_space() {
+    fn set_loss_detection_timer(&mut self, now: Instant, path_id: PathId) {
+        if !self.paths.contains_key(&path_id) {
+            return;
+        }
+
+        if let Some((loss_time, _)) = self.loss_time_and_space(path_id) {
             // Time threshold loss detection.
-            self.timers.set(Timer::LossDetection, loss_time);
+            self.path_mut(path_id)
+                .timers
+                .set(Timer::LossDetection, loss_time);
             return;
         }
 
-        if self.path.anti_amplification_blocked(1) {
+        let path = &self.paths[&path_id];
+        if path.data.anti_amplification_blocked(1) {
             // We wouldn't be able to send anything, so don't bother.
-            self.timers.stop(Timer::LossDetection);
+            self.path_mut(path_id).timers.stop(Timer::LossDetection);
             return;
         }
 
-        if self.in_flight.ack_eliciting == 0 && self.peer_completed_address_validation() {
+        if path.in_flight.ack_eliciting == 0 && self.peer_completed_address_validation() {
             // There is nothing to detect lost, so no timer is set. However, the client needs to arm
             // the timer if the server might be blocked by the anti-amplification limit.
-            self.timers.stop(Timer::LossDetection);
+            self.path_mut(path_id).timers.stop(Timer::LossDetection);
             return;
         }
 
         // Determine which PN space to arm PTO for.
         // Calculate PTO duration
-        if let Some((timeout, _)) = self.pto_time_and_space(now) {
-            self.timers.set(Timer::LossDetection, timeout);
+        if let Some((timeout, _)) = self.pto_time_and_space(now, path_id) {
+            self.path_mut(path_id)
+                .timers
+                .set(Timer::LossDetection, timeout);
         } else {
-            self.timers.stop(Timer::LossDetection);
+            self.path_mut(path_id).timers.stop(Timer::LossDetection);
         }
     }
 
     /// Probe Timeout
-    fn pto(&self) -> Duration {
-        self.path.rtt.pto_base() + self.max_ack_delay()
+    fn pto(&self, path_id: PathId) -> Duration {
+        self.path_data(path_id).rtt.pto_base() + self.max_ack_delay()
     }
 
     fn on_packet_authenticated(
         &mut self,
         now: Instant,
         space_id: SpaceId,
+        path_id: PathId,
         ecn: Option<EcnCodepoint>,
         packet: Option<u64>,
         spin: bool,
@@ -1454,9 +2224,9 @@ where
         self.reset_keep_alive(now);
         self.reset_idle_timeout(now);
         self.permit_idle_reset = true;
-        self.receiving_ecn |= ecn.is_some();
+        self.path_mut(path_id).receiving_ecn |= ecn.is_some();
         if let Some(x) = ecn {
-            self.spaces[space_id].ecn_counters += x;
+            self.spaces[space_id].for_path_mut(path_id).ecn_counters += x;
         }
 
         let packet = match packet {
@@ -1473,7 +2243,7 @@ where
                 self.set_key_discard_timer(now)
             }
         }
-        let space = &mut self.spaces[space_id];
+        let space = self.spaces[space_id].for_path_mut(path_id);
         space.pending_acks.insert_one(packet, now);
         if packet >= space.rx_packet {
             space.rx_packet = packet;
@@ -1491,7 +2261,7 @@ where
             self.timers.stop(Timer::Idle);
             return;
         }
-        let dt = cmp::max(timeout, 3 * self.pto());
+        let dt = cmp::max(timeout, 3 * self.pto(self.primary_path()));
         self.timers.set(Timer::Idle, now + dt);
     }
 
@@ -1509,6 +2279,10 @@ where
         }
     }
 
+    pub(crate) fn set_0rtt_replay_protected(&mut self, value: bool) {
+        self.zero_rtt_replay_protected = value;
+    }
+
     /// Handle the already-decrypted first packet from the client
     ///
     /// Decrypting the first packet in the `Endpoint` allows stateless packet handling to be more
@@ -1526,19 +2300,20 @@ where
         let _guard = span.enter();
         debug_assert!(self.side.is_server());
         let len = packet.header_data.len() + packet.payload.len();
-        self.path.total_recvd = len as u64;
+        self.path_data_mut(PathId::INITIAL).total_recvd = len as u64;
 
         self.on_packet_authenticated(
             now,
             SpaceId::Initial,
+            PathId::INITIAL,
             ecn,
             Some(packet_number),
             false,
             false,
         );
-        self.process_decrypted_packet(now, remote, Some(packet_number), packet)?;
+        self.process_decrypted_packet(now, PathId::INITIAL, remote, Some(packet_number), packet)?;
         if let Some(data) = remaining {
-            self.handle_coalesced(now, remote, ecn, data);
+            self.handle_coalesced(now, PathId::INITIAL, remote, ecn, data);
         }
         Ok(())
     }
@@ -1619,6 +2394,18 @@ where
             .insert(crypto.offset, crypto.data.clone(), payload_len);
         while let Some(chunk) = space.crypto_stream.read(usize::MAX, true) {
             trace!("consumed {} CRYPTO bytes", chunk.bytes.len());
+            if let State::Handshake(state::Handshake {
+                client_hello: Some(ref mut client_hello),
+                ..
+            }) = self.state
+            {
+                if self.side.is_server() {
+                    // Retained in case compatible version negotiation restarts the handshake
+                    let mut buf = BytesMut::from(&client_hello[..]);
+                    buf.extend_from_slice(&chunk.bytes);
+                    *client_hello = buf.freeze();
+                }
+            }
             if self.crypto.read_handshake(&chunk.bytes)? {
                 self.events.push_back(Event::HandshakeDataReady);
             }
@@ -1697,29 +2484,32 @@ where
         trace!("discarding {:?} keys", space_id);
         let space = &mut self.spaces[space_id];
         space.crypto = None;
+        let space = space.for_path_mut(PathId::INITIAL);
         space.time_of_last_ack_eliciting_packet = None;
         space.loss_time = None;
         let sent_packets = mem::take(&mut space.sent_packets);
         for (_, packet) in sent_packets.into_iter() {
-            self.remove_in_flight(space_id, &packet);
+            self.remove_in_flight(space_id, PathId::INITIAL, &packet);
         }
-        self.set_loss_detection_timer(now)
+        self.set_loss_detection_timer(now, PathId::INITIAL)
     }
 
     fn handle_coalesced(
         &mut self,
         now: Instant,
+        path_id: PathId,
         remote: SocketAddr,
         ecn: Option<EcnCodepoint>,
         data: BytesMut,
     ) {
-        self.path.total_recvd = self.path.total_recvd.saturating_add(data.len() as u64);
+        let path = self.path_data_mut(path_id);
+        path.total_recvd = path.total_recvd.saturating_add(data.len() as u64);
         let mut remaining = Some(data);
         while let Some(data) = remaining {
             match PartialDecode::new(data, self.local_cid_state.cid_len(), &[self.version]) {
                 Ok((partial_decode, rest)) => {
                     remaining = rest;
-                    self.handle_decode(now, remote, ecn, partial_decode);
+                    self.handle_decode(now, path_id, remote, ecn, partial_decode);
                 }
                 Err(e) => {
                     trace!("malformed header: {}", e);
@@ -1732,10 +2522,27 @@ where
     fn handle_decode(
         &mut self,
         now: Instant,
+        path_id: PathId,
         remote: SocketAddr,
         ecn: Option<EcnCodepoint>,
         partial_decode: PartialDecode,
     ) {
+        if path_id != PathId::INITIAL && partial_decode.has_long_header() {
+            // Only the path the handshake took ever carries long header packets
+            debug!("discarding long header packet on path {}", path_id);
+            return;
+        }
+        if self.side.is_client() && partial_decode.is_initial() {
+            let version = partial_decode.version().unwrap();
+            if version != self.version && !self.accept_compatible_version(version) {
+                debug!(
+                    "discarding Initial packet with unexpected version {:x}",
+                    version
+                );
+                return;
+            }
+        }
+
         let header_crypto = if partial_decode.is_0rtt() {
             if let Some(ref crypto) = self.zero_rtt_crypto {
                 Some(&crypto.header)
@@ -1760,7 +2567,7 @@ where
         };
 
         match partial_decode.finish(header_crypto) {
-            Ok(packet) => self.handle_packet(now, remote, ecn, packet),
+            Ok(packet) => self.handle_packet(now, path_id, remote, ecn, packet),
             Err(e) => {
                 trace!("unable to complete packet decoding: {}", e);
             }
@@ -1770,6 +2577,7 @@ where
     fn handle_packet(
         &mut self,
         now: Instant,
+        path_id: PathId,
         remote: SocketAddr,
         ecn: Option<EcnCodepoint>,
         mut packet: Packet,
@@ -1782,7 +2590,7 @@ where
             packet.header.dst_cid(),
         );
 
-        if self.is_handshaking() && remote != self.path.remote {
+        if self.is_handshaking() && remote != self.path_data(path_id).remote {
             debug!("discarding packet with unexpected remote during handshake");
             return;
         }
@@ -1797,7 +2605,7 @@ where
                     && packet.payload[packet.payload.len() - RESET_TOKEN_SIZE..] == token[..]
             });
 
-        let result = match self.decrypt_packet(now, &mut packet) {
+        let result = match self.decrypt_packet(now, path_id, &mut packet) {
             Err(Some(e)) => {
                 warn!("illegal packet: {}", e);
                 Err(e.into())
@@ -1830,7 +2638,12 @@ where
                 };
                 let _guard = span.enter();
 
-                let is_duplicate = |n| self.spaces[packet.header.space()].dedup.insert(n);
+                let is_duplicate = |n| {
+                    self.spaces[packet.header.space()]
+                        .for_path_mut(path_id)
+                        .dedup
+                        .insert(n)
+                };
                 if number.map_or(false, is_duplicate) {
                     if stateless_reset {
                         Err(ConnectionError::Reset)
@@ -1851,13 +2664,14 @@ where
                         self.on_packet_authenticated(
                             now,
                             packet.header.space(),
+                            path_id,
                             ecn,
                             number,
                             spin,
                             packet.header.is_1rtt(),
                         );
                     }
-                    self.process_decrypted_packet(now, remote, number, packet)
+                    self.process_decrypted_packet(now, path_id, remote, number, packet)
                 }
             }
         };
@@ -1902,13 +2716,14 @@ where
 
         // Transmit CONNECTION_CLOSE if necessary
         if let State::Closed(_) = self.state {
-            self.close = remote == self.path.remote;
+            self.close = remote == self.path_data(path_id).remote;
         }
     }
 
     fn process_decrypted_packet(
         &mut self,
         now: Instant,
+        path_id: PathId,
         remote: SocketAddr,
         number: Option<u64>,
         packet: Packet,
@@ -1916,9 +2731,13 @@ where
         let state = match self.state {
             State::Established => {
                 match packet.header.space() {
-                    SpaceId::Data => {
-                        self.process_payload(now, remote, number.unwrap(), packet.payload.freeze())?
-                    }
+                    SpaceId::Data => self.process_payload(
+                        now,
+                        path_id,
+                        remote,
+                        number.unwrap(),
+                        packet.payload.freeze(),
+                    )?,
                     _ => self.process_early_payload(now, packet)?,
                 }
                 return Ok(());
@@ -1954,6 +2773,7 @@ where
                 if self.total_authed_packets > 1
                             || packet.payload.len() <= 16 // token + 16 byte tag
                             || !S::is_valid_retry(
+                                self.version,
                                 &self.rem_cids.active(),
                                 &packet.header_data,
                                 &packet.payload,
@@ -1976,19 +2796,24 @@ where
                 self.rem_cids.update_cid(rem_cid);
                 self.rem_handshake_cid = rem_cid;
 
-                let space = &mut self.spaces[SpaceId::Initial];
+                let space = self.spaces[SpaceId::Initial].for_path_mut(PathId::INITIAL);
                 if let Some(info) = space.sent_packets.remove(&0) {
                     space.pending_acks.subtract(&info.acks);
-                    self.on_packet_acked(now, SpaceId::Initial, info);
+                    self.on_packet_acked(now, SpaceId::Initial, PathId::INITIAL, 0, info);
                 };
 
                 self.discard_space(now, SpaceId::Initial); // Make sure we clean up after any retransmitted Initials
+                let next_packet_number = self.spaces[SpaceId::Initial]
+                    .for_path(PathId::INITIAL)
+                    .next_packet_number;
                 self.spaces[SpaceId::Initial] = PacketSpace {
-                    crypto: Some(S::initial_keys(&rem_cid, self.side)),
-                    next_packet_number: self.spaces[SpaceId::Initial].next_packet_number,
+                    crypto: Some(S::initial_keys(self.version, &rem_cid, self.side)),
                     crypto_offset: client_hello.len() as u64,
                     ..PacketSpace::new(now)
                 };
+                self.spaces[SpaceId::Initial]
+                    .for_path_mut(PathId::INITIAL)
+                    .next_packet_number = next_packet_number;
                 self.spaces[SpaceId::Initial]
                     .pending
                     .crypto
@@ -1998,9 +2823,13 @@ where
                     });
 
                 // Retransmit all 0-RTT data
-                let zero_rtt = mem::take(&mut self.spaces[SpaceId::Data].sent_packets);
+                let zero_rtt = mem::take(
+                    &mut self.spaces[SpaceId::Data]
+                        .for_path_mut(PathId::INITIAL)
+                        .sent_packets,
+                );
                 for (_, info) in zero_rtt {
-                    self.remove_in_flight(SpaceId::Data, &info);
+                    self.remove_in_flight(SpaceId::Data, PathId::INITIAL, &info);
                     self.spaces[SpaceId::Data].pending |= info.retransmits;
                 }
                 self.streams.retransmit_all_for_0rtt();
@@ -2025,7 +2854,7 @@ where
                     );
                     return Ok(());
                 }
-                self.path.validated = true;
+                self.paths.get_mut(&PathId::INITIAL).unwrap().data.validated = true;
 
                 let state = state.clone();
                 self.process_early_payload(now, packet)?;
@@ -2064,10 +2893,13 @@ where
                             self.spaces[SpaceId::Data].pending = Retransmits::default();
 
                             // Discard 0-RTT packets
-                            let sent_packets =
-                                mem::take(&mut self.spaces[SpaceId::Data].sent_packets);
+                            let sent_packets = mem::take(
+                                &mut self.spaces[SpaceId::Data]
+                                    .for_path_mut(PathId::INITIAL)
+                                    .sent_packets,
+                            );
                             for (_, packet) in sent_packets {
-                                self.remove_in_flight(SpaceId::Data, &packet);
+                                self.remove_in_flight(SpaceId::Data, PathId::INITIAL, &packet);
                             }
                         } else {
                             self.accepted_0rtt = true;
@@ -2075,18 +2907,22 @@ where
                         }
                     }
                     if let Some(token) = params.stateless_reset_token {
+                        let remote = self.path_data(PathId::INITIAL).remote;
                         self.endpoint_events
-                            .push_back(EndpointEventInner::ResetToken(self.path.remote, token));
+                            .push_back(EndpointEventInner::ResetToken(remote, token));
                     }
                     self.handle_peer_params(params)?;
                     self.issue_cids(now);
                 } else {
                     // Server-only
                     self.spaces[SpaceId::Data].pending.handshake_done = true;
+                    self.queue_new_tokens();
                     self.discard_space(now, SpaceId::Handshake);
                 }
 
                 self.events.push_back(Event::Connected);
+                self.endpoint_events
+                    .push_back(EndpointEventInner::Established);
                 self.state = State::Established;
                 trace!("established");
                 Ok(())
@@ -2135,25 +2971,47 @@ where
                 ty: LongType::ZeroRtt,
                 ..
             } => {
-                self.process_payload(now, remote, number.unwrap(), packet.payload.freeze())?;
+                self.process_payload(
+                    now,
+                    path_id,
+                    remote,
+                    number.unwrap(),
+                    packet.payload.freeze(),
+                )?;
                 Ok(())
             }
             Header::VersionNegotiate { .. } => {
                 if self.total_authed_packets > 1 {
                     return Ok(());
                 }
-                let supported = packet
+                let offered = packet
                     .payload
                     .chunks(4)
-                    .any(|x| match <[u8; 4]>::try_from(x) {
-                        Ok(version) => self.version == u32::from_be_bytes(version),
-                        Err(_) => false,
-                    });
-                if supported {
+                    .filter_map(|x| <[u8; 4]>::try_from(x).ok())
+                    .map(u32::from_be_bytes)
+                    .collect::<Vec<_>>();
+                if offered.contains(&self.version) {
                     return Ok(());
                 }
-                debug!("remote doesn't support our version");
-                Err(ConnectionError::VersionMismatch)
+                let restart = match self.client_restart.take() {
+                    Some(x) => x,
+                    // Only the first Version Negotiation packet is acted upon
+                    None => return Ok(()),
+                };
+                let local_info = self.local_params.version_information.as_ref().unwrap();
+                let version = match local_info
+                    .available_versions
+                    .iter()
+                    .find(|x| offered.contains(x))
+                {
+                    Some(&x) => x,
+                    None => {
+                        debug!("remote doesn't support our version");
+                        return Err(ConnectionError::VersionMismatch);
+                    }
+                };
+                self.restart_handshake(now, version, restart);
+                Ok(())
             }
             Header::Short { .. } => unreachable!(
                 "short packets received during handshake are discarded in handle_packet"
@@ -2161,6 +3019,75 @@ where
         }
     }
 
+    /// Begin a new handshake using `version` in response to a Version Negotiation packet
+    fn restart_handshake(&mut self, now: Instant, version: u32, restart: ClientRestart<S>) {
+        debug!(
+            "restarting handshake with version {:x} (was {:x})",
+            version, self.version
+        );
+        self.local_params
+            .version_information
+            .as_mut()
+            .unwrap()
+            .chosen_version = version;
+        self.crypto = restart
+            .crypto
+            .start_session(version, &restart.server_name, &self.local_params)
+            .expect("client configuration was already accepted");
+        self.version = version;
+
+        self.discard_space(now, SpaceId::Initial);
+        let next_packet_number = self.spaces[SpaceId::Initial]
+            .for_path(PathId::INITIAL)
+            .next_packet_number;
+        self.spaces[SpaceId::Initial] = PacketSpace {
+            crypto: Some(S::initial_keys(version, &self.initial_dst_cid, self.side)),
+            ..PacketSpace::new(now)
+        };
+        self.spaces[SpaceId::Initial]
+            .for_path_mut(PathId::INITIAL)
+            .next_packet_number = next_packet_number;
+
+        // Early data must be sent again under the new handshake
+        let zero_rtt = mem::take(
+            &mut self.spaces[SpaceId::Data]
+                .for_path_mut(PathId::INITIAL)
+                .sent_packets,
+        );
+        for (_, info) in zero_rtt {
+            self.remove_in_flight(SpaceId::Data, PathId::INITIAL, &info);
+            self.spaces[SpaceId::Data].pending |= info.retransmits;
+        }
+        self.streams.retransmit_all_for_0rtt();
+        self.zero_rtt_enabled = false;
+        self.zero_rtt_crypto = None;
+
+        self.write_crypto();
+        self.init_0rtt();
+    }
+
+    /// Mint address validation tokens for the client to use on future connections
+    fn queue_new_tokens(&mut self) {
+        let config = match &self.server_config {
+            Some(x) => x,
+            None => return,
+        };
+        let ip = self.paths[&PathId::INITIAL].data.remote.ip();
+        for _ in 0..config.new_token_count {
+            let mut random_bytes = [0u8; ValidationToken::RANDOM_BYTES_LEN];
+            self.rng.fill(&mut random_bytes);
+            let token = ValidationToken {
+                issued: SystemTime::now(),
+                random_bytes: &random_bytes,
+            }
+            .encode(&config.token_keys, &ip);
+            self.spaces[SpaceId::Data]
+                .pending
+                .new_tokens
+                .push(token.into());
+        }
+    }
+
     /// Process an Initial or Handshake packet payload
     fn process_early_payload(
         &mut self,
@@ -2183,6 +3110,7 @@ where
                 Frame::Ack(_) | Frame::Padding | Frame::Close(Close::Connection(_)) => {}
                 _ => {
                     self.spaces[packet.header.space()]
+                        .for_path_mut(PathId::INITIAL)
                         .pending_acks
                         .ack_eliciting_frame_received();
                 }
@@ -2194,7 +3122,7 @@ where
                     self.read_crypto(packet.header.space(), &frame, payload_len)?;
                 }
                 Frame::Ack(ack) => {
-                    self.on_ack_received(now, packet.header.space(), ack)?;
+                    self.on_ack_received(now, packet.header.space(), PathId::INITIAL, ack)?;
                 }
                 Frame::Close(reason) => {
                     self.error = Some(reason.into());
@@ -2215,13 +3143,99 @@ where
             }
         }
 
+        if self.side.is_server() && self.highest_space == SpaceId::Initial {
+            self.negotiate_version()?;
+        }
         self.write_crypto();
         Ok(())
     }
 
+    /// Choose the connection's version once the client's transport parameters are known
+    ///
+    /// Implements the server side of compatible version negotiation (RFC 9368): if the client
+    /// supports a compatible version we prefer over the one it started with, the handshake is
+    /// restarted with that version before anything is sent in response.
+    fn negotiate_version(&mut self) -> Result<(), TransportError> {
+        match self.state {
+            State::Handshake(ref state) if state.client_hello.is_some() => {}
+            _ => return Ok(()),
+        }
+        let params = match self.crypto.transport_parameters()? {
+            Some(x) => x,
+            // The client's first flight is incomplete
+            None => return Ok(()),
+        };
+        let client_hello = match self.state {
+            State::Handshake(ref mut state) => state.client_hello.take().unwrap(),
+            _ => unreachable!(),
+        };
+
+        let info = match params.version_information {
+            Some(info) => info,
+            None => return Ok(()),
+        };
+        if info.chosen_version != self.version {
+            return Err(TransportError::VERSION_NEGOTIATION_ERROR(
+                "client's chosen version doesn't match its Initial packets",
+            ));
+        }
+
+        let current = self.version;
+        let local_info = self.local_params.version_information.as_mut().unwrap();
+        let version = local_info
+            .available_versions
+            .iter()
+            .cloned()
+            .find(|&x| info.available_versions.contains(&x) && is_compatible_version(current, x))
+            .unwrap_or(current);
+        if version == current {
+            return Ok(());
+        }
+
+        debug!("upgrading from version {:x} to {:x}", self.version, version);
+        local_info.chosen_version = version;
+        let server_config = self.server_config.as_ref().unwrap();
+        // 0-RTT packets are still protected as packets of the client's original version
+        let mut crypto = server_config
+            .crypto
+            .start_session(current, &self.local_params)
+            .expect("server configuration was already accepted");
+        crypto.set_version(version);
+        crypto.read_handshake(&client_hello)?;
+        self.crypto = crypto;
+        self.version = version;
+        self.spaces[SpaceId::Initial].crypto =
+            Some(S::initial_keys(version, &self.initial_dst_cid, self.side));
+        Ok(())
+    }
+
+    /// Switch to the compatible version the server chose, as indicated by its Initial packets
+    ///
+    /// Returns whether `version` is acceptable.
+    fn accept_compatible_version(&mut self, version: u32) -> bool {
+        let local_info = self.local_params.version_information.as_ref().unwrap();
+        if !self.state.is_handshake()
+            || self.highest_space != SpaceId::Initial
+            || self.version != local_info.chosen_version
+            || !local_info.available_versions.contains(&version)
+            || !is_compatible_version(self.version, version)
+        {
+            return false;
+        }
+
+        debug!("server upgraded to version {:x}", version);
+        let initial_cid = self.retry_src_cid.unwrap_or(self.initial_dst_cid);
+        self.version = version;
+        self.crypto.set_version(version);
+        self.spaces[SpaceId::Initial].crypto =
+            Some(S::initial_keys(version, &initial_cid, self.side));
+        true
+    }
+
     fn process_payload(
         &mut self,
         now: Instant,
+        path_id: PathId,
         remote: SocketAddr,
         number: u64,
         payload: Bytes,
@@ -2268,9 +3282,10 @@ where
 
             // Check for ack-eliciting frames
             match frame {
-                Frame::Ack(_) | Frame::Padding | Frame::Close(_) => {}
+                Frame::Ack(_) | Frame::AckMp { .. } | Frame::Padding | Frame::Close(_) => {}
                 _ => {
                     self.spaces[SpaceId::Data]
+                        .for_path_mut(path_id)
                         .pending_acks
                         .ack_eliciting_frame_received();
                 }
@@ -2285,6 +3300,18 @@ where
                     is_probing_packet = false;
                 }
             }
+            match frame {
+                Frame::AckMp { .. } | Frame::PathAbandon(_) | Frame::PathStatus(_)
+                    if !self.multipath =>
+                {
+                    let mut err = TransportError::PROTOCOL_VIOLATION(
+                        "multipath frame without negotiating multipath",
+                    );
+                    err.frame = Some(frame.ty());
+                    return Err(err);
+                }
+                _ => {}
+            }
             match frame {
                 Frame::Invalid { ty, reason } => {
                     let mut err = TransportError::FRAME_ENCODING_ERROR(reason);
@@ -2300,39 +3327,85 @@ where
                     }
                 }
                 Frame::Ack(ack) => {
-                    self.on_ack_received(now, SpaceId::Data, ack)?;
+                    // Plain ACK frames acknowledge packets sent on the initial path
+                    self.on_path_ack_received(now, 0, ack)?;
+                }
+                Frame::AckMp { path_id, ack } => {
+                    self.on_path_ack_received(now, path_id, ack)?;
+                }
+                Frame::PathAbandon(frame) => {
+                    // Answer with a PATH_ABANDON of our own, as the draft requires
+                    let result = match u32::try_from(frame.path_id) {
+                        Ok(id) => self.abandon_path(
+                            PathId(id),
+                            VarInt(0),
+                            PathError::AbandonedByPeer(frame.error_code),
+                        ),
+                        Err(_) => Err(PathError::UnknownPath),
+                    };
+                    if let Err(e) = result {
+                        debug!(path_id = frame.path_id, "ignoring PATH_ABANDON: {}", e);
+                    }
+                }
+                Frame::PathStatus(frame) => {
+                    let entry = u32::try_from(frame.path_id)
+                        .ok()
+                        .and_then(|id| self.paths.get_mut(&PathId(id)));
+                    match entry {
+                        Some(entry) if !matches!(entry.peer_status_seq, Some(x) if frame.seq <= x) =>
+                        {
+                            entry.peer_status_seq = Some(frame.seq);
+                            entry.peer_status = match frame.available {
+                                true => PathStatus::Available,
+                                false => PathStatus::Standby,
+                            };
+                        }
+                        Some(_) => trace!("ignoring outdated path status"),
+                        None => debug!(path_id = frame.path_id, "ignoring unknown path"),
+                    }
                 }
                 Frame::Padding | Frame::Ping => {}
                 Frame::Close(reason) => {
                     close = Some(reason);
                 }
                 Frame::PathChallenge(token) => {
-                    if self
+                    let path = self.path_mut(path_id);
+                    if path
                         .path_response
                         .as_ref()
                         .map_or(true, |x| x.packet <= number)
                     {
-                        self.path_response = Some(PathResponse {
+                        path.path_response = Some(PathResponse {
                             packet: number,
+                            remote,
                             token,
                         });
                     }
-                    if remote == self.path.remote {
+                    if remote == path.data.remote {
                         // PATH_CHALLENGE on active path, possible off-path packet forwarding
                         // attack. Send a non-probing packet to recover the active path.
-                        self.ping();
+                        self.spaces[self.highest_space]
+                            .for_path_mut(path_id)
+                            .ping_pending = true;
                     }
                 }
                 Frame::PathResponse(token) => {
-                    if self.path.challenge == Some(token) && remote == self.path.remote {
+                    let path = self.paths.get_mut(&path_id).unwrap();
+                    if path.data.challenge == Some(token) && remote == path.data.remote {
                         trace!("new path validated");
-                        self.timers.stop(Timer::PathValidation);
-                        self.path.challenge = None;
-                        self.path.validated = true;
-                        if let Some(ref mut prev_path) = self.prev_path {
-                            prev_path.challenge = None;
-                            prev_path.challenge_pending = false;
+                        path.timers.stop(Timer::PathValidation);
+                        path.data.challenge = None;
+                        path.data.validated = true;
+                        if let Some(ref mut prev) = path.prev {
+                            prev.challenge = None;
+                            prev.challenge_pending = false;
+                        }
+                        if !path.validated {
+                            path.validated = true;
+                            self.events.push_back(Event::PathOpened { id: path_id });
                         }
+                    } else if matches!(self.migration, Some(ref x) if x.challenge == token) {
+                        self.complete_migration(now, path_id);
                     } else {
                         debug!(token, "ignoring invalid PATH_RESPONSE");
                     }
@@ -2396,6 +3469,7 @@ where
                     let allow_more_cids = self
                         .local_cid_state
                         .on_cid_retirement(sequence, self.peer_params.issue_cids_limit())?;
+                    self.local_cids.retain(|_, &mut x| x != sequence);
                     self.endpoint_events
                         .push_back(EndpointEventInner::RetireConnectionId(
                             now,
@@ -2448,9 +3522,13 @@ where
                         }
                     }
 
-                    if self.side.is_server() && self.peer_params.stateless_reset_token.is_none() {
+                    if self.side.is_server()
+                        && self.peer_params.stateless_reset_token.is_none()
+                        && !self.multipath
+                    {
                         // We're a server using the initial remote CID for the client, so let's
-                        // switch immediately to enable clientside stateless resets.
+                        // switch immediately to enable clientside stateless resets. Multipath
+                        // connections keep it, as it identifies the initial path.
                         debug_assert_eq!(self.rem_cids.active_seq(), 0);
                         self.update_rem_cid().unwrap();
                     } else if self.rem_cids.is_active_retired() {
@@ -2468,7 +3546,9 @@ where
                         return Err(TransportError::FRAME_ENCODING_ERROR("empty token"));
                     }
                     trace!("got new token");
-                    // TODO: Cache, or perhaps forward to user?
+                    if let Some(tokens) = &self.token_store {
+                        tokens.store.insert(&tokens.server_name, token);
+                    }
                 }
                 Frame::Datagram(datagram) => {
                     if self
@@ -2487,6 +3567,8 @@ where
                     if self.spaces[SpaceId::Handshake].crypto.is_some() {
                         self.discard_space(now, SpaceId::Handshake);
                     }
+                    // The handshake is confirmed, so we may now move to the preferred address
+                    self.migrate_to_preferred_address(now);
                 }
             }
         }
@@ -2509,9 +3591,10 @@ where
             self.close = true;
         }
 
-        if remote != self.path.remote
+        if remote != self.path_data(path_id).remote
+            && self.side.is_server()
             && !is_probing_packet
-            && number == self.spaces[SpaceId::Data].rx_packet
+            && number == self.spaces[SpaceId::Data].for_path(path_id).rx_packet
         {
             debug_assert!(
                 self.server_config
@@ -2520,46 +3603,243 @@ where
                     .migration,
                 "migration-initiating packets should have been dropped immediately"
             );
-            self.migrate(now, remote);
-            // Break linkability, if possible
-            let _ = self.update_rem_cid();
+            self.migrate(now, path_id, remote);
+            // Break linkability, if possible. On multipath connections CIDs identify paths, so
+            // they stay fixed.
+            if !self.multipath {
+                let _ = self.update_rem_cid();
+            }
         }
 
         Ok(())
     }
 
-    fn migrate(&mut self, now: Instant, remote: SocketAddr) {
+    fn migrate(&mut self, now: Instant, path_id: PathId, remote: SocketAddr) {
         trace!(%remote, "migration initiated");
+        let prev_pto = self.pto(path_id);
+        let path = self.paths.get_mut(&path_id).unwrap();
         // Reset rtt/congestion state for new path unless it looks like a NAT rebinding.
         // Note that the congestion window will not grow until validation terminates. Helps mitigate
         // amplification attacks performed by spoofing source addresses.
-        let mut new_path = if remote.is_ipv4() && remote.ip() == self.path.remote.ip() {
-            PathData::from_previous(remote, &self.path, now)
+        let mut new_path = if remote.is_ipv4() && remote.ip() == path.data.remote.ip() {
+            PathData::from_previous(remote, &path.data, now)
         } else {
-            PathData::new(
-                remote,
-                self.config.initial_rtt,
-                self.config.congestion_controller_factory.build(now),
-                now,
-                false,
-            )
+            let mut new_path = PathData::new(remote, &self.config, now, false);
+            new_path
+                .mtud
+                .on_peer_max_udp_payload_size_received(self.peer_params.max_udp_payload_size.0);
+            new_path
         };
         new_path.challenge = Some(self.rng.gen());
         new_path.challenge_pending = true;
-        let prev_pto = self.pto();
 
-        let mut prev = mem::replace(&mut self.path, new_path);
+        let mut prev = mem::replace(&mut path.data, new_path);
         // Don't clobber the original path if the previous one hasn't been validated yet
         if prev.challenge.is_none() {
             prev.challenge = Some(self.rng.gen());
             prev.challenge_pending = true;
-            self.prev_path = Some(prev);
+            path.prev = Some(prev);
         }
 
-        self.timers.set(
-            Timer::PathValidation,
-            now + 3 * cmp::max(self.pto(), prev_pto),
-        );
+        let timeout = now + 3 * cmp::max(self.pto(path_id), prev_pto);
+        self.path_mut(path_id)
+            .timers
+            .set(Timer::PathValidation, timeout);
+    }
+
+    /// Begin validating the server's preferred address, if any, and switch to it once validated
+    ///
+    /// The current path stays in use until the server answers from the preferred address, and
+    /// the connection simply remains on it if the server never does.
+    fn migrate_to_preferred_address(&mut self, now: Instant) {
+        let info = match self.peer_params.preferred_address.take() {
+            // Multipath clients may open a path to the preferred address instead
+            Some(_) if self.multipath => return,
+            Some(x) => x,
+            None => return,
+        };
+        let path_id = self.primary_path();
+        let current = self.path_data(path_id).remote;
+        let remote = match current {
+            SocketAddr::V4(_) => info.address_v4.map(SocketAddr::V4),
+            SocketAddr::V6(_) => info.address_v6.map(SocketAddr::V6),
+        };
+        let remote = match remote {
+            Some(x) if x != current => x,
+            _ => return,
+        };
+        // The CID supplied with the preferred address has sequence number 1, and must be used on
+        // the new path
+        let rem_cid = match self.rem_cids.get(1) {
+            Some(x) => x,
+            None => return,
+        };
+        trace!(%remote, "validating preferred address");
+        self.migration = Some(MigrationProbe {
+            local_ip: None,
+            remote,
+            rem_cid,
+            challenge: self.rng.gen(),
+            challenge_pending: true,
+        });
+        let timeout = now + self.new_path_validation_timeout();
+        self.path_mut(path_id)
+            .timers
+            .set(Timer::PathValidation, timeout);
+    }
+
+    /// Switch to the path validated for a migration started by `initiate_migration` or towards
+    /// the server's preferred address
+    fn complete_migration(&mut self, now: Instant, path_id: PathId) {
+        let migration = self.migration.take().unwrap();
+        trace!(remote = %migration.remote, local_ip = ?migration.local_ip, "new path validated");
+        // The network path has changed, so congestion and RTT state must be rediscovered. Servers
+        // don't apply an anti-amplification limit to our own address, so the path counts as
+        // validated for that purpose.
+        let mut data = PathData::new(migration.remote, &self.config, now, true);
+        data.mtud
+            .on_peer_max_udp_payload_size_received(self.peer_params.max_udp_payload_size.0);
+        let path = self.path_mut(path_id);
+        path.timers.stop(Timer::PathValidation);
+        path.data = data;
+        if let Some(local_ip) = migration.local_ip {
+            path.local_ip = Some(local_ip);
+        }
+        // Continue with the CID the new path was validated with, unless the peer has since
+        // retired it
+        let _ = self.update_rem_cid();
+        if migration.local_ip.is_some() {
+            self.events.push_back(Event::Migrated);
+        }
+    }
+
+    /// How long to wait for a path nothing is known about yet to be validated
+    fn new_path_validation_timeout(&self) -> Duration {
+        // Allow for at least the initial RTT
+        3 * cmp::max(self.pto(self.primary_path()), 2 * self.config.initial_rtt)
+    }
+
+    /// CID to address packets on path `path_id` to
+    fn rem_cid(&self, path_id: PathId) -> ConnectionId {
+        self.paths[&path_id]
+            .rem_cid
+            .unwrap_or_else(|| self.rem_cids.active())
+    }
+
+    fn path_info(&self, id: PathId, path: &PathState) -> PathInfo {
+        PathInfo {
+            id,
+            remote: path.data.remote,
+            local_ip: path.local_ip,
+            status: path.status,
+            peer_status: path.peer_status,
+            validated: path.validated,
+            rtt: path.data.rtt.get(),
+            cwnd: path.data.congestion_window(),
+            bytes_in_flight: path.in_flight.bytes,
+        }
+    }
+
+    fn path_data(&self, id: PathId) -> &PathData {
+        &self.paths[&id].data
+    }
+
+    fn path_data_mut(&mut self, id: PathId) -> &mut PathData {
+        &mut self.path_mut(id).data
+    }
+
+    fn path_mut(&mut self, id: PathId) -> &mut PathState {
+        self.paths.get_mut(&id).expect("unknown path")
+    }
+
+    /// The lowest-numbered path that hasn't been abandoned
+    fn primary_path(&self) -> PathId {
+        self.paths
+            .iter()
+            .find(|&(_, x)| !x.abandoned)
+            .map(|(&id, _)| id)
+            .expect("the last path is never abandoned")
+    }
+
+    /// Forget the paths that were abandoned
+    fn discard_abandoned_paths(&mut self) {
+        while let Some(id) = self
+            .paths
+            .iter()
+            .find(|&(_, x)| x.abandoned)
+            .map(|(&id, _)| id)
+        {
+            self.discard_path(id);
+        }
+    }
+
+    /// Forget an abandoned path, retransmitting whatever was still in flight on it elsewhere
+    fn discard_path(&mut self, id: PathId) {
+        trace!("discarding {}", id);
+        let space = self.remove_path(id).expect("discarded path must exist");
+        for (_, packet) in space.sent_packets {
+            for frame in packet.stream_frames {
+                self.streams.retransmit(frame);
+            }
+            self.spaces[SpaceId::Data].pending |= packet.retransmits;
+        }
+        self.discarded_paths.insert(id);
+    }
+
+    /// Stop using path `id`, queueing a PATH_ABANDON frame and reporting `reason` to the
+    /// application
+    ///
+    /// The path is discarded by the next call to `discard_abandoned_paths`.
+    fn abandon_path(
+        &mut self,
+        id: PathId,
+        error_code: VarInt,
+        reason: PathError,
+    ) -> Result<(), PathError> {
+        match self.paths.get(&id) {
+            Some(x) if !x.abandoned => {}
+            _ => return Err(PathError::UnknownPath),
+        }
+        if self
+            .paths
+            .iter()
+            .all(|(&x, entry)| x == id || entry.abandoned)
+        {
+            return Err(PathError::LastPath);
+        }
+        debug!(%reason, "abandoning {}", id);
+        self.paths.get_mut(&id).unwrap().abandoned = true;
+        self.spaces[SpaceId::Data]
+            .pending
+            .path_abandon
+            .push(frame::PathAbandon {
+                path_id: id.0.into(),
+                error_code,
+                reason: Bytes::new(),
+            });
+        self.events.push_back(Event::PathClosed { id, reason });
+        Ok(())
+    }
+
+    /// Handle an acknowledgement of packets sent on path `path_id`
+    fn on_path_ack_received(
+        &mut self,
+        now: Instant,
+        path_id: u64,
+        ack: frame::Ack,
+    ) -> Result<(), TransportError> {
+        let id = match u32::try_from(path_id) {
+            Ok(x) => PathId(x),
+            Err(_) => return Ok(()),
+        };
+        match self.paths.get(&id) {
+            Some(x) if !x.abandoned => {}
+            _ => {
+                debug!(path_id, "ignoring ACK for unknown path");
+                return Ok(());
+            }
+        }
+        self.on_ack_received(now, SpaceId::Data, id, ack)
     }
 
     /// Returns Err(()) if no CIDs were available
@@ -2570,11 +3850,9 @@ where
         let retire_cids = &mut self.spaces[SpaceId::Data].pending.retire_cids;
         retire_cids.extend(retired);
 
+        let remote = self.path_data(self.primary_path()).remote;
         self.endpoint_events
-            .push_back(EndpointEventInner::ResetToken(
-                self.path.remote,
-                reset_token,
-            ));
+            .push_back(EndpointEventInner::ResetToken(remote, reset_token));
         self.peer_params.stateless_reset_token = Some(reset_token);
 
         // Reduce linkability
@@ -2588,8 +3866,8 @@ where
             return;
         }
 
-        // Subtract 1 to account for the CID we supplied while handshaking
-        let n = self.peer_params.issue_cids_limit() - 1;
+        // Account for the CIDs we supplied while handshaking and in the preferred address
+        let n = self.peer_params.issue_cids_limit() - self.local_cid_state.issued();
         self.endpoint_events
             .push_back(EndpointEventInner::NeedIdentifiers(now, n));
     }
@@ -2597,10 +3875,14 @@ where
     fn populate_packet(
         &mut self,
         space_id: SpaceId,
+        path_id: PathId,
         buf: &mut Vec<u8>,
         max_size: usize,
+        app_data: bool,
     ) -> SentFrames {
         let mut sent = SentFrames::default();
+        let ack_path = self.ack_path(space_id, path_id);
+        let path = self.paths.get_mut(&path_id).unwrap();
         let space = &mut self.spaces[space_id];
         let is_0rtt = space_id == SpaceId::Data && space.crypto.is_none();
 
@@ -2614,7 +3896,7 @@ where
         }
 
         // PING
-        if mem::replace(&mut space.ping_pending, false) {
+        if mem::replace(&mut space.for_path_mut(path_id).ping_pending, false) {
             trace!("PING");
             buf.write(frame::Type::PING);
             sent.non_retransmits = true;
@@ -2622,16 +3904,24 @@ where
         }
 
         // ACK
-        if !space.pending_acks.ranges().is_empty() {
-            Self::populate_acks(self.receiving_ecn, &mut sent, space, buf, &mut self.stats);
+        if !space.for_path(path_id).pending_acks.ranges().is_empty() {
+            Self::populate_acks(
+                path.receiving_ecn,
+                ack_path,
+                &mut sent,
+                space,
+                path_id,
+                buf,
+                &mut self.stats,
+            );
         }
 
         // PATH_CHALLENGE
         if buf.len() + 9 < max_size && space_id == SpaceId::Data {
             // Transmit challenges with every outgoing frame on an unvalidated path
-            if let Some(token) = self.path.challenge {
+            if let Some(token) = path.data.challenge {
                 // But only send a packet solely for that purpose at most once
-                self.path.challenge_pending = false;
+                path.data.challenge_pending = false;
                 sent.non_retransmits = true;
                 sent.requires_padding = true;
                 trace!("PATH_CHALLENGE {:08x}", token);
@@ -2643,7 +3933,12 @@ where
 
         // PATH_RESPONSE
         if buf.len() + 9 < max_size && space_id == SpaceId::Data {
-            if let Some(response) = self.path_response.take() {
+            // Responses for other paths are sent separately by `poll_transmit`
+            let response = match path.path_response {
+                Some(ref x) if x.remote == path.data.remote => path.path_response.take(),
+                _ => None,
+            };
+            if let Some(response) = response {
                 sent.non_retransmits = true;
                 sent.requires_padding = true;
                 trace!("PATH_RESPONSE {:08x}", response.token);
@@ -2740,8 +4035,52 @@ where
             self.stats.frame_tx.retire_connection_id += 1;
         }
 
+        // NEW_TOKEN
+        while let Some(token) = space.pending.new_tokens.pop() {
+            let len = VarInt::from_u64(token.len() as u64).unwrap();
+            if buf.len() + 1 + len.size() + token.len() >= max_size {
+                space.pending.new_tokens.push(token);
+                break;
+            }
+            trace!("NEW_TOKEN");
+            buf.write(frame::Type::NEW_TOKEN);
+            buf.write(len);
+            buf.extend_from_slice(&token);
+            sent.retransmits.get_or_create().new_tokens.push(token);
+            self.stats.frame_tx.new_token += 1;
+        }
+
+        // PATH_ABANDON
+        while let Some(frame) = space.pending.path_abandon.pop() {
+            if buf.len() + frame.size() >= max_size {
+                space.pending.path_abandon.push(frame);
+                break;
+            }
+            trace!(path_id = frame.path_id, "PATH_ABANDON");
+            frame.encode(buf);
+            sent.retransmits.get_or_create().path_abandon.push(frame);
+            self.stats.frame_tx.path_abandon += 1;
+        }
+
+        // PATH_STANDBY, PATH_AVAILABLE
+        while buf.len() + frame::PathStatus::SIZE_BOUND < max_size {
+            let frame = match space.pending.path_status.pop() {
+                Some(x) => x,
+                None => break,
+            };
+            trace!(
+                path_id = frame.path_id,
+                seq = frame.seq,
+                available = frame.available,
+                "PATH_STATUS"
+            );
+            frame.encode(buf);
+            sent.retransmits.get_or_create().path_status.push(frame);
+            self.stats.frame_tx.path_status += 1;
+        }
+
         // DATAGRAM
-        while buf.len() + Datagram::SIZE_BOUND < max_size && space_id == SpaceId::Data {
+        while buf.len() + Datagram::SIZE_BOUND < max_size && space_id == SpaceId::Data && app_data {
             match self.datagrams.write(buf, max_size) {
                 true => {
                     sent.non_retransmits = true;
@@ -2752,7 +4091,7 @@ where
         }
 
         // STREAM
-        if space_id == SpaceId::Data {
+        if space_id == SpaceId::Data && app_data {
             sent.stream_frames = self.streams.write_stream_frames(buf, max_size);
             self.stats.frame_tx.stream += sent.stream_frames.len() as u64;
         }
@@ -2766,15 +4105,18 @@ where
     /// `!PendingAcks::ranges().is_empty()` returns `true`.
     fn populate_acks(
         receiving_ecn: bool,
+        ack_path: Option<u64>,
         sent: &mut SentFrames,
         space: &mut PacketSpace<S>,
+        path_id: PathId,
         buf: &mut Vec<u8>,
         stats: &mut ConnectionStats,
     ) {
-        debug_assert!(!space.pending_acks.ranges().is_empty());
-
         // 0-RTT packets must never carry acks (which would have to be of handshake packets)
         debug_assert!(space.crypto.is_some(), "tried to send ACK in 0-RTT");
+        let space = space.for_path_mut(path_id);
+        debug_assert!(!space.pending_acks.ranges().is_empty());
+
         let ecn = if receiving_ecn {
             Some(&space.ecn_counters)
         } else {
@@ -2788,21 +4130,42 @@ where
         let ack_delay_exp = TransportParameters::default().ack_delay_exponent;
         let delay = delay_micros >> ack_delay_exp.into_inner();
 
-        trace!("ACK {:?}, Delay = {}us", sent.acks, delay);
-
-        frame::Ack::encode(delay as _, &sent.acks, ecn, buf);
+        match ack_path {
+            Some(path_id) => {
+                trace!("ACK_MP {} {:?}, Delay = {}us", path_id, sent.acks, delay);
+                frame::Ack::encode_mp(path_id, delay as _, &sent.acks, ecn, buf);
+            }
+            None => {
+                trace!("ACK {:?}, Delay = {}us", sent.acks, delay);
+                frame::Ack::encode(delay as _, &sent.acks, ecn, buf);
+            }
+        }
         stats.frame_tx.acks += 1;
     }
 
+    /// The path ID to acknowledge packets received in `space_id` with, if ACK_MP frames are needed
+    fn ack_path(&self, space_id: SpaceId, path_id: PathId) -> Option<u64> {
+        match (space_id, path_id) {
+            (SpaceId::Data, id) if self.multipath && id != PathId::INITIAL => Some(id.0.into()),
+            _ => None,
+        }
+    }
+
     fn close_common(&mut self) {
         trace!("connection closed");
         for &timer in &Timer::VALUES {
             self.timers.stop(timer);
         }
+        for path in self.paths.values_mut() {
+            for &timer in &Timer::PATH_VALUES {
+                path.timers.stop(timer);
+            }
+        }
     }
 
     fn set_close_timer(&mut self, now: Instant) {
-        self.timers.set(Timer::Close, now + 3 * self.pto());
+        let pto = self.pto(self.primary_path());
+        self.timers.set(Timer::Close, now + 3 * pto);
     }
 
     /// Handle transport parameters received from the peer
@@ -2817,6 +4180,37 @@ where
             ));
         }
 
+        if self.side.is_client() {
+            // Downgrade protection for version negotiation (RFC 9368 §4)
+            let local_info = self.local_params.version_information.as_ref().unwrap();
+            let reacted_to_vn = self.client_restart.is_none();
+            let valid = match params.version_information {
+                Some(ref info) => {
+                    info.chosen_version == self.version
+                        && (!reacted_to_vn
+                            || local_info
+                                .available_versions
+                                .iter()
+                                .find(|x| info.available_versions.contains(x))
+                                == Some(&local_info.chosen_version))
+                }
+                None => !reacted_to_vn && self.version == local_info.chosen_version,
+            };
+            if !valid {
+                return Err(TransportError::VERSION_NEGOTIATION_ERROR(
+                    "server's chosen version doesn't match negotiated version",
+                ));
+            }
+        }
+
+        if let Some(ref info) = params.preferred_address {
+            if info.connection_id.is_empty() {
+                return Err(TransportError::TRANSPORT_PARAMETER_ERROR(
+                    "zero-length CID in preferred_address",
+                ));
+            }
+        }
+
         self.set_peer_params(params);
         Ok(())
     }
@@ -2836,12 +4230,19 @@ where
                 reset_token: info.stateless_reset_token,
             }).expect("preferred address CID is the first received, and hence is guaranteed to be legal");
         }
+        for path in self.paths.values_mut() {
+            path.data
+                .mtud
+                .on_peer_max_udp_payload_size_received(params.max_udp_payload_size.0);
+        }
+        self.multipath = self.local_params.enable_multipath && params.enable_multipath;
         self.peer_params = params;
     }
 
     fn decrypt_packet(
         &mut self,
         now: Instant,
+        path_id: PathId,
         packet: &mut Packet,
     ) -> Result<Option<u64>, Option<TransportError>> {
         if !packet.header.is_protected() {
@@ -2849,7 +4250,7 @@ where
             return Ok(None);
         }
         let space = packet.header.space();
-        let rx_packet = self.spaces[space].rx_packet;
+        let rx_packet = self.spaces[space].for_path(path_id).rx_packet;
         let number = packet.header.number().ok_or(None)?.expand(rx_packet + 1);
         let key_phase = packet.header.key_phase();
 
@@ -2880,7 +4281,7 @@ where
         };
 
         crypto
-            .decrypt(number, &packet.header_data, &mut packet.payload)
+            .decrypt_path(path_id.0, number, &packet.header_data, &mut packet.payload)
             .map_err(|_| {
                 trace!("decryption failed with packet number {}", number);
                 None
@@ -2947,27 +4348,37 @@ where
     /// acknowledged or declared lost.
     #[cfg(test)]
     pub(crate) fn bytes_in_flight(&self) -> u64 {
-        self.in_flight.bytes
+        self.paths[&self.primary_path()].in_flight.bytes
     }
 
     /// Number of bytes worth of non-ack-only packets that may be sent
     #[cfg(test)]
     pub(crate) fn congestion_state(&self) -> u64 {
-        self.path
+        let path = &self.paths[&self.primary_path()];
+        path.data
             .congestion
             .window()
-            .saturating_sub(self.in_flight.bytes)
+            .saturating_sub(path.in_flight.bytes)
     }
 
     /// Whether no timers but keepalive, idle and pushnewcid are running
     #[cfg(test)]
     pub(crate) fn is_idle(&self) -> bool {
-        Timer::VALUES
+        let next = Timer::VALUES
             .iter()
             .filter(|&&t| t != Timer::KeepAlive && t != Timer::PushNewCid)
             .filter_map(|&t| Some((t, self.timers.get(t)?)))
-            .min_by_key(|&(_, time)| time)
-            .map_or(true, |(timer, _)| timer == Timer::Idle)
+            .min_by_key(|&(_, time)| time);
+        let path = self
+            .paths
+            .values()
+            .filter_map(|x| x.timers.next_timeout())
+            .min();
+        match (next, path) {
+            (Some((_, time)), Some(path)) if path < time => false,
+            (None, Some(_)) => false,
+            (next, _) => next.map_or(true, |(timer, _)| timer == Timer::Idle),
+        }
     }
 
     /// Total number of outgoing packets that have been deemed lost
@@ -2979,13 +4390,13 @@ where
     /// Whether explicit congestion notification is in use on outgoing packets.
     #[cfg(test)]
     pub(crate) fn using_ecn(&self) -> bool {
-        self.path.sending_ecn
+        self.path_data(self.primary_path()).sending_ecn
     }
 
     /// The number of received bytes in the current path
     #[cfg(test)]
     pub(crate) fn total_recvd(&self) -> u64 {
-        self.path.total_recvd
+        self.path_data(self.primary_path()).total_recvd
     }
 
     #[cfg(test)]
@@ -3012,25 +4423,23 @@ where
         Duration::from_micros(self.peer_params.max_ack_delay.0 * 1000)
     }
 
-    /// Whether we have 1-RTT data to send
+    /// Whether we have 1-RTT data to send, counting new application data only if `app_data`
     ///
     /// See also `self.space(SpaceId::Data).can_send()`
-    fn can_send_1rtt(&self) -> bool {
-        self.streams.can_send()
-            || self.path.challenge_pending
-            || self
-                .prev_path
-                .as_ref()
-                .map_or(false, |x| x.challenge_pending)
-            || self.path_response.is_some()
-            || !self.datagrams.outgoing.is_empty()
+    fn can_send_1rtt(&self, path_id: PathId, app_data: bool) -> bool {
+        let path = &self.paths[&path_id];
+        (app_data && (self.streams.can_send() || !self.datagrams.outgoing.is_empty()))
+            || path.data.challenge_pending
+            || path.prev.as_ref().map_or(false, |x| x.challenge_pending)
+            || path.path_response.is_some()
     }
 
     /// Update counters to account for a packet becoming acknowledged, lost, or abandoned
-    fn remove_in_flight(&mut self, space: SpaceId, packet: &SentPacket) {
-        self.in_flight.bytes -= u64::from(packet.size);
-        self.in_flight.ack_eliciting -= u64::from(packet.ack_eliciting);
-        self.spaces[space].in_flight -= u64::from(packet.size);
+    fn remove_in_flight(&mut self, space: SpaceId, path_id: PathId, packet: &SentPacket) {
+        let in_flight = &mut self.path_mut(path_id).in_flight;
+        in_flight.bytes -= u64::from(packet.size);
+        in_flight.ack_eliciting -= u64::from(packet.ack_eliciting);
+        self.spaces[space].for_path_mut(path_id).in_flight -= u64::from(packet.size);
     }
 
     /// Terminate the connection instantly, without sending a close packet
@@ -3083,6 +4492,64 @@ pub enum ConnectionError {
     LocallyClosed,
 }
 
+/// Reasons why a client-initiated migration could not be started or did not complete
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum MigrationError {
+    /// Only clients can initiate migration
+    #[error("only clients can initiate migration")]
+    NotClient,
+    /// The handshake has not been confirmed yet
+    #[error("handshake not confirmed")]
+    HandshakeNotConfirmed,
+    /// Multipath connections change networks by opening and closing paths instead
+    #[error("connection uses multipath")]
+    Multipath,
+    /// The peer's `disable_active_migration` transport parameter forbids migration
+    #[error("migration disabled by peer")]
+    DisabledByPeer,
+    /// Another migration or path validation is in progress
+    #[error("migration already in progress")]
+    InProgress,
+    /// The peer hasn't supplied a spare connection ID for use on a new path
+    #[error("no connection IDs available")]
+    NoConnectionIds,
+    /// The peer didn't respond on the new path in time
+    #[error("path validation failed")]
+    ValidationFailed,
+}
+
+/// Reasons why a path of a multipath connection could not be used, or was closed
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum PathError {
+    /// The multipath extension is disabled locally or unsupported by the peer
+    #[error("multipath not negotiated")]
+    MultipathNotNegotiated,
+    /// Only clients can open paths
+    #[error("only clients can open paths")]
+    NotClient,
+    /// The handshake has not been confirmed yet
+    #[error("handshake not confirmed")]
+    HandshakeNotConfirmed,
+    /// No connection ID is left to identify a new path with
+    #[error("no connection IDs available")]
+    NoConnectionIds,
+    /// The path doesn't exist or has already been closed
+    #[error("unknown path")]
+    UnknownPath,
+    /// The last open path can't be closed
+    #[error("last open path")]
+    LastPath,
+    /// The peer didn't respond on the new path in time
+    #[error("path validation failed")]
+    ValidationFailed,
+    /// The peer closed the path
+    #[error("abandoned by peer: {0}")]
+    AbandonedByPeer(VarInt),
+    /// The local application closed the path
+    #[error("closed")]
+    LocallyClosed,
+}
+
 impl From<Close> for ConnectionError {
     fn from(x: Close) -> Self {
         match x {
@@ -3149,13 +4616,15 @@ mod state {
         ///
         /// Always set for servers
         pub rem_cid_set: bool,
-        /// Stateless retry token, if the peer has provided one
+        /// Address validation token, from a Retry packet or a NEW_TOKEN frame received on an
+        /// earlier connection
         ///
         /// Only set for clients
         pub token: Option<Bytes>,
         /// First cryptographic message
         ///
-        /// Only set for clients
+        /// Kept by clients to resend after a Retry, and by servers until the connection's version
+        /// has been negotiated.
         pub client_hello: Option<Bytes>,
     }
 
@@ -3229,11 +4698,35 @@ pub enum Event {
     Stream(StreamEvent),
     /// One or more application datagrams have been received
     DatagramReceived,
+    /// A migration started by [`Connection::initiate_migration`] completed, and traffic now
+    /// uses the new local address
+    Migrated,
+    /// A migration started by [`Connection::initiate_migration`] failed, and traffic continues
+    /// over the previous path
+    MigrationFailed {
+        /// Reason the migration failed
+        reason: MigrationError,
+    },
+    /// A path opened by [`Connection::open_path`] or by the peer was validated, and may now carry
+    /// application data
+    PathOpened {
+        /// Identifier of the path
+        id: PathId,
+    },
+    /// A path was closed, or failed to open
+    PathClosed {
+        /// Identifier of the path
+        id: PathId,
+        /// Reason the path was closed
+        reason: PathError,
+    },
 }
 
 struct PathResponse {
     /// The packet number the corresponding PATH_CHALLENGE was received in
     packet: u64,
+    /// The address the corresponding PATH_CHALLENGE was received from
+    remote: SocketAddr,
     token: u64,
 }
 
@@ -3261,6 +4754,18 @@ struct ZeroRttCrypto<S: crypto::Session> {
     packet: S::PacketKey,
 }
 
+/// What a client needs to start a new handshake after version negotiation
+pub(crate) struct ClientRestart<S: crypto::Session> {
+    pub(crate) crypto: S::ClientConfig,
+    pub(crate) server_name: String,
+}
+
+/// Where a client keeps the address validation tokens received from a server
+pub(crate) struct ClientTokenStore {
+    pub(crate) store: Arc<dyn TokenStore>,
+    pub(crate) server_name: String,
+}
+
 #[derive(Default)]
 struct SentFrames {
     retransmits: ThinRetransmits,
@@ -0,0 +1,415 @@
+//! Datagram Packetization Layer Path MTU Discovery (RFC 8899)
+
+use std::{cmp, time::Instant};
+
+use tracing::{debug, trace};
+
+use crate::config::MtuDiscoveryConfig;
+
+/// Tracks the maximum UDP payload size usable on a path
+///
+/// Starts out at the configured minimum MTU. If discovery is enabled, the path is then searched
+/// for larger sizes once the handshake is complete, using PING-only probe packets padded to the
+/// size being tested. Losses of ordinary packets larger than the minimum MTU are monitored in order
+/// to fall back to the minimum if the path stops delivering them.
+#[derive(Debug, Clone)]
+pub struct MtuDiscovery {
+    /// The largest UDP payload size known to be supported by the path
+    current_mtu: u16,
+    /// Search state, if MTU discovery is enabled
+    state: Option<EnabledMtuDiscovery>,
+    black_hole_detector: BlackHoleDetector,
+}
+
+impl MtuDiscovery {
+    pub fn new(min_mtu: u16, config: Option<MtuDiscoveryConfig>) -> Self {
+        Self {
+            current_mtu: min_mtu,
+            state: config.map(|config| EnabledMtuDiscovery {
+                config,
+                peer_max_udp_payload_size: None,
+                phase: Phase::Initial,
+            }),
+            black_hole_detector: BlackHoleDetector::new(min_mtu),
+        }
+    }
+
+    pub fn current_mtu(&self) -> u16 {
+        self.current_mtu
+    }
+
+    /// Limit the search to the peer's `max_udp_payload_size` transport parameter
+    pub fn on_peer_max_udp_payload_size_received(&mut self, value: u64) {
+        if let Some(state) = &mut self.state {
+            let value = cmp::min(value, u64::from(u16::MAX)) as u16;
+            state.peer_max_udp_payload_size = Some(value);
+        }
+    }
+
+    /// Returns the size of the probe to send now, if any
+    ///
+    /// The caller must report the probe through `on_probe_sent` if it sends it.
+    pub fn poll_transmit(&mut self, now: Instant) -> Option<u16> {
+        let current_mtu = self.current_mtu;
+        let state = self.state.as_mut()?;
+        let upper_bound = cmp::min(state.config.upper_bound, state.peer_max_udp_payload_size?);
+
+        if let Phase::Initial = state.phase {
+            state.phase = Phase::Searching(SearchState::new(
+                current_mtu,
+                upper_bound,
+                state.config.minimum_change,
+            ));
+        }
+        if let Phase::Complete(next_search) = state.phase {
+            if now < next_search || current_mtu >= upper_bound {
+                return None;
+            }
+            state.phase = Phase::Searching(SearchState::new(
+                current_mtu,
+                upper_bound,
+                state.config.minimum_change,
+            ));
+        }
+
+        let search = match &mut state.phase {
+            Phase::Searching(search) => search,
+            _ => unreachable!(),
+        };
+        if search.in_flight_probe.is_some() {
+            return None;
+        }
+        let size = search.next_probe_size();
+        if size.is_none() {
+            debug!(mtu = current_mtu, "MTU search complete");
+            state.phase = Phase::Complete(now + state.config.interval);
+        }
+        size
+    }
+
+    /// Record that a probe of `size` bytes was sent with packet number `pn`
+    pub fn on_probe_sent(&mut self, pn: u64, size: u16) {
+        if let Some(Phase::Searching(search)) = self.state.as_mut().map(|x| &mut x.phase) {
+            trace!(size, "sent MTU probe");
+            search.in_flight_probe = Some((pn, size));
+        }
+    }
+
+    /// Packet number of the probe that is currently in flight, if any
+    pub fn in_flight_probe(&self) -> Option<u64> {
+        match self.state.as_ref()?.phase {
+            Phase::Searching(ref search) => search.in_flight_probe.map(|(pn, _)| pn),
+            _ => None,
+        }
+    }
+
+    /// Notify of the acknowledgement of a 1-RTT packet
+    ///
+    /// Returns whether the MTU was raised as a result.
+    pub fn on_acked(&mut self, pn: u64, size: u16) -> bool {
+        self.black_hole_detector.on_acked(size, self.current_mtu);
+        let search = match self.state.as_mut().map(|x| &mut x.phase) {
+            Some(Phase::Searching(search)) => search,
+            _ => return false,
+        };
+        match search.in_flight_probe {
+            Some((probe_pn, probe_size)) if probe_pn == pn => {
+                search.on_probe_acked(probe_size);
+                debug!(mtu = probe_size, "MTU probe acknowledged");
+                self.current_mtu = probe_size;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Notify of the loss of the probe that was in flight
+    pub fn on_probe_lost(&mut self) {
+        if let Some(Phase::Searching(search)) = self.state.as_mut().map(|x| &mut x.phase) {
+            search.on_probe_lost();
+        }
+    }
+
+    /// Notify of the loss of a 1-RTT packet that isn't a probe
+    pub fn on_non_probe_lost(&mut self, size: u16) {
+        self.black_hole_detector.on_non_probe_lost(size);
+    }
+
+    /// Check the losses reported since the last call for signs of a black hole
+    ///
+    /// Returns whether a black hole was detected, in which case the MTU has been reset to the
+    /// minimum.
+    pub fn detect_black_hole(&mut self, now: Instant) -> bool {
+        if !self.black_hole_detector.on_loss_burst_end() {
+            return false;
+        }
+
+        debug!(
+            mtu = self.current_mtu,
+            "black hole detected, falling back to the minimum MTU"
+        );
+        self.current_mtu = self.black_hole_detector.min_mtu;
+        if let Some(state) = &mut self.state {
+            state.phase = Phase::Complete(now + state.config.black_hole_cooldown);
+        }
+        true
+    }
+}
+
+#[derive(Debug, Clone)]
+struct EnabledMtuDiscovery {
+    config: MtuDiscoveryConfig,
+    /// Unknown until the peer's transport parameters are received
+    peer_max_udp_payload_size: Option<u16>,
+    phase: Phase,
+}
+
+#[derive(Debug, Clone)]
+enum Phase {
+    /// No search has been started yet
+    Initial,
+    /// Probing for a larger MTU
+    Searching(SearchState),
+    /// Not searching until the given time
+    Complete(Instant),
+}
+
+#[derive(Debug, Clone)]
+struct SearchState {
+    /// Largest size known to be supported
+    lower_bound: u16,
+    /// Largest size not known to be unsupported
+    upper_bound: u16,
+    /// Smallest improvement worth another probe
+    minimum_change: u16,
+    /// Packet number and size of the outstanding probe
+    in_flight_probe: Option<(u64, u16)>,
+    /// Number of consecutive losses of probes of the size currently being tested
+    lost_probe_count: u8,
+    /// Whether a probe of the full upper bound has been found unsupported
+    ///
+    /// Paths commonly support the upper bound, so it is tried first before resorting to a binary
+    /// search.
+    upper_bound_lost: bool,
+}
+
+impl SearchState {
+    fn new(lower_bound: u16, upper_bound: u16, minimum_change: u16) -> Self {
+        Self {
+            lower_bound,
+            upper_bound,
+            minimum_change: cmp::max(minimum_change, 1),
+            in_flight_probe: None,
+            lost_probe_count: 0,
+            upper_bound_lost: false,
+        }
+    }
+
+    fn next_probe_size(&self) -> Option<u16> {
+        if self.upper_bound <= self.lower_bound
+            || self.upper_bound - self.lower_bound < self.minimum_change
+        {
+            return None;
+        }
+        if !self.upper_bound_lost {
+            return Some(self.upper_bound);
+        }
+        Some(self.upper_bound - (self.upper_bound - self.lower_bound) / 2)
+    }
+
+    fn on_probe_acked(&mut self, size: u16) {
+        self.in_flight_probe = None;
+        self.lost_probe_count = 0;
+        self.lower_bound = size;
+    }
+
+    fn on_probe_lost(&mut self) {
+        let (_, size) = match self.in_flight_probe.take() {
+            Some(x) => x,
+            None => return,
+        };
+        self.lost_probe_count += 1;
+        if self.lost_probe_count < MAX_PROBE_RETRANSMITS {
+            return;
+        }
+        // The path doesn't support this size
+        self.lost_probe_count = 0;
+        self.upper_bound_lost = true;
+        self.upper_bound = size - 1;
+    }
+}
+
+/// Detects paths that stopped delivering packets larger than the minimum MTU
+///
+/// Every batch of losses that includes such a packet is considered suspicious; once
+/// `BLACK_HOLE_THRESHOLD` suspicious batches occur without a packet of the current MTU being
+/// acknowledged, the path is assumed to be a black hole for the current MTU.
+#[derive(Debug, Clone)]
+struct BlackHoleDetector {
+    min_mtu: u16,
+    /// Whether the current batch of losses includes packets larger than `min_mtu`
+    suspicious_loss: bool,
+    /// Number of suspicious loss batches since a packet of the current MTU was acknowledged
+    suspicious_loss_bursts: u8,
+}
+
+impl BlackHoleDetector {
+    fn new(min_mtu: u16) -> Self {
+        Self {
+            min_mtu,
+            suspicious_loss: false,
+            suspicious_loss_bursts: 0,
+        }
+    }
+
+    fn on_acked(&mut self, size: u16, current_mtu: u16) {
+        if size >= current_mtu {
+            self.suspicious_loss_bursts = 0;
+        }
+    }
+
+    fn on_non_probe_lost(&mut self, size: u16) {
+        if size > self.min_mtu {
+            self.suspicious_loss = true;
+        }
+    }
+
+    /// Returns whether a black hole was detected
+    fn on_loss_burst_end(&mut self) -> bool {
+        if !std::mem::replace(&mut self.suspicious_loss, false) {
+            return false;
+        }
+        self.suspicious_loss_bursts += 1;
+        if self.suspicious_loss_bursts < BLACK_HOLE_THRESHOLD {
+            return false;
+        }
+        self.suspicious_loss_bursts = 0;
+        true
+    }
+}
+
+/// Number of times a probe of a given size is sent before concluding the path doesn't support it
+///
+/// Corresponds to MAX_PROBES in RFC 8899.
+const MAX_PROBE_RETRANSMITS: u8 = 3;
+
+/// Number of suspicious loss bursts after which a black hole is assumed
+const BLACK_HOLE_THRESHOLD: u8 = 3;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn discovery(upper_bound: u16) -> MtuDiscovery {
+        let mut config = MtuDiscoveryConfig::default();
+        config.upper_bound(upper_bound);
+        let mut mtud = MtuDiscovery::new(1200, Some(config));
+        mtud.on_peer_max_udp_payload_size_received(65527);
+        mtud
+    }
+
+    #[test]
+    fn upper_bound_first() {
+        let now = Instant::now();
+        let mut mtud = discovery(1452);
+        assert_eq!(mtud.poll_transmit(now), Some(1452));
+        mtud.on_probe_sent(0, 1452);
+        // Only one probe in flight at a time
+        assert_eq!(mtud.poll_transmit(now), None);
+        assert!(mtud.on_acked(0, 1452));
+        assert_eq!(mtud.current_mtu(), 1452);
+        assert_eq!(mtud.poll_transmit(now), None);
+    }
+
+    #[test]
+    fn binary_search() {
+        let now = Instant::now();
+        let mut mtud = discovery(1500);
+        let mut pn = 0;
+        // The path supports up to 1400 bytes
+        while let Some(size) = mtud.poll_transmit(now) {
+            mtud.on_probe_sent(pn, size);
+            if size <= 1400 {
+                assert!(mtud.on_acked(pn, size));
+            } else {
+                mtud.on_probe_lost();
+            }
+            pn += 1;
+        }
+        assert!(mtud.current_mtu() <= 1400);
+        assert!(mtud.current_mtu() > 1400 - 20);
+    }
+
+    #[test]
+    fn probe_retransmits() {
+        let now = Instant::now();
+        let mut mtud = discovery(1452);
+        for pn in 0..MAX_PROBE_RETRANSMITS as u64 {
+            assert_eq!(mtud.poll_transmit(now), Some(1452));
+            mtud.on_probe_sent(pn, 1452);
+            mtud.on_probe_lost();
+        }
+        assert!(mtud.poll_transmit(now).unwrap() < 1452);
+    }
+
+    #[test]
+    fn peer_limit() {
+        let now = Instant::now();
+        let mut mtud = discovery(1452);
+        mtud.on_peer_max_udp_payload_size_received(1300);
+        assert_eq!(mtud.poll_transmit(now), Some(1300));
+    }
+
+    #[test]
+    fn no_probes_before_peer_params() {
+        let mut config = MtuDiscoveryConfig::default();
+        config.upper_bound(1452);
+        let mut mtud = MtuDiscovery::new(1200, Some(config));
+        assert_eq!(mtud.poll_transmit(Instant::now()), None);
+    }
+
+    #[test]
+    fn black_hole() {
+        let now = Instant::now();
+        let mut mtud = discovery(1452);
+        mtud.poll_transmit(now);
+        mtud.on_probe_sent(0, 1452);
+        mtud.on_acked(0, 1452);
+        for _ in 0..BLACK_HOLE_THRESHOLD {
+            assert_eq!(mtud.current_mtu(), 1452);
+            mtud.on_non_probe_lost(1452);
+            mtud.on_non_probe_lost(1452);
+            mtud.detect_black_hole(now);
+        }
+        assert_eq!(mtud.current_mtu(), 1200);
+
+        // Search resumes after the cooldown
+        assert_eq!(mtud.poll_transmit(now), None);
+        let later =
+            now + MtuDiscoveryConfig::default().black_hole_cooldown + Duration::from_secs(1);
+        assert_eq!(mtud.poll_transmit(later), Some(1452));
+    }
+
+    #[test]
+    fn small_losses_are_not_suspicious() {
+        let now = Instant::now();
+        let mut mtud = discovery(1452);
+        mtud.poll_transmit(now);
+        mtud.on_probe_sent(0, 1452);
+        mtud.on_acked(0, 1452);
+        for _ in 0..BLACK_HOLE_THRESHOLD {
+            mtud.on_non_probe_lost(1200);
+            assert!(!mtud.detect_black_hole(now));
+        }
+        assert_eq!(mtud.current_mtu(), 1452);
+    }
+
+    #[test]
+    fn disabled() {
+        let mut mtud = MtuDiscovery::new(1200, None);
+        mtud.on_peer_max_udp_payload_size_received(65527);
+        assert_eq!(mtud.poll_transmit(Instant::now()), None);
+        assert_eq!(mtud.current_mtu(), 1200);
+    }
+}
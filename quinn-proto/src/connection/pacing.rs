@@ -47,13 +47,15 @@ impl Pacer {
     /// where `d` is the time before this function should be called again.
     ///
     /// The 5/4 ratio used here comes from the suggestion that N = 1.25 in the draft IETF RFC for
-    /// QUIC.
+    /// QUIC. If the congestion controller specifies a `pacing_rate`, packets are paced at that
+    /// rate instead.
     pub fn delay(
         &mut self,
         smoothed_rtt: Duration,
         bytes_to_send: u64,
         mtu: u16,
         window: u64,
+        pacing_rate: Option<u64>,
         now: Instant,
     ) -> Option<Instant> {
         debug_assert_ne!(
@@ -61,6 +63,16 @@ impl Pacer {
             "zero-sized congestion control window is nonsense"
         );
 
+        // Pace at the requested rate by substituting the window that the rate would otherwise be
+        // derived from
+        let window = match pacing_rate {
+            Some(rate) => {
+                let window = u128::from(rate) * smoothed_rtt.as_nanos() * 4 / 5 / 1_000_000_000;
+                (window.min(u64::MAX.into()) as u64).max(mtu.into())
+            }
+            None => window,
+        };
+
         if window != self.last_window {
             self.capacity = optimal_capacity(smoothed_rtt, window, mtu);
 
@@ -165,13 +177,20 @@ mod tests {
         let rtt = Duration::from_micros(400);
 
         assert!(Pacer::new(rtt, 30000, 1500, new_instant)
-            .delay(Duration::from_micros(0), 0, 1500, 1, old_instant)
+            .delay(Duration::from_micros(0), 0, 1500, 1, None, old_instant)
             .is_none());
         assert!(Pacer::new(rtt, 30000, 1500, new_instant)
-            .delay(Duration::from_micros(0), 1600, 1500, 1, old_instant)
+            .delay(Duration::from_micros(0), 1600, 1500, 1, None, old_instant)
             .is_none());
         assert!(Pacer::new(rtt, 30000, 1500, new_instant)
-            .delay(Duration::from_micros(0), 1500, 1500, 3000, old_instant)
+            .delay(
+                Duration::from_micros(0),
+                1500,
+                1500,
+                3000,
+                None,
+                old_instant
+            )
             .is_none());
     }
 
@@ -213,14 +232,14 @@ mod tests {
         assert_eq!(pacer.tokens, pacer.capacity);
         let initial_tokens = pacer.tokens;
 
-        pacer.delay(rtt, mtu as u64, mtu, window * 2, now);
+        pacer.delay(rtt, mtu as u64, mtu, window * 2, None, now);
         assert_eq!(
             pacer.capacity,
             (2 * window as u128 * BURST_INTERVAL_NANOS / rtt.as_nanos()) as u64
         );
         assert_eq!(pacer.tokens, initial_tokens);
 
-        pacer.delay(rtt, mtu as u64, mtu, window / 2, now);
+        pacer.delay(rtt, mtu as u64, mtu, window / 2, None, now);
         assert_eq!(
             pacer.capacity,
             (window as u128 / 2 * BURST_INTERVAL_NANOS / rtt.as_nanos()) as u64
@@ -240,7 +259,7 @@ mod tests {
 
         for _ in 0..packet_capacity {
             assert_eq!(
-                pacer.delay(rtt, mtu as u64, mtu, window, old_instant),
+                pacer.delay(rtt, mtu as u64, mtu, window, None, old_instant),
                 None,
                 "When capacity is available packets should be sent immediately"
             );
@@ -252,7 +271,7 @@ mod tests {
 
         assert_eq!(
             pacer
-                .delay(rtt, mtu as u64, mtu, window, old_instant)
+                .delay(rtt, mtu as u64, mtu, window, None, old_instant)
                 .expect("Send must be delayed")
                 .duration_since(old_instant),
             pace_duration
@@ -265,6 +284,7 @@ mod tests {
                 mtu as u64,
                 mtu,
                 window,
+                None,
                 old_instant + pace_duration / 2
             ),
             None
@@ -273,7 +293,7 @@ mod tests {
 
         for _ in 0..packet_capacity / 2 {
             assert_eq!(
-                pacer.delay(rtt, mtu as u64, mtu, window, old_instant),
+                pacer.delay(rtt, mtu as u64, mtu, window, None, old_instant),
                 None,
                 "When capacity is available packets should be sent immediately"
             );
@@ -288,10 +308,35 @@ mod tests {
                 mtu as u64,
                 mtu,
                 window,
+                None,
                 old_instant + pace_duration * 3 / 2
             ),
             None
         );
         assert_eq!(pacer.tokens, pacer.capacity);
     }
+
+    #[test]
+    fn honours_pacing_rate() {
+        let rate = 1_000_000;
+        let mtu = 1000;
+        let rtt = Duration::from_millis(50);
+        let now = Instant::now();
+
+        let mut pacer = Pacer::new(rtt, 2_000_000, mtu, now);
+        assert_eq!(
+            pacer.delay(rtt, mtu as u64, mtu, 2_000_000, Some(rate), now),
+            None
+        );
+        assert_eq!(pacer.capacity, MIN_BURST_SIZE * mtu as u64);
+        for _ in 0..MIN_BURST_SIZE {
+            pacer.on_transmit(mtu);
+        }
+
+        // Refilling the capacity takes as long as sending it at the requested rate
+        let delay = pacer
+            .delay(rtt, mtu as u64, mtu, 2_000_000, Some(rate), now)
+            .expect("send must be delayed");
+        assert_eq!(delay.duration_since(now), Duration::from_millis(10));
+    }
 }
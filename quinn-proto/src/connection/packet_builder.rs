@@ -8,13 +8,15 @@ use super::{spaces::SentPacket, Connection, SentFrames, State};
 use crate::{
     crypto::{HeaderKey, PacketKey, Session},
     frame::{self, Close},
+    multipath::PathId,
     packet::{Header, LongType, PacketNumber, PartialEncode, SpaceId},
-    TransportError, TransportErrorCode,
+    ConnectionId, TransportError, TransportErrorCode,
 };
 
 pub(super) struct PacketBuilder {
     pub datagram_start: usize,
     pub space: SpaceId,
+    pub path_id: PathId,
     pub partial_encode: PartialEncode,
     pub ack_eliciting: bool,
     pub exact_number: u64,
@@ -33,10 +35,12 @@ impl PacketBuilder {
     pub fn new<S: Session>(
         now: Instant,
         space_id: SpaceId,
+        path_id: PathId,
         buffer: &mut Vec<u8>,
         buffer_capacity: usize,
         datagram_start: usize,
         ack_eliciting: bool,
+        dst_cid: ConnectionId,
         conn: &mut Connection<S>,
         version: u32,
     ) -> Option<PacketBuilder> {
@@ -72,16 +76,18 @@ impl PacketBuilder {
 
         let space = &mut conn.spaces[space_id];
 
-        space.loss_probes = space.loss_probes.saturating_sub(1);
-        let exact_number = space.get_tx_number();
+        let number_space = space.for_path_mut(path_id);
+        number_space.loss_probes = number_space.loss_probes.saturating_sub(1);
+        let largest_acked = number_space.largest_acked_packet.unwrap_or(0);
+        let exact_number = space.get_tx_number(path_id);
 
         let span = trace_span!("send", space = ?space_id, pn = exact_number);
         span.with_subscriber(|(id, dispatch)| dispatch.enter(id));
 
-        let number = PacketNumber::new(exact_number, space.largest_acked_packet.unwrap_or(0));
+        let number = PacketNumber::new(exact_number, largest_acked);
         let header = match space_id {
             SpaceId::Data if space.crypto.is_some() => Header::Short {
-                dst_cid: conn.rem_cids.active(),
+                dst_cid,
                 number,
                 spin: if conn.spin_enabled {
                     conn.spin
@@ -93,20 +99,20 @@ impl PacketBuilder {
             SpaceId::Data => Header::Long {
                 ty: LongType::ZeroRtt,
                 src_cid: conn.handshake_cid,
-                dst_cid: conn.rem_cids.active(),
+                dst_cid,
                 number,
                 version,
             },
             SpaceId::Handshake => Header::Long {
                 ty: LongType::Handshake,
                 src_cid: conn.handshake_cid,
-                dst_cid: conn.rem_cids.active(),
+                dst_cid,
                 number,
                 version,
             },
             SpaceId::Initial => Header::Initial {
                 src_cid: conn.handshake_cid,
-                dst_cid: conn.rem_cids.active(),
+                dst_cid,
                 token: match conn.state {
                     State::Handshake(ref state) => state.token.clone().unwrap_or_else(Bytes::new),
                     _ => Bytes::new(),
@@ -140,6 +146,7 @@ impl PacketBuilder {
         Some(PacketBuilder {
             datagram_start,
             space: space_id,
+            path_id,
             partial_encode,
             exact_number,
             short_header: header.is_short(),
@@ -167,6 +174,7 @@ impl PacketBuilder {
         let ack_eliciting = self.ack_eliciting;
         let exact_number = self.exact_number;
         let space_id = self.space;
+        let path_id = self.path_id;
         let (size, padded) = self.finish(conn, buffer);
         let sent = match sent {
             Some(sent) => sent,
@@ -187,19 +195,46 @@ impl PacketBuilder {
             stream_frames: sent.stream_frames,
         };
 
-        conn.in_flight.insert(&packet);
-        conn.spaces[space_id].sent(exact_number, packet);
+        let path = conn.paths.get_mut(&path_id).unwrap();
+        path.in_flight.insert(&packet);
+        conn.spaces[space_id]
+            .for_path_mut(path_id)
+            .sent(exact_number, packet);
         conn.reset_keep_alive(now);
         if size != 0 {
             if ack_eliciting {
-                conn.spaces[space_id].time_of_last_ack_eliciting_packet = Some(now);
+                conn.spaces[space_id]
+                    .for_path_mut(path_id)
+                    .time_of_last_ack_eliciting_packet = Some(now);
                 if conn.permit_idle_reset {
                     conn.reset_idle_timeout(now);
                 }
                 conn.permit_idle_reset = false;
+                let packet_number = match space_id {
+                    SpaceId::Data => Some(exact_number),
+                    _ => None,
+                };
+                let path = conn.paths.get_mut(&path_id).unwrap();
+                let in_flight = path.in_flight.bytes;
+                let path = &mut path.data;
+                path.congestion
+                    .on_sent(now, size.into(), packet_number, in_flight);
+                if let Some(ref mut resume) = path.resume {
+                    if space_id == SpaceId::Data
+                        && !resume.on_sent(
+                            exact_number,
+                            in_flight,
+                            path.mtud.current_mtu(),
+                            path.validated,
+                            &*path.congestion,
+                        )
+                    {
+                        path.resume = None;
+                    }
+                }
             }
-            conn.set_loss_detection_timer(now);
-            conn.path.pacing.on_transmit(size);
+            conn.set_loss_detection_timer(now, path_id);
+            conn.path_data_mut(path_id).pacing.on_transmit(size);
         }
     }
 
@@ -233,14 +268,18 @@ impl PacketBuilder {
 
         buffer.resize(buffer.len() + packet_crypto.tag_len(), 0);
         debug_assert!(
-            buffer.len() <= self.datagram_start + conn.path.max_udp_payload_size as usize
+            buffer.len()
+                <= self.partial_encode.start
+                    + self.partial_encode.header_len
+                    + self.max_size
+                    + self.tag_len
         );
         let encode_start = self.partial_encode.start;
         let packet_buf = &mut buffer[encode_start..];
         self.partial_encode.finish(
             packet_buf,
             header_crypto,
-            Some((self.exact_number, packet_crypto)),
+            Some((self.path_id.0, self.exact_number, packet_crypto)),
         );
         self.span
             .with_subscriber(|(id, dispatch)| dispatch.exit(id));
@@ -1,7 +1,17 @@
-use std::{cmp, net::SocketAddr, time::Duration, time::Instant};
+use std::{
+    cmp,
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+    time::Instant,
+};
 
-use super::pacing::Pacer;
-use crate::{congestion, INITIAL_MAX_UDP_PAYLOAD_SIZE, TIMER_GRANULARITY};
+use super::{
+    careful_resume::CarefulResume, mtud::MtuDiscovery, pacing::Pacer, timer::TimerTable, InFlight,
+    PathResponse,
+};
+use crate::{
+    config::TransportConfig, congestion, multipath::PathStatus, ConnectionId, TIMER_GRANULARITY,
+};
 
 /// Description of a particular network path
 pub struct PathData {
@@ -24,25 +34,28 @@ pub struct PathData {
     pub total_sent: u64,
     /// Total size of all UDP datagrams received on this path
     pub total_recvd: u64,
-    pub max_udp_payload_size: u16,
+    /// State of the search for this path's maximum UDP payload size
+    pub mtud: MtuDiscovery,
+    /// Careful resumption of a previous connection's congestion window, while in progress
+    pub resume: Option<CarefulResume>,
 }
 
 impl PathData {
     pub fn new(
         remote: SocketAddr,
-        initial_rtt: Duration,
-        congestion: Box<dyn congestion::Controller>,
+        config: &TransportConfig,
         now: Instant,
         validated: bool,
     ) -> Self {
+        let congestion = config.congestion_controller_factory.build(now);
         PathData {
             remote,
-            rtt: RttEstimator::new(initial_rtt),
+            rtt: RttEstimator::new(config.initial_rtt),
             sending_ecn: true,
             pacing: Pacer::new(
-                initial_rtt,
+                config.initial_rtt,
                 congestion.initial_window(),
-                INITIAL_MAX_UDP_PAYLOAD_SIZE,
+                config.min_mtu,
                 now,
             ),
             congestion,
@@ -51,7 +64,8 @@ impl PathData {
             validated,
             total_sent: 0,
             total_recvd: 0,
-            max_udp_payload_size: INITIAL_MAX_UDP_PAYLOAD_SIZE,
+            mtud: MtuDiscovery::new(config.min_mtu, config.mtu_discovery_config.clone()),
+            resume: None,
         }
     }
 
@@ -61,12 +75,7 @@ impl PathData {
         PathData {
             remote,
             rtt: prev.rtt,
-            pacing: Pacer::new(
-                smoothed_rtt,
-                congestion.window(),
-                prev.max_udp_payload_size,
-                now,
-            ),
+            pacing: Pacer::new(smoothed_rtt, congestion.window(), prev.current_mtu(), now),
             sending_ecn: true,
             congestion,
             challenge: None,
@@ -74,7 +83,36 @@ impl PathData {
             validated: false,
             total_sent: 0,
             total_recvd: 0,
-            max_udp_payload_size: prev.max_udp_payload_size,
+            mtud: prev.mtud.clone(),
+            // The saved state describes the old path
+            resume: None,
+        }
+    }
+
+    /// The largest UDP payload size currently usable on this path
+    pub fn current_mtu(&self) -> u16 {
+        self.mtud.current_mtu()
+    }
+
+    /// Number of ack-eliciting bytes that may be in flight
+    ///
+    /// Exceeds the congestion controller's window while careful resumption is using a saved one.
+    pub fn congestion_window(&self) -> u64 {
+        let window = self.congestion.window();
+        match self.resume {
+            Some(ref resume) => cmp::max(window, resume.window()),
+            None => window,
+        }
+    }
+
+    /// Rate at which to pace outgoing packets, if the congestion controller chooses one
+    ///
+    /// While careful resumption's window applies, packets are paced out over the RTT instead, lest
+    /// a rate based on the controller's own estimates hold back the jump.
+    pub fn pacing_rate(&self) -> Option<u64> {
+        match self.resume {
+            Some(ref resume) if resume.window() > self.congestion.window() => None,
+            _ => self.congestion.pacing_rate(),
         }
     }
 
@@ -85,7 +123,94 @@ impl PathData {
     }
 }
 
-#[derive(Copy, Clone)]
+/// A new path being validated before a client moves the connection to it
+///
+/// Traffic continues over the current path until the peer answers the PATH_CHALLENGE.
+pub struct MigrationProbe {
+    /// Local IP address we're migrating to, or `None` when moving to the server's preferred
+    /// address
+    pub local_ip: Option<IpAddr>,
+    /// Address of the peer on the new path
+    pub remote: SocketAddr,
+    /// CID the PATH_CHALLENGE is addressed to
+    pub rem_cid: ConnectionId,
+    /// PATH_CHALLENGE token sent on the new path
+    pub challenge: u64,
+    /// Whether the PATH_CHALLENGE still needs to be sent
+    pub challenge_pending: bool,
+}
+
+/// State of one path of a connection
+///
+/// Connections only have the path they were established on unless the multipath extension was
+/// negotiated, in which case each path has its own congestion control, loss detection and packet
+/// numbers in the Data space.
+pub struct PathState {
+    pub data: PathData,
+    /// The path `data` replaced in a migration, until `data` is validated
+    pub prev: Option<PathData>,
+    /// Local IP address packets on the path are sent from, if known
+    ///
+    /// Only populated for servers, with the address the path's first packet was received on.
+    pub local_ip: Option<IpAddr>,
+    /// Summary statistics of packets that have been sent, but not yet acked or deemed lost
+    pub in_flight: InFlight,
+    /// The number of times a PTO has been sent without receiving an ack.
+    pub pto_count: u32,
+    /// Whether the most recently received packet had an ECN codepoint set
+    pub receiving_ecn: bool,
+    /// Whether the last `poll_transmit` call yielded no data because there was
+    /// no outgoing application data.
+    pub app_limited: bool,
+    /// Queued PATH_RESPONSE for a PATH_CHALLENGE received on the path
+    pub path_response: Option<PathResponse>,
+    /// Timers in `Timer::PATH_VALUES`, which each path has its own of
+    pub timers: TimerTable,
+    /// CID packets on this path are sent to, or `None` to follow the connection's active CID
+    pub rem_cid: Option<ConnectionId>,
+    /// Status we asked the peer to treat the path with
+    pub status: PathStatus,
+    /// Status the peer asked us to treat the path with
+    pub peer_status: PathStatus,
+    /// Sequence number of the PATH_STANDBY or PATH_AVAILABLE frame `peer_status` came from
+    pub peer_status_seq: Option<u64>,
+    /// Whether the peer has proven to be reachable over the path
+    pub validated: bool,
+    /// Whether the path has been abandoned and is only waiting to be discarded
+    pub abandoned: bool,
+}
+
+impl PathState {
+    pub fn new(
+        data: PathData,
+        local_ip: Option<IpAddr>,
+        rem_cid: Option<ConnectionId>,
+        validated: bool,
+    ) -> Self {
+        Self {
+            data,
+            prev: None,
+            local_ip,
+            in_flight: InFlight::new(),
+            pto_count: 0,
+            receiving_ecn: false,
+            app_limited: false,
+            path_response: None,
+            timers: TimerTable::default(),
+            rem_cid,
+            status: PathStatus::Available,
+            peer_status: PathStatus::Available,
+            peer_status_seq: None,
+            validated,
+            abandoned: false,
+        }
+    }
+}
+
+/// Round-trip time estimates for a path, maintained as described in RFC 9002
+///
+/// Until the first RTT sample is taken, the estimates are derived from the configured initial RTT.
+#[derive(Debug, Copy, Clone)]
 pub struct RttEstimator {
     /// The most recent RTT measurement made when receiving an ack for a previously unacked packet
     latest: Duration,
@@ -107,7 +232,12 @@ impl RttEstimator {
         }
     }
 
-    pub fn update(&mut self, ack_delay: Duration, rtt: Duration) {
+    /// Whether an RTT sample has been taken yet
+    pub(crate) fn has_samples(&self) -> bool {
+        self.smoothed.is_some()
+    }
+
+    pub(crate) fn update(&mut self, ack_delay: Duration, rtt: Duration) {
         self.latest = rtt;
         // min_rtt ignores ack delay.
         self.min = cmp::min(self.min, self.latest);
@@ -132,6 +262,7 @@ impl RttEstimator {
         }
     }
 
+    /// Smoothed estimate of RTT
     pub fn get(&self) -> Duration {
         self.smoothed.unwrap_or(self.latest)
     }
@@ -144,7 +275,22 @@ impl RttEstimator {
         self.get().max(self.latest)
     }
 
-    pub fn pto_base(&self) -> Duration {
+    /// Most recent RTT measurement, not adjusted for the peer's ACK delay
+    pub fn latest(&self) -> Duration {
+        self.latest
+    }
+
+    /// Minimum RTT measured, not adjusted for the peer's ACK delay
+    pub fn min(&self) -> Duration {
+        self.min
+    }
+
+    /// Mean deviation of RTT measurements
+    pub fn var(&self) -> Duration {
+        self.var
+    }
+
+    pub(crate) fn pto_base(&self) -> Duration {
         self.get() + cmp::max(4 * self.var, TIMER_GRANULARITY)
     }
 }
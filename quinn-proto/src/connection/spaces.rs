@@ -6,12 +6,13 @@ use std::{
     time::{Duration, Instant},
 };
 
+use bytes::Bytes;
 use fxhash::FxHashSet;
 
 use super::assembler::Assembler;
 use crate::{
-    crypto, crypto::Keys, frame, packet::SpaceId, range_set::ArrayRangeSet, shared::IssuedCid,
-    StreamId, VarInt,
+    crypto, crypto::Keys, frame, multipath::PathId, packet::SpaceId, range_set::ArrayRangeSet,
+    shared::IssuedCid, StreamId, VarInt,
 };
 
 pub(crate) struct PacketSpace<S>
@@ -19,49 +20,22 @@ where
     S: crypto::Session,
 {
     pub(crate) crypto: Option<Keys<S>>,
-    pub(crate) dedup: Dedup,
-    /// Highest received packet number
-    pub(crate) rx_packet: u64,
 
     /// Data to send
     pub(crate) pending: Retransmits,
-    /// Packet numbers to acknowledge
-    pub(crate) pending_acks: PendingAcks,
 
-    /// The packet number of the next packet that will be sent, if any.
-    pub(crate) next_packet_number: u64,
-    /// The largest packet number the remote peer acknowledged in an ACK frame.
-    pub(crate) largest_acked_packet: Option<u64>,
-    pub(crate) largest_acked_packet_sent: Instant,
-    /// Transmitted but not acked
-    // We use a BTreeMap here so we can efficiently query by range on ACK and for loss detection
-    pub(crate) sent_packets: BTreeMap<u64, SentPacket>,
-    /// Number of explicit congestion notification codepoints seen on incoming packets
-    pub(crate) ecn_counters: frame::EcnCounts,
-    /// Recent ECN counters sent by the peer in ACK frames
+    /// Packet number state of each path using this space
     ///
-    /// Updated (and inspected) whenever we receive an ACK with a new highest acked packet
-    /// number. Stored per-space to simplify verification, which would otherwise have difficulty
-    /// distinguishing between ECN bleaching and counts having been updated by a near-simultaneous
-    /// ACK already processed in another space.
-    pub(crate) ecn_feedback: frame::EcnCounts,
+    /// Only the Data space of a multipath connection has more than one, as every path numbers its
+    /// packets independently. The Initial and Handshake spaces are only used by
+    /// `PathId::INITIAL`.
+    pub(crate) number_spaces: BTreeMap<PathId, PacketNumberSpace>,
 
     /// Incoming cryptographic handshake stream
     pub(crate) crypto_stream: Assembler,
     /// Current offset of outgoing cryptographic handshake stream
     pub(crate) crypto_offset: u64,
 
-    /// The time the most recently sent retransmittable packet was sent.
-    pub(crate) time_of_last_ack_eliciting_packet: Option<Instant>,
-    /// The time at which the earliest sent packet in this space will be considered lost based on
-    /// exceeding the reordering window in time. Only set for packets numbered prior to a packet
-    /// that has been acknowledged.
-    pub(crate) loss_time: Option<Instant>,
-    /// Number of tail loss probes to send
-    pub(crate) loss_probes: u32,
-    pub(crate) ping_pending: bool,
-    /// Number of congestion control "in flight" bytes
-    pub(crate) in_flight: u64,
     /// Number of packets sent in the current key phase
     pub(crate) sent_with_keys: u64,
 }
@@ -71,34 +45,36 @@ where
     S: crypto::Session,
 {
     pub(crate) fn new(now: Instant) -> Self {
+        let mut number_spaces = BTreeMap::new();
+        number_spaces.insert(PathId::INITIAL, PacketNumberSpace::new(now));
         Self {
             crypto: None,
-            dedup: Dedup::new(),
-            rx_packet: 0,
 
             pending: Retransmits::default(),
-            pending_acks: PendingAcks::default(),
 
-            next_packet_number: 0,
-            largest_acked_packet: None,
-            largest_acked_packet_sent: now,
-            sent_packets: BTreeMap::new(),
-            ecn_counters: frame::EcnCounts::ZERO,
-            ecn_feedback: frame::EcnCounts::ZERO,
+            number_spaces,
 
             crypto_stream: Assembler::new(),
             crypto_offset: 0,
 
-            time_of_last_ack_eliciting_packet: None,
-            loss_time: None,
-            loss_probes: 0,
-            ping_pending: false,
-            in_flight: 0,
             sent_with_keys: 0,
         }
     }
 
-    /// Queue data for a tail loss probe (or anti-amplification deadlock prevention) packet
+    /// Packet number state of path `id`
+    pub(crate) fn for_path(&self, id: PathId) -> &PacketNumberSpace {
+        &self.number_spaces[&id]
+    }
+
+    /// Packet number state of path `id`
+    pub(crate) fn for_path_mut(&mut self, id: PathId) -> &mut PacketNumberSpace {
+        self.number_spaces
+            .get_mut(&id)
+            .expect("path has no packet number space")
+    }
+
+    /// Queue data for a tail loss probe (or anti-amplification deadlock prevention) packet on path
+    /// `id`
     ///
     /// Probes are sent similarly to normal packets when an expect ACK has not arrived. We never
     /// deem a packet lost until we receive an ACK that should have included it, but if a trailing
@@ -110,10 +86,11 @@ where
     /// waiting to be sent, then we retransmit in-flight data to reduce odds of loss. If there's no
     /// in-flight data either, we're probably a client guarding against a handshake
     /// anti-amplification deadlock and we just make something up.
-    pub(crate) fn maybe_queue_probe(&mut self) {
-        if self.loss_probes == 0 {
-            return;
-        }
+    pub(crate) fn maybe_queue_probe(&mut self, id: PathId) {
+        let number_space = match self.number_spaces.get_mut(&id) {
+            Some(x) if x.loss_probes != 0 => x,
+            _ => return,
+        };
 
         // Retransmit the data of the oldest in-flight packet
         if !self.pending.is_empty() {
@@ -121,7 +98,7 @@ where
             return;
         }
 
-        for packet in self.sent_packets.values_mut() {
+        for packet in number_space.sent_packets.values_mut() {
             if !packet.retransmits.is_empty() {
                 // Remove retransmitted data from the old packet so we don't end up retransmitting
                 // it *again* even if the copy we're sending now gets acknowledged.
@@ -133,31 +110,100 @@ where
         // Nothing new to send and nothing to retransmit, so fall back on a ping. This should only
         // happen in rare cases during the handshake when the server becomes blocked by
         // anti-amplification.
-        self.ping_pending = true;
+        number_space.ping_pending = true;
     }
 
-    pub(crate) fn get_tx_number(&mut self) -> u64 {
+    pub(crate) fn get_tx_number(&mut self, id: PathId) -> u64 {
+        let number_space = self.for_path_mut(id);
         // TODO: Handle packet number overflow gracefully
-        assert!(self.next_packet_number < 2u64.pow(62));
-        let x = self.next_packet_number;
-        self.next_packet_number += 1;
+        assert!(number_space.next_packet_number < 2u64.pow(62));
+        let x = number_space.next_packet_number;
+        number_space.next_packet_number += 1;
         self.sent_with_keys += 1;
         x
     }
 
-    pub(crate) fn can_send(&self) -> SendableFrames {
-        let acks = self.pending_acks.can_send();
-        let other = !self.pending.is_empty() || self.ping_pending;
+    pub(crate) fn can_send(&self, id: PathId) -> SendableFrames {
+        let (acks, ping_pending) = match self.number_spaces.get(&id) {
+            Some(x) => (x.pending_acks.can_send(), x.ping_pending),
+            None => (false, false),
+        };
+        let other = !self.pending.is_empty() || ping_pending;
 
         SendableFrames { acks, other }
     }
+}
+
+/// The state of a packet number space that belongs to a single path
+pub(crate) struct PacketNumberSpace {
+    pub(crate) dedup: Dedup,
+    /// Highest received packet number
+    pub(crate) rx_packet: u64,
+
+    /// Packet numbers to acknowledge
+    pub(crate) pending_acks: PendingAcks,
+
+    /// The packet number of the next packet that will be sent, if any.
+    pub(crate) next_packet_number: u64,
+    /// The largest packet number the remote peer acknowledged in an ACK frame.
+    pub(crate) largest_acked_packet: Option<u64>,
+    pub(crate) largest_acked_packet_sent: Instant,
+    /// Transmitted but not acked
+    // We use a BTreeMap here so we can efficiently query by range on ACK and for loss detection
+    pub(crate) sent_packets: BTreeMap<u64, SentPacket>,
+    /// Number of explicit congestion notification codepoints seen on incoming packets
+    pub(crate) ecn_counters: frame::EcnCounts,
+    /// Recent ECN counters sent by the peer in ACK frames
+    ///
+    /// Updated (and inspected) whenever we receive an ACK with a new highest acked packet
+    /// number. Stored per-space to simplify verification, which would otherwise have difficulty
+    /// distinguishing between ECN bleaching and counts having been updated by a near-simultaneous
+    /// ACK already processed in another space.
+    pub(crate) ecn_feedback: frame::EcnCounts,
+
+    /// The time the most recently sent retransmittable packet was sent.
+    pub(crate) time_of_last_ack_eliciting_packet: Option<Instant>,
+    /// The time at which the earliest sent packet in this space will be considered lost based on
+    /// exceeding the reordering window in time. Only set for packets numbered prior to a packet
+    /// that has been acknowledged.
+    pub(crate) loss_time: Option<Instant>,
+    /// Number of tail loss probes to send
+    pub(crate) loss_probes: u32,
+    pub(crate) ping_pending: bool,
+    /// Number of congestion control "in flight" bytes
+    pub(crate) in_flight: u64,
+}
+
+impl PacketNumberSpace {
+    pub(crate) fn new(now: Instant) -> Self {
+        Self {
+            dedup: Dedup::new(),
+            rx_packet: 0,
+
+            pending_acks: PendingAcks::default(),
+
+            next_packet_number: 0,
+            largest_acked_packet: None,
+            largest_acked_packet_sent: now,
+            sent_packets: BTreeMap::new(),
+            ecn_counters: frame::EcnCounts::ZERO,
+            ecn_feedback: frame::EcnCounts::ZERO,
+
+            time_of_last_ack_eliciting_packet: None,
+            loss_time: None,
+            loss_probes: 0,
+            ping_pending: false,
+            in_flight: 0,
+        }
+    }
 
-    /// Verifies sanity of an ECN block and returns whether congestion was encountered.
+    /// Verifies sanity of an ECN block and returns the number of packets newly marked as having
+    /// encountered congestion.
     pub(crate) fn detect_ecn(
         &mut self,
         newly_acked: u64,
         ecn: frame::EcnCounts,
-    ) -> Result<bool, &'static str> {
+    ) -> Result<u64, &'static str> {
         let ect0_increase = ecn
             .ect0
             .checked_sub(self.ecn_feedback.ect0)
@@ -182,7 +228,7 @@ where
         // to count CE packets as CE or ECT0. Recording them as CE is more consistent and keeps the
         // congestion check obvious.
         self.ecn_feedback = ecn;
-        Ok(ce_increase != 0)
+        Ok(ce_increase)
     }
 
     pub(crate) fn sent(&mut self, number: u64, packet: SentPacket) {
@@ -239,6 +285,9 @@ pub struct Retransmits {
     pub(crate) new_cids: Vec<IssuedCid>,
     pub(crate) retire_cids: Vec<u64>,
     pub(crate) handshake_done: bool,
+    pub(crate) new_tokens: Vec<Bytes>,
+    pub(crate) path_abandon: Vec<frame::PathAbandon>,
+    pub(crate) path_status: Vec<frame::PathStatus>,
 }
 
 impl Retransmits {
@@ -253,6 +302,9 @@ impl Retransmits {
             && self.new_cids.is_empty()
             && self.retire_cids.is_empty()
             && !self.handshake_done
+            && self.new_tokens.is_empty()
+            && self.path_abandon.is_empty()
+            && self.path_status.is_empty()
     }
 }
 
@@ -269,6 +321,9 @@ impl Default for Retransmits {
             new_cids: Vec::new(),
             retire_cids: Vec::new(),
             handshake_done: false,
+            new_tokens: Vec::new(),
+            path_abandon: Vec::new(),
+            path_status: Vec::new(),
         }
     }
 }
@@ -289,6 +344,9 @@ impl ::std::ops::BitOrAssign for Retransmits {
         self.new_cids.extend(&rhs.new_cids);
         self.retire_cids.extend(rhs.retire_cids);
         self.handshake_done |= rhs.handshake_done;
+        self.new_tokens.extend(rhs.new_tokens);
+        self.path_abandon.extend(rhs.path_abandon);
+        self.path_status.extend(rhs.path_status);
     }
 }
 
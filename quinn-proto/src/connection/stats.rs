@@ -34,8 +34,10 @@ pub struct FrameStats {
     pub max_streams_uni: u64,
     pub new_connection_id: u64,
     pub new_token: u64,
+    pub path_abandon: u64,
     pub path_challenge: u64,
     pub path_response: u64,
+    pub path_status: u64,
     pub ping: u64,
     pub reset_stream: u64,
     pub retire_connection_id: u64,
@@ -82,6 +84,9 @@ impl FrameStats {
             Frame::PathResponse(_) => self.path_response += 1,
             Frame::Close(_) => self.connection_close += 1,
             Frame::HandshakeDone => self.handshake_done += 1,
+            Frame::AckMp { .. } => self.acks += 1,
+            Frame::PathAbandon(_) => self.path_abandon += 1,
+            Frame::PathStatus(_) => self.path_status += 1,
             Frame::Invalid { .. } => {}
         }
     }
@@ -102,8 +107,10 @@ impl std::fmt::Debug for FrameStats {
             .field("MAX_STREAMS_UNI", &self.max_streams_uni)
             .field("NEW_CONNECTION_ID", &self.new_connection_id)
             .field("NEW_TOKEN", &self.new_token)
+            .field("PATH_ABANDON", &self.path_abandon)
             .field("PATH_CHALLENGE", &self.path_challenge)
             .field("PATH_RESPONSE", &self.path_response)
+            .field("PATH_STATUS", &self.path_status)
             .field("PING", &self.ping)
             .field("RESET_STREAM", &self.reset_stream)
             .field("RETIRE_CONNECTION_ID", &self.retire_connection_id)
@@ -126,6 +133,14 @@ pub struct PathStats {
     pub cwnd: u64,
     /// Congestion events on the connection
     pub congestion_events: u64,
+    /// Current maximum UDP payload size of the path
+    pub current_mtu: u16,
+    /// MTU discovery probes sent on the connection
+    pub sent_plpmtud_probes: u64,
+    /// MTU discovery probes lost on the connection
+    pub lost_plpmtud_probes: u64,
+    /// Number of times the MTU fell back to the minimum after packets stopped getting through
+    pub black_holes_detected: u64,
 }
 
 /// Connection statistics
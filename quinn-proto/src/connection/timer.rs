@@ -31,6 +31,10 @@ impl Timer {
         Timer::Pacing,
         Timer::PushNewCid,
     ];
+
+    /// Timers tracking a single path, which are kept in the path's own `TimerTable`
+    pub(crate) const PATH_VALUES: [Self; 3] =
+        [Timer::LossDetection, Timer::PathValidation, Timer::Pacing];
 }
 
 /// A table of data associated with each distinct kind of `Timer`
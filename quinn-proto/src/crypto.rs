@@ -47,8 +47,24 @@ pub trait Session: Send + Sized {
     /// Type used to hold configuration for server sessions
     type ServerConfig: ServerConfig<Self>;
 
-    /// Create the initial set of keys given the client's initial destination ConnectionId
-    fn initial_keys(dst_cid: &ConnectionId, side: Side) -> Keys<Self>;
+    /// Whether keys can be derived for QUIC `version`
+    ///
+    /// `EndpointConfig::supported_versions` refuses versions for which this returns `false`. The
+    /// default implementation supports every version.
+    fn is_supported_version(_version: u32) -> bool {
+        true
+    }
+
+    /// Create the initial set of keys given the QUIC version and the client's initial
+    /// destination ConnectionId
+    fn initial_keys(version: u32, dst_cid: &ConnectionId, side: Side) -> Keys<Self>;
+
+    /// Derive Handshake and 1-RTT keys for QUIC `version`
+    ///
+    /// Called when compatible version negotiation settles on a version other than the one the
+    /// session was started for, before any Handshake keys are derived. 0-RTT keys remain those of
+    /// the original version. Sessions whose keys don't depend on the version can ignore this.
+    fn set_version(&mut self, _version: u32) {}
 
     /// Get data negotiated during the handshake, if available
     ///
@@ -70,6 +86,21 @@ pub trait Session: Send + Sized {
     /// If the 0-RTT-encrypted data has been accepted by the peer
     fn early_data_accepted(&self) -> Option<bool>;
 
+    /// Refuse any 0-RTT data the client attempts to send (servers only)
+    ///
+    /// Must be called before any handshake data is read. The handshake then proceeds as if the
+    /// server didn't support 0-RTT for the client's session. The default implementation does
+    /// nothing, for sessions that never accept 0-RTT.
+    fn reject_0rtt(&mut self) {}
+
+    /// Set application data to associate with the session tickets issued to the client (servers
+    /// only)
+    ///
+    /// Must be called before the handshake completes to affect every ticket. The data must not
+    /// be revealed to or altered by the client. The default implementation discards it, in which
+    /// case `ServerConfig::resumption_data` never finds any.
+    fn set_resumption_data(&mut self, _data: &[u8]) {}
+
     /// Returns `true` until the connection is fully established.
     fn is_handshaking(&self) -> bool;
 
@@ -96,11 +127,16 @@ pub trait Session: Send + Sized {
     /// Compute keys for the next key update
     fn next_1rtt_keys(&mut self) -> Option<KeyPair<Self::PacketKey>>;
 
-    /// Generate the integrity tag for a retry packet
-    fn retry_tag(orig_dst_cid: &ConnectionId, packet: &[u8]) -> [u8; 16];
+    /// Generate the integrity tag for a retry packet of the given QUIC version
+    fn retry_tag(version: u32, orig_dst_cid: &ConnectionId, packet: &[u8]) -> [u8; 16];
 
-    /// Verify the integrity of a retry packet
-    fn is_valid_retry(orig_dst_cid: &ConnectionId, header: &[u8], payload: &[u8]) -> bool;
+    /// Verify the integrity of a retry packet of the given QUIC version
+    fn is_valid_retry(
+        version: u32,
+        orig_dst_cid: &ConnectionId,
+        header: &[u8],
+        payload: &[u8],
+    ) -> bool;
 
     /// Fill `output` with `output.len()` bytes of keying material derived
     /// from the [Session]'s secrets, using `label` and `context` for domain
@@ -136,7 +172,7 @@ where
 }
 
 /// Client-side configuration for the crypto protocol
-pub trait ClientConfig<S>: Clone
+pub trait ClientConfig<S>: Clone + Send + Sync
 where
     S: Session,
 {
@@ -145,9 +181,10 @@ where
     where
         Self: Sized;
 
-    /// Start a client session with this configuration
+    /// Start a client session with this configuration, for a connection using QUIC `version`
     fn start_session(
         &self,
+        version: u32,
         server_name: &str,
         params: &TransportParameters,
     ) -> Result<S, ConnectError>;
@@ -163,8 +200,17 @@ where
     where
         Self: Sized;
 
-    /// Start a server session with this configuration
-    fn start_session(&self, params: &TransportParameters) -> S;
+    /// Start a server session with this configuration, for a connection using QUIC `version`
+    fn start_session(&self, version: u32, params: &TransportParameters) -> Result<S, ConnectError>;
+
+    /// Application data embedded in the session ticket a client offered as `identity`
+    ///
+    /// Returns `None` if the ticket can't be resumed, e.g. because it wasn't issued by this
+    /// server. Must not prevent the ticket from being used afterwards. The default implementation
+    /// always returns `None`.
+    fn resumption_data(&self, _identity: &[u8]) -> Option<Vec<u8>> {
+        None
+    }
 }
 
 /// Keys used to protect packet payloads
@@ -178,6 +224,36 @@ pub trait PacketKey: Send {
         header: &[u8],
         payload: &mut BytesMut,
     ) -> Result<(), CryptoError>;
+    /// Whether `encrypt_path` and `decrypt_path` support paths other than path 0
+    ///
+    /// Multipath is only negotiated with keys that do.
+    const MULTIPATH: bool = false;
+    /// Encrypt the payload of a packet sent on the given path of a multipath connection
+    ///
+    /// Every path of a multipath connection numbers its packets independently, so the path ID
+    /// forms part of the nonce alongside the packet number. Path 0 uses the same nonce as
+    /// `encrypt`. The default implementation only supports path 0, which is the only path used
+    /// unless `MULTIPATH` is set.
+    fn encrypt_path(&self, path_id: u32, packet: u64, buf: &mut [u8], header_len: usize) {
+        debug_assert_eq!(path_id, 0, "packet key does not support multipath");
+        self.encrypt(packet, buf, header_len);
+    }
+    /// Decrypt the payload of a packet received on the given path of a multipath connection
+    ///
+    /// The counterpart of `encrypt_path`. The default implementation fails to decrypt packets on
+    /// any path but path 0.
+    fn decrypt_path(
+        &self,
+        path_id: u32,
+        packet: u64,
+        header: &[u8],
+        payload: &mut BytesMut,
+    ) -> Result<(), CryptoError> {
+        if path_id != 0 {
+            return Err(CryptoError);
+        }
+        self.decrypt(packet, header, payload)
+    }
     /// The length of the AEAD tag appended to packets on encryption
     fn tag_len(&self) -> usize;
     /// Maximum number of packets that may be sent using a single key
@@ -1,45 +1,40 @@
 use std::{
-    io,
-    ops::{Deref, DerefMut},
-    str,
-    sync::Arc,
+    convert::TryInto,
+    fmt, io, str,
+    sync::{Arc, Mutex},
 };
 
 use bytes::BytesMut;
-use ring::{aead, aead::quic::HeaderProtectionKey, hkdf, hmac};
-pub use rustls::TLSError;
+use ring::{aead, hkdf, hmac};
+pub use rustls::Error;
 use rustls::{
     self,
-    quic::{ClientQuicExt, PacketKey, ServerQuicExt},
-    Session,
+    quic::{ClientQuicExt, HeaderProtectionKey, KeyChange, PacketKey, QuicExt, ServerQuicExt},
 };
-use webpki::DNSNameRef;
 
 use crate::{
     crypto::{self, CryptoError, ExportKeyingMaterialError, KeyPair, Keys},
     transport_parameters::TransportParameters,
-    CertificateChain, ConnectError, ConnectionId, Side, TransportError, TransportErrorCode,
+    CertificateChain, ConnectError, ConnectionId, SessionStore, Side, TransportError,
+    TransportErrorCode, LEGACY_DRAFT_VERSIONS,
 };
 
 /// A rustls TLS session
 #[derive(Debug)]
 pub struct TlsSession {
-    using_alpn: bool,
     got_handshake_data: bool,
-    inner: SessionKind,
-}
-
-#[derive(Debug)]
-enum SessionKind {
-    Client(rustls::ClientSession),
-    Server(rustls::ServerSession),
+    /// Secrets from which the next 1-RTT keys are derived, once the handshake is complete
+    next_secrets: Option<rustls::quic::Secrets>,
+    /// Data to store alongside the sessions a server issues tickets for
+    resumption_data: Option<Arc<Mutex<Vec<u8>>>>,
+    inner: rustls::Connection,
 }
 
 impl TlsSession {
     fn side(&self) -> Side {
         match self.inner {
-            SessionKind::Client(_) => Side::Client,
-            SessionKind::Server(_) => Side::Server,
+            rustls::Connection::Client(_) => Side::Client,
+            rustls::Connection::Server(_) => Side::Server,
         }
     }
 }
@@ -54,24 +49,14 @@ impl crypto::Session for TlsSession {
     type HeaderKey = HeaderProtectionKey;
     type ServerConfig = Arc<rustls::ServerConfig>;
 
-    fn initial_keys(dst_cid: &ConnectionId, side: Side) -> Keys<Self> {
-        const INITIAL_SALT: [u8; 20] = [
-            0xaf, 0xbf, 0xec, 0x28, 0x99, 0x93, 0xd2, 0x4c, 0x9e, 0x97, 0x86, 0xf1, 0x9c, 0x61,
-            0x11, 0xe0, 0x43, 0x90, 0xa8, 0x99,
-        ];
-
-        let salt = ring::hkdf::Salt::new(ring::hkdf::HKDF_SHA256, &INITIAL_SALT);
-        let keys = rustls::quic::Keys::initial(&salt, dst_cid, side.is_client());
-        Keys {
-            header: KeyPair {
-                local: keys.local.header,
-                remote: keys.remote.header,
-            },
-            packet: KeyPair {
-                local: keys.local.packet,
-                remote: keys.remote.packet,
-            },
-        }
+    /// rustls only derives keys with the labels of QUIC v1, which the drafts share
+    fn is_supported_version(version: u32) -> bool {
+        version == 1 || LEGACY_DRAFT_VERSIONS.contains(&version)
+    }
+
+    fn initial_keys(version: u32, dst_cid: &ConnectionId, side: Side) -> Keys<Self> {
+        let keys = rustls::quic::Keys::initial(tls_version(version), dst_cid, side.is_client());
+        to_keys(keys)
     }
 
     fn handshake_data(&self) -> Option<HandshakeData> {
@@ -79,40 +64,49 @@ impl crypto::Session for TlsSession {
             return None;
         }
         Some(HandshakeData {
-            protocol: self.get_alpn_protocol().map(|x| x.into()),
+            protocol: self.inner.alpn_protocol().map(|x| x.into()),
             server_name: match self.inner {
-                SessionKind::Client(_) => None,
-                SessionKind::Server(ref session) => session.get_sni_hostname().map(|x| x.into()),
+                rustls::Connection::Client(_) => None,
+                rustls::Connection::Server(ref session) => session.sni_hostname().map(|x| x.into()),
             },
         })
     }
 
     fn peer_identity(&self) -> Option<CertificateChain> {
-        self.get_peer_certificates().map(|v| v.into())
+        self.inner.peer_certificates().map(|v| v.to_vec().into())
     }
 
     fn early_crypto(&self) -> Option<(Self::HeaderKey, Self::PacketKey)> {
-        let keys = self.get_0rtt_keys()?;
+        let keys = self.inner.zero_rtt_keys()?;
         Some((keys.header, keys.packet))
     }
 
     fn early_data_accepted(&self) -> Option<bool> {
         match self.inner {
-            SessionKind::Client(ref session) => Some(session.is_early_data_accepted()),
+            rustls::Connection::Client(ref session) => Some(session.is_early_data_accepted()),
             _ => None,
         }
     }
 
-    fn is_handshaking(&self) -> bool {
-        match self.inner {
-            SessionKind::Client(ref session) => session.is_handshaking(),
-            SessionKind::Server(ref session) => session.is_handshaking(),
+    fn reject_0rtt(&mut self) {
+        if let rustls::Connection::Server(ref mut session) = self.inner {
+            session.reject_early_data();
+        }
+    }
+
+    fn set_resumption_data(&mut self, data: &[u8]) {
+        if let Some(ref resumption_data) = self.resumption_data {
+            *resumption_data.lock().unwrap() = data.to_vec();
         }
     }
 
+    fn is_handshaking(&self) -> bool {
+        self.inner.is_handshaking()
+    }
+
     fn read_handshake(&mut self, buf: &[u8]) -> Result<bool, TransportError> {
-        self.read_hs(buf).map_err(|e| {
-            if let Some(alert) = self.get_alert() {
+        self.inner.read_hs(buf).map_err(|e| {
+            if let Some(alert) = self.inner.alert() {
                 TransportError {
                     code: TransportErrorCode::crypto(alert.get_u8()),
                     frame: None,
@@ -127,19 +121,11 @@ impl crypto::Session for TlsSession {
             // ready on incoming connections, or ALPN negotiation completing on outgoing
             // connections.
             let have_server_name = match self.inner {
-                SessionKind::Client(_) => false,
-                SessionKind::Server(ref session) => session.get_sni_hostname().is_some(),
+                rustls::Connection::Client(_) => false,
+                rustls::Connection::Server(ref session) => session.sni_hostname().is_some(),
             };
-            if self.get_alpn_protocol().is_some() || have_server_name || !self.is_handshaking() {
+            if self.inner.alpn_protocol().is_some() || have_server_name || !self.is_handshaking() {
                 self.got_handshake_data = true;
-                if self.using_alpn && self.get_alpn_protocol().is_none() {
-                    // rustls ignores total ALPN failure for compat, but QUIC gets a fresh start
-                    return Err(TransportError {
-                        code: TransportErrorCode::crypto(0x78),
-                        frame: None,
-                        reason: "ALPN negotiation failed".into(),
-                    });
-                }
                 return Ok(true);
             }
         }
@@ -147,7 +133,7 @@ impl crypto::Session for TlsSession {
     }
 
     fn transport_parameters(&self) -> Result<Option<TransportParameters>, TransportError> {
-        match self.get_quic_transport_parameters() {
+        match self.inner.quic_transport_parameters() {
             None => Ok(None),
             Some(buf) => match TransportParameters::read(self.side(), &mut io::Cursor::new(buf)) {
                 Ok(params) => Ok(Some(params)),
@@ -157,37 +143,32 @@ impl crypto::Session for TlsSession {
     }
 
     fn write_handshake(&mut self, buf: &mut Vec<u8>) -> Option<Keys<Self>> {
-        let keys = self.write_hs(buf)?;
-        Some(Keys {
-            header: KeyPair {
-                local: keys.local.header,
-                remote: keys.remote.header,
-            },
-            packet: KeyPair {
-                local: keys.local.packet,
-                remote: keys.remote.packet,
-            },
-        })
+        let keys = match self.inner.write_hs(buf)? {
+            KeyChange::Handshake { keys } => keys,
+            KeyChange::OneRtt { keys, next } => {
+                self.next_secrets = Some(next);
+                keys
+            }
+        };
+        Some(to_keys(keys))
     }
 
     fn next_1rtt_keys(&mut self) -> Option<KeyPair<Self::PacketKey>> {
-        let keys = (**self).next_1rtt_keys();
+        let secrets = self.next_secrets.as_mut()?;
+        let keys = secrets.next_packet_keys();
         Some(KeyPair {
             local: keys.local,
             remote: keys.remote,
         })
     }
 
-    fn retry_tag(orig_dst_cid: &ConnectionId, packet: &[u8]) -> [u8; 16] {
+    fn retry_tag(version: u32, orig_dst_cid: &ConnectionId, packet: &[u8]) -> [u8; 16] {
         let mut pseudo_packet = Vec::with_capacity(packet.len() + orig_dst_cid.len() + 1);
         pseudo_packet.push(orig_dst_cid.len() as u8);
         pseudo_packet.extend_from_slice(orig_dst_cid);
         pseudo_packet.extend_from_slice(packet);
 
-        let nonce = aead::Nonce::assume_unique_for_key(RETRY_INTEGRITY_NONCE);
-        let key = aead::LessSafeKey::new(
-            aead::UnboundKey::new(&aead::AES_128_GCM, &RETRY_INTEGRITY_KEY).unwrap(),
-        );
+        let (key, nonce) = retry_integrity(version);
 
         let tag = key
             .seal_in_place_separate_tag(nonce, aead::Aad::from(pseudo_packet), &mut [])
@@ -197,7 +178,12 @@ impl crypto::Session for TlsSession {
         result
     }
 
-    fn is_valid_retry(orig_dst_cid: &ConnectionId, header: &[u8], payload: &[u8]) -> bool {
+    fn is_valid_retry(
+        version: u32,
+        orig_dst_cid: &ConnectionId,
+        header: &[u8],
+        payload: &[u8],
+    ) -> bool {
         let tag_start = match payload.len().checked_sub(16) {
             Some(x) => x,
             None => return false,
@@ -211,10 +197,7 @@ impl crypto::Session for TlsSession {
         let tag_start = tag_start + pseudo_packet.len();
         pseudo_packet.extend_from_slice(payload);
 
-        let nonce = aead::Nonce::assume_unique_for_key(RETRY_INTEGRITY_NONCE);
-        let key = aead::LessSafeKey::new(
-            aead::UnboundKey::new(&aead::AES_128_GCM, &RETRY_INTEGRITY_KEY).unwrap(),
-        );
+        let (key, nonce) = retry_integrity(version);
 
         let (aad, tag) = pseudo_packet.split_at_mut(tag_start);
         key.open_in_place(nonce, aead::Aad::from(aad), tag).is_ok()
@@ -226,39 +209,37 @@ impl crypto::Session for TlsSession {
         label: &[u8],
         context: &[u8],
     ) -> Result<(), ExportKeyingMaterialError> {
-        let session: &dyn rustls::Session = match &self.inner {
-            SessionKind::Client(s) => s,
-            SessionKind::Server(s) => s,
-        };
-        session
+        self.inner
             .export_keying_material(output, label, Some(context))
             .map_err(|_| ExportKeyingMaterialError)
     }
 }
 
-impl Deref for TlsSession {
-    type Target = dyn rustls::Session;
-    fn deref(&self) -> &Self::Target {
-        match self.inner {
-            SessionKind::Client(ref session) => session,
-            SessionKind::Server(ref session) => session,
-        }
-    }
+/// The key and nonce used to protect Retry packets of the given version (RFC 9001 §5.8)
+fn retry_integrity(version: u32) -> (aead::LessSafeKey, aead::Nonce) {
+    let (key, nonce) = match version {
+        0xff00_001d..=0xff00_0020 => (
+            &RETRY_INTEGRITY_KEY_DRAFT_29,
+            RETRY_INTEGRITY_NONCE_DRAFT_29,
+        ),
+        _ => (&RETRY_INTEGRITY_KEY_V1, RETRY_INTEGRITY_NONCE_V1),
+    };
+    (
+        aead::LessSafeKey::new(aead::UnboundKey::new(&aead::AES_128_GCM, key).unwrap()),
+        aead::Nonce::assume_unique_for_key(nonce),
+    )
 }
 
-impl DerefMut for TlsSession {
-    fn deref_mut(&mut self) -> &mut (dyn rustls::Session + 'static) {
-        match self.inner {
-            SessionKind::Client(ref mut session) => session,
-            SessionKind::Server(ref mut session) => session,
-        }
-    }
-}
-
-const RETRY_INTEGRITY_KEY: [u8; 16] = [
+const RETRY_INTEGRITY_KEY_V1: [u8; 16] = [
+    0xbe, 0x0c, 0x69, 0x0b, 0x9f, 0x66, 0x57, 0x5a, 0x1d, 0x76, 0x6b, 0x54, 0xe3, 0x68, 0xc8, 0x4e,
+];
+const RETRY_INTEGRITY_NONCE_V1: [u8; 12] = [
+    0x46, 0x15, 0x99, 0xd3, 0x5d, 0x63, 0x2b, 0xf2, 0x23, 0x98, 0x25, 0xbb,
+];
+const RETRY_INTEGRITY_KEY_DRAFT_29: [u8; 16] = [
     0xcc, 0xce, 0x18, 0x7e, 0xd0, 0x9a, 0x09, 0xd0, 0x57, 0x28, 0x15, 0x5a, 0x6c, 0xb9, 0x6b, 0xe1,
 ];
-const RETRY_INTEGRITY_NONCE: [u8; 12] = [
+const RETRY_INTEGRITY_NONCE_DRAFT_29: [u8; 12] = [
     0xe5, 0x49, 0x30, 0xf9, 0x7f, 0x21, 0x36, 0xf0, 0x53, 0x0a, 0x8c, 0x1c,
 ];
 
@@ -274,67 +255,203 @@ pub struct HandshakeData {
     pub server_name: Option<String>,
 }
 
+/// Adapts a `SessionStore` to the interface rustls expects
+pub(crate) struct SessionStoreAdapter(pub(crate) Arc<dyn SessionStore>);
+
+impl rustls::client::StoresClientSessions for SessionStoreAdapter {
+    fn put(&self, key: Vec<u8>, value: Vec<u8>) -> bool {
+        self.0.insert(&key, value);
+        true
+    }
+
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.0.get(key)
+    }
+}
+
 impl crypto::ClientConfig<TlsSession> for Arc<rustls::ClientConfig> {
     fn new() -> Self {
-        let mut cfg = rustls::ClientConfig::with_ciphersuites(&QUIC_CIPHER_SUITES);
-        cfg.versions = vec![rustls::ProtocolVersion::TLSv1_3];
+        let mut cfg = rustls::ClientConfig::builder()
+            .with_cipher_suites(&QUIC_CIPHER_SUITES)
+            .with_safe_default_kx_groups()
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .unwrap()
+            .with_custom_certificate_verifier(verifier(default_roots()))
+            .with_no_client_auth();
         cfg.enable_early_data = true;
-        #[cfg(feature = "native-certs")]
-        match rustls_native_certs::load_native_certs() {
-            Ok(x) => {
-                cfg.root_store = x;
-            }
-            Err((Some(x), e)) => {
-                cfg.root_store = x;
-                tracing::warn!("couldn't load some default trust roots: {}", e);
-            }
-            Err((None, e)) => {
-                tracing::warn!("couldn't load any default trust roots: {}", e);
-            }
-        }
-        #[cfg(feature = "certificate-transparency")]
-        {
-            cfg.ct_logs = Some(&ct_logs::LOGS);
-        }
         Arc::new(cfg)
     }
 
     fn start_session(
         &self,
+        version: u32,
         server_name: &str,
         params: &TransportParameters,
     ) -> Result<TlsSession, ConnectError> {
-        let pki_server_name = DNSNameRef::try_from_ascii_str(server_name)
+        let server_name = server_name
+            .try_into()
             .map_err(|_| ConnectError::InvalidDnsName(server_name.into()))?;
+        let inner = rustls::ClientConnection::new_quic(
+            self.clone(),
+            tls_version(version),
+            server_name,
+            to_vec(params),
+        )
+        .map_err(|e| ConnectError::InvalidCryptoConfig(e.to_string()))?;
         Ok(TlsSession {
-            using_alpn: !self.alpn_protocols.is_empty(),
             got_handshake_data: false,
-            inner: SessionKind::Client(rustls::ClientSession::new_quic(
-                self,
-                pki_server_name,
-                to_vec(params),
-            )),
+            next_secrets: None,
+            resumption_data: None,
+            inner: rustls::Connection::Client(inner),
         })
     }
 }
 
 impl crypto::ServerConfig<TlsSession> for Arc<rustls::ServerConfig> {
     fn new() -> Self {
-        let mut cfg = rustls::ServerConfig::with_ciphersuites(
-            rustls::NoClientAuth::new(),
-            &QUIC_CIPHER_SUITES,
-        );
-        cfg.versions = vec![rustls::ProtocolVersion::TLSv1_3];
+        let mut cfg = rustls::ServerConfig::builder()
+            .with_cipher_suites(&QUIC_CIPHER_SUITES)
+            .with_safe_default_kx_groups()
+            .with_protocol_versions(&[&rustls::version::TLS13])
+            .unwrap()
+            .with_no_client_auth()
+            .with_cert_resolver(Arc::new(rustls::server::ResolvesServerCertUsingSni::new()));
         cfg.max_early_data_size = u32::max_value();
         Arc::new(cfg)
     }
 
-    fn start_session(&self, params: &TransportParameters) -> TlsSession {
-        TlsSession {
-            using_alpn: !self.alpn_protocols.is_empty(),
+    fn start_session(
+        &self,
+        version: u32,
+        params: &TransportParameters,
+    ) -> Result<TlsSession, ConnectError> {
+        // Resumption data is only ever looked up in stored sessions that may carry 0-RTT data, so
+        // the configuration only needs to be copied to keep it for those
+        let (config, resumption_data) = if self.max_early_data_size == 0 || self.ticketer.enabled()
+        {
+            (self.clone(), None)
+        } else {
+            let resumption_data = Arc::new(Mutex::new(Vec::new()));
+            let mut config = (**self).clone();
+            config.session_storage = Arc::new(ResumptionDataStore {
+                inner: self.session_storage.clone(),
+                data: resumption_data.clone(),
+            });
+            (Arc::new(config), Some(resumption_data))
+        };
+        let inner =
+            rustls::ServerConnection::new_quic(config, tls_version(version), to_vec(params))
+                .map_err(|e| ConnectError::InvalidCryptoConfig(e.to_string()))?;
+        Ok(TlsSession {
             got_handshake_data: false,
-            inner: SessionKind::Server(rustls::ServerSession::new_quic(self, to_vec(params))),
+            next_secrets: None,
+            resumption_data,
+            inner: rustls::Connection::Server(inner),
+        })
+    }
+
+    fn resumption_data(&self, identity: &[u8]) -> Option<Vec<u8>> {
+        // rustls only accepts 0-RTT for sessions it stored itself, never for stateless tickets.
+        // The session is only peeked at so that the connection can still claim it.
+        if self.max_early_data_size == 0 || self.ticketer.enabled() {
+            return None;
         }
+        let (_, data) = split_resumption_data(self.session_storage.get(identity)?)?;
+        Some(data)
+    }
+}
+
+/// Wraps a server's session storage to keep the data set with `set_resumption_data` after each
+/// session that rustls stores
+///
+/// This lets `resumption_data` find the data before the handshake starts without having to
+/// decode rustls' representation of the session.
+struct ResumptionDataStore {
+    inner: Arc<dyn rustls::server::StoresServerSessions>,
+    data: Arc<Mutex<Vec<u8>>>,
+}
+
+impl rustls::server::StoresServerSessions for ResumptionDataStore {
+    fn put(&self, key: Vec<u8>, mut value: Vec<u8>) -> bool {
+        let data = self.data.lock().unwrap();
+        value.extend_from_slice(&data);
+        value.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        self.inner.put(key, value)
+    }
+
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        Some(split_resumption_data(self.inner.get(key)?)?.0)
+    }
+
+    fn take(&self, key: &[u8]) -> Option<Vec<u8>> {
+        Some(split_resumption_data(self.inner.take(key)?)?.0)
+    }
+
+    fn can_cache(&self) -> bool {
+        self.inner.can_cache()
+    }
+}
+
+impl fmt::Debug for ResumptionDataStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ResumptionDataStore").finish()
+    }
+}
+
+/// Split a value stored by `ResumptionDataStore` into the session and the resumption data
+fn split_resumption_data(mut value: Vec<u8>) -> Option<(Vec<u8>, Vec<u8>)> {
+    let len_start = value.len().checked_sub(4)?;
+    let len = u32::from_be_bytes(value[len_start..].try_into().unwrap()) as usize;
+    let data_start = len_start.checked_sub(len)?;
+    let data = value[data_start..len_start].to_vec();
+    value.truncate(data_start);
+    Some((value, data))
+}
+
+/// The trust anchors of a default client configuration
+pub(crate) fn default_roots() -> rustls::RootCertStore {
+    #[allow(unused_mut)]
+    let mut roots = rustls::RootCertStore::empty();
+    #[cfg(feature = "native-certs")]
+    match rustls_native_certs::load_native_certs() {
+        Ok(certs) => {
+            let certs = certs.into_iter().map(|x| x.0).collect::<Vec<_>>();
+            let (_, ignored) = roots.add_parsable_certificates(&certs);
+            if ignored > 0 {
+                tracing::warn!("couldn't load {} default trust roots", ignored);
+            }
+        }
+        Err(e) => {
+            tracing::warn!("couldn't load any default trust roots: {}", e);
+        }
+    }
+    roots
+}
+
+/// Verifies server certificates against `roots`
+pub(crate) fn verifier(
+    roots: rustls::RootCertStore,
+) -> Arc<dyn rustls::client::ServerCertVerifier> {
+    Arc::new(rustls::client::WebPkiVerifier::new(roots, None))
+}
+
+/// Presents the same certificate chain to every client
+pub(crate) struct SingleCertResolver(pub(crate) Arc<rustls::sign::CertifiedKey>);
+
+impl rustls::server::ResolvesServerCert for SingleCertResolver {
+    fn resolve(&self, _: rustls::server::ClientHello) -> Option<Arc<rustls::sign::CertifiedKey>> {
+        Some(self.0.clone())
+    }
+}
+
+/// The version of the QUIC TLS binding to use for a QUIC version
+///
+/// Selects the initial salt and the `quic_transport_parameters` extension codepoint: drafts use
+/// 0xffa5, while QUIC v1 uses 0x39 (RFC 9001 §8.2).
+fn tls_version(version: u32) -> rustls::quic::Version {
+    match version {
+        0xff00_001d..=0xff00_0020 => rustls::quic::Version::V1Draft,
+        _ => rustls::quic::Version::V1,
     }
 }
 
@@ -344,17 +461,54 @@ fn to_vec(params: &TransportParameters) -> Vec<u8> {
     bytes
 }
 
+fn to_keys(keys: rustls::quic::Keys) -> Keys<TlsSession> {
+    Keys {
+        header: KeyPair {
+            local: keys.local.header,
+            remote: keys.remote.header,
+        },
+        packet: KeyPair {
+            local: keys.local.packet,
+            remote: keys.remote.packet,
+        },
+    }
+}
+
+impl crypto::HeaderKey for HeaderProtectionKey {
+    fn decrypt(&self, pn_offset: usize, packet: &mut [u8]) {
+        let (header, sample) = packet.split_at_mut(pn_offset + 4);
+        let (first, rest) = header.split_first_mut().unwrap();
+        let pn_end = Ord::min(pn_offset + 3, rest.len());
+        self.decrypt_in_place(
+            &sample[..self.sample_size()],
+            first,
+            &mut rest[pn_offset - 1..pn_end],
+        )
+        .unwrap();
+    }
+
+    fn encrypt(&self, pn_offset: usize, packet: &mut [u8]) {
+        let (header, sample) = packet.split_at_mut(pn_offset + 4);
+        let (first, rest) = header.split_first_mut().unwrap();
+        let pn_end = Ord::min(pn_offset + 3, rest.len());
+        self.encrypt_in_place(
+            &sample[..self.sample_size()],
+            first,
+            &mut rest[pn_offset - 1..pn_end],
+        )
+        .unwrap();
+    }
+
+    fn sample_size(&self) -> usize {
+        self.sample_len()
+    }
+}
+
 impl crypto::PacketKey for PacketKey {
     fn encrypt(&self, packet: u64, buf: &mut [u8], header_len: usize) {
-        let (header, payload) = buf.split_at_mut(header_len);
-        let (payload, tag_storage) =
-            payload.split_at_mut(payload.len() - self.key.algorithm().tag_len());
-        let aad = aead::Aad::from(header);
-        let nonce = self.iv.nonce_for(packet);
-        let tag = self
-            .key
-            .seal_in_place_separate_tag(nonce, aad, payload)
-            .unwrap();
+        let (header, payload_tag) = buf.split_at_mut(header_len);
+        let (payload, tag_storage) = payload_tag.split_at_mut(payload_tag.len() - self.tag_len());
+        let tag = self.encrypt_in_place(packet, header, payload).unwrap();
         tag_storage.copy_from_slice(tag.as_ref());
     }
 
@@ -364,55 +518,112 @@ impl crypto::PacketKey for PacketKey {
         header: &[u8],
         payload: &mut BytesMut,
     ) -> Result<(), CryptoError> {
-        if payload.len() < self.key.algorithm().tag_len() {
-            return Err(CryptoError);
-        }
-
-        let payload_len = payload.len();
-        let aad = aead::Aad::from(header);
-        let nonce = self.iv.nonce_for(packet);
-        self.key.open_in_place(nonce, aad, payload.as_mut())?;
-        payload.truncate(payload_len - self.key.algorithm().tag_len());
+        let plain = self
+            .decrypt_in_place(packet, header, payload.as_mut())
+            .map_err(|_| CryptoError)?;
+        let plain_len = plain.len();
+        payload.truncate(plain_len);
         Ok(())
     }
 
     fn tag_len(&self) -> usize {
-        self.key.algorithm().tag_len()
+        self.tag_len()
     }
 
     fn confidentiality_limit(&self) -> u64 {
-        let cipher = self.key.algorithm();
-        if cipher == &aead::AES_128_GCM || cipher == &aead::AES_256_GCM {
-            2u64.pow(23)
-        } else if cipher == &aead::CHACHA20_POLY1305 {
-            u64::MAX
-        } else {
-            panic!("unknown cipher")
-        }
+        self.confidentiality_limit()
     }
 
     fn integrity_limit(&self) -> u64 {
-        let cipher = self.key.algorithm();
-        if cipher == &aead::AES_128_GCM || cipher == &aead::AES_256_GCM {
-            2u64.pow(52)
-        } else if cipher == &aead::CHACHA20_POLY1305 {
-            2u64.pow(36)
-        } else {
-            panic!("unknown cipher")
-        }
+        self.integrity_limit()
     }
 }
 
 /// Cipher suites suitable for QUIC
 ///
-/// The list is equivalent to TLS1.3 ciphers.
-/// It matches the rustls prefernce list that was introduced with
-/// https://github.com/ctz/rustls/commit/7117a805e0104705da50259357d8effa7d599e37.
-/// This list prefers AES ciphers, which are hardware accelerated on most platforms.
-/// This list can be removed if the rustls dependency is updated to a new version
-/// which contains the linked change.
-static QUIC_CIPHER_SUITES: [&rustls::SupportedCipherSuite; 3] = [
-    &rustls::ciphersuite::TLS13_AES_256_GCM_SHA384,
-    &rustls::ciphersuite::TLS13_AES_128_GCM_SHA256,
-    &rustls::ciphersuite::TLS13_CHACHA20_POLY1305_SHA256,
+/// The list is equivalent to the TLS 1.3 ciphers in rustls' default preference order, which
+/// prefers AES ciphers, as they are hardware accelerated on most platforms.
+static QUIC_CIPHER_SUITES: [rustls::SupportedCipherSuite; 3] = [
+    rustls::cipher_suite::TLS13_AES_256_GCM_SHA384,
+    rustls::cipher_suite::TLS13_AES_128_GCM_SHA256,
+    rustls::cipher_suite::TLS13_CHACHA20_POLY1305_SHA256,
 ];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crypto::{ClientConfig as _, ServerConfig as _, Session as _};
+    use hex_literal::hex;
+
+    /// The codepoint of the extension carrying transport parameters in a client's ClientHello
+    fn transport_parameters_extension(version: u32) -> Option<u16> {
+        let config = <Arc<rustls::ClientConfig> as crypto::ClientConfig<TlsSession>>::new();
+        let mut session = config
+            .start_session(version, "localhost", &TransportParameters::default())
+            .unwrap();
+        let mut buf = Vec::new();
+        session.write_handshake(&mut buf);
+
+        // Skip the handshake header, legacy_version and random
+        let mut rest = &buf[4 + 2 + 32..];
+        // Skip legacy_session_id, cipher_suites and legacy_compression_methods
+        for &len_bytes in &[1, 2, 1] {
+            let len = rest[..len_bytes]
+                .iter()
+                .fold(0, |acc, &x| acc << 8 | usize::from(x));
+            rest = &rest[len_bytes + len..];
+        }
+        rest = &rest[2..];
+        while !rest.is_empty() {
+            let ty = u16::from_be_bytes([rest[0], rest[1]]);
+            if ty == 0x39 || ty == 0xffa5 {
+                return Some(ty);
+            }
+            let len = usize::from(u16::from_be_bytes([rest[2], rest[3]]));
+            rest = &rest[4 + len..];
+        }
+        None
+    }
+
+    #[test]
+    fn transport_parameters_codepoint() {
+        assert_eq!(transport_parameters_extension(1), Some(0x39));
+        assert_eq!(transport_parameters_extension(0xff00_001d), Some(0xffa5));
+    }
+
+    /// Retry packet from RFC 9001 Appendix A.4
+    #[test]
+    fn retry_tag_v1() {
+        let orig_dst_cid = ConnectionId::new(&hex!("8394c8f03e515708"));
+        let header = hex!("ff000000010008f067a5502a4262b5");
+        let token = hex!("746f6b656e");
+        let tag = hex!("04a265ba2eff4d829058fb3f0f2496ba");
+
+        let mut packet = header.to_vec();
+        packet.extend_from_slice(&token);
+        assert_eq!(TlsSession::retry_tag(1, &orig_dst_cid, &packet), tag);
+
+        let mut payload = token.to_vec();
+        payload.extend_from_slice(&tag);
+        assert!(TlsSession::is_valid_retry(
+            1,
+            &orig_dst_cid,
+            &header,
+            &payload
+        ));
+        assert!(!TlsSession::is_valid_retry(
+            0xff00_001d,
+            &orig_dst_cid,
+            &header,
+            &payload
+        ));
+    }
+
+    #[test]
+    fn invalid_config() {
+        let mut config = (*crate::tests::util::server_config().crypto).clone();
+        config.max_early_data_size = 1;
+        let result = Arc::new(config).start_session(1, &TransportParameters::default());
+        assert!(matches!(result, Err(ConnectError::InvalidCryptoConfig(_))));
+    }
+}
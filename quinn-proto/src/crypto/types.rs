@@ -1,7 +1,5 @@
 use std::fmt;
 
-use rustls::internal::pemfile;
-
 /// A single TLS certificate
 #[derive(Debug, Clone)]
 pub struct Certificate {
@@ -18,9 +16,12 @@ impl Certificate {
 
     /// Parse a PEM-formatted certificate
     pub fn from_pem(pem: &[u8]) -> Result<Self, ParseError> {
-        let certs = pemfile::certs(&mut &*pem).map_err(|()| ParseError("invalid pem cert"))?;
-        if let Some(pem) = certs.into_iter().next() {
-            return Ok(Self { inner: pem });
+        let certs =
+            rustls_pemfile::certs(&mut &*pem).map_err(|_| ParseError("invalid pem cert"))?;
+        if let Some(der) = certs.into_iter().next() {
+            return Ok(Self {
+                inner: rustls::Certificate(der),
+            });
         }
 
         Err(ParseError("no cert found"))
@@ -53,8 +54,11 @@ impl CertificateChain {
     /// ```
     pub fn from_pem(pem: &[u8]) -> Result<Self, ParseError> {
         Ok(Self {
-            certs: pemfile::certs(&mut &*pem)
-                .map_err(|()| ParseError("malformed certificate chain"))?,
+            certs: rustls_pemfile::certs(&mut &*pem)
+                .map_err(|_| ParseError("malformed certificate chain"))?
+                .into_iter()
+                .map(rustls::Certificate)
+                .collect(),
         })
     }
 
@@ -118,15 +122,19 @@ impl PrivateKey {
     /// let key = quinn_proto::PrivateKey::from_pem(&pem).expect("error parsing key");
     /// ```
     pub fn from_pem(pem: &[u8]) -> Result<Self, ParseError> {
-        let pkcs8 = pemfile::pkcs8_private_keys(&mut &*pem)
-            .map_err(|()| ParseError("malformed PKCS #8 private key"))?;
+        let pkcs8 = rustls_pemfile::pkcs8_private_keys(&mut &*pem)
+            .map_err(|_| ParseError("malformed PKCS #8 private key"))?;
         if let Some(x) = pkcs8.into_iter().next() {
-            return Ok(Self { inner: x });
+            return Ok(Self {
+                inner: rustls::PrivateKey(x),
+            });
         }
-        let rsa = pemfile::rsa_private_keys(&mut &*pem)
-            .map_err(|()| ParseError("malformed PKCS #1 private key"))?;
+        let rsa = rustls_pemfile::rsa_private_keys(&mut &*pem)
+            .map_err(|_| ParseError("malformed PKCS #1 private key"))?;
         if let Some(x) = rsa.into_iter().next() {
-            return Ok(Self { inner: x });
+            return Ok(Self {
+                inner: rustls::PrivateKey(x),
+            });
         }
         Err(ParseError("no private key found"))
     }
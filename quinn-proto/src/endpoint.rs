@@ -1,11 +1,11 @@
 use std::{
-    collections::{HashMap, VecDeque},
+    collections::{hash_map, HashMap, VecDeque},
     convert::TryFrom,
     fmt, iter,
     net::{IpAddr, SocketAddr},
     ops::{Index, IndexMut},
-    sync::Arc,
-    time::{Instant, SystemTime},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
 };
 
 use bytes::{BufMut, Bytes, BytesMut};
@@ -16,23 +16,26 @@ use thiserror::Error;
 use tracing::{debug, trace, warn};
 
 use crate::{
+    anti_replay::AntiReplay,
     cid_generator::{ConnectionIdGenerator, RandomConnectionIdGenerator},
+    client_hello::ClientHello,
     coding::BufMutExt,
     config::{ClientConfig, EndpointConfig, ServerConfig},
-    connection::{Connection, ConnectionError},
+    connection::{ClientRestart, ClientTokenStore, Connection, ConnectionError},
     crypto::{
         self, ClientConfig as ClientCryptoConfig, Keys, PacketKey,
         ServerConfig as ServerCryptoConfig,
     },
     frame,
+    handshake_limiter::HandshakeLimiter,
     packet::{Header, Packet, PacketDecodeError, PacketNumber, PartialDecode},
     shared::{
         ConnectionEvent, ConnectionEventInner, ConnectionId, EcnCodepoint, EndpointEvent,
         EndpointEventInner, IssuedCid,
     },
-    transport_parameters::TransportParameters,
-    ResetToken, RetryToken, Side, Transmit, TransportError, INITIAL_MAX_UDP_PAYLOAD_SIZE,
-    MAX_CID_SIZE, MIN_INITIAL_SIZE, RESET_TOKEN_SIZE,
+    transport_parameters::{PreferredAddress, TransportParameters},
+    ResetToken, RetryToken, Side, TokenType, Transmit, TransportError, ValidationToken,
+    ZeroRttRequest, INITIAL_MAX_UDP_PAYLOAD_SIZE, MAX_CID_SIZE, MIN_INITIAL_SIZE, RESET_TOKEN_SIZE,
 };
 
 /// The main entry point to the library
@@ -71,6 +74,22 @@ where
     ///
     /// Equivalent to a `ServerConfig.accept_buffer` of `0`, but can be changed after the endpoint is constructed.
     reject_new_connections: bool,
+    /// Connection attempts awaiting a decision from the application, keyed by the initial DCID
+    /// the client used
+    ///
+    /// Uses a standard `HashMap` to protect against hash collision attacks.
+    incoming_pending: HashMap<ConnectionId, PendingIncoming>,
+    /// When each entry of `incoming_pending` was received, in order
+    ///
+    /// May refer to entries that have since been removed, or replaced by a later attempt.
+    incoming_expiry: VecDeque<(Instant, ConnectionId)>,
+    /// Number of incoming connections that have been accepted but not yet established
+    handshaking: usize,
+    /// Shared with the endpoints passed to `share_server_state`
+    handshake_limiter: Option<Arc<Mutex<HandshakeLimiter>>>,
+    /// Shared with the endpoints passed to `share_server_state`
+    anti_replay: Option<Arc<Mutex<AntiReplay>>>,
+    stats: EndpointStats,
 }
 
 impl<S> Endpoint<S>
@@ -94,6 +113,18 @@ where
             connections: Slab::new(),
             local_cid_generator: (config.connection_id_generator_factory.as_ref())(),
             reject_new_connections: false,
+            incoming_pending: HashMap::default(),
+            incoming_expiry: VecDeque::new(),
+            handshaking: 0,
+            handshake_limiter: server_config
+                .as_ref()
+                .and_then(|x| x.handshake_limit.clone())
+                .map(|x| Arc::new(Mutex::new(HandshakeLimiter::new(x)))),
+            anti_replay: server_config
+                .as_ref()
+                .and_then(|x| x.anti_replay.clone())
+                .map(|x| Arc::new(Mutex::new(AntiReplay::new(x)))),
+            stats: EndpointStats::default(),
             config,
             server_config,
         }
@@ -103,6 +134,17 @@ where
         self.server_config.is_some()
     }
 
+    /// Enforce connection attempt rate limits and detect 0-RTT replays together with `other`
+    ///
+    /// For endpoints serving the same address from several sockets, which peers may reach
+    /// interchangeably: otherwise a peer can exceed its rate limit, or replay 0-RTT data, by
+    /// sending to a different one. This endpoint's own state is discarded in favor of `other`'s, so
+    /// call this before it receives any datagrams.
+    pub fn share_server_state(&mut self, other: &Self) {
+        self.handshake_limiter = other.handshake_limiter.clone();
+        self.anti_replay = other.anti_replay.clone();
+    }
+
     /// Get the next packet to transmit
     #[must_use]
     pub fn poll_transmit(&mut self) -> Option<Transmit> {
@@ -139,8 +181,17 @@ where
                     }
                 }
             }
+            Established => {
+                if self.connections[ch].handshaking {
+                    self.connections[ch].handshaking = false;
+                    self.handshaking -= 1;
+                }
+            }
             Drained => {
                 let conn = self.connections.remove(ch.0);
+                if conn.handshaking {
+                    self.handshaking -= 1;
+                }
                 if conn.init_cid.len() > 0 {
                     self.connection_ids_initial.remove(&conn.init_cid);
                 }
@@ -157,6 +208,15 @@ where
     }
 
     /// Process an incoming UDP datagram
+    ///
+    /// Connection attempts that pass the server's configured policy are returned as
+    /// [`DatagramEvent::NewConnection`], and must be passed to exactly one of [`accept()`],
+    /// [`refuse()`], [`retry()`] or [`ignore()`].
+    ///
+    /// [`accept()`]: Endpoint::accept
+    /// [`refuse()`]: Endpoint::refuse
+    /// [`retry()`]: Endpoint::retry
+    /// [`ignore()`]: Endpoint::ignore
     pub fn handle(
         &mut self,
         now: Instant,
@@ -164,7 +224,7 @@ where
         local_ip: Option<IpAddr>,
         ecn: Option<EcnCodepoint>,
         data: BytesMut,
-    ) -> Option<(ConnectionHandle, DatagramEvent<S>)> {
+    ) -> Option<DatagramEvent> {
         let datagram_len = data.len();
         let (first_decode, remaining) = match PartialDecode::new(
             data,
@@ -196,7 +256,9 @@ where
                 } else {
                     buf.write::<u32>(0x0a1a_2a4a);
                 }
-                buf.write(self.config.initial_version); // supported version
+                for &version in &self.config.supported_versions {
+                    buf.write(version);
+                }
                 self.transmits.push_back(Transmit {
                     destination: remote,
                     ecn: None,
@@ -248,18 +310,41 @@ where
             .cloned()
         };
         if let Some(ch) = known_ch {
-            return Some((
+            return Some(DatagramEvent::ConnectionEvent(
                 ch,
-                DatagramEvent::ConnectionEvent(ConnectionEvent(ConnectionEventInner::Datagram {
+                ConnectionEvent(ConnectionEventInner::Datagram {
                     now,
                     remote,
+                    local_ip,
                     ecn,
                     first_decode,
                     remaining,
-                })),
+                }),
             ));
         }
 
+        // Hold on to further packets of connection attempts the application hasn't decided on yet,
+        // e.g. the rest of a large ClientHello or 0-RTT data, so they aren't lost
+        if first_decode.is_initial() || first_decode.is_0rtt() {
+            if let Some(pending) = self.incoming_pending.get_mut(&dst_cid) {
+                if pending.datagrams.len() < MAX_PENDING_DATAGRAMS {
+                    let mut data = first_decode.into_data();
+                    if let Some(rest) = remaining {
+                        data.unsplit(rest);
+                    }
+                    pending.datagrams.push(PendingDatagram {
+                        remote,
+                        local_ip,
+                        ecn,
+                        data,
+                    });
+                } else {
+                    debug!("dropping packet for pending connection {}", dst_cid);
+                }
+                return None;
+            }
+        }
+
         //
         // Potentially create a new connection
         //
@@ -283,11 +368,12 @@ where
                 return None;
             }
 
-            let crypto = S::initial_keys(&dst_cid, Side::Server);
+            let version = first_decode.version().unwrap();
+            let crypto = S::initial_keys(version, &dst_cid, Side::Server);
             return match first_decode.finish(Some(&crypto.header.remote)) {
                 Ok(packet) => self
                     .handle_first_packet(now, remote, local_ip, ecn, packet, remaining, &crypto)
-                    .map(|(ch, conn)| (ch, DatagramEvent::NewConnection(conn))),
+                    .map(DatagramEvent::NewConnection),
                 Err(e) => {
                     trace!("unable to decode initial packet: {}", e);
                     None
@@ -328,6 +414,17 @@ where
             }
         };
 
+        let key = match self.reset_key(dst_cid) {
+            Some(x) => x,
+            None => {
+                debug!(
+                    "not resetting {}: its reset key is no longer known",
+                    dst_cid
+                );
+                return;
+            }
+        };
+
         debug!("sending stateless reset for {} to {}", dst_cid, remote);
         let mut buf = Vec::<u8>::new();
         // Resets with at least this much padding can't possibly be distinguished from real packets
@@ -341,7 +438,7 @@ where
         buf.resize(padding_len, 0);
         self.rng.fill_bytes(&mut buf[0..padding_len]);
         buf[0] = 0b0100_0000 | buf[0] >> 2;
-        buf.extend_from_slice(&ResetToken::new(&*self.config.reset_key, dst_cid));
+        buf.extend_from_slice(&ResetToken::new(&*key, dst_cid));
 
         debug_assert!(buf.len() < inciting_dgram_len);
 
@@ -354,12 +451,45 @@ where
         });
     }
 
+    /// The key the stateless reset token for `cid` is derived from
+    ///
+    /// Uses the key whose ID `cid` records, or `None` if it has since been removed from the ring.
+    /// CIDs that don't record one are assumed to use the current key.
+    fn reset_key(&self, cid: &ConnectionId) -> Option<Arc<S::HmacKey>> {
+        match self.local_cid_generator.reset_key_id(cid) {
+            Some(id) => self.config.reset_keys.get(id),
+            None => Some(self.config.reset_keys.current().1),
+        }
+    }
+
+    /// The stateless reset token to issue along with `cid`
+    fn reset_token(&self, cid: &ConnectionId) -> ResetToken {
+        let key = self
+            .reset_key(cid)
+            .unwrap_or_else(|| self.config.reset_keys.current().1);
+        ResetToken::new(&*key, cid)
+    }
+
     /// Initiate a connection
     pub fn connect(
         &mut self,
         config: ClientConfig<S>,
         remote: SocketAddr,
         server_name: &str,
+    ) -> Result<(ConnectionHandle, Connection<S>), ConnectError> {
+        self.connect_at(Instant::now(), config, remote, server_name)
+    }
+
+    /// Initiate a connection, taking `now` as the current time
+    ///
+    /// For applications that don't measure time with [`Instant::now()`], like tests running on a
+    /// simulated clock.
+    pub fn connect_at(
+        &mut self,
+        now: Instant,
+        config: ClientConfig<S>,
+        remote: SocketAddr,
+        server_name: &str,
     ) -> Result<(ConnectionHandle, Connection<S>), ConnectError> {
         if self.is_full() {
             return Err(ConnectError::TooManyConnections);
@@ -367,9 +497,14 @@ where
         if remote.port() == 0 {
             return Err(ConnectError::InvalidRemoteAddress(remote));
         }
+        let version = config.version.unwrap_or(self.config.initial_version);
+        if !self.config.supported_versions.contains(&version) {
+            return Err(ConnectError::UnsupportedVersion);
+        }
         let remote_id = RandomConnectionIdGenerator::new(MAX_CID_SIZE).generate_cid();
         trace!(initial_dcid = %remote_id);
         let (ch, conn) = self.add_connection(
+            version,
             remote_id,
             remote_id,
             remote,
@@ -378,7 +513,7 @@ where
                 config,
                 server_name: server_name.into(),
             },
-            Instant::now(),
+            now,
         )?;
         Ok((ch, conn))
     }
@@ -400,15 +535,18 @@ where
             ids.push(IssuedCid {
                 sequence,
                 id,
-                reset_token: ResetToken::new(&*self.config.reset_key, &id),
+                reset_token: self.reset_token(&id),
             });
         }
         ConnectionEvent(ConnectionEventInner::NewIdentifiers(ids, now))
     }
 
     fn new_cid(&mut self) -> ConnectionId {
+        let reset_key_id = self.config.reset_keys.current().0;
         loop {
-            let cid = self.local_cid_generator.generate_cid();
+            let cid = self
+                .local_cid_generator
+                .generate_cid_for_reset_key(reset_key_id);
             if !self.connection_ids.contains_key(&cid) {
                 break cid;
             }
@@ -418,6 +556,7 @@ where
 
     fn add_connection(
         &mut self,
+        version: u32,
         init_cid: ConnectionId,
         rem_cid: ConnectionId,
         remote: SocketAddr,
@@ -426,7 +565,15 @@ where
         now: Instant,
     ) -> Result<(ConnectionHandle, Connection<S>), ConnectError> {
         let loc_cid = self.new_cid();
-        let (server_config, tls, transport_config) = match opts {
+        let (
+            server_config,
+            tls,
+            transport_config,
+            params,
+            client_restart,
+            token_store,
+            path_validated,
+        ) = match opts {
             ConnectionOpts::Client {
                 config,
                 server_name,
@@ -434,42 +581,83 @@ where
                 let params = TransportParameters::new::<S>(
                     &config.transport,
                     &self.config,
+                    version,
                     self.local_cid_generator.as_ref(),
                     loc_cid,
                     None,
                 );
                 (
                     None,
-                    config.crypto.start_session(&server_name, &params)?,
+                    config
+                        .crypto
+                        .start_session(version, &server_name, &params)?,
                     config.transport,
+                    params,
+                    Some(ClientRestart {
+                        crypto: config.crypto,
+                        server_name: server_name.clone(),
+                    }),
+                    config
+                        .token_store
+                        .map(|store| ClientTokenStore { store, server_name }),
+                    true,
                 )
             }
             ConnectionOpts::Server {
                 orig_dst_cid,
                 retry_src_cid,
+                address_validated,
+                reject_0rtt,
             } => {
-                let config = self.server_config.as_ref().unwrap();
+                let config = self.server_config.clone().unwrap();
                 let params = TransportParameters::new(
                     &config.transport,
                     &self.config,
+                    version,
                     self.local_cid_generator.as_ref(),
                     loc_cid,
-                    Some(config),
+                    Some(&config),
                 );
+                // A preferred address can't be advertised with zero-length CIDs, since the client
+                // must switch to the CID supplied along with it.
+                let preferred_address = if self.local_cid_generator.cid_len() > 0
+                    && (config.preferred_address_v4.is_some()
+                        || config.preferred_address_v6.is_some())
+                {
+                    let cid = self.new_cid();
+                    Some(PreferredAddress {
+                        address_v4: config.preferred_address_v4,
+                        address_v6: config.preferred_address_v6,
+                        connection_id: cid,
+                        stateless_reset_token: self.reset_token(&cid),
+                    })
+                } else {
+                    None
+                };
                 let server_params = TransportParameters {
-                    stateless_reset_token: Some(ResetToken::new(&*self.config.reset_key, &loc_cid)),
+                    stateless_reset_token: Some(self.reset_token(&loc_cid)),
                     original_dst_cid: Some(orig_dst_cid),
                     retry_src_cid,
+                    preferred_address,
                     ..params
                 };
+                let mut tls = config.crypto.start_session(version, &server_params)?;
+                if reject_0rtt {
+                    tls.reject_0rtt();
+                }
                 (
                     Some(config.clone()),
-                    config.crypto.start_session(&server_params),
+                    tls,
                     config.transport.clone(),
+                    server_params,
+                    None,
+                    None,
+                    address_validated,
                 )
             }
         };
 
+        let preferred_cid = params.preferred_address.as_ref().map(|x| x.connection_id);
         let conn = Connection::new(
             server_config,
             transport_config,
@@ -479,21 +667,35 @@ where
             remote,
             local_ip,
             tls,
+            params,
+            client_restart,
+            token_store,
+            path_validated,
             self.local_cid_generator.as_ref(),
             now,
-            self.config.initial_version,
+            version,
         );
-        let id = self.connections.insert(ConnectionMeta {
+        let mut meta = ConnectionMeta {
             init_cid,
             cids_issued: 0,
             loc_cids: iter::once((0, loc_cid)).collect(),
             initial_remote: remote,
             reset_token: None,
-        });
+            handshaking: false,
+        };
+        // The CID supplied with the preferred address has sequence number 1
+        if let Some(cid) = preferred_cid {
+            meta.cids_issued = 1;
+            meta.loc_cids.insert(1, cid);
+        }
+        let id = self.connections.insert(meta);
         let ch = ConnectionHandle(id);
 
         if self.local_cid_generator.cid_len() > 0 {
             self.connection_ids.insert(loc_cid, ch);
+            if let Some(cid) = preferred_cid {
+                self.connection_ids.insert(cid, ch);
+            }
         } else {
             self.connection_remotes.insert(remote, ch);
         }
@@ -509,15 +711,15 @@ where
         mut packet: Packet,
         rest: Option<BytesMut>,
         crypto: &Keys<S>,
-    ) -> Option<(ConnectionHandle, Connection<S>)> {
-        let (src_cid, dst_cid, token, packet_number) = match packet.header {
+    ) -> Option<IncomingConnection> {
+        let (src_cid, dst_cid, token, packet_number, version) = match packet.header {
             Header::Initial {
                 src_cid,
                 dst_cid,
                 ref token,
                 number,
-                ..
-            } => (src_cid, dst_cid, token.clone(), number),
+                version,
+            } => (src_cid, dst_cid, token.clone(), number, version),
             _ => panic!("non-initial packet in handle_first_packet()"),
         };
         let packet_number = packet_number.expand(0);
@@ -541,151 +743,372 @@ where
             return None;
         }
 
-        // Local CID used for stateless packets
-        let temp_loc_cid = self.new_cid();
-        let server_config = self.server_config.as_ref().unwrap();
+        let server_config = self.server_config.as_ref().unwrap().clone();
 
-        if self.connections.len() >= server_config.concurrent_connections as usize
+        // Forget attempts the application never decided on, e.g. because it dropped the
+        // `IncomingConnection`, so they don't count against the limits below forever
+        while let Some(&(received_at, dst_cid)) = self.incoming_expiry.front() {
+            if now.saturating_duration_since(received_at) < INCOMING_PENDING_TIMEOUT {
+                break;
+            }
+            if let hash_map::Entry::Occupied(e) = self.incoming_pending.entry(dst_cid) {
+                if e.get().received_at == received_at {
+                    e.remove();
+                }
+            }
+            self.incoming_expiry.pop_front();
+        }
+
+        if self.connections.len() + self.incoming_pending.len()
+            >= server_config.concurrent_connections as usize
             || self.reject_new_connections
             || self.is_full()
         {
             debug!("refusing connection");
+            self.stats.refused += 1;
             self.initial_close(
+                version,
                 remote,
                 local_ip,
                 crypto,
                 &src_cid,
-                &temp_loc_cid,
                 TransportError::CONNECTION_REFUSED(""),
             );
             return None;
         }
 
-        if dst_cid.len() < 8
-            && (!server_config.use_stateless_retry
-                || dst_cid.len() != self.local_cid_generator.cid_len())
-        {
+        // Only our own Retry packets can have caused a client to pick a short CID
+        let is_retry = TokenType::of(&token) == Some(TokenType::Retry);
+        if dst_cid.len() < 8 && (!is_retry || dst_cid.len() != self.local_cid_generator.cid_len()) {
             debug!(
                 "rejecting connection due to invalid DCID length {}",
                 dst_cid.len()
             );
             self.initial_close(
+                version,
                 remote,
                 local_ip,
                 crypto,
                 &src_cid,
-                &temp_loc_cid,
                 TransportError::PROTOCOL_VIOLATION("invalid destination CID length"),
             );
             return None;
         }
 
-        let (retry_src_cid, orig_dst_cid) = if server_config.use_stateless_retry {
-            if token.is_empty() {
-                // First Initial
-                let mut random_bytes = vec![0u8; RetryToken::RANDOM_BYTES_LEN];
-                self.rng.fill_bytes(&mut random_bytes);
-
-                let token = RetryToken {
-                    orig_dst_cid: dst_cid,
-                    issued: SystemTime::now(),
-                    random_bytes: &random_bytes,
+        let (retry_src_cid, orig_dst_cid, address_validated) = match TokenType::of(&token) {
+            Some(TokenType::Retry) => {
+                match RetryToken::from_bytes(&server_config.token_keys, &remote, &dst_cid, &token) {
+                    Ok(token)
+                        if token.issued + server_config.retry_token_lifetime
+                            > SystemTime::now() =>
+                    {
+                        (Some(dst_cid), token.orig_dst_cid, true)
+                    }
+                    _ => {
+                        debug!("rejecting invalid stateless retry token");
+                        self.initial_close(
+                            version,
+                            remote,
+                            local_ip,
+                            crypto,
+                            &src_cid,
+                            TransportError::INVALID_TOKEN(""),
+                        );
+                        return None;
+                    }
                 }
-                .encode(&*server_config.token_key, &remote, &temp_loc_cid);
-
-                let header = Header::Retry {
-                    src_cid: temp_loc_cid,
-                    dst_cid: src_cid,
-                    version: self.config.initial_version,
+            }
+            // A token from a NEW_TOKEN frame sent on an earlier connection proves ownership of the
+            // address without a Retry. Bad tokens are ignored rather than fatal, since the client
+            // can't know that a token it was given has expired or that its address has changed.
+            Some(TokenType::Validation) => {
+                let valid = match ValidationToken::from_bytes(
+                    &server_config.token_keys,
+                    &remote.ip(),
+                    &token,
+                ) {
+                    Ok(token)
+                        if token.issued + server_config.validation_token_lifetime
+                            > SystemTime::now() =>
+                    {
+                        true
+                    }
+                    _ => {
+                        debug!("ignoring invalid address validation token");
+                        false
+                    }
                 };
+                (None, dst_cid, valid)
+            }
+            None => (None, dst_cid, false),
+        };
 
-                let mut buf = Vec::new();
-                let encode = header.encode(&mut buf);
-                buf.put_slice(&token);
-                buf.extend_from_slice(&S::retry_tag(&dst_cid, &buf));
-                encode.finish::<S::PacketKey, S::HeaderKey>(&mut buf, &crypto.header.local, None);
+        let client_hello =
+            ClientHello::from_initial_payload(packet.payload.clone().freeze()).map(Box::new);
+        let incoming = IncomingConnection {
+            remote,
+            local_ip,
+            ecn,
+            packet,
+            rest,
+            version,
+            src_cid,
+            dst_cid,
+            packet_number: packet_number as u64,
+            retry_src_cid,
+            orig_dst_cid,
+            address_validated,
+            client_hello,
+            received_at: now,
+        };
 
-                self.transmits.push_back(Transmit {
-                    destination: remote,
-                    ecn: None,
-                    contents: buf,
-                    segment_size: None,
-                    src_ip: local_ip,
-                });
+        if !address_validated {
+            let overloaded = match server_config.retry_threshold {
+                Some(threshold) => self.pending_handshakes() >= threshold as usize,
+                None => false,
+            };
+            if server_config.use_stateless_retry || overloaded {
+                if overloaded {
+                    trace!("requiring address validation under load");
+                    self.stats.load_retries += 1;
+                }
+                // Unwrap is safe: the address hasn't been validated
+                self.retry(incoming).unwrap();
                 return None;
             }
+        }
 
-            match RetryToken::from_bytes(&*server_config.token_key, &remote, &dst_cid, &token) {
-                Ok(token)
-                    if token.issued + server_config.retry_token_lifetime > SystemTime::now() =>
-                {
-                    (Some(dst_cid), token.orig_dst_cid)
-                }
-                _ => {
-                    debug!("rejecting invalid stateless retry token");
+        if let Some(ref limiter) = self.handshake_limiter {
+            if !limiter.lock().unwrap().check(now, remote.ip()) {
+                debug!("connection attempt from {} exceeds rate limit", remote);
+                self.stats.rate_limited += 1;
+                if server_config.handshake_limit.as_ref().unwrap().refuse {
                     self.initial_close(
+                        version,
                         remote,
                         local_ip,
                         crypto,
                         &src_cid,
-                        &temp_loc_cid,
-                        TransportError::INVALID_TOKEN(""),
+                        TransportError::CONNECTION_REFUSED(""),
                     );
-                    return None;
                 }
+                return None;
             }
-        } else {
-            (None, dst_cid)
-        };
+        }
 
-        let (ch, mut conn) = self
-            .add_connection(
+        if !dst_cid.is_empty() {
+            self.incoming_pending.insert(
                 dst_cid,
-                src_cid,
-                remote,
-                local_ip,
-                ConnectionOpts::Server {
-                    retry_src_cid,
-                    orig_dst_cid,
+                PendingIncoming {
+                    received_at: now,
+                    datagrams: Vec::new(),
                 },
-                now,
-            )
-            .unwrap();
+            );
+            self.incoming_expiry.push_back((now, dst_cid));
+        }
+        self.stats.incoming_handshakes += 1;
+        Some(incoming)
+    }
+
+    /// Number of incoming connections awaiting a decision from the application or still
+    /// handshaking
+    fn pending_handshakes(&self) -> usize {
+        self.incoming_pending.len() + self.handshaking
+    }
+
+    /// Accept a connection attempt, creating its `Connection`
+    ///
+    /// Fails if the endpoint can no longer take on connections, or the first packet turns out to
+    /// be invalid. The client is informed in either case.
+    pub fn accept(
+        &mut self,
+        incoming: IncomingConnection,
+        now: Instant,
+    ) -> Result<(ConnectionHandle, Connection<S>), ConnectionError> {
+        let pending = self
+            .incoming_pending
+            .remove(&incoming.dst_cid)
+            .map(|x| x.datagrams)
+            .unwrap_or_default();
+        let crypto = S::initial_keys(incoming.version, &incoming.dst_cid, Side::Server);
+
+        if self.reject_new_connections || self.is_full() {
+            debug!("refusing connection");
+            self.stats.refused += 1;
+            let reason = TransportError::CONNECTION_REFUSED("");
+            self.initial_close(
+                incoming.version,
+                incoming.remote,
+                incoming.local_ip,
+                &crypto,
+                &incoming.src_cid,
+                reason.clone(),
+            );
+            return Err(reason.into());
+        }
+
+        let (reject_0rtt, replay_protected) = self.check_0rtt(now, &incoming);
+        let IncomingConnection {
+            remote,
+            local_ip,
+            ecn,
+            packet,
+            rest,
+            version,
+            src_cid,
+            dst_cid,
+            packet_number,
+            retry_src_cid,
+            orig_dst_cid,
+            address_validated,
+            ..
+        } = incoming;
+        let (ch, mut conn) = match self.add_connection(
+            version,
+            dst_cid,
+            src_cid,
+            remote,
+            local_ip,
+            ConnectionOpts::Server {
+                retry_src_cid,
+                orig_dst_cid,
+                address_validated,
+                reject_0rtt,
+            },
+            now,
+        ) {
+            Ok(x) => x,
+            Err(e) => {
+                debug!("failed to start connection: {}", e);
+                let reason = TransportError::INTERNAL_ERROR("");
+                self.initial_close(version, remote, local_ip, &crypto, &src_cid, reason.clone());
+                return Err(reason.into());
+            }
+        };
+        conn.set_0rtt_replay_protected(replay_protected);
+        self.connections[ch].handshaking = true;
+        self.handshaking += 1;
         if dst_cid.len() != 0 {
             self.connection_ids_initial.insert(dst_cid, ch);
         }
-        match conn.handle_first_packet(now, remote, ecn, packet_number as u64, packet, rest) {
-            Ok(()) => {
-                trace!(id = ch.0, icid = %dst_cid, "connection incoming");
-                Some((ch, conn))
+        if let Err(e) = conn.handle_first_packet(now, remote, ecn, packet_number, packet, rest) {
+            debug!("handshake failed: {}", e);
+            self.handle_event(ch, EndpointEvent(EndpointEventInner::Drained));
+            if let ConnectionError::TransportError(ref e) = e {
+                self.initial_close(version, remote, local_ip, &crypto, &src_cid, e.clone());
             }
-            Err(e) => {
-                debug!("handshake failed: {}", e);
-                self.handle_event(ch, EndpointEvent(EndpointEventInner::Drained));
-                if let ConnectionError::TransportError(e) = e {
-                    self.initial_close(remote, local_ip, crypto, &src_cid, &temp_loc_cid, e);
-                }
-                None
+            return Err(e);
+        }
+        trace!(id = ch.0, icid = %dst_cid, "connection incoming");
+
+        // Deliver anything that arrived while the application was deciding
+        for x in pending {
+            if let Some(DatagramEvent::ConnectionEvent(_, event)) =
+                self.handle(now, x.remote, x.local_ip, x.ecn, x.data)
+            {
+                conn.handle_event(event);
             }
         }
+        Ok((ch, conn))
+    }
+
+    /// Refuse a connection attempt, informing the client with `reason`
+    ///
+    /// Use [`TransportErrorCode::CONNECTION_REFUSED`] for the usual case. Application-specific
+    /// reasons must be conveyed with [`TransportErrorCode::APPLICATION_ERROR`], since the
+    /// handshake hasn't progressed far enough to send an application close.
+    ///
+    /// [`TransportErrorCode::CONNECTION_REFUSED`]: crate::TransportErrorCode::CONNECTION_REFUSED
+    /// [`TransportErrorCode::APPLICATION_ERROR`]: crate::TransportErrorCode::APPLICATION_ERROR
+    pub fn refuse(&mut self, incoming: IncomingConnection, reason: TransportError) {
+        debug!("refusing connection: {}", reason);
+        self.stats.refused += 1;
+        self.incoming_pending.remove(&incoming.dst_cid);
+        let crypto = S::initial_keys(incoming.version, &incoming.dst_cid, Side::Server);
+        self.initial_close(
+            incoming.version,
+            incoming.remote,
+            incoming.local_ip,
+            &crypto,
+            &incoming.src_cid,
+            reason,
+        );
+    }
+
+    /// Ask the client to prove ownership of its address by sending a Retry packet
+    ///
+    /// The client will start a new connection attempt carrying a token, which shows up as a new
+    /// [`IncomingConnection`] with [`IncomingConnection::remote_address_validated()`] set. Fails
+    /// if the client's address was already validated, since retrying again is not allowed.
+    pub fn retry(&mut self, incoming: IncomingConnection) -> Result<(), RetryError> {
+        if incoming.address_validated {
+            return Err(RetryError(Box::new(incoming)));
+        }
+        self.incoming_pending.remove(&incoming.dst_cid);
+        self.stats.retries_sent += 1;
+        let server_config = self.server_config.as_ref().unwrap().clone();
+        let crypto = S::initial_keys(incoming.version, &incoming.dst_cid, Side::Server);
+        // Local CID the client will address its next attempt to
+        let temp_loc_cid = self.new_cid();
+
+        let mut random_bytes = vec![0u8; RetryToken::RANDOM_BYTES_LEN];
+        self.rng.fill_bytes(&mut random_bytes);
+
+        let token = RetryToken {
+            orig_dst_cid: incoming.dst_cid,
+            issued: SystemTime::now(),
+            random_bytes: &random_bytes,
+        }
+        .encode(&server_config.token_keys, &incoming.remote, &temp_loc_cid);
+
+        let header = Header::Retry {
+            src_cid: temp_loc_cid,
+            dst_cid: incoming.src_cid,
+            version: incoming.version,
+        };
+
+        let mut buf = Vec::new();
+        let encode = header.encode(&mut buf);
+        buf.put_slice(&token);
+        buf.extend_from_slice(&S::retry_tag(incoming.version, &incoming.dst_cid, &buf));
+        encode.finish::<S::PacketKey, S::HeaderKey>(&mut buf, &crypto.header.local, None);
+
+        self.transmits.push_back(Transmit {
+            destination: incoming.remote,
+            ecn: None,
+            contents: buf,
+            segment_size: None,
+            src_ip: incoming.local_ip,
+        });
+        Ok(())
+    }
+
+    /// Drop a connection attempt without telling the client
+    ///
+    /// The client will keep retransmitting until its handshake times out.
+    pub fn ignore(&mut self, incoming: IncomingConnection) {
+        trace!("ignoring connection attempt from {}", incoming.remote);
+        self.incoming_pending.remove(&incoming.dst_cid);
     }
 
     fn initial_close(
         &mut self,
+        version: u32,
         destination: SocketAddr,
         local_ip: Option<IpAddr>,
         crypto: &Keys<S>,
         remote_id: &ConnectionId,
-        local_id: &ConnectionId,
         reason: TransportError,
     ) {
+        // Local CID used for stateless packets
+        let local_id = self.new_cid();
         let number = PacketNumber::U8(0);
         let header = Header::Initial {
             dst_cid: *remote_id,
-            src_cid: *local_id,
+            src_cid: local_id,
             number,
             token: Bytes::new(),
-            version: self.config.initial_version,
+            version,
         };
 
         let mut buf = Vec::<u8>::new();
@@ -698,7 +1121,7 @@ where
         partial_encode.finish(
             &mut buf,
             &crypto.header.local,
-            Some((0, &crypto.packet.local)),
+            Some((0, 0, &crypto.packet.local)),
         );
         self.transmits.push_back(Transmit {
             destination,
@@ -714,11 +1137,68 @@ where
         self.reject_new_connections = true;
     }
 
+    /// Decide whether 0-RTT data may be accepted for `incoming`
+    ///
+    /// Returns whether to reject 0-RTT, and whether any 0-RTT data accepted is known not to be a
+    /// replay.
+    fn check_0rtt(&mut self, now: Instant, incoming: &IncomingConnection) -> (bool, bool) {
+        let config = self.server_config.as_ref().unwrap();
+        if self.anti_replay.is_none() && config.zero_rtt_policy.is_none() {
+            return (false, false);
+        }
+        let (hello, identity) = match incoming.client_hello {
+            Some(ref hello) => match hello.psk_identity {
+                Some(ref identity) if hello.early_data => (hello, identity),
+                _ => return (false, false),
+            },
+            // The ticket can't be identified, so nothing is known about the 0-RTT data
+            None => return (true, false),
+        };
+
+        if let Some(ref policy) = config.zero_rtt_policy {
+            // Tickets the crypto layer doesn't recognize can't be used for 0-RTT anyway
+            if let Some(resumption_data) = config.crypto.resumption_data(identity) {
+                let request = ZeroRttRequest {
+                    remote: incoming.remote,
+                    resumption_data: &resumption_data,
+                    server_name: hello.server_name.as_deref(),
+                    alpn_protocols: hello.alpn_protocols.as_deref(),
+                };
+                if !policy.accept(&request) {
+                    debug!("0-RTT rejected by application");
+                    return (true, false);
+                }
+            }
+        }
+
+        let anti_replay = match self.anti_replay {
+            Some(ref x) => x,
+            None => return (false, false),
+        };
+        if !anti_replay.lock().unwrap().check(now, identity) {
+            debug!("rejecting 0-RTT from previously used session ticket");
+            self.stats.rejected_0rtt += 1;
+            return (true, false);
+        }
+        (false, true)
+    }
+
     /// Access the configuration used by this endpoint
     pub fn config(&self) -> &EndpointConfig<S> {
         &self.config
     }
 
+    /// Returns statistics about incoming connection attempts
+    pub fn stats(&self) -> EndpointStats {
+        let mut stats = self.stats;
+        stats.pending_handshakes = self.pending_handshakes() as u64;
+        stats.tracked_sources = self
+            .handshake_limiter
+            .as_ref()
+            .map_or(0, |x| x.lock().unwrap().sources() as u64);
+        stats
+    }
+
     #[cfg(test)]
     pub(crate) fn known_connections(&self) -> usize {
         let x = self.connections.len();
@@ -764,6 +1244,7 @@ where
             .field("config", &self.config)
             .field("server_config", &self.server_config)
             .field("reject_new_connections", &self.reject_new_connections)
+            .field("incoming_pending", &self.incoming_pending.len())
             .finish()
     }
 }
@@ -782,6 +1263,32 @@ pub(crate) struct ConnectionMeta {
     /// Reset token provided by the peer for the CID we're currently sending to, and the address
     /// being sent to
     reset_token: Option<(SocketAddr, ResetToken)>,
+    /// Whether this is an incoming connection that hasn't been established yet
+    handshaking: bool,
+}
+
+/// Statistics about incoming connection attempts handled by an endpoint
+#[derive(Debug, Default, Copy, Clone)]
+#[non_exhaustive]
+pub struct EndpointStats {
+    /// Connection attempts passed on to the application
+    pub incoming_handshakes: u64,
+    /// Retry packets sent, whether required by the configuration, by load, or by the application
+    pub retries_sent: u64,
+    /// Retry packets sent because the number of handshakes in progress reached the server's
+    /// `retry_threshold`
+    pub load_retries: u64,
+    /// Connection attempts refused because the server was at capacity or by the application
+    pub refused: u64,
+    /// Connection attempts refused or dropped because their source exceeded its rate limit
+    pub rate_limited: u64,
+    /// Incoming connections currently awaiting a decision from the application or handshaking
+    pub pending_handshakes: u64,
+    /// Source address prefixes currently tracked by the rate limiter
+    pub tracked_sources: u64,
+    /// Connection attempts whose 0-RTT data was rejected because their session ticket had already
+    /// been used
+    pub rejected_0rtt: u64,
 }
 
 /// Internal identifier for a `Connection` currently associated with an endpoint
@@ -808,16 +1315,133 @@ impl IndexMut<ConnectionHandle> for Slab<ConnectionMeta> {
 }
 
 /// Event resulting from processing a single datagram
-pub enum DatagramEvent<S>
-where
-    S: crypto::Session,
-{
+pub enum DatagramEvent {
     /// The datagram is redirected to its `Connection`
-    ConnectionEvent(ConnectionEvent),
-    /// The datagram has resulted in starting a new `Connection`
-    NewConnection(Connection<S>),
+    ConnectionEvent(ConnectionHandle, ConnectionEvent),
+    /// The datagram is a new connection attempt, which the application must decide on
+    NewConnection(IncomingConnection),
+}
+
+/// A connection attempt that hasn't been accepted or refused yet
+///
+/// Obtained from [`DatagramEvent::NewConnection`]. The server's global policy, such as
+/// [`ServerConfig::concurrent_connections()`] and [`ServerConfig::use_stateless_retry()`], has
+/// already been applied. Pass it to [`Endpoint::accept()`], [`Endpoint::refuse()`],
+/// [`Endpoint::retry()`] or [`Endpoint::ignore()`]. An attempt that is simply dropped keeps
+/// counting against [`ServerConfig::concurrent_connections()`] until it expires, 10 seconds after
+/// it was received.
+///
+/// [`ServerConfig::concurrent_connections()`]: crate::generic::ServerConfig::concurrent_connections
+/// [`ServerConfig::use_stateless_retry()`]: crate::generic::ServerConfig::use_stateless_retry
+#[must_use = "connection attempts must be passed to `Endpoint::accept()`, `refuse()`, `retry()` or `ignore()`"]
+pub struct IncomingConnection {
+    remote: SocketAddr,
+    local_ip: Option<IpAddr>,
+    ecn: Option<EcnCodepoint>,
+    packet: Packet,
+    rest: Option<BytesMut>,
+    version: u32,
+    src_cid: ConnectionId,
+    dst_cid: ConnectionId,
+    packet_number: u64,
+    retry_src_cid: Option<ConnectionId>,
+    orig_dst_cid: ConnectionId,
+    address_validated: bool,
+    client_hello: Option<Box<ClientHello>>,
+    received_at: Instant,
+}
+
+impl IncomingConnection {
+    /// The peer's UDP address
+    pub fn remote_address(&self) -> SocketAddr {
+        self.remote
+    }
+
+    /// The local IP address the attempt was received on, if known
+    pub fn local_ip(&self) -> Option<IpAddr> {
+        self.local_ip
+    }
+
+    /// Whether the client proved ownership of its address with a Retry or NEW_TOKEN token
+    ///
+    /// Unvalidated addresses may be spoofed, and are subject to an anti-amplification limit.
+    pub fn remote_address_validated(&self) -> bool {
+        self.address_validated
+    }
+
+    /// The server name the client asked for with TLS SNI
+    ///
+    /// `None` if the client didn't send one, or the ClientHello couldn't be parsed from the first
+    /// packet.
+    pub fn server_name(&self) -> Option<&str> {
+        self.client_hello.as_ref()?.server_name.as_deref()
+    }
+
+    /// The application protocols the client offered with TLS ALPN, in preference order
+    ///
+    /// `None` if the client didn't offer any, or the ClientHello couldn't be parsed from the first
+    /// packet.
+    pub fn alpn_protocols(&self) -> Option<&[Vec<u8>]> {
+        self.client_hello.as_ref()?.alpn_protocols.as_deref()
+    }
+
+    /// When the attempt's first packet was received
+    pub fn received_at(&self) -> Instant {
+        self.received_at
+    }
+}
+
+impl fmt::Debug for IncomingConnection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("IncomingConnection")
+            .field("remote", &self.remote)
+            .field("local_ip", &self.local_ip)
+            .field("dst_cid", &self.dst_cid)
+            .field("address_validated", &self.address_validated)
+            .field("client_hello", &self.client_hello)
+            .finish()
+    }
+}
+
+/// The client's address was already validated, so a connection attempt can't be retried
+///
+/// Returns the attempt so it can be accepted or refused instead.
+#[derive(Debug, Error)]
+#[error("retry of a validated connection attempt")]
+pub struct RetryError(Box<IncomingConnection>);
+
+impl RetryError {
+    /// Recover the connection attempt
+    pub fn into_incoming(self) -> IncomingConnection {
+        *self.0
+    }
+}
+
+/// An [`IncomingConnection`] the application hasn't decided on yet
+#[derive(Debug)]
+struct PendingIncoming {
+    received_at: Instant,
+    datagrams: Vec<PendingDatagram>,
 }
 
+/// How long to wait for the application to decide on an [`IncomingConnection`] before forgetting
+/// about it
+///
+/// Matches the default idle timeout, after which clients usually give up on the handshake.
+const INCOMING_PENDING_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A datagram received for an [`IncomingConnection`] before it was accepted
+#[derive(Debug)]
+struct PendingDatagram {
+    remote: SocketAddr,
+    local_ip: Option<IpAddr>,
+    ecn: Option<EcnCodepoint>,
+    data: BytesMut,
+}
+
+/// Maximum number of datagrams buffered for each [`IncomingConnection`]
+const MAX_PENDING_DATAGRAMS: usize = 10;
+
 enum ConnectionOpts<S: crypto::Session> {
     Client {
         config: ClientConfig<S>,
@@ -826,6 +1450,10 @@ enum ConnectionOpts<S: crypto::Session> {
     Server {
         retry_src_cid: Option<ConnectionId>,
         orig_dst_cid: ConnectionId,
+        /// Whether the client proved ownership of its address with a token
+        address_validated: bool,
+        /// Whether the crypto session must refuse 0-RTT data
+        reject_0rtt: bool,
     },
 }
 
@@ -852,6 +1480,15 @@ pub enum ConnectError {
     /// Examples include attempting to connect to port 0, or using an inappropriate address family.
     #[error("invalid remote address: {0}")]
     InvalidRemoteAddress(SocketAddr),
+    /// The QUIC version requested by the `ClientConfig` is not supported by the endpoint
+    #[error("unsupported QUIC version")]
+    UnsupportedVersion,
+    /// The crypto configuration can't be used for QUIC connections
+    ///
+    /// With rustls, this is the case if TLS 1.3 isn't enabled, or if a server's
+    /// `max_early_data_size` is neither 0 nor `u32::MAX`.
+    #[error("invalid crypto configuration: {0}")]
+    InvalidCryptoConfig(String),
 }
 
 /// Reset Tokens which are associated with peer socket addresses
@@ -130,6 +130,12 @@ frame_types! {
     APPLICATION_CLOSE = 0x1d,
     HANDSHAKE_DONE = 0x1e,
     // DATAGRAM
+    // Multipath extension
+    ACK_MP = 0x15228c00,
+    ACK_MP_ECN = 0x15228c01,
+    PATH_ABANDON = 0x15228c05,
+    PATH_STANDBY = 0x15228c07,
+    PATH_AVAILABLE = 0x15228c08,
 }
 
 const STREAM_TYS: RangeInclusive<u64> = RangeInclusive::new(0x08, 0x0f);
@@ -159,6 +165,9 @@ pub enum Frame {
     Datagram(Datagram),
     Invalid { ty: Type, reason: &'static str },
     HandshakeDone,
+    AckMp { path_id: u64, ack: Ack },
+    PathAbandon(PathAbandon),
+    PathStatus(PathStatus),
 }
 
 impl Frame {
@@ -199,6 +208,12 @@ impl Frame {
             Datagram(_) => Type(*DATAGRAM_TYS.start()),
             Invalid { ty, .. } => ty,
             HandshakeDone => Type::HANDSHAKE_DONE,
+            AckMp { .. } => Type::ACK_MP,
+            PathAbandon(_) => Type::PATH_ABANDON,
+            PathStatus(self::PathStatus {
+                available: true, ..
+            }) => Type::PATH_AVAILABLE,
+            PathStatus(_) => Type::PATH_STANDBY,
         }
     }
 }
@@ -371,15 +386,41 @@ impl Ack {
         ecn: Option<&EcnCounts>,
         buf: &mut W,
     ) {
-        let mut rest = ranges.iter().rev();
-        let first = rest.next().unwrap();
-        let largest = first.end - 1;
-        let first_size = first.end - first.start;
         buf.write(if ecn.is_some() {
             Type::ACK_ECN
         } else {
             Type::ACK
         });
+        Self::encode_body(delay, ranges, ecn, buf);
+    }
+
+    /// Encode an ACK_MP frame, acknowledging packets received on the path `path_id`
+    pub fn encode_mp<W: BufMut>(
+        path_id: u64,
+        delay: u64,
+        ranges: &ArrayRangeSet,
+        ecn: Option<&EcnCounts>,
+        buf: &mut W,
+    ) {
+        buf.write(if ecn.is_some() {
+            Type::ACK_MP_ECN
+        } else {
+            Type::ACK_MP
+        });
+        buf.write_var(path_id);
+        Self::encode_body(delay, ranges, ecn, buf);
+    }
+
+    fn encode_body<W: BufMut>(
+        delay: u64,
+        ranges: &ArrayRangeSet,
+        ecn: Option<&EcnCounts>,
+        buf: &mut W,
+    ) {
+        let mut rest = ranges.iter().rev();
+        let first = rest.next().unwrap();
+        let largest = first.end - 1;
+        let first_size = first.end - first.start;
         buf.write_var(largest);
         buf.write_var(delay);
         buf.write_var(ranges.len() as u64 - 1);
@@ -623,28 +664,21 @@ impl Iter {
             Type::RETIRE_CONNECTION_ID => Frame::RetireConnectionId {
                 sequence: self.bytes.get_var()?,
             },
-            Type::ACK | Type::ACK_ECN => {
-                let largest = self.bytes.get_var()?;
-                let delay = self.bytes.get_var()?;
-                let extra_blocks = self.bytes.get_var()? as usize;
-                let start = self.bytes.position() as usize;
-                scan_ack_blocks(&mut self.bytes, largest, extra_blocks)?;
-                let end = self.bytes.position() as usize;
-                Frame::Ack(Ack {
-                    delay,
-                    largest,
-                    additional: self.bytes.get_ref().slice(start..end),
-                    ecn: if ty != Type::ACK_ECN {
-                        None
-                    } else {
-                        Some(EcnCounts {
-                            ect0: self.bytes.get_var()?,
-                            ect1: self.bytes.get_var()?,
-                            ce: self.bytes.get_var()?,
-                        })
-                    },
-                })
-            }
+            Type::ACK | Type::ACK_ECN => Frame::Ack(self.take_ack(ty == Type::ACK_ECN)?),
+            Type::ACK_MP | Type::ACK_MP_ECN => Frame::AckMp {
+                path_id: self.bytes.get_var()?,
+                ack: self.take_ack(ty == Type::ACK_MP_ECN)?,
+            },
+            Type::PATH_ABANDON => Frame::PathAbandon(PathAbandon {
+                path_id: self.bytes.get_var()?,
+                error_code: self.bytes.get()?,
+                reason: self.take_len()?,
+            }),
+            Type::PATH_STANDBY | Type::PATH_AVAILABLE => Frame::PathStatus(PathStatus {
+                path_id: self.bytes.get_var()?,
+                seq: self.bytes.get_var()?,
+                available: ty == Type::PATH_AVAILABLE,
+            }),
             Type::PATH_CHALLENGE => Frame::PathChallenge(self.bytes.get()?),
             Type::PATH_RESPONSE => Frame::PathResponse(self.bytes.get()?),
             Type::NEW_CONNECTION_ID => {
@@ -710,6 +744,29 @@ impl Iter {
         })
     }
 
+    fn take_ack(&mut self, ecn: bool) -> Result<Ack, IterErr> {
+        let largest = self.bytes.get_var()?;
+        let delay = self.bytes.get_var()?;
+        let extra_blocks = self.bytes.get_var()? as usize;
+        let start = self.bytes.position() as usize;
+        scan_ack_blocks(&mut self.bytes, largest, extra_blocks)?;
+        let end = self.bytes.position() as usize;
+        Ok(Ack {
+            delay,
+            largest,
+            additional: self.bytes.get_ref().slice(start..end),
+            ecn: if !ecn {
+                None
+            } else {
+                Some(EcnCounts {
+                    ect0: self.bytes.get_var()?,
+                    ect1: self.bytes.get_var()?,
+                    ce: self.bytes.get_var()?,
+                })
+            },
+        })
+    }
+
     fn take_remaining(&mut self) -> Bytes {
         let mut x = mem::replace(self.bytes.get_mut(), Bytes::new());
         x.advance(self.bytes.position() as usize);
@@ -869,6 +926,65 @@ impl Datagram {
     }
 }
 
+/// Tells the peer that a path of a multipath connection will no longer be used
+#[derive(Debug, Clone)]
+pub struct PathAbandon {
+    /// Sequence number of the connection ID identifying the path
+    pub path_id: u64,
+    /// Application-specific reason code
+    pub error_code: VarInt,
+    /// Human-readable reason for abandoning the path
+    pub reason: Bytes,
+}
+
+impl FrameStruct for PathAbandon {
+    const SIZE_BOUND: usize = 4 + 8 + 8 + 8;
+}
+
+impl PathAbandon {
+    pub(crate) fn encode<W: BufMut>(&self, out: &mut W) {
+        out.write(Type::PATH_ABANDON); // 4 bytes
+        out.write_var(self.path_id); // <= 8 bytes
+        out.write(self.error_code); // <= 8 bytes
+        out.write_var(self.reason.len() as u64); // <= 8 bytes
+        out.put_slice(&self.reason);
+    }
+
+    pub(crate) fn size(&self) -> usize {
+        4 + VarInt::from_u64(self.path_id).unwrap().size()
+            + self.error_code.size()
+            + VarInt::from_u64(self.reason.len() as u64).unwrap().size()
+            + self.reason.len()
+    }
+}
+
+/// Tells the peer whether a path of a multipath connection should be used to send data
+#[derive(Debug, Copy, Clone)]
+pub struct PathStatus {
+    /// Sequence number of the connection ID identifying the path
+    pub path_id: u64,
+    /// Increases with every status change, so that reordered frames can be ignored
+    pub seq: u64,
+    /// Whether the path is available for data, as opposed to being on standby
+    pub available: bool,
+}
+
+impl FrameStruct for PathStatus {
+    const SIZE_BOUND: usize = 4 + 8 + 8;
+}
+
+impl PathStatus {
+    pub(crate) fn encode<W: BufMut>(&self, out: &mut W) {
+        out.write(if self.available {
+            Type::PATH_AVAILABLE
+        } else {
+            Type::PATH_STANDBY
+        }); // 4 bytes
+        out.write_var(self.path_id); // <= 8 bytes
+        out.write_var(self.seq); // <= 8 bytes
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -900,4 +1016,53 @@ mod test {
             ref x => panic!("incorrect frame {:?}", x),
         }
     }
+
+    #[test]
+    #[allow(clippy::range_plus_one)]
+    fn multipath_coding() {
+        let mut ranges = ArrayRangeSet::new();
+        ranges.insert(3..5);
+        ranges.insert(7..8);
+        let mut buf = Vec::new();
+        Ack::encode_mp(2, 17, &ranges, None, &mut buf);
+        PathAbandon {
+            path_id: 3,
+            error_code: VarInt(42),
+            reason: Bytes::from_static(b"gone"),
+        }
+        .encode(&mut buf);
+        PathStatus {
+            path_id: 1,
+            seq: 5,
+            available: false,
+        }
+        .encode(&mut buf);
+        let frames = Iter::new(Bytes::from(buf)).collect::<Vec<_>>();
+        assert_eq!(frames.len(), 3);
+        match frames[0] {
+            Frame::AckMp { path_id, ref ack } => {
+                assert_eq!(path_id, 2);
+                assert_eq!(ack.delay, 17);
+                let mut packets = ack.iter().flatten().collect::<Vec<_>>();
+                packets.sort_unstable();
+                assert_eq!(packets, [3, 4, 7]);
+                assert_eq!(ack.ecn, None);
+            }
+            ref x => panic!("incorrect frame {:?}", x),
+        }
+        match frames[1] {
+            Frame::PathAbandon(ref x) => {
+                assert_eq!(x.path_id, 3);
+                assert_eq!(x.error_code, VarInt(42));
+                assert_eq!(&x.reason[..], b"gone");
+            }
+            ref x => panic!("incorrect frame {:?}", x),
+        }
+        match frames[2] {
+            Frame::PathStatus(x) => {
+                assert_eq!((x.path_id, x.seq, x.available), (1, 5, false));
+            }
+            ref x => panic!("incorrect frame {:?}", x),
+        }
+    }
 }
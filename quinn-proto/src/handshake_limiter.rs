@@ -0,0 +1,261 @@
+//! Per-source rate limiting of incoming connection attempts
+
+use std::{
+    collections::{HashMap, VecDeque},
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use crate::config::HandshakeLimitConfig;
+
+/// Token buckets for the address prefixes connection attempts have recently come from
+///
+/// Uses a standard `HashMap` to protect against hash collision attacks, since keys are chosen by
+/// the peer.
+#[derive(Debug)]
+pub(crate) struct HandshakeLimiter {
+    config: HandshakeLimitConfig,
+    buckets: HashMap<IpAddr, Bucket>,
+    /// Keys of `buckets`, oldest first
+    order: VecDeque<IpAddr>,
+    /// Shared by all sources that can't be tracked because `buckets` is full
+    overflow: Bucket,
+}
+
+impl HandshakeLimiter {
+    pub(crate) fn new(config: HandshakeLimitConfig) -> Self {
+        Self {
+            overflow: Bucket {
+                tokens: config.burst,
+                updated: Instant::now(),
+            },
+            config,
+            buckets: HashMap::new(),
+            order: VecDeque::new(),
+        }
+    }
+
+    /// Take a token for a connection attempt from `source`, returning whether one was available
+    pub(crate) fn check(&mut self, now: Instant, source: IpAddr) -> bool {
+        let key = self.config.prefix(source);
+        if !self.buckets.contains_key(&key) && self.buckets.len() >= self.config.max_sources {
+            self.evict_oldest(now);
+        }
+        let bucket = if self.buckets.len() < self.config.max_sources {
+            let config = &self.config;
+            let order = &mut self.order;
+            self.buckets.entry(key).or_insert_with(|| {
+                order.push_back(key);
+                Bucket {
+                    tokens: config.burst,
+                    updated: now,
+                }
+            })
+        } else {
+            match self.buckets.get_mut(&key) {
+                Some(bucket) => bucket,
+                // Spreading attempts over more prefixes than we can track mustn't lift the limit,
+                // so the excess is limited as if it came from a single source.
+                None => &mut self.overflow,
+            }
+        };
+        bucket.take(now, &self.config)
+    }
+
+    /// Number of address prefixes currently tracked
+    pub(crate) fn sources(&self) -> usize {
+        self.buckets.len()
+    }
+
+    /// Forget the oldest bucket if it has refilled completely, since it's then equivalent to an
+    /// untracked one
+    ///
+    /// Buckets that haven't refilled are moved to the back of the queue, so that a bucket which
+    /// keeps getting drained can't stop others from being reclaimed.
+    fn evict_oldest(&mut self, now: Instant) {
+        let key = match self.order.pop_front() {
+            Some(x) => x,
+            None => return,
+        };
+        let bucket = self.buckets.get_mut(&key).unwrap();
+        bucket.refill(now, &self.config);
+        if bucket.tokens >= self.config.burst {
+            self.buckets.remove(&key);
+        } else {
+            self.order.push_back(key);
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: u32,
+    /// When `tokens` was last brought up to date
+    updated: Instant,
+}
+
+impl Bucket {
+    /// Take a token, returning whether one was available
+    fn take(&mut self, now: Instant, config: &HandshakeLimitConfig) -> bool {
+        self.refill(now, config);
+        if self.tokens == 0 {
+            return false;
+        }
+        self.tokens -= 1;
+        true
+    }
+
+    fn refill(&mut self, now: Instant, config: &HandshakeLimitConfig) {
+        if self.tokens >= config.burst {
+            self.updated = now;
+            return;
+        }
+        if config.interval == Duration::new(0, 0) {
+            self.tokens = config.burst;
+            self.updated = now;
+            return;
+        }
+        let elapsed = now.saturating_duration_since(self.updated);
+        let new = elapsed.as_nanos() / config.interval.as_nanos();
+        if new == 0 {
+            return;
+        }
+        if new >= u128::from(config.burst - self.tokens) {
+            self.tokens = config.burst;
+            self.updated = now;
+        } else {
+            self.tokens += new as u32;
+            // Keep the remainder so that frequent checks don't starve the bucket
+            self.updated += config.interval * new as u32;
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::{Ipv4Addr, Ipv6Addr};
+
+    fn config() -> HandshakeLimitConfig {
+        let mut config = HandshakeLimitConfig::default();
+        config.burst(2).interval(Duration::from_millis(100));
+        config
+    }
+
+    #[test]
+    fn burst_then_refill() {
+        let mut limiter = HandshakeLimiter::new(config());
+        let now = Instant::now();
+        let source = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+        assert!(limiter.check(now, source));
+        assert!(limiter.check(now, source));
+        assert!(!limiter.check(now, source));
+        assert!(!limiter.check(now + Duration::from_millis(99), source));
+        assert!(limiter.check(now + Duration::from_millis(100), source));
+        assert!(!limiter.check(now + Duration::from_millis(150), source));
+        assert!(limiter.check(now + Duration::from_millis(200), source));
+    }
+
+    #[test]
+    fn sources_are_independent() {
+        let mut limiter = HandshakeLimiter::new(config());
+        let now = Instant::now();
+        let a = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+        let b = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+        assert!(limiter.check(now, a));
+        assert!(limiter.check(now, a));
+        assert!(!limiter.check(now, a));
+        assert!(limiter.check(now, b));
+        assert_eq!(limiter.sources(), 2);
+    }
+
+    #[test]
+    fn prefixes_share_a_bucket() {
+        let mut config = config();
+        config
+            .ipv4_prefix_len(24)
+            .unwrap()
+            .ipv6_prefix_len(48)
+            .unwrap();
+        let mut limiter = HandshakeLimiter::new(config);
+        let now = Instant::now();
+        assert!(limiter.check(now, IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1))));
+        assert!(limiter.check(now, IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2))));
+        assert!(!limiter.check(now, IpAddr::V4(Ipv4Addr::new(192, 0, 2, 3))));
+        assert!(limiter.check(now, IpAddr::V4(Ipv4Addr::new(192, 0, 3, 1))));
+
+        let a = "2001:db8:1:2::1".parse::<Ipv6Addr>().unwrap();
+        let b = "2001:db8:1:3::1".parse::<Ipv6Addr>().unwrap();
+        assert!(limiter.check(now, IpAddr::V6(a)));
+        assert!(limiter.check(now, IpAddr::V6(b)));
+        assert!(!limiter.check(now, IpAddr::V6(a)));
+        assert_eq!(limiter.sources(), 3);
+    }
+
+    #[test]
+    fn mapped_ipv4_uses_ipv4_prefix() {
+        let mut config = config();
+        config
+            .ipv4_prefix_len(24)
+            .unwrap()
+            .ipv6_prefix_len(48)
+            .unwrap();
+        let mut limiter = HandshakeLimiter::new(config);
+        let now = Instant::now();
+        let a = "::ffff:192.0.2.1".parse::<Ipv6Addr>().unwrap();
+        let b = "::ffff:192.0.3.1".parse::<Ipv6Addr>().unwrap();
+        assert!(limiter.check(now, IpAddr::V6(a)));
+        assert!(limiter.check(now, IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2))));
+        assert!(!limiter.check(now, IpAddr::V6(a)));
+        assert!(limiter.check(now, IpAddr::V6(b)));
+        assert_eq!(limiter.sources(), 2);
+    }
+
+    #[test]
+    fn untracked_sources_share_a_bucket() {
+        let mut config = config();
+        config.max_sources(1);
+        let mut limiter = HandshakeLimiter::new(config);
+        let now = Instant::now();
+        let a = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+        let b = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+        let c = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 3));
+        assert!(limiter.check(now, a));
+        assert!(limiter.check(now, b));
+        assert!(limiter.check(now, c));
+        assert!(!limiter.check(now, b));
+        assert!(!limiter.check(now, c));
+        assert_eq!(limiter.sources(), 1);
+        // Once `a` has refilled, its bucket can be reclaimed
+        let later = now + Duration::from_millis(100);
+        assert!(limiter.check(later, b));
+        assert!(limiter.check(later, b));
+        assert!(!limiter.check(later, b));
+        assert_eq!(limiter.sources(), 1);
+    }
+
+    #[test]
+    fn drained_buckets_are_not_evicted() {
+        let mut config = config();
+        config.max_sources(2);
+        let mut limiter = HandshakeLimiter::new(config);
+        let now = Instant::now();
+        let a = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1));
+        let b = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2));
+        let c = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 3));
+        assert!(limiter.check(now, a));
+        assert!(limiter.check(now, a));
+        assert!(limiter.check(now, b));
+        // `a` is the oldest but still drained, so `c` isn't tracked and `a` stays limited
+        let later = now + Duration::from_millis(150);
+        assert!(limiter.check(later, c));
+        assert!(limiter.check(later, a));
+        assert!(!limiter.check(later, a));
+        assert_eq!(limiter.sources(), 2);
+        // `b` has refilled by now, so it makes way for `c`
+        assert!(limiter.check(later, c));
+        assert!(limiter.check(later, c));
+        assert!(!limiter.check(later, c));
+        assert_eq!(limiter.sources(), 2);
+    }
+}
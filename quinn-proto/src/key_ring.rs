@@ -0,0 +1,108 @@
+use std::{
+    fmt,
+    sync::{Arc, RwLock},
+};
+
+/// A set of secret keys identified by one-byte IDs, which can be rotated while in use
+///
+/// The most recently added key is used to create new values, such as address validation tokens,
+/// while every key in the ring is accepted when checking them. Rotating keys this way doesn't
+/// invalidate tokens issued shortly beforehand, or by other servers sharing the keys that haven't
+/// rotated yet. Rings are shared by reference with the endpoints and connections configured with
+/// them, so changes take effect immediately.
+pub struct KeyRing<K> {
+    /// Keys in the ring, current first
+    keys: RwLock<Vec<(u8, Arc<K>)>>,
+}
+
+impl<K> KeyRing<K> {
+    /// Create a ring containing only `key`, identified by `id`
+    pub fn new(id: u8, key: K) -> Self {
+        Self {
+            keys: RwLock::new(vec![(id, Arc::new(key))]),
+        }
+    }
+
+    /// Start using `key`, identified by `id`, to create new values
+    ///
+    /// Previous keys remain accepted until they're removed. Replaces any key with the same `id`.
+    pub fn rotate(&self, id: u8, key: K) {
+        let mut keys = self.keys.write().unwrap();
+        keys.retain(|&(x, _)| x != id);
+        keys.insert(0, (id, Arc::new(key)));
+    }
+
+    /// Stop accepting the key identified by `id`
+    ///
+    /// Returns whether a key was removed. The current key can't be removed, only replaced with
+    /// `rotate()`.
+    pub fn remove(&self, id: u8) -> bool {
+        let mut keys = self.keys.write().unwrap();
+        match keys.iter().skip(1).position(|&(x, _)| x == id) {
+            Some(i) => {
+                keys.remove(i + 1);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// IDs of the keys in the ring, starting with the current one
+    pub fn ids(&self) -> Vec<u8> {
+        self.keys
+            .read()
+            .unwrap()
+            .iter()
+            .map(|&(id, _)| id)
+            .collect()
+    }
+
+    /// The key new values should be created with, and its ID
+    pub(crate) fn current(&self) -> (u8, Arc<K>) {
+        let keys = self.keys.read().unwrap();
+        (keys[0].0, keys[0].1.clone())
+    }
+
+    /// The key identified by `id`, if it's still accepted
+    pub(crate) fn get(&self, id: u8) -> Option<Arc<K>> {
+        let keys = self.keys.read().unwrap();
+        keys.iter()
+            .find(|&&(x, _)| x == id)
+            .map(|(_, key)| key.clone())
+    }
+}
+
+impl<K> fmt::Debug for KeyRing<K> {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt.debug_struct("KeyRing")
+            .field("ids", &self.ids())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn rotation() {
+        let ring = KeyRing::new(1, "a");
+        assert_eq!(*ring.current().1, "a");
+        ring.rotate(2, "b");
+        assert_eq!(ring.current().0, 2);
+        assert_eq!(ring.get(1).as_deref(), Some(&"a"));
+        assert_eq!(ring.ids(), vec![2, 1]);
+
+        // The current key can't be removed
+        assert!(!ring.remove(2));
+        assert!(ring.remove(1));
+        assert!(!ring.remove(1));
+        assert_eq!(ring.get(1), None);
+
+        // Reusing an ID replaces the key
+        ring.rotate(3, "c");
+        ring.rotate(2, "d");
+        assert_eq!(ring.ids(), vec![2, 3]);
+        assert_eq!(*ring.current().1, "d");
+    }
+}
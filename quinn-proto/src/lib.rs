@@ -25,6 +25,7 @@ use std::{
     time::Duration,
 };
 
+mod anti_replay;
 mod cid_queue;
 #[doc(hidden)]
 pub mod coding;
@@ -40,13 +41,15 @@ pub use varint::{VarInt, VarIntBoundsExceeded};
 
 mod connection;
 pub use crate::connection::{
-    BytesSource, Chunk, Chunks, ConnectionError, ConnectionStats, Event, FinishError, ReadError,
-    ReadableError, RecvStream, SendDatagramError, SendStream, StreamEvent, Streams, UnknownStream,
-    WriteError, Written,
+    BytesSource, Chunk, Chunks, CongestionSnapshot, ConnectionError, ConnectionStats, Event,
+    FinishError, MigrationError, PathError, ReadError, ReadableError, RecvStream, RttEstimator,
+    SendDatagramError, SendStream, StreamEvent, Streams, UnknownStream, WriteError, Written,
 };
 
 mod config;
-pub use config::{ConfigError, TransportConfig};
+pub use config::{
+    AntiReplayConfig, ConfigError, HandshakeLimitConfig, MtuDiscoveryConfig, TransportConfig,
+};
 
 pub mod crypto;
 #[cfg(feature = "rustls")]
@@ -54,10 +57,14 @@ pub use crypto::types::*;
 
 mod frame;
 use crate::frame::Frame;
+
+mod handshake_limiter;
 pub use crate::frame::{ApplicationClose, ConnectionClose, Datagram};
 
 mod endpoint;
-pub use crate::endpoint::{ConnectError, ConnectionHandle, DatagramEvent};
+pub use crate::endpoint::{
+    ConnectError, ConnectionHandle, DatagramEvent, EndpointStats, IncomingConnection, RetryError,
+};
 
 mod shared;
 pub use crate::shared::{ConnectionEvent, ConnectionId, EcnCodepoint, EndpointEvent};
@@ -67,11 +74,29 @@ pub use crate::transport_error::{Code as TransportErrorCode, Error as TransportE
 
 pub mod congestion;
 
+pub mod multipath;
+pub use crate::multipath::{PathId, PathInfo, PathStatus};
+
+pub mod quic_lb;
+
 mod cid_generator;
+mod client_hello;
 pub use crate::cid_generator::{ConnectionIdGenerator, RandomConnectionIdGenerator};
 
+mod key_ring;
+pub use crate::key_ring::KeyRing;
+
 mod token;
-use token::{ResetToken, RetryToken};
+use token::{ResetToken, RetryToken, TokenType, ValidationToken};
+
+mod token_store;
+pub use crate::token_store::{TokenMemoryCache, TokenStore};
+
+mod session_store;
+pub use crate::session_store::SessionStore;
+
+mod zero_rtt_policy;
+pub use crate::zero_rtt_policy::{ZeroRttPolicy, ZeroRttRequest};
 
 /// Types that are generic over the crypto protocol implementation
 pub mod generic {
@@ -148,9 +173,29 @@ pub mod fuzzing {
     }
 }
 
-/// The QUIC protocol version implemented.
-pub const DEFAULT_SUPPORTED_VERSIONS: &[u32] =
-    &[0xff00_001d, 0xff00_001e, 0xff00_001f, 0xff00_0020];
+/// The QUIC protocol versions supported by default: QUIC version 1 (RFC 9000)
+pub const DEFAULT_SUPPORTED_VERSIONS: &[u32] = &[0x0000_0001];
+
+/// Draft QUIC versions that can still be enabled for interoperability with legacy peers
+///
+/// These are not advertised or accepted unless explicitly passed to
+/// `EndpointConfig::supported_versions`.
+pub const LEGACY_DRAFT_VERSIONS: &[u32] = &[0xff00_001d, 0xff00_001e, 0xff00_001f, 0xff00_0020];
+
+/// QUIC version 2 (RFC 9369)
+///
+/// Not enabled by default; add it to `EndpointConfig::supported_versions` to accept it, and select
+/// it through `ClientConfig::version` or the endpoint's initial version to use it. Requires a crypto
+/// session that supports it, which the rustls session doesn't.
+pub const QUIC_VERSION_2: u32 = 0x6b33_43cf;
+
+/// Whether a connection begun with version `from` may be upgraded to version `to` through
+/// compatible version negotiation (RFC 9368)
+///
+/// QUIC v1 and v2 are compatible with each other (RFC 9369 §4).
+fn is_compatible_version(from: u32, to: u32) -> bool {
+    from == to || matches!((from, to), (1, QUIC_VERSION_2) | (QUIC_VERSION_2, 1))
+}
 
 /// Whether an endpoint was the initiator of a connection
 #[cfg_attr(feature = "arbitrary", derive(Arbitrary))]
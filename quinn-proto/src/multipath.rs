@@ -0,0 +1,115 @@
+//! Support for the QUIC multipath extension
+//!
+//! When both peers enable it with [`TransportConfig::enable_multipath()`], a client can open
+//! additional paths to the server, e.g. one over each of its network interfaces, and traffic is
+//! spread across every path at once. Each path keeps its own packet numbers, RTT estimate,
+//! congestion controller and pacer. A [`PathScheduler`] decides which paths new application data
+//! is sent on.
+//!
+//! [`TransportConfig::enable_multipath()`]: crate::TransportConfig::enable_multipath
+
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+/// Identifier for a path within a multipath connection
+///
+/// The path a connection is established on is [`PathId::INITIAL`]. Other paths are numbered after
+/// the sequence number of the connection IDs they use, and numbers are never reused.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct PathId(pub(crate) u32);
+
+impl PathId {
+    /// The path the connection was established on
+    pub const INITIAL: Self = Self(0);
+
+    /// Extract the integer value, which is also the path identifier used on the wire
+    pub fn into_inner(self) -> u32 {
+        self.0
+    }
+}
+
+impl fmt::Display for PathId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "path {}", self.0)
+    }
+}
+
+/// Whether a path should be used to send application data
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum PathStatus {
+    /// The path may carry application data
+    Available,
+    /// The path should only carry application data if no available path can
+    ///
+    /// Standby paths are still validated and kept alive, so they can take over quickly.
+    Standby,
+}
+
+/// A snapshot of the state of one path of a connection
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub struct PathInfo {
+    /// Identifier of the path
+    pub id: PathId,
+    /// Address of the peer on this path
+    pub remote: SocketAddr,
+    /// Local IP address packets on this path are sent from, if one was specified
+    pub local_ip: Option<IpAddr>,
+    /// Status we asked the peer to treat the path with
+    pub status: PathStatus,
+    /// Status the peer asked us to treat the path with
+    pub peer_status: PathStatus,
+    /// Whether the peer has proven to be reachable over the path
+    pub validated: bool,
+    /// Current best estimate of the path's round-trip time
+    pub rtt: Duration,
+    /// Current congestion window of the path
+    pub cwnd: u64,
+    /// Number of bytes sent on the path that have been neither acknowledged nor deemed lost
+    pub bytes_in_flight: u64,
+}
+
+/// Chooses which paths new application data is sent on
+///
+/// Packets that concern a particular path, such as acknowledgements and path validation, are
+/// always sent on that path regardless of the scheduler.
+pub trait PathScheduler: Send {
+    /// Sort `paths` into the order in which they should be offered new application data
+    ///
+    /// Each path is used until its congestion window or pacing stops it before data is offered to
+    /// the next one. Only validated paths are passed, and standby paths only when no available path
+    /// is usable. Called every time the connection looks for packets to send.
+    fn prioritize(&mut self, paths: &mut [PathInfo]);
+}
+
+/// Sends on the path with the lowest round-trip time, spilling over onto slower paths when it
+/// can't send any more
+///
+/// This is the default scheduler.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct MinRtt;
+
+impl PathScheduler for MinRtt {
+    fn prioritize(&mut self, paths: &mut [PathInfo]) {
+        paths.sort_by_key(|x| x.rtt);
+    }
+}
+
+/// Takes turns between paths, regardless of their round-trip times
+#[derive(Debug, Copy, Clone, Default)]
+pub struct RoundRobin {
+    next: usize,
+}
+
+impl PathScheduler for RoundRobin {
+    fn prioritize(&mut self, paths: &mut [PathInfo]) {
+        if paths.is_empty() {
+            return;
+        }
+        paths.rotate_left(self.next % paths.len());
+        self.next = self.next.wrapping_add(1);
+    }
+}
@@ -5,7 +5,7 @@ use thiserror::Error;
 
 use crate::{
     coding::{self, BufExt, BufMutExt},
-    crypto, ConnectionId,
+    crypto, ConnectionId, QUIC_VERSION_2,
 };
 
 // Due to packet number encryption, it is impossible to fully decode a header
@@ -55,6 +55,11 @@ impl PartialDecode {
         self.buf.get_ref()
     }
 
+    /// Recover the undecoded packet data
+    pub(crate) fn into_data(self) -> BytesMut {
+        self.buf.into_inner()
+    }
+
     pub(crate) fn has_long_header(&self) -> bool {
         !matches!(self.plain_header, PlainHeader::Short { .. })
     }
@@ -91,6 +96,15 @@ impl PartialDecode {
         self.plain_header.dst_cid()
     }
 
+    /// The QUIC version of a long header packet
+    pub(crate) fn version(&self) -> Option<u32> {
+        use self::PlainHeader::*;
+        match self.plain_header {
+            Initial { version, .. } | Long { version, .. } | Retry { version, .. } => Some(version),
+            Short { .. } | VersionNegotiate { .. } => None,
+        }
+    }
+
     /// Length of QUIC packet being decoded
     pub fn len(&self) -> usize {
         self.buf.get_ref().len()
@@ -271,7 +285,7 @@ impl Header {
                 number,
                 version,
             } => {
-                w.write(u8::from(LongHeaderType::Initial) | number.tag());
+                w.write(LongHeaderType::Initial.to_byte(version) | number.tag());
                 w.write(version);
                 dst_cid.encode_long(w);
                 src_cid.encode_long(w);
@@ -292,7 +306,7 @@ impl Header {
                 number,
                 version,
             } => {
-                w.write(u8::from(LongHeaderType::Standard(ty)) | number.tag());
+                w.write(LongHeaderType::Standard(ty).to_byte(version) | number.tag());
                 w.write(version);
                 dst_cid.encode_long(w);
                 src_cid.encode_long(w);
@@ -309,7 +323,7 @@ impl Header {
                 ref src_cid,
                 version,
             } => {
-                w.write(u8::from(LongHeaderType::Retry));
+                w.write(LongHeaderType::Retry.to_byte(version));
                 w.write(version);
                 dst_cid.encode_long(w);
                 src_cid.encode_long(w);
@@ -438,8 +452,16 @@ pub(crate) struct PartialEncode {
 }
 
 impl PartialEncode {
-    pub(crate) fn finish<K, H>(self, buf: &mut [u8], header_crypto: &H, crypto: Option<(u64, &K)>)
-    where
+    /// Fill in the packet length, then protect the payload and header
+    ///
+    /// `crypto` holds the path ID and packet number used to form the payload protection nonce,
+    /// along with the key.
+    pub(crate) fn finish<K, H>(
+        self,
+        buf: &mut [u8],
+        header_crypto: &H,
+        crypto: Option<(u32, u64, &K)>,
+    ) where
         K: crypto::PacketKey,
         H: crypto::HeaderKey,
     {
@@ -457,8 +479,8 @@ impl PartialEncode {
             slice.put_u16(len as u16 | 0b01 << 14);
         }
 
-        if let Some((number, crypto)) = crypto {
-            crypto.encrypt(number, buf, header_len);
+        if let Some((path_id, number, crypto)) = crypto {
+            crypto.encrypt_path(path_id, number, buf, header_len);
         }
 
         debug_assert!(
@@ -566,7 +588,7 @@ impl PlainHeader {
                 });
             }
 
-            match LongHeaderType::from_byte(first)? {
+            match LongHeaderType::from_byte(first, version)? {
                 LongHeaderType::Initial => {
                     let token_len = buf.get_var()? as usize;
                     let token_start = buf.position() as usize;
@@ -710,31 +732,31 @@ pub(crate) enum LongHeaderType {
 }
 
 impl LongHeaderType {
-    fn from_byte(b: u8) -> Result<Self, PacketDecodeError> {
+    fn from_byte(b: u8, version: u32) -> Result<Self, PacketDecodeError> {
         use self::{LongHeaderType::*, LongType::*};
         if b & FIXED_BIT == 0 {
             return Err(PacketDecodeError::InvalidHeader("fixed bit unset"));
         }
         debug_assert!(b & LONG_HEADER_FORM != 0, "not a long packet");
-        Ok(match (b & 0x30) >> 4 {
-            0x0 => Initial,
-            0x1 => Standard(ZeroRtt),
-            0x2 => Standard(Handshake),
-            0x3 => Retry,
+        // QUIC v2 rotates the packet type codepoints (RFC 9369 §3.2)
+        Ok(match ((b & 0x30) >> 4, version == QUIC_VERSION_2) {
+            (0x0, false) | (0x1, true) => Initial,
+            (0x1, false) | (0x2, true) => Standard(ZeroRtt),
+            (0x2, false) | (0x3, true) => Standard(Handshake),
+            (0x3, false) | (0x0, true) => Retry,
             _ => unreachable!(),
         })
     }
-}
 
-impl From<LongHeaderType> for u8 {
-    fn from(ty: LongHeaderType) -> u8 {
+    fn to_byte(self, version: u32) -> u8 {
         use self::{LongHeaderType::*, LongType::*};
-        match ty {
-            Initial => LONG_HEADER_FORM | FIXED_BIT,
-            Standard(ZeroRtt) => LONG_HEADER_FORM | FIXED_BIT | (0x1 << 4),
-            Standard(Handshake) => LONG_HEADER_FORM | FIXED_BIT | (0x2 << 4),
-            Retry => LONG_HEADER_FORM | FIXED_BIT | (0x3 << 4),
-        }
+        let ty = match (self, version == QUIC_VERSION_2) {
+            (Initial, false) | (Retry, true) => 0x0,
+            (Standard(ZeroRtt), false) | (Initial, true) => 0x1,
+            (Standard(Handshake), false) | (Standard(ZeroRtt), true) => 0x2,
+            (Retry, false) | (Standard(Handshake), true) => 0x3,
+        };
+        LONG_HEADER_FORM | FIXED_BIT | (ty << 4)
     }
 }
 
@@ -791,7 +813,6 @@ impl SpaceId {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::DEFAULT_SUPPORTED_VERSIONS;
     use hex_literal::hex;
     use std::io;
 
@@ -836,15 +857,16 @@ mod tests {
             Side,
         };
 
+        const VERSION: u32 = 0xff00_001d;
         let dcid = ConnectionId::new(&hex!("06b858ec6f80452b"));
-        let client = TlsSession::initial_keys(&dcid, Side::Client);
+        let client = TlsSession::initial_keys(VERSION, &dcid, Side::Client);
         let mut buf = Vec::new();
         let header = Header::Initial {
             number: PacketNumber::U8(0),
             src_cid: ConnectionId::new(&[]),
             dst_cid: dcid,
             token: Bytes::new(),
-            version: DEFAULT_SUPPORTED_VERSIONS[0],
+            version: VERSION,
         };
         let encode = header.encode(&mut buf);
         let header_len = buf.len();
@@ -852,7 +874,7 @@ mod tests {
         encode.finish(
             &mut buf,
             &client.header.local,
-            Some((0, &client.packet.local)),
+            Some((0, 0, &client.packet.local)),
         );
 
         for byte in &buf {
@@ -867,9 +889,8 @@ mod tests {
             )[..]
         );
 
-        let server = TlsSession::initial_keys(&dcid, Side::Server);
-        let supported_versions = DEFAULT_SUPPORTED_VERSIONS.to_vec();
-        let decode = PartialDecode::new(buf.as_slice().into(), 0, &supported_versions)
+        let server = TlsSession::initial_keys(VERSION, &dcid, Side::Server);
+        let decode = PartialDecode::new(buf.as_slice().into(), 0, &[VERSION])
             .unwrap()
             .0;
         let mut packet = decode.finish(Some(&server.header.remote)).unwrap();
@@ -893,4 +914,68 @@ mod tests {
             }
         }
     }
+
+    /// Protect the server Initial from RFC 9001 Appendix A.3 with the given version's keys
+    #[cfg(feature = "rustls")]
+    fn protect_sample_server_initial(version: u32) -> Vec<u8> {
+        use crate::{
+            crypto::{rustls::TlsSession, Session},
+            Side,
+        };
+
+        let dcid = ConnectionId::new(&hex!("8394c8f03e515708"));
+        let server = TlsSession::initial_keys(version, &dcid, Side::Server);
+        let mut buf = Vec::new();
+        let header = Header::Initial {
+            number: PacketNumber::U16(1),
+            src_cid: ConnectionId::new(&hex!("f067a5502a4262b5")),
+            dst_cid: ConnectionId::new(&[]),
+            token: Bytes::new(),
+            version,
+        };
+        let encode = header.encode(&mut buf);
+        buf.extend_from_slice(&hex!(
+            "02000000000600405a020000560303ee fce7f7b37ba1d1632e96677825ddf739
+             88cfc79825df566dc5430b9a045a1200 130100002e00330024001d00209d3c94
+             0d89690b84d08a60993c144eca684d10 81287c834d5311bcf32bb9da1a002b00
+             020304"
+        ));
+        buf.resize(buf.len() + 16, 0);
+        encode.finish(
+            &mut buf,
+            &server.header.local,
+            Some((0, 1, &server.packet.local)),
+        );
+        buf
+    }
+
+    #[cfg(feature = "rustls")]
+    #[test]
+    fn header_encoding_v1() {
+        assert_eq!(
+            protect_sample_server_initial(1)[..],
+            hex!(
+                "cf000000010008f067a5502a4262b5004075c0d95a482cd0991cd25b0aac406a
+                 5816b6394100f37a1c69797554780bb38cc5a99f5ede4cf73c3ec2493a1839b3
+                 dbcba3f6ea46c5b7684df3548e7ddeb9c3bf9c73cc3f3bded74b562bfb19fb84
+                 022f8ef4cdd93795d77d06edbb7aaf2f58891850abbdca3d20398c276456cbc4
+                 2158407dd074ee"
+            )[..]
+        );
+    }
+
+    /// Packet types of QUIC v2 (RFC 9369 §3.2)
+    #[test]
+    fn long_header_types_v2() {
+        use self::{LongHeaderType::*, LongType::*};
+        for &(ty, byte) in &[
+            (Retry, 0xc0),
+            (Initial, 0xd0),
+            (Standard(ZeroRtt), 0xe0),
+            (Standard(Handshake), 0xf0),
+        ] {
+            assert_eq!(ty.to_byte(QUIC_VERSION_2), byte);
+            assert_eq!(LongHeaderType::from_byte(byte, QUIC_VERSION_2), Ok(ty));
+        }
+    }
 }
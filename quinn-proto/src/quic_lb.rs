@@ -0,0 +1,621 @@
+//! Connection IDs that let load balancers route packets to servers without keeping state
+//!
+//! Implements the connection ID formats of the QUIC-LB draft (draft-ietf-quic-load-balancers)
+//! revisions defining the plaintext, stream cipher and block cipher algorithms. Each connection ID
+//! a server issues encodes its server ID, so that a load balancer sharing the server's [`Config`]
+//! can recover it with a [`Decoder`] and forward packets to the right server, even after the
+//! client's address changes.
+//!
+//! The two most significant bits of every connection ID identify which of up to three
+//! configurations it was generated with, so that configurations can be rotated without disrupting
+//! existing connections. The value `0b11` is reserved for connection IDs that can't be routed.
+
+use std::{fmt, ops::Deref, sync::Arc, time::Duration};
+
+use aes::{
+    cipher::{BlockDecrypt, BlockEncrypt, KeyInit},
+    Aes128, Block,
+};
+use rand::RngCore;
+
+use crate::{
+    cid_generator::ConnectionIdGenerator, config::ConfigError, shared::ConnectionId, MAX_CID_SIZE,
+};
+
+/// Parameters of a connection ID format shared by servers and their load balancer
+#[derive(Clone)]
+pub struct Config {
+    id: u8,
+    server_id_len: usize,
+    cid_len: usize,
+    length_self_description: bool,
+    record_reset_key_id: bool,
+    algorithm: Algorithm,
+}
+
+#[derive(Clone)]
+enum Algorithm {
+    Plaintext,
+    StreamCipher { aes: Arc<Aes128>, nonce_len: usize },
+    BlockCipher { aes: Arc<Aes128> },
+}
+
+impl Config {
+    /// Connection IDs of `cid_len` bytes carrying the server ID in the clear after the first
+    /// octet, followed by random bytes
+    ///
+    /// Observers can link connection IDs issued by the same server, so this is only appropriate
+    /// where that isn't a concern. `id` must be at most 2, and `cid_len` must leave room for the
+    /// first octet and server ID.
+    pub fn plaintext(id: u8, server_id_len: usize, cid_len: usize) -> Result<Self, ConfigError> {
+        if server_id_len == 0 || cid_len < 1 + server_id_len || cid_len > MAX_CID_SIZE {
+            return Err(ConfigError::OutOfBounds);
+        }
+        Self::new(id, server_id_len, cid_len, Algorithm::Plaintext)
+    }
+
+    /// Connection IDs consisting of the first octet, a random nonce of `nonce_len` bytes and the
+    /// server ID, with the nonce and server ID encrypted in three passes of AES-128 with `key`
+    ///
+    /// `nonce_len` must be between 8 and 16, and the connection ID no longer than 20 bytes.
+    pub fn stream_cipher(
+        id: u8,
+        server_id_len: usize,
+        nonce_len: usize,
+        key: [u8; 16],
+    ) -> Result<Self, ConfigError> {
+        let cid_len = 1 + nonce_len + server_id_len;
+        if server_id_len == 0 || !(8..=16).contains(&nonce_len) || cid_len > MAX_CID_SIZE {
+            return Err(ConfigError::OutOfBounds);
+        }
+        let aes = Arc::new(Aes128::new(&key.into()));
+        Self::new(
+            id,
+            server_id_len,
+            cid_len,
+            Algorithm::StreamCipher { aes, nonce_len },
+        )
+    }
+
+    /// 17-byte connection IDs consisting of the first octet and a single AES-128 block
+    /// encrypted with `key`, holding the server ID followed by a random nonce
+    ///
+    /// `server_id_len` must be at most 12, leaving at least 4 bytes of nonce.
+    pub fn block_cipher(id: u8, server_id_len: usize, key: [u8; 16]) -> Result<Self, ConfigError> {
+        if server_id_len == 0 || server_id_len > 12 {
+            return Err(ConfigError::OutOfBounds);
+        }
+        let aes = Arc::new(Aes128::new(&key.into()));
+        Self::new(id, server_id_len, 17, Algorithm::BlockCipher { aes })
+    }
+
+    fn new(
+        id: u8,
+        server_id_len: usize,
+        cid_len: usize,
+        algorithm: Algorithm,
+    ) -> Result<Self, ConfigError> {
+        if id > MAX_CONFIG_ID {
+            return Err(ConfigError::OutOfBounds);
+        }
+        Ok(Self {
+            id,
+            server_id_len,
+            cid_len,
+            length_self_description: false,
+            record_reset_key_id: false,
+            algorithm,
+        })
+    }
+
+    /// Whether the six low bits of the first octet encode the connection ID's length minus one,
+    /// rather than being random
+    ///
+    /// Lets load balancers find the end of connection IDs in short header packets without
+    /// knowing their configuration. Defaults to `false`.
+    pub fn length_self_description(&mut self, value: bool) -> &mut Self {
+        self.length_self_description = value;
+        self
+    }
+
+    /// Whether connection IDs record the ID of the endpoint's current reset key in place of a
+    /// random nonce byte
+    ///
+    /// Lets the endpoint, or a later instance of it, send stateless resets with the key a
+    /// connection ID's reset token was derived from after rotating its reset keys (see
+    /// `EndpointConfig::reset_keys`), rather than always using the current key. The ID is
+    /// encrypted along with the server ID, but leaves one byte less of the nonce random. Not
+    /// supported by plaintext configurations, which would reveal it. Defaults to `false`.
+    pub fn record_reset_key_id(&mut self, value: bool) -> Result<&mut Self, ConfigError> {
+        if value && matches!(self.algorithm, Algorithm::Plaintext) {
+            return Err(ConfigError::OutOfBounds);
+        }
+        self.record_reset_key_id = value;
+        Ok(self)
+    }
+
+    /// The config rotation bits identifying this configuration
+    pub fn id(&self) -> u8 {
+        self.id
+    }
+
+    /// Length of the connection IDs generated with this configuration
+    pub fn cid_len(&self) -> usize {
+        self.cid_len
+    }
+
+    /// Length of the server IDs encoded with this configuration
+    pub fn server_id_len(&self) -> usize {
+        self.server_id_len
+    }
+
+    fn encode(&self, server_id: &[u8], reset_key_id: Option<u8>) -> ConnectionId {
+        let mut cid = [0; MAX_CID_SIZE];
+        let cid = &mut cid[..self.cid_len];
+        rand::thread_rng().fill_bytes(cid);
+        let low_bits = if self.length_self_description {
+            (self.cid_len - 1) as u8
+        } else {
+            cid[0] & LOW_BITS
+        };
+        cid[0] = self.id << 6 | low_bits;
+
+        let payload = &mut cid[1..];
+        let start = self.server_id_start();
+        payload[start..start + self.server_id_len].copy_from_slice(server_id);
+        if let (Some(id), Some(i)) = (reset_key_id, self.reset_key_id_index()) {
+            payload[i] = id;
+        }
+        self.encrypt(payload);
+        ConnectionId::new(cid)
+    }
+
+    /// Recover the server ID from `cid`, which must be exactly `cid_len` bytes long
+    fn decode(&self, cid: &[u8]) -> ServerId {
+        let payload = self.decrypt(cid);
+        let start = self.server_id_start();
+        ServerId::new(&payload[start..start + self.server_id_len])
+    }
+
+    /// Offset of the server ID in the plaintext following the first octet
+    fn server_id_start(&self) -> usize {
+        match self.algorithm {
+            Algorithm::StreamCipher { nonce_len, .. } => nonce_len,
+            Algorithm::Plaintext | Algorithm::BlockCipher { .. } => 0,
+        }
+    }
+
+    /// Offset of the nonce byte in the plaintext following the first octet that records a reset
+    /// key ID instead, if any
+    fn reset_key_id_index(&self) -> Option<usize> {
+        if !self.record_reset_key_id {
+            return None;
+        }
+        match self.algorithm {
+            Algorithm::Plaintext => None,
+            Algorithm::StreamCipher { nonce_len, .. } => Some(nonce_len - 1),
+            Algorithm::BlockCipher { .. } => Some(15),
+        }
+    }
+
+    /// Encrypt the plaintext following the first octet in place
+    fn encrypt(&self, payload: &mut [u8]) {
+        match self.algorithm {
+            Algorithm::Plaintext => {}
+            Algorithm::StreamCipher { ref aes, nonce_len } => {
+                let (nonce, sid) = payload.split_at_mut(nonce_len);
+                stream_passes(aes, nonce, sid);
+            }
+            Algorithm::BlockCipher { ref aes } => {
+                aes.encrypt_block(Block::from_mut_slice(payload));
+            }
+        }
+    }
+
+    /// Recover the plaintext following the first octet of `cid`, which must be exactly `cid_len`
+    /// bytes long
+    fn decrypt(&self, cid: &[u8]) -> [u8; MAX_CID_SIZE] {
+        let mut buf = [0; MAX_CID_SIZE];
+        let payload = &mut buf[..cid.len() - 1];
+        payload.copy_from_slice(&cid[1..]);
+        match self.algorithm {
+            Algorithm::Plaintext => {}
+            Algorithm::StreamCipher { ref aes, nonce_len } => {
+                let (nonce, sid) = payload.split_at_mut(nonce_len);
+                // The three passes are their own inverse
+                stream_passes(aes, nonce, sid);
+            }
+            Algorithm::BlockCipher { ref aes } => {
+                aes.decrypt_block(Block::from_mut_slice(payload));
+            }
+        }
+        buf
+    }
+}
+
+impl fmt::Debug for Config {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let algorithm = match self.algorithm {
+            Algorithm::Plaintext => "plaintext",
+            Algorithm::StreamCipher { .. } => "stream cipher",
+            Algorithm::BlockCipher { .. } => "block cipher",
+        };
+        f.debug_struct("Config")
+            .field("id", &self.id)
+            .field("server_id_len", &self.server_id_len)
+            .field("cid_len", &self.cid_len)
+            .field("length_self_description", &self.length_self_description)
+            .field("record_reset_key_id", &self.record_reset_key_id)
+            .field("algorithm", &algorithm)
+            .finish()
+    }
+}
+
+/// XOR each half of the connection ID with the encryption of the other, three times over
+fn stream_passes(aes: &Aes128, nonce: &mut [u8], server_id: &mut [u8]) {
+    xor_encrypted(aes, server_id, nonce);
+    xor_encrypted(aes, nonce, server_id);
+    xor_encrypted(aes, server_id, nonce);
+}
+
+/// XOR `target` with the encryption of `input` padded with zeroes to a full block
+fn xor_encrypted(aes: &Aes128, target: &mut [u8], input: &[u8]) {
+    let mut block = Block::default();
+    block[..input.len()].copy_from_slice(input);
+    aes.encrypt_block(&mut block);
+    for (x, y) in target.iter_mut().zip(block.iter()) {
+        *x ^= y;
+    }
+}
+
+/// Generates connection IDs encoding a server ID according to a QUIC-LB [`Config`]
+#[derive(Debug, Clone)]
+pub struct RoutableConnectionIdGenerator {
+    config: Config,
+    server_id: ServerId,
+    lifetime: Option<Duration>,
+}
+
+impl RoutableConnectionIdGenerator {
+    /// Generate connection IDs routing to `server_id` under `config`
+    ///
+    /// Fails if `server_id` isn't `config.server_id_len()` bytes long.
+    pub fn new(config: Config, server_id: &[u8]) -> Result<Self, ConfigError> {
+        if server_id.len() != config.server_id_len {
+            return Err(ConfigError::OutOfBounds);
+        }
+        Ok(Self {
+            config,
+            server_id: ServerId::new(server_id),
+            lifetime: None,
+        })
+    }
+
+    /// Set the lifetime of CIDs created by this generator
+    ///
+    /// Limiting the lifetime lets connections move to a new configuration after it's rotated in.
+    pub fn set_lifetime(&mut self, d: Duration) -> &mut Self {
+        self.lifetime = Some(d);
+        self
+    }
+}
+
+impl ConnectionIdGenerator for RoutableConnectionIdGenerator {
+    fn generate_cid(&mut self) -> ConnectionId {
+        self.config.encode(&self.server_id, None)
+    }
+
+    fn cid_len(&self) -> usize {
+        self.config.cid_len
+    }
+
+    fn cid_lifetime(&self) -> Option<Duration> {
+        self.lifetime
+    }
+
+    /// Records the key ID in one of the nonce bytes if enabled with
+    /// [`Config::record_reset_key_id()`]
+    fn generate_cid_for_reset_key(&mut self, reset_key_id: u8) -> ConnectionId {
+        self.config.encode(&self.server_id, Some(reset_key_id))
+    }
+
+    fn reset_key_id(&self, cid: &ConnectionId) -> Option<u8> {
+        if cid.len() != self.config.cid_len || cid[0] >> 6 != self.config.id {
+            return None;
+        }
+        Some(self.config.decrypt(cid)[self.config.reset_key_id_index()?])
+    }
+}
+
+/// Recovers server IDs from connection IDs, for use by load balancers
+#[derive(Debug, Clone, Default)]
+pub struct Decoder {
+    configs: [Option<Config>; MAX_CONFIG_ID as usize + 1],
+}
+
+impl Decoder {
+    /// Create a decoder with no configurations
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Decode connection IDs with `config`, replacing any configuration with the same ID
+    pub fn insert(&mut self, config: Config) -> &mut Self {
+        let id = config.id as usize;
+        self.configs[id] = Some(config);
+        self
+    }
+
+    /// Stop decoding connection IDs with the configuration identified by `id`
+    pub fn remove(&mut self, id: u8) -> &mut Self {
+        if let Some(x) = self.configs.get_mut(id as usize) {
+            *x = None;
+        }
+        self
+    }
+
+    /// Find the server a connection ID routes to
+    ///
+    /// `cid` may extend past the end of the connection ID, e.g. to the end of a short header
+    /// packet. Returns `None` if `cid` is too short or its configuration is unknown, including
+    /// when it's marked unroutable.
+    pub fn decode(&self, cid: &[u8]) -> Option<ServerId> {
+        let config = self.config_for(cid)?;
+        if cid.len() < config.cid_len {
+            return None;
+        }
+        Some(config.decode(&cid[..config.cid_len]))
+    }
+
+    /// Find the server the QUIC packet at the start of `datagram` routes to, based on its
+    /// destination connection ID
+    ///
+    /// Clients choose the destination connection ID of their first packets themselves, so the
+    /// result is meaningless for Initial and 0-RTT packets; load balancers should instead route
+    /// those consistently by some other means, such as a hash of the connection ID.
+    pub fn route(&self, datagram: &[u8]) -> Option<ServerId> {
+        const LONG_HEADER_FORM: u8 = 0x80;
+        if *datagram.first()? & LONG_HEADER_FORM == 0 {
+            return self.decode(&datagram[1..]);
+        }
+        // Long header: first octet, 4-byte version, then the length-prefixed DCID
+        let len = *datagram.get(5)? as usize;
+        let cid = datagram.get(6..6 + len)?;
+        let config = self.config_for(cid)?;
+        if cid.len() != config.cid_len {
+            return None;
+        }
+        Some(config.decode(cid))
+    }
+
+    fn config_for(&self, cid: &[u8]) -> Option<&Config> {
+        self.configs.get((*cid.first()? >> 6) as usize)?.as_ref()
+    }
+}
+
+/// Identifies a server behind a load balancer
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct ServerId {
+    len: u8,
+    bytes: [u8; MAX_CID_SIZE],
+}
+
+impl ServerId {
+    fn new(bytes: &[u8]) -> Self {
+        let mut res = Self {
+            len: bytes.len() as u8,
+            bytes: [0; MAX_CID_SIZE],
+        };
+        res.bytes[..bytes.len()].copy_from_slice(bytes);
+        res
+    }
+}
+
+impl Deref for ServerId {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+/// Largest config ID; `0b11` marks unroutable connection IDs
+const MAX_CONFIG_ID: u8 = 0b10;
+/// Bits of the first octet not used for the config ID
+const LOW_BITS: u8 = 0b0011_1111;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use hex_literal::hex;
+
+    const KEY: [u8; 16] = [0x42; 16];
+    const SERVER_ID: &[u8] = &[1, 2, 3, 4];
+
+    fn roundtrip(config: Config) {
+        let server_id = (1..=config.server_id_len() as u8).collect::<Vec<_>>();
+        let mut decoder = Decoder::new();
+        decoder.insert(config.clone());
+        let mut generator = RoutableConnectionIdGenerator::new(config.clone(), &server_id).unwrap();
+        let a = generator.generate_cid();
+        let b = generator.generate_cid();
+        assert_ne!(a, b);
+        for cid in [a, b].iter() {
+            assert_eq!(cid.len(), config.cid_len());
+            assert_eq!(cid[0] >> 6, config.id());
+            assert_eq!(decoder.decode(cid).as_deref(), Some(&server_id[..]));
+        }
+    }
+
+    /// Check that `config` encrypts `plaintext` to `cid`, and recovers it again
+    fn vector(config: &Config, plaintext: &[u8], cid: &[u8]) {
+        let mut encrypted = plaintext.to_vec();
+        config.encrypt(&mut encrypted[1..]);
+        assert_eq!(encrypted, cid);
+        assert_eq!(&config.decrypt(cid)[..cid.len() - 1], &plaintext[1..]);
+    }
+
+    /// Key of the fixed vectors, from the FIPS-197 AES-128 example
+    const VECTOR_KEY: [u8; 16] = [
+        0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b, 0x0c, 0x0d, 0x0e,
+        0x0f,
+    ];
+
+    #[test]
+    fn aes_known_answer() {
+        // FIPS-197 appendix C.1
+        let aes = Aes128::new(&VECTOR_KEY.into());
+        let mut block = Block::clone_from_slice(&hex!("00112233445566778899aabbccddeeff"));
+        aes.encrypt_block(&mut block);
+        assert_eq!(block[..], hex!("69c4e0d86a7b0430d8cdb78070b4c55a"));
+    }
+
+    #[test]
+    fn plaintext_vector() {
+        let mut config = Config::plaintext(0, 4, 8).unwrap();
+        config.length_self_description(true);
+        let cid = hex!("0701020304aabbcc");
+        vector(&config, &cid, &cid);
+        assert_eq!(&*config.decode(&cid), &hex!("01020304"));
+    }
+
+    #[test]
+    fn stream_cipher_vector() {
+        let config = Config::stream_cipher(1, 3, 8, VECTOR_KEY).unwrap();
+        vector(
+            &config,
+            &hex!("4b 0001020304050607 a1a2a3"),
+            &hex!("4b e1bfe30cfe4c074b 2edef0"),
+        );
+        assert_eq!(
+            &*config.decode(&hex!("4be1bfe30cfe4c074b2edef0")),
+            &hex!("a1a2a3")
+        );
+    }
+
+    #[test]
+    fn block_cipher_vector() {
+        let config = Config::block_cipher(2, 4, VECTOR_KEY).unwrap();
+        vector(
+            &config,
+            &hex!("90 01020304 101112131415161718191a1b"),
+            &hex!("90 dbc20026bd482ee46a0c18da6a018fb6"),
+        );
+    }
+
+    #[test]
+    fn plaintext() {
+        roundtrip(Config::plaintext(0, SERVER_ID.len(), 8).unwrap());
+        let config = Config::plaintext(1, SERVER_ID.len(), 8).unwrap();
+        let mut generator = RoutableConnectionIdGenerator::new(config, SERVER_ID).unwrap();
+        assert_eq!(&generator.generate_cid()[1..5], SERVER_ID);
+    }
+
+    #[test]
+    fn stream_cipher() {
+        roundtrip(Config::stream_cipher(1, SERVER_ID.len(), 8, KEY).unwrap());
+        roundtrip(Config::stream_cipher(2, 3, 16, KEY).unwrap());
+    }
+
+    #[test]
+    fn block_cipher() {
+        roundtrip(Config::block_cipher(2, SERVER_ID.len(), KEY).unwrap());
+        roundtrip(Config::block_cipher(0, 12, KEY).unwrap());
+    }
+
+    #[test]
+    fn reset_key_id() {
+        for config in [
+            Config::stream_cipher(1, SERVER_ID.len(), 8, KEY).unwrap(),
+            Config::block_cipher(2, SERVER_ID.len(), KEY).unwrap(),
+        ]
+        .iter()
+        {
+            let mut generator =
+                RoutableConnectionIdGenerator::new(config.clone(), SERVER_ID).unwrap();
+            let cid = generator.generate_cid_for_reset_key(0xab);
+            assert_eq!(generator.reset_key_id(&cid), None);
+
+            let mut config = config.clone();
+            config.record_reset_key_id(true).unwrap();
+            let mut decoder = Decoder::new();
+            decoder.insert(config.clone());
+            let mut generator = RoutableConnectionIdGenerator::new(config, SERVER_ID).unwrap();
+            let cid = generator.generate_cid_for_reset_key(0xab);
+            assert_eq!(generator.reset_key_id(&cid), Some(0xab));
+            assert_eq!(decoder.decode(&cid).as_deref(), Some(SERVER_ID));
+        }
+
+        // Plaintext connection IDs would reveal the key ID
+        let mut config = Config::plaintext(0, SERVER_ID.len(), 8).unwrap();
+        assert!(config.record_reset_key_id(true).is_err());
+    }
+
+    #[test]
+    fn invalid_configs() {
+        assert!(Config::plaintext(3, 4, 8).is_err());
+        assert!(Config::plaintext(0, 8, 8).is_err());
+        assert!(Config::plaintext(0, 4, MAX_CID_SIZE + 1).is_err());
+        assert!(Config::stream_cipher(0, 4, 7, KEY).is_err());
+        assert!(Config::stream_cipher(0, 4, 16, KEY).is_err());
+        assert!(Config::block_cipher(0, 13, KEY).is_err());
+        let config = Config::plaintext(0, 4, 8).unwrap();
+        assert!(RoutableConnectionIdGenerator::new(config, &[1, 2]).is_err());
+    }
+
+    #[test]
+    fn length_self_description() {
+        let mut config = Config::block_cipher(1, SERVER_ID.len(), KEY).unwrap();
+        config.length_self_description(true);
+        let mut generator = RoutableConnectionIdGenerator::new(config, SERVER_ID).unwrap();
+        assert_eq!(generator.generate_cid()[0], 0b0100_0000 | 16);
+    }
+
+    #[test]
+    fn config_rotation() {
+        let old = Config::stream_cipher(0, SERVER_ID.len(), 8, KEY).unwrap();
+        let new = Config::block_cipher(1, SERVER_ID.len(), [7; 16]).unwrap();
+        let old_cid = RoutableConnectionIdGenerator::new(old.clone(), SERVER_ID)
+            .unwrap()
+            .generate_cid();
+        let new_cid = RoutableConnectionIdGenerator::new(new.clone(), SERVER_ID)
+            .unwrap()
+            .generate_cid();
+
+        let mut decoder = Decoder::new();
+        decoder.insert(old).insert(new);
+        assert_eq!(decoder.decode(&old_cid).as_deref(), Some(SERVER_ID));
+        assert_eq!(decoder.decode(&new_cid).as_deref(), Some(SERVER_ID));
+        decoder.remove(0);
+        assert_eq!(decoder.decode(&old_cid), None);
+        assert_eq!(decoder.decode(&new_cid).as_deref(), Some(SERVER_ID));
+
+        // Unroutable and truncated connection IDs
+        assert_eq!(decoder.decode(&[0b1100_0000; 17]), None);
+        assert_eq!(decoder.decode(&new_cid[..16]), None);
+    }
+
+    #[test]
+    fn route_packets() {
+        let config = Config::plaintext(0, SERVER_ID.len(), 8).unwrap();
+        let mut decoder = Decoder::new();
+        decoder.insert(config.clone());
+        let cid = RoutableConnectionIdGenerator::new(config, SERVER_ID)
+            .unwrap()
+            .generate_cid();
+
+        let mut short = vec![0x40];
+        short.extend_from_slice(&cid);
+        short.extend_from_slice(&[0; 20]);
+        assert_eq!(decoder.route(&short).as_deref(), Some(SERVER_ID));
+
+        let mut long = vec![0xc0, 0, 0, 0, 1, cid.len() as u8];
+        long.extend_from_slice(&cid);
+        long.extend_from_slice(&[0; 20]);
+        assert_eq!(decoder.route(&long).as_deref(), Some(SERVER_ID));
+
+        // A long header connection ID of the wrong length
+        long[5] = 9;
+        assert_eq!(decoder.route(&long), None);
+        assert_eq!(decoder.route(&[]), None);
+    }
+}
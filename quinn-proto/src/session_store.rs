@@ -0,0 +1,17 @@
+/// Storage for the TLS session tickets that let a client resume earlier sessions
+///
+/// Resuming a session saves a round-trip of certificate exchange and allows the client to send
+/// 0-RTT data. Values are opaque to the store; with rustls they hold the ticket together with
+/// the server's transport parameters, which must be remembered for 0-RTT and are checked against
+/// the new ones once the handshake completes. A store that outlives the process lets resumption
+/// work across restarts.
+///
+/// Stores may discard entries at any time, e.g. once tickets are too old to be accepted by
+/// servers, which is at most 7 days in TLS 1.3.
+pub trait SessionStore: Send + Sync {
+    /// Record `value` under `key`, replacing any earlier value
+    fn insert(&self, key: &[u8], value: Vec<u8>);
+
+    /// Return the latest value recorded under `key`, if any
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>>;
+}
@@ -1,8 +1,10 @@
 use std::{
+    collections::HashMap,
     convert::TryInto,
-    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
-    sync::Arc,
-    time::{Duration, Instant},
+    mem,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
 };
 
 use assert_matches::assert_matches;
@@ -10,14 +12,14 @@ use bytes::Bytes;
 use hex_literal::hex;
 use rand::RngCore;
 use ring::hmac;
-use rustls::internal::msgs::enums::AlertDescription;
+use rustls::AlertDescription;
 use tracing::info;
 
 use super::*;
 use crate::cid_generator::{ConnectionIdGenerator, RandomConnectionIdGenerator};
 use crate::crypto::Session as _;
 use crate::{Certificate, CertificateChain, PrivateKey};
-mod util;
+pub(crate) mod util;
 use util::*;
 
 #[test]
@@ -77,7 +79,7 @@ fn version_negotiate_client() {
         )[..]
             .into(),
     );
-    if let Some((_, DatagramEvent::ConnectionEvent(event))) = opt_event {
+    if let Some(DatagramEvent::ConnectionEvent(_, event)) = opt_event {
         client_ch.handle_event(event);
     }
     assert_matches!(
@@ -129,6 +131,206 @@ fn stateless_retry() {
     pair.connect();
 }
 
+#[test]
+fn new_token_skips_retry() {
+    let _guard = subscribe();
+    let mut pair = Pair::new(
+        Default::default(),
+        ServerConfig {
+            use_stateless_retry: true,
+            ..server_config()
+        },
+    );
+    let store = Arc::new(TokenMemoryCache::default());
+    let config = ClientConfig {
+        token_store: Some(store.clone()),
+        ..client_config()
+    };
+
+    let (client_ch, server_ch) = pair.connect_with(config.clone());
+    pair.drive();
+    assert_eq!(
+        pair.server_conn_mut(server_ch).stats().frame_tx.new_token,
+        2
+    );
+    let first = pair.client_conn_mut(client_ch).stats();
+    assert_eq!(first.frame_rx.new_token, 2);
+    let now = pair.time;
+    pair.client_conn_mut(client_ch)
+        .close(now, VarInt(0), [][..].into());
+    pair.drive();
+
+    // The token remains valid from a different port, and replaces the Retry round trip
+    pair.client.addr = SocketAddr::new(
+        Ipv6Addr::LOCALHOST.into(),
+        CLIENT_PORTS.lock().unwrap().next().unwrap(),
+    );
+    let (client_ch, _) = pair.connect_with(config);
+    pair.drive();
+    let second = pair.client_conn_mut(client_ch).stats();
+    assert!(second.udp_tx.datagrams < first.udp_tx.datagrams);
+    assert!(second.udp_rx.datagrams < first.udp_rx.datagrams);
+
+    // The used token was removed, and the cache holds the two most recent ones
+    for _ in 0..2 {
+        assert!(store.take("localhost").is_some());
+    }
+    assert!(store.take("localhost").is_none());
+}
+
+#[test]
+fn new_token_survives_key_rotation() {
+    let _guard = subscribe();
+    let token_key = |x| <ring::hkdf::Prk as crypto::HandshakeTokenKey>::from_secret(&[x; 64]);
+    let keys = Arc::new(KeyRing::new(1, token_key(1)));
+    let mut server_config = server_config();
+    server_config
+        .use_stateless_retry(true)
+        .token_keys(keys.clone());
+    let mut pair = Pair::new(Default::default(), server_config);
+    let config = ClientConfig {
+        token_store: Some(Arc::new(TokenMemoryCache::default())),
+        ..client_config()
+    };
+
+    let (client_ch, _) = pair.connect_with(config.clone());
+    pair.drive();
+    let now = pair.time;
+    pair.client_conn_mut(client_ch)
+        .close(now, VarInt(0), [][..].into());
+    pair.drive();
+    assert_eq!(pair.server.endpoint.stats().retries_sent, 1);
+
+    // Tokens sealed with the previous key are still accepted after rotating
+    keys.rotate(2, token_key(2));
+    let (client_ch, _) = pair.connect_with(config.clone());
+    pair.drive();
+    let now = pair.time;
+    pair.client_conn_mut(client_ch)
+        .close(now, VarInt(0), [][..].into());
+    pair.drive();
+    assert_eq!(pair.server.endpoint.stats().retries_sent, 1);
+
+    // ...until the key they were sealed with is removed
+    keys.rotate(3, token_key(3));
+    keys.remove(2);
+    pair.connect_with(config);
+    assert_eq!(pair.server.endpoint.stats().retries_sent, 2);
+}
+
+#[test]
+fn new_token_from_other_address() {
+    let _guard = subscribe();
+    let mut pair = Pair::new(
+        Default::default(),
+        ServerConfig {
+            use_stateless_retry: true,
+            ..server_config()
+        },
+    );
+    let config = ClientConfig {
+        token_store: Some(Arc::new(TokenMemoryCache::default())),
+        ..client_config()
+    };
+    let (client_ch, _) = pair.connect_with(config.clone());
+    pair.drive();
+    let first = pair.client_conn_mut(client_ch).stats();
+    let now = pair.time;
+    pair.client_conn_mut(client_ch)
+        .close(now, VarInt(0), [][..].into());
+    pair.drive();
+
+    // Tokens are bound to the client's IP address, so the server falls back to a Retry
+    pair.client.addr = SocketAddr::new(
+        Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 2).into(),
+        CLIENT_PORTS.lock().unwrap().next().unwrap(),
+    );
+    let (client_ch, _) = pair.connect_with(config);
+    pair.drive();
+    let second = pair.client_conn_mut(client_ch).stats();
+    assert_eq!(second.udp_tx.datagrams, first.udp_tx.datagrams);
+    assert_eq!(second.udp_rx.datagrams, first.udp_rx.datagrams);
+}
+
+#[test]
+fn legacy_draft_version() {
+    let _guard = subscribe();
+    let mut versions = DEFAULT_SUPPORTED_VERSIONS.to_vec();
+    versions.extend_from_slice(LEGACY_DRAFT_VERSIONS);
+    let mut server_endpoint_config = EndpointConfig::default();
+    server_endpoint_config
+        .supported_versions(versions, DEFAULT_SUPPORTED_VERSIONS[0])
+        .unwrap();
+    let server = Endpoint::new(
+        Arc::new(server_endpoint_config),
+        Some(Arc::new(ServerConfig {
+            use_stateless_retry: true,
+            ..server_config()
+        })),
+    );
+    let mut client_endpoint_config = EndpointConfig::default();
+    client_endpoint_config
+        .supported_versions(LEGACY_DRAFT_VERSIONS.to_vec(), 0xff00_001d)
+        .unwrap();
+    let client = Endpoint::new(Arc::new(client_endpoint_config), None);
+
+    let mut pair = Pair::new_from_endpoint(client, server);
+    pair.connect();
+}
+
+#[test]
+fn version_negotiation_restart() {
+    let _guard = subscribe();
+    let mut client_endpoint_config = EndpointConfig::default();
+    client_endpoint_config
+        .supported_versions(
+            vec![DEFAULT_SUPPORTED_VERSIONS[0], 0xff00_001d],
+            0xff00_001d,
+        )
+        .unwrap();
+    let client = Endpoint::new(Arc::new(client_endpoint_config), None);
+    let server = Endpoint::new(
+        Arc::new(EndpointConfig::default()),
+        Some(Arc::new(server_config())),
+    );
+
+    let mut pair = Pair::new_from_endpoint(client, server);
+    let (client_ch, server_ch) = pair.connect();
+    assert_eq!(
+        pair.client_conn_mut(client_ch).version(),
+        DEFAULT_SUPPORTED_VERSIONS[0]
+    );
+    assert_eq!(
+        pair.server_conn_mut(server_ch).version(),
+        DEFAULT_SUPPORTED_VERSIONS[0]
+    );
+}
+
+#[test]
+fn version_2_unsupported() {
+    // rustls can't derive keys with the QUIC v2 labels
+    let mut endpoint_config = EndpointConfig::default();
+    assert_matches!(
+        endpoint_config.supported_versions(
+            vec![QUIC_VERSION_2, DEFAULT_SUPPORTED_VERSIONS[0]],
+            DEFAULT_SUPPORTED_VERSIONS[0],
+        ),
+        Err(ConfigError::OutOfBounds)
+    );
+}
+
+#[test]
+fn connect_unsupported_version() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let mut client_config = client_config();
+    client_config.version(QUIC_VERSION_2);
+    let result = pair
+        .client
+        .connect(client_config, pair.server.addr, "localhost");
+    assert_matches!(result, Err(ConnectError::UnsupportedVersion));
+}
+
 #[test]
 fn server_stateless_reset() {
     let _guard = subscribe();
@@ -158,6 +360,48 @@ fn server_stateless_reset() {
     );
 }
 
+#[test]
+fn server_stateless_reset_rotated_key() {
+    let _guard = subscribe();
+    let reset_key = |x| hmac::Key::new(hmac::HMAC_SHA256, &[x; 64]);
+    let keys = Arc::new(KeyRing::new(1, reset_key(1)));
+    let mut lb_config = quic_lb::Config::block_cipher(0, 2, [0xab; 16]).unwrap();
+    lb_config.record_reset_key_id(true).unwrap();
+    let mut endpoint_config = EndpointConfig::default();
+    endpoint_config
+        .reset_keys(keys.clone())
+        .cid_generator(move || {
+            Box::new(
+                quic_lb::RoutableConnectionIdGenerator::new(lb_config.clone(), &[1, 2]).unwrap(),
+            )
+        });
+    let endpoint_config = Arc::new(endpoint_config);
+
+    let mut pair = Pair::new(endpoint_config.clone(), server_config());
+    let (client_ch, _) = pair.connect();
+    // The restarted server has moved on to a new key, but still accepts the old one
+    keys.rotate(2, reset_key(2));
+    pair.server.endpoint = Endpoint::new(endpoint_config, Some(Arc::new(server_config())));
+    pair.client.connections.get_mut(&client_ch).unwrap().close(
+        pair.time,
+        VarInt(42),
+        (&[0xab; 128][..]).into(),
+    );
+    info!("resetting");
+    pair.drive_client();
+    let arrival = pair.server.inbound.front().unwrap().0;
+    pair.server.drive(arrival);
+    // Only the key the connection ID records is used
+    assert_eq!(pair.server.outbound.len(), 1);
+    pair.drive();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::ConnectionLost {
+            reason: ConnectionError::Reset
+        })
+    );
+}
+
 #[test]
 fn client_stateless_reset() {
     let _guard = subscribe();
@@ -329,10 +573,20 @@ fn reject_self_signed_server_cert() {
 #[test]
 fn reject_missing_client_cert() {
     let _guard = subscribe();
-    let mut server_config = server_config();
-    Arc::make_mut(&mut server_config.crypto).set_client_certificate_verifier(
-        rustls::AllowAnyAuthenticatedClient::new(rustls::RootCertStore::empty()),
-    );
+    let crypto = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_client_cert_verifier(rustls::server::AllowAnyAuthenticatedClient::new(
+            rustls::RootCertStore::empty(),
+        ))
+        .with_single_cert(
+            vec![rustls::Certificate(CERTIFICATE.serialize_der().unwrap())],
+            rustls::PrivateKey(CERTIFICATE.serialize_private_key_der()),
+        )
+        .unwrap();
+    let server_config = ServerConfig {
+        crypto: Arc::new(crypto),
+        ..Default::default()
+    };
     let mut pair = Pair::new(Default::default(), server_config);
     info!("connecting");
     let client_ch = pair.begin_connect(client_config());
@@ -383,6 +637,96 @@ fn congestion() {
     pair.client_send(client_ch, s).write(&[42; 1024]).unwrap();
 }
 
+/// What a [`RecordingController`] was told
+#[derive(Default)]
+struct ControllerLog {
+    sent: Vec<u64>,
+    largest_acked: Option<u64>,
+    in_flight: u64,
+    min_rtt: Option<Duration>,
+    /// Latest RTT passed with the first acknowledged packet
+    first_rtt: Option<Duration>,
+    ce_count: u64,
+}
+
+/// A congestion controller with a fixed window that logs the information it's given
+#[derive(Clone)]
+struct RecordingController(Arc<Mutex<ControllerLog>>);
+
+impl congestion::Controller for RecordingController {
+    fn on_ack(&mut self, _: Instant, _: Instant, _: u64, _: bool, rtt: &RttEstimator) {
+        let mut log = self.0.lock().unwrap();
+        log.min_rtt = Some(rtt.min());
+        log.first_rtt.get_or_insert(rtt.latest());
+    }
+
+    fn on_sent(&mut self, _: Instant, _: u64, packet_number: Option<u64>, _: u64) {
+        self.0.lock().unwrap().sent.extend(packet_number);
+    }
+
+    fn on_end_acks(&mut self, _: Instant, in_flight: u64, _: bool, largest_acked: Option<u64>) {
+        let mut log = self.0.lock().unwrap();
+        log.in_flight = in_flight;
+        log.largest_acked = largest_acked;
+    }
+
+    fn on_congestion_event(&mut self, _: Instant, _: Instant, _: bool, _: u64) {}
+
+    fn on_ecn_ce(&mut self, _: Instant, _: Instant, ce_count: u64) {
+        self.0.lock().unwrap().ce_count += ce_count;
+    }
+
+    fn window(&self) -> u64 {
+        1_000_000
+    }
+
+    fn clone_box(&self) -> Box<dyn congestion::Controller> {
+        Box::new(self.clone())
+    }
+
+    fn initial_window(&self) -> u64 {
+        1_000_000
+    }
+}
+
+impl congestion::ControllerFactory for RecordingController {
+    fn build(&self, _: Instant) -> Box<dyn congestion::Controller> {
+        Box::new(self.clone())
+    }
+}
+
+#[test]
+fn congestion_controller_callbacks() {
+    let _guard = subscribe();
+    let log = Arc::new(Mutex::new(ControllerLog::default()));
+    let mut transport = TransportConfig::default();
+    transport.congestion_controller_factory(RecordingController(log.clone()));
+    let mut pair = Pair::default();
+    pair.latency = Duration::from_millis(10);
+    let (client_ch, _) = pair.connect_with(ClientConfig {
+        transport: Arc::new(transport),
+        ..client_config()
+    });
+
+    // Mark every packet carrying the stream data as having experienced congestion
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, s).write(&[42; 1024]).unwrap();
+    pair.drive_client();
+    let marked = pair.server.inbound.len() as u64;
+    assert_ne!(marked, 0);
+    for x in pair.server.inbound.iter_mut() {
+        x.1 = Some(EcnCodepoint::Ce);
+    }
+    pair.drive();
+
+    let log = log.lock().unwrap();
+    assert_eq!(log.largest_acked, log.sent.last().cloned());
+    assert_eq!(log.in_flight, 0);
+    assert_eq!(log.min_rtt, Some(Duration::from_millis(20)));
+    assert_eq!(log.first_rtt, Some(Duration::from_millis(20)));
+    assert_eq!(log.ce_count, marked);
+}
+
 #[allow(clippy::field_reassign_with_default)] // https://github.com/rust-lang/rust-clippy/issues/6527
 #[test]
 fn high_latency_handshake() {
@@ -443,108 +787,327 @@ fn zero_rtt_happypath() {
     assert_eq!(pair.client_conn_mut(client_ch).lost_packets(), 0);
 }
 
+/// A `SessionStore` standing in for persistent storage
+#[derive(Default)]
+struct SharedSessionStore(Mutex<HashMap<Vec<u8>, Vec<u8>>>);
+
+impl SessionStore for SharedSessionStore {
+    fn insert(&self, key: &[u8], value: Vec<u8>) {
+        self.0.lock().unwrap().insert(key.to_vec(), value);
+    }
+
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        self.0.lock().unwrap().get(key).cloned()
+    }
+}
+
 #[test]
-fn zero_rtt_rejection() {
+fn zero_rtt_session_store() {
     let _guard = subscribe();
-    let mut server_config = server_config();
-    Arc::get_mut(&mut server_config.crypto)
-        .unwrap()
-        .set_protocols(&["foo".into(), "bar".into()]);
-    let mut pair = Pair::new(Arc::new(EndpointConfig::default()), server_config);
-    let mut client_config = client_config();
-    Arc::get_mut(&mut client_config.crypto)
+    let mut pair = Pair::default();
+    let store = Arc::new(SharedSessionStore::default());
+    let mut config = client_config();
+    config.session_store(store.clone());
+    let (client_ch, _) = pair.connect_with(config);
+    pair.client
+        .connections
+        .get_mut(&client_ch)
         .unwrap()
-        .set_protocols(&["foo".into()]);
-
-    // Establish normal connection
-    let client_ch = pair.begin_connect(client_config.clone());
+        .close(pair.time, VarInt(0), [][..].into());
     pair.drive();
-    let server_ch = pair.server.assert_accept();
-    assert_matches!(
-        pair.server_conn_mut(server_ch).poll(),
-        Some(Event::HandshakeDataReady)
-    );
-    assert_matches!(
-        pair.server_conn_mut(server_ch).poll(),
-        Some(Event::Connected)
-    );
-    assert_matches!(pair.server_conn_mut(server_ch).poll(), None);
+    assert!(!store.0.lock().unwrap().is_empty());
+
+    // A client that lost its in-memory session cache can't resume...
+    let client_ch = pair.begin_connect(client_config());
+    assert!(!pair.client_conn_mut(client_ch).has_0rtt());
     pair.client
         .connections
         .get_mut(&client_ch)
         .unwrap()
         .close(pair.time, VarInt(0), [][..].into());
     pair.drive();
-    assert_matches!(
-        pair.server_conn_mut(server_ch).poll(),
-        Some(Event::ConnectionLost { .. })
-    );
-    assert_matches!(pair.server_conn_mut(server_ch).poll(), None);
-    pair.client.connections.clear();
-    pair.server.connections.clear();
 
-    // Changing protocols invalidates 0-RTT
-    Arc::get_mut(&mut client_config.crypto)
-        .unwrap()
-        .set_protocols(&["bar".into()]);
+    // ...but one using the same store can
     info!("resuming session");
-    let client_ch = pair.begin_connect(client_config);
+    let mut config = client_config();
+    config.session_store(store);
+    let client_ch = pair.begin_connect(config);
     assert!(pair.client_conn_mut(client_ch).has_0rtt());
     let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
     const MSG: &[u8] = b"Hello, 0-RTT!";
     pair.client_send(client_ch, s).write(MSG).unwrap();
     pair.drive();
-    assert!(!pair.client_conn_mut(client_ch).accepted_0rtt());
+    assert!(pair.client_conn_mut(client_ch).accepted_0rtt());
     let server_ch = pair.server.assert_accept();
+    let mut recv = pair.server_recv(server_ch, s);
+    let mut chunks = recv.read(false).unwrap();
     assert_matches!(
-        pair.server_conn_mut(server_ch).poll(),
-        Some(Event::HandshakeDataReady)
-    );
-    assert_matches!(
-        pair.server_conn_mut(server_ch).poll(),
-        Some(Event::Connected)
+        chunks.next(usize::MAX),
+        Ok(Some(chunk)) if chunk.offset == 0 && chunk.bytes == MSG
     );
-    assert_matches!(pair.server_conn_mut(server_ch).poll(), None);
-    let s2 = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
-    assert_eq!(s, s2);
-
-    let mut recv = pair.server_recv(server_ch, s2);
-    let mut chunks = recv.read(false).unwrap();
-    assert_eq!(chunks.next(usize::MAX), Err(ReadError::Blocked));
     let _ = chunks.finalize();
-    assert_eq!(pair.client_conn_mut(client_ch).lost_packets(), 0);
 }
 
 #[test]
-fn alpn_success() {
+fn zero_rtt_anti_replay() {
     let _guard = subscribe();
     let mut server_config = server_config();
-    Arc::get_mut(&mut server_config.crypto)
-        .unwrap()
-        .set_protocols(&["foo".into(), "bar".into(), "baz".into()]);
-    let mut pair = Pair::new(Arc::new(EndpointConfig::default()), server_config);
-    let mut client_config = client_config();
-    Arc::get_mut(&mut client_config.crypto)
-        .unwrap()
-        .set_protocols(&["bar".into(), "quux".into(), "corge".into()]);
+    server_config.anti_replay(Some(AntiReplayConfig::default()));
+    let mut pair = Pair::new(Default::default(), server_config);
+    let config = client_config();
 
     // Establish normal connection
-    let client_ch = pair.begin_connect(client_config);
+    let client_ch = pair.begin_connect(config.clone());
     pair.drive();
     let server_ch = pair.server.assert_accept();
-    assert_matches!(
-        pair.server_conn_mut(server_ch).poll(),
-        Some(Event::HandshakeDataReady)
-    );
-    assert_matches!(
-        pair.server_conn_mut(server_ch).poll(),
-        Some(Event::Connected)
-    );
+    assert!(!pair.server_conn_mut(server_ch).is_0rtt_replay_protected());
+    pair.client
+        .connections
+        .get_mut(&client_ch)
+        .unwrap()
+        .close(pair.time, VarInt(0), [][..].into());
+    pair.drive();
 
-    let hd = pair
-        .client_conn_mut(client_ch)
-        .crypto_session()
-        .handshake_data()
+    info!("resuming session");
+    let client_ch = pair.begin_connect(config);
+    assert!(pair.client_conn_mut(client_ch).has_0rtt());
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    const MSG: &[u8] = b"Hello, 0-RTT!";
+    pair.client_send(client_ch, s).write(MSG).unwrap();
+    pair.drive_client();
+    let captured = pair.server.inbound.clone();
+    pair.drive();
+    assert!(pair.client_conn_mut(client_ch).accepted_0rtt());
+    let server_ch = pair.server.assert_accept();
+    assert!(pair.server_conn_mut(server_ch).is_0rtt_replay_protected());
+    let mut recv = pair.server_recv(server_ch, s);
+    let mut chunks = recv.read(false).unwrap();
+    assert_matches!(
+        chunks.next(usize::MAX),
+        Ok(Some(chunk)) if chunk.offset == 0 && chunk.bytes == MSG
+    );
+    let _ = chunks.finalize();
+    pair.client
+        .connections
+        .get_mut(&client_ch)
+        .unwrap()
+        .close(pair.time, VarInt(0), [][..].into());
+    pair.drive();
+    pair.client.connections.clear();
+    pair.server.connections.clear();
+    assert_eq!(pair.server.endpoint.stats().rejected_0rtt, 0);
+
+    info!("replaying 0-RTT attempt");
+    for (_, ecn, packet, remote, local_ip) in captured {
+        pair.server
+            .inbound
+            .push_back((pair.time, ecn, packet, remote, local_ip));
+    }
+    pair.drive_server();
+    let server_ch = pair.server.assert_accept();
+    assert!(!pair.server_conn_mut(server_ch).is_0rtt_replay_protected());
+    assert_eq!(pair.server.endpoint.stats().rejected_0rtt, 1);
+    assert_matches!(pair.server_streams(server_ch).accept(Dir::Uni), None);
+}
+
+/// Accepts 0-RTT on tickets carrying `b"ok"`, recording the data of every ticket it's asked about
+#[derive(Default)]
+struct TicketDataPolicy(Mutex<Vec<Vec<u8>>>);
+
+impl ZeroRttPolicy for TicketDataPolicy {
+    fn accept(&self, request: &ZeroRttRequest<'_>) -> bool {
+        assert_eq!(request.server_name(), Some("localhost"));
+        let data = request.resumption_data();
+        self.0.lock().unwrap().push(data.to_vec());
+        data == b"ok"
+    }
+}
+
+#[test]
+fn zero_rtt_policy() {
+    let _guard = subscribe();
+    let policy = Arc::new(TicketDataPolicy::default());
+    let mut server_config = server_config();
+    server_config.zero_rtt_policy(Some(policy.clone()));
+    let mut pair = Pair::new(Default::default(), server_config);
+    let config = client_config();
+
+    // Establish a normal connection, issuing tickets that allow 0-RTT
+    let client_ch = pair.begin_connect(config.clone());
+    while pair.server.connections.is_empty() {
+        pair.step();
+    }
+    let server_ch = pair.server.assert_accept();
+    pair.server_conn_mut(server_ch).set_resumption_data(b"ok");
+    pair.drive();
+    pair.client
+        .connections
+        .get_mut(&client_ch)
+        .unwrap()
+        .close(pair.time, VarInt(0), [][..].into());
+    pair.drive();
+    assert!(policy.0.lock().unwrap().is_empty());
+
+    // Resume with 0-RTT, issuing tickets without any data this time
+    const MSG: &[u8] = b"Hello, 0-RTT!";
+    let client_ch = pair.begin_connect(config.clone());
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, s).write(MSG).unwrap();
+    pair.drive();
+    assert!(pair.client_conn_mut(client_ch).accepted_0rtt());
+    pair.server.assert_accept();
+    pair.client
+        .connections
+        .get_mut(&client_ch)
+        .unwrap()
+        .close(pair.time, VarInt(0), [][..].into());
+    pair.drive();
+
+    info!("resuming with a ticket the policy refuses");
+    let client_ch = pair.begin_connect(config);
+    assert!(pair.client_conn_mut(client_ch).has_0rtt());
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, s).write(MSG).unwrap();
+    pair.drive();
+    assert!(!pair.client_conn_mut(client_ch).accepted_0rtt());
+    assert_eq!(*policy.0.lock().unwrap(), vec![b"ok".to_vec(), Vec::new()]);
+    let server_ch = pair.server.assert_accept();
+    assert!(!pair.server_conn_mut(server_ch).is_handshaking());
+    assert_eq!(pair.server_streams(server_ch).accept(Dir::Uni), None);
+}
+
+#[test]
+fn large_resumption_data() {
+    let _guard = subscribe();
+    let policy = Arc::new(TicketDataPolicy::default());
+    let mut server_config = server_config();
+    server_config.zero_rtt_policy(Some(policy.clone()));
+    let mut pair = Pair::new(Default::default(), server_config);
+    let config = client_config();
+
+    let data = vec![0xab; 64 * 1024];
+    let client_ch = pair.begin_connect(config.clone());
+    while pair.server.connections.is_empty() {
+        pair.step();
+    }
+    let server_ch = pair.server.assert_accept();
+    pair.server_conn_mut(server_ch).set_resumption_data(&data);
+    pair.drive();
+    pair.client
+        .connections
+        .get_mut(&client_ch)
+        .unwrap()
+        .close(pair.time, VarInt(0), [][..].into());
+    pair.drive();
+
+    let client_ch = pair.begin_connect(config);
+    assert!(pair.client_conn_mut(client_ch).has_0rtt());
+    pair.drive();
+    assert_eq!(*policy.0.lock().unwrap(), vec![data]);
+}
+
+#[test]
+fn zero_rtt_rejection() {
+    let _guard = subscribe();
+    let mut server_config = server_config();
+    Arc::get_mut(&mut server_config.crypto)
+        .unwrap()
+        .alpn_protocols = vec!["foo".into(), "bar".into()];
+    let mut pair = Pair::new(Arc::new(EndpointConfig::default()), server_config);
+    let mut client_config = client_config();
+    Arc::get_mut(&mut client_config.crypto)
+        .unwrap()
+        .alpn_protocols = vec!["foo".into()];
+
+    // Establish normal connection
+    let client_ch = pair.begin_connect(client_config.clone());
+    pair.drive();
+    let server_ch = pair.server.assert_accept();
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::HandshakeDataReady)
+    );
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::Connected)
+    );
+    assert_matches!(pair.server_conn_mut(server_ch).poll(), None);
+    pair.client
+        .connections
+        .get_mut(&client_ch)
+        .unwrap()
+        .close(pair.time, VarInt(0), [][..].into());
+    pair.drive();
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::ConnectionLost { .. })
+    );
+    assert_matches!(pair.server_conn_mut(server_ch).poll(), None);
+    pair.client.connections.clear();
+    pair.server.connections.clear();
+
+    // Changing protocols invalidates 0-RTT
+    Arc::get_mut(&mut client_config.crypto)
+        .unwrap()
+        .alpn_protocols = vec!["bar".into()];
+    info!("resuming session");
+    let client_ch = pair.begin_connect(client_config);
+    assert!(pair.client_conn_mut(client_ch).has_0rtt());
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    const MSG: &[u8] = b"Hello, 0-RTT!";
+    pair.client_send(client_ch, s).write(MSG).unwrap();
+    pair.drive();
+    assert!(!pair.client_conn_mut(client_ch).accepted_0rtt());
+    let server_ch = pair.server.assert_accept();
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::HandshakeDataReady)
+    );
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::Connected)
+    );
+    assert_matches!(pair.server_conn_mut(server_ch).poll(), None);
+    let s2 = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    assert_eq!(s, s2);
+
+    let mut recv = pair.server_recv(server_ch, s2);
+    let mut chunks = recv.read(false).unwrap();
+    assert_eq!(chunks.next(usize::MAX), Err(ReadError::Blocked));
+    let _ = chunks.finalize();
+    assert_eq!(pair.client_conn_mut(client_ch).lost_packets(), 0);
+}
+
+#[test]
+fn alpn_success() {
+    let _guard = subscribe();
+    let mut server_config = server_config();
+    Arc::get_mut(&mut server_config.crypto)
+        .unwrap()
+        .alpn_protocols = vec!["foo".into(), "bar".into(), "baz".into()];
+    let mut pair = Pair::new(Arc::new(EndpointConfig::default()), server_config);
+    let mut client_config = client_config();
+    Arc::get_mut(&mut client_config.crypto)
+        .unwrap()
+        .alpn_protocols = vec!["bar".into(), "quux".into(), "corge".into()];
+
+    // Establish normal connection
+    let client_ch = pair.begin_connect(client_config);
+    pair.drive();
+    let server_ch = pair.server.assert_accept();
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::HandshakeDataReady)
+    );
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::Connected)
+    );
+
+    let hd = pair
+        .client_conn_mut(client_ch)
+        .crypto_session()
+        .handshake_data()
         .unwrap();
     assert_eq!(hd.protocol.unwrap(), &b"bar"[..]);
 }
@@ -556,13 +1119,14 @@ fn server_alpn_unset() {
     let mut client_config = client_config();
     Arc::get_mut(&mut client_config.crypto)
         .unwrap()
-        .set_protocols(&["foo".into()]);
+        .alpn_protocols = vec!["foo".into()];
 
     let client_ch = pair.begin_connect(client_config);
     pair.drive();
+    // The server refuses the handshake, as it can't select any of the client's protocols
     assert_matches!(
         pair.client_conn_mut(client_ch).poll(),
-        Some(Event::ConnectionLost { reason: ConnectionError::TransportError(ref err) }) if err.code == TransportErrorCode::crypto(0x78)
+        Some(Event::ConnectionLost { reason: ConnectionError::ConnectionClosed(ref close) }) if close.error_code == TransportErrorCode::crypto(0x78)
     );
 }
 
@@ -572,7 +1136,7 @@ fn client_alpn_unset() {
     let mut server_config = server_config();
     Arc::get_mut(&mut server_config.crypto)
         .unwrap()
-        .set_protocols(&["foo".into(), "bar".into(), "baz".into()]);
+        .alpn_protocols = vec!["foo".into(), "bar".into(), "baz".into()];
     let mut pair = Pair::new(Arc::new(EndpointConfig::default()), server_config);
 
     let client_ch = pair.begin_connect(client_config());
@@ -588,12 +1152,12 @@ fn alpn_mismatch() {
     let mut server_config = server_config();
     Arc::get_mut(&mut server_config.crypto)
         .unwrap()
-        .set_protocols(&["foo".into(), "bar".into(), "baz".into()]);
+        .alpn_protocols = vec!["foo".into(), "bar".into(), "baz".into()];
     let mut pair = Pair::new(Arc::new(EndpointConfig::default()), server_config);
     let mut client_config = client_config();
     Arc::get_mut(&mut client_config.crypto)
         .unwrap()
-        .set_protocols(&["quux".into(), "corge".into()]);
+        .alpn_protocols = vec!["quux".into(), "corge".into()];
 
     let client_ch = pair.begin_connect(client_config);
     pair.drive();
@@ -760,7 +1324,7 @@ fn key_update_reordered() {
 
     const MSG1: &[u8] = b"1";
     pair.client_send(client_ch, s).write(MSG1).unwrap();
-    pair.client.drive(pair.time, pair.server.addr);
+    pair.client.drive(pair.time);
     assert!(!pair.client.outbound.is_empty());
     pair.client.delay_outbound();
 
@@ -769,7 +1333,7 @@ fn key_update_reordered() {
 
     const MSG2: &[u8] = b"two";
     pair.client_send(client_ch, s).write(MSG2).unwrap();
-    pair.client.drive(pair.time, pair.server.addr);
+    pair.client.drive(pair.time);
     pair.client.finish_delay();
     pair.drive();
 
@@ -797,7 +1361,7 @@ fn initial_retransmit() {
     let _guard = subscribe();
     let mut pair = Pair::default();
     let client_ch = pair.begin_connect(client_config());
-    pair.client.drive(pair.time, pair.server.addr);
+    pair.client.drive(pair.time);
     pair.client.outbound.clear(); // Drop initial
     pair.drive();
     assert_matches!(
@@ -893,112 +1457,572 @@ fn idle_timeout() {
         pair.client.inbound.clear(); // Simulate total S->C packet loss
     }
 
-    assert!(pair.time - start < 2 * IDLE_TIMEOUT);
+    assert!(pair.time - start < 2 * IDLE_TIMEOUT);
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::ConnectionLost {
+            reason: ConnectionError::TimedOut,
+        })
+    );
+    assert_matches!(
+        pair.server_conn_mut(server_ch).poll(),
+        Some(Event::ConnectionLost {
+            reason: ConnectionError::TimedOut,
+        })
+    );
+}
+
+#[test]
+fn connection_close_sends_acks() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, _server_ch) = pair.connect();
+
+    let client_acks = pair.client_conn_mut(client_ch).stats().frame_rx.acks;
+
+    pair.client_conn_mut(client_ch).ping();
+    pair.drive_client();
+
+    let time = pair.time;
+    pair.server_conn_mut(client_ch)
+        .close(time, VarInt(42), Bytes::new());
+
+    pair.drive();
+
+    let client_acks_2 = pair.client_conn_mut(client_ch).stats().frame_rx.acks;
+    assert!(
+        client_acks_2 > client_acks,
+        "Connection close should send pending ACKs"
+    );
+}
+
+#[test]
+fn concurrent_connections_full() {
+    let _guard = subscribe();
+    let mut pair = Pair::new(
+        Default::default(),
+        ServerConfig {
+            concurrent_connections: 0,
+            ..server_config()
+        },
+    );
+    let client_ch = pair.begin_connect(client_config());
+    pair.drive();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::ConnectionLost {
+            reason: ConnectionError::ConnectionClosed(frame::ConnectionClose {
+                error_code: TransportErrorCode::CONNECTION_REFUSED,
+                ..
+            }),
+        })
+    );
+    assert_eq!(pair.server.connections.len(), 0);
+    assert_eq!(pair.server.known_connections(), 0);
+    assert_eq!(pair.server.known_cids(), 0);
+}
+
+#[test]
+fn incoming_connection_info() {
+    let _guard = subscribe();
+    let mut server_config = server_config();
+    Arc::make_mut(&mut server_config.crypto).alpn_protocols = vec![b"bar".to_vec()];
+    let mut pair = Pair::new(Default::default(), server_config);
+    let seen = Arc::new(Mutex::new(None));
+    let seen2 = seen.clone();
+    pair.server.incoming_decision = Box::new(move |incoming| {
+        *seen2.lock().unwrap() = Some((
+            incoming.remote_address(),
+            incoming.remote_address_validated(),
+            incoming.server_name().map(|x| x.to_owned()),
+            incoming.alpn_protocols().map(|x| x.to_vec()),
+        ));
+        IncomingDecision::Accept
+    });
+    let mut config = client_config();
+    Arc::make_mut(&mut config.crypto).alpn_protocols = vec![b"foo".to_vec(), b"bar".to_vec()];
+    pair.connect_with(config);
+    let (remote, validated, server_name, alpn) = seen.lock().unwrap().take().unwrap();
+    assert_eq!(remote, pair.client.addr);
+    assert!(!validated);
+    assert_eq!(server_name.as_deref(), Some("localhost"));
+    assert_eq!(alpn, Some(vec![b"foo".to_vec(), b"bar".to_vec()]));
+}
+
+#[test]
+fn incoming_connection_refuse() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    pair.server.incoming_decision = Box::new(|_| {
+        IncomingDecision::Refuse(TransportError {
+            code: TransportErrorCode::APPLICATION_ERROR,
+            frame: None,
+            reason: "go away".into(),
+        })
+    });
+    let client_ch = pair.begin_connect(client_config());
+    pair.drive();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::ConnectionLost {
+            reason: ConnectionError::ConnectionClosed(frame::ConnectionClose {
+                error_code: TransportErrorCode::APPLICATION_ERROR,
+                ref reason,
+                ..
+            }),
+        }) if &reason[..] == b"go away"
+    );
+    assert_eq!(pair.server.connections.len(), 0);
+    assert_eq!(pair.server.known_connections(), 0);
+    assert_eq!(pair.server.known_cids(), 0);
+}
+
+#[test]
+fn incoming_connection_retry() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let attempts = Arc::new(Mutex::new(Vec::new()));
+    let attempts2 = attempts.clone();
+    pair.server.incoming_decision = Box::new(move |incoming| {
+        attempts2
+            .lock()
+            .unwrap()
+            .push(incoming.remote_address_validated());
+        if incoming.remote_address_validated() {
+            IncomingDecision::Accept
+        } else {
+            IncomingDecision::Retry
+        }
+    });
+    pair.connect();
+    assert_eq!(*attempts.lock().unwrap(), [false, true]);
+}
+
+#[test]
+fn incoming_connection_ignore() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    pair.server.incoming_decision = Box::new(|_| IncomingDecision::Ignore);
+    let client_ch = pair.begin_connect(client_config());
+    pair.drive();
+    // The client hears nothing back, and will eventually time out
+    assert_matches!(pair.client_conn_mut(client_ch).poll(), None);
+    assert!(pair.client_conn_mut(client_ch).is_handshaking());
+    assert_eq!(pair.server.connections.len(), 0);
+    assert_eq!(pair.server.known_connections(), 0);
+}
+
+#[test]
+fn incoming_connection_forgotten() {
+    let _guard = subscribe();
+    let mut server_config = server_config();
+    server_config.concurrent_connections(1);
+    let mut pair = Pair::new(Default::default(), server_config);
+    let mut first = true;
+    pair.server.incoming_decision = Box::new(move |_| {
+        if mem::replace(&mut first, false) {
+            IncomingDecision::Forget
+        } else {
+            IncomingDecision::Accept
+        }
+    });
+    let client_ch = pair.begin_connect(client_config());
+    pair.drive_client();
+    pair.drive_server();
+    pair.client.connections.remove(&client_ch);
+
+    // The forgotten attempt takes up the only slot until it expires
+    pair.time += Duration::from_secs(10);
+    pair.connect();
+    assert_eq!(pair.server.connections.len(), 1);
+}
+
+#[test]
+fn handshake_rate_limit() {
+    let _guard = subscribe();
+    let mut limit = HandshakeLimitConfig::default();
+    limit
+        .burst(1)
+        .interval(Duration::from_secs(10))
+        .refuse(true);
+    let mut server_config = server_config();
+    server_config.handshake_limit(Some(limit));
+    let mut pair = Pair::new(Default::default(), server_config);
+    pair.connect();
+
+    let client_ch = pair.begin_connect(client_config());
+    pair.drive();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::ConnectionLost {
+            reason: ConnectionError::ConnectionClosed(frame::ConnectionClose {
+                error_code: TransportErrorCode::CONNECTION_REFUSED,
+                ..
+            }),
+        })
+    );
+    let stats = pair.server.stats();
+    assert_eq!(stats.incoming_handshakes, 1);
+    assert_eq!(stats.rate_limited, 1);
+    assert_eq!(stats.tracked_sources, 1);
+    assert_eq!(pair.server.known_connections(), 1);
+
+    // The source earns another attempt over time
+    pair.time += Duration::from_secs(10);
+    pair.connect();
+    assert_eq!(pair.server.stats().rate_limited, 1);
+}
+
+#[test]
+fn shared_handshake_rate_limit() {
+    let _guard = subscribe();
+    let mut limit = HandshakeLimitConfig::default();
+    limit
+        .burst(1)
+        .interval(Duration::from_secs(10))
+        .refuse(true);
+    let mut server_config = server_config();
+    server_config.handshake_limit(Some(limit));
+    let server_config = Arc::new(server_config);
+    let first = Endpoint::new(Default::default(), Some(server_config.clone()));
+    let mut second = Endpoint::new(Default::default(), Some(server_config));
+    second.share_server_state(&first);
+
+    let mut pair = Pair::new_from_endpoint(Endpoint::new(Default::default(), None), first);
+    pair.connect();
+
+    // The same source reaching the other endpoint is still over its limit
+    let mut pair = Pair::new_from_endpoint(Endpoint::new(Default::default(), None), second);
+    let client_ch = pair.begin_connect(client_config());
+    pair.drive();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::ConnectionLost {
+            reason: ConnectionError::ConnectionClosed(frame::ConnectionClose {
+                error_code: TransportErrorCode::CONNECTION_REFUSED,
+                ..
+            }),
+        })
+    );
+    let stats = pair.server.stats();
+    assert_eq!(stats.rate_limited, 1);
+    assert_eq!(stats.tracked_sources, 1);
+}
+
+#[test]
+fn handshake_rate_limit_drop() {
+    let _guard = subscribe();
+    let mut limit = HandshakeLimitConfig::default();
+    limit.burst(1).interval(Duration::from_secs(10));
+    let mut server_config = server_config();
+    server_config.handshake_limit(Some(limit));
+    let mut pair = Pair::new(Default::default(), server_config);
+    pair.connect();
+
+    let client_ch = pair.begin_connect(client_config());
+    pair.drive();
+    assert_matches!(pair.client_conn_mut(client_ch).poll(), None);
+    assert!(pair.client_conn_mut(client_ch).is_handshaking());
+    // Every retransmission of the client's Initial is dropped in turn
+    assert!(pair.server.stats().rate_limited > 1);
+    assert_eq!(pair.server.known_connections(), 1);
+}
+
+#[test]
+fn retry_under_load() {
+    let _guard = subscribe();
+    let mut server_config = server_config();
+    server_config.retry_threshold(Some(1));
+    let mut pair = Pair::new(Default::default(), server_config);
+
+    // Two clients start connecting at once, so the second finds a handshake in progress
+    let first = pair.begin_connect(client_config());
+    let second = pair.begin_connect(client_config());
+    pair.drive();
+    for ch in [first, second].iter() {
+        assert_matches!(
+            pair.client_conn_mut(*ch).poll(),
+            Some(Event::HandshakeDataReady)
+        );
+        assert_matches!(pair.client_conn_mut(*ch).poll(), Some(Event::Connected));
+    }
+    let stats = pair.server.stats();
+    assert_eq!(stats.incoming_handshakes, 2);
+    assert_eq!(stats.retries_sent, 1);
+    assert_eq!(stats.load_retries, 1);
+    assert_eq!(stats.pending_handshakes, 0);
+
+    // Without a backlog, no Retry is needed
+    pair.connect();
+    assert_eq!(pair.server.stats().retries_sent, 1);
+}
+
+#[test]
+fn server_hs_retransmit() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let client_ch = pair.begin_connect(client_config());
+    pair.step();
+    assert!(!pair.client.inbound.is_empty()); // Initial + Handshakes
+    pair.client.inbound.clear();
+    pair.drive();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::HandshakeDataReady)
+    );
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::Connected { .. })
+    );
+}
+
+#[test]
+fn migration() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+    pair.client.addr = SocketAddr::new(
+        Ipv4Addr::new(127, 0, 0, 1).into(),
+        CLIENT_PORTS.lock().unwrap().next().unwrap(),
+    );
+    pair.client_conn_mut(client_ch).ping();
+
+    // Assert that just receiving the ping message is accounted into the servers
+    // anti-amplification budget
+    pair.drive_client();
+    pair.drive_server();
+    assert_ne!(pair.server_conn_mut(server_ch).total_recvd(), 0);
+
+    pair.drive();
+    assert_matches!(pair.client_conn_mut(client_ch).poll(), None);
+    assert_eq!(
+        pair.server_conn_mut(server_ch).remote_address(),
+        pair.client.addr
+    );
+}
+
+#[test]
+fn preferred_address() {
+    let _guard = subscribe();
+    let preferred = SocketAddrV6::new(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 2), 4433, 0, 0);
+    let mut server_config = server_config();
+    server_config.preferred_address_v6(Some(preferred));
+    let mut pair = Pair::new(Default::default(), server_config);
+    pair.server.alt_addr = Some(preferred.into());
+    let (client_ch, server_ch) = pair.connect();
+    pair.drive();
+
+    assert_eq!(
+        pair.client_conn_mut(client_ch).remote_address(),
+        SocketAddr::from(preferred)
+    );
+    assert_eq!(
+        pair.client_conn_mut(client_ch)
+            .stats()
+            .frame_rx
+            .path_response,
+        1
+    );
+    assert_eq!(
+        pair.server_conn_mut(server_ch).local_ip(),
+        Some((*preferred.ip()).into())
+    );
+
+    // The connection keeps working over the new path
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, s).write(b"hello").unwrap();
+    pair.drive();
+    assert_matches!(pair.server_streams(server_ch).accept(Dir::Uni), Some(stream) if stream == s);
+    assert_eq!(
+        pair.client_conn_mut(client_ch).remote_address(),
+        SocketAddr::from(preferred)
+    );
+}
+
+#[test]
+fn preferred_address_unreachable() {
+    let _guard = subscribe();
+    let preferred = SocketAddrV6::new(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 2), 4433, 0, 0);
+    let mut server_config = server_config();
+    server_config.preferred_address_v6(Some(preferred));
+    let mut pair = Pair::new(Default::default(), server_config);
+    // Datagrams sent to the preferred address are lost
+    let (client_ch, server_ch) = pair.connect();
+    // Data sent while the preferred address is being validated still uses the original path
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, s).write(b"early").unwrap();
+    pair.drive();
+    assert_matches!(pair.server_streams(server_ch).accept(Dir::Uni), Some(stream) if stream == s);
+    assert_eq!(
+        pair.client_conn_mut(client_ch)
+            .stats()
+            .path
+            .congestion_events,
+        0
+    );
+
+    assert_eq!(
+        pair.client_conn_mut(client_ch).remote_address(),
+        pair.server.addr
+    );
+    assert_eq!(
+        pair.client_conn_mut(client_ch)
+            .stats()
+            .frame_rx
+            .path_response,
+        0
+    );
+
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, s).write(b"hello").unwrap();
+    pair.drive();
+    assert_matches!(pair.server_streams(server_ch).accept(Dir::Uni), Some(stream) if stream == s);
+}
+
+#[test]
+fn client_migration() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+    pair.drive();
+    let new_addr = SocketAddr::new(
+        Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 3).into(),
+        pair.client.addr.port(),
+    );
+    pair.client.alt_addr = Some(new_addr);
+
+    let now = pair.time;
+    pair.client_conn_mut(client_ch)
+        .initiate_migration(now, new_addr.ip())
+        .unwrap();
+    assert_eq!(
+        pair.client_conn_mut(client_ch)
+            .initiate_migration(now, new_addr.ip()),
+        Err(MigrationError::InProgress)
+    );
+    // The current path keeps its CID until the new one is validated
+    assert_eq!(pair.client_conn_mut(client_ch).active_rem_cid_seq(), 0);
+    pair.drive();
     assert_matches!(
         pair.client_conn_mut(client_ch).poll(),
-        Some(Event::ConnectionLost {
-            reason: ConnectionError::TimedOut,
-        })
+        Some(Event::Migrated)
     );
-    assert_matches!(
-        pair.server_conn_mut(server_ch).poll(),
-        Some(Event::ConnectionLost {
-            reason: ConnectionError::TimedOut,
-        })
+    assert_eq!(pair.client_conn_mut(client_ch).active_rem_cid_seq(), 1);
+    assert_eq!(
+        pair.client_conn_mut(client_ch).local_ip(),
+        Some(new_addr.ip())
     );
+
+    // The server follows once the client sends non-probing packets from the new address
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, s).write(b"hello").unwrap();
+    pair.drive();
+    assert_matches!(pair.server_streams(server_ch).accept(Dir::Uni), Some(stream) if stream == s);
+    assert_eq!(pair.server_conn_mut(server_ch).remote_address(), new_addr);
 }
 
 #[test]
-fn connection_close_sends_acks() {
+fn client_migration_unreachable() {
     let _guard = subscribe();
     let mut pair = Pair::default();
-    let (client_ch, _server_ch) = pair.connect();
-
-    let client_acks = pair.client_conn_mut(client_ch).stats().frame_rx.acks;
-
-    pair.client_conn_mut(client_ch).ping();
-    pair.drive_client();
-
-    let time = pair.time;
-    pair.server_conn_mut(client_ch)
-        .close(time, VarInt(42), Bytes::new());
+    let (client_ch, server_ch) = pair.connect();
+    pair.drive();
+    // Datagrams sent from the new address are lost
+    let new_ip = Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 3).into();
 
+    let now = pair.time;
+    pair.client_conn_mut(client_ch)
+        .initiate_migration(now, new_ip)
+        .unwrap();
     pair.drive();
+    assert_matches!(
+        pair.client_conn_mut(client_ch).poll(),
+        Some(Event::MigrationFailed {
+            reason: MigrationError::ValidationFailed
+        })
+    );
+    assert_eq!(pair.client_conn_mut(client_ch).local_ip(), None);
+    assert_eq!(pair.client_conn_mut(client_ch).active_rem_cid_seq(), 0);
 
-    let client_acks_2 = pair.client_conn_mut(client_ch).stats().frame_rx.acks;
-    assert!(
-        client_acks_2 > client_acks,
-        "Connection close should send pending ACKs"
+    // The original path remained usable throughout
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, s).write(b"hello").unwrap();
+    pair.drive();
+    assert_matches!(pair.server_streams(server_ch).accept(Dir::Uni), Some(stream) if stream == s);
+    assert_eq!(
+        pair.server_conn_mut(server_ch).remote_address(),
+        pair.client.addr
     );
 }
 
 #[test]
-fn concurrent_connections_full() {
+fn client_migration_refused() {
     let _guard = subscribe();
-    let mut pair = Pair::new(
-        Default::default(),
-        ServerConfig {
-            concurrent_connections: 0,
-            ..server_config()
-        },
+    let mut server_config = server_config();
+    server_config.migration(false);
+    let mut pair = Pair::new(Default::default(), server_config);
+    let (client_ch, server_ch) = pair.connect();
+    let new_ip = Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 3).into();
+    let now = pair.time;
+    assert_eq!(
+        pair.server_conn_mut(server_ch)
+            .initiate_migration(now, new_ip),
+        Err(MigrationError::NotClient)
     );
-    let client_ch = pair.begin_connect(client_config());
-    pair.drive();
-    assert_matches!(
-        pair.client_conn_mut(client_ch).poll(),
-        Some(Event::ConnectionLost {
-            reason: ConnectionError::ConnectionClosed(frame::ConnectionClose {
-                error_code: TransportErrorCode::CONNECTION_REFUSED,
-                ..
-            }),
-        })
+    assert_eq!(
+        pair.client_conn_mut(client_ch)
+            .initiate_migration(now, new_ip),
+        Err(MigrationError::DisabledByPeer)
     );
-    assert_eq!(pair.server.connections.len(), 0);
-    assert_eq!(pair.server.known_connections(), 0);
-    assert_eq!(pair.server.known_cids(), 0);
 }
 
 #[test]
-fn server_hs_retransmit() {
+fn multipath_not_negotiated() {
     let _guard = subscribe();
+    let mut transport = TransportConfig::default();
+    transport.enable_multipath(true);
     let mut pair = Pair::default();
-    let client_ch = pair.begin_connect(client_config());
-    pair.step();
-    assert!(!pair.client.inbound.is_empty()); // Initial + Handshakes
-    pair.client.inbound.clear();
+    let (client_ch, server_ch) = pair.connect_with(ClientConfig {
+        transport: Arc::new(transport),
+        ..client_config()
+    });
     pair.drive();
+    let now = pair.time;
+    let server_addr = pair.server.addr;
     assert_matches!(
-        pair.client_conn_mut(client_ch).poll(),
-        Some(Event::HandshakeDataReady)
-    );
-    assert_matches!(
-        pair.client_conn_mut(client_ch).poll(),
-        Some(Event::Connected { .. })
+        pair.client_conn_mut(client_ch)
+            .open_path(now, server_addr, None),
+        Err(PathError::MultipathNotNegotiated)
     );
+    assert_eq!(pair.client_conn_mut(client_ch).paths().len(), 1);
+    assert_eq!(pair.server_conn_mut(server_ch).paths().len(), 1);
 }
 
 #[test]
-fn migration() {
+fn multipath_unsupported_keys() {
     let _guard = subscribe();
-    let mut pair = Pair::default();
-    let (client_ch, server_ch) = pair.connect();
-    pair.client.addr = SocketAddr::new(
-        Ipv4Addr::new(127, 0, 0, 1).into(),
-        CLIENT_PORTS.lock().unwrap().next().unwrap(),
+    // rustls' packet keys can't protect packets sent on other paths
+    let mut transport = TransportConfig::default();
+    transport.enable_multipath(true);
+    let transport = Arc::new(transport);
+    let mut pair = Pair::new(
+        Default::default(),
+        ServerConfig {
+            transport: transport.clone(),
+            ..server_config()
+        },
     );
-    pair.client_conn_mut(client_ch).ping();
-
-    // Assert that just receiving the ping message is accounted into the servers
-    // anti-amplification budget
-    pair.drive_client();
-    pair.drive_server();
-    assert_ne!(pair.server_conn_mut(server_ch).total_recvd(), 0);
-
+    let (client_ch, _) = pair.connect_with(ClientConfig {
+        transport,
+        ..client_config()
+    });
     pair.drive();
-    assert_matches!(pair.client_conn_mut(client_ch).poll(), None);
-    assert_eq!(
-        pair.server_conn_mut(server_ch).remote_address(),
-        pair.client.addr
+    let now = pair.time;
+    let server_addr = pair.server.addr;
+    assert_matches!(
+        pair.client_conn_mut(client_ch)
+            .open_path(now, server_addr, None),
+        Err(PathError::MultipathNotNegotiated)
     );
 }
 
@@ -1300,6 +2324,29 @@ fn cid_rotation() {
     }
 }
 
+#[test]
+fn routable_cids() {
+    let _guard = subscribe();
+    const SERVER_ID: &[u8] = &[0xab, 0xcd];
+    let lb_config = quic_lb::Config::block_cipher(1, SERVER_ID.len(), [0x42; 16]).unwrap();
+    let generator =
+        quic_lb::RoutableConnectionIdGenerator::new(lb_config.clone(), SERVER_ID).unwrap();
+    let mut endpoint_config = EndpointConfig::default();
+    endpoint_config.cid_generator(move || Box::new(generator.clone()));
+    let mut pair = Pair::new(Arc::new(endpoint_config), server_config());
+    let (client_ch, _) = pair.connect();
+
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, s).write(b"hello").unwrap();
+    pair.drive_client();
+    let mut decoder = quic_lb::Decoder::new();
+    decoder.insert(lb_config);
+    assert!(!pair.server.inbound.is_empty());
+    for (_, _, datagram, _, _) in &pair.server.inbound {
+        assert_eq!(decoder.route(datagram).as_deref(), Some(SERVER_ID));
+    }
+}
+
 #[test]
 fn cid_retirement() {
     let _guard = subscribe();
@@ -1345,7 +2392,7 @@ fn finish_stream_flow_control_reordered() {
     const MSG: &[u8] = b"hello";
     pair.client_send(client_ch, s).write(MSG).unwrap();
     pair.drive_client(); // Send stream data
-    pair.server.drive(pair.time, pair.client.addr); // Receive
+    pair.server.drive(pair.time); // Receive
 
     // Issue flow control credit
     let mut recv = pair.server_recv(server_ch, s);
@@ -1356,12 +2403,12 @@ fn finish_stream_flow_control_reordered() {
     );
     let _ = chunks.finalize();
 
-    pair.server.drive(pair.time, pair.client.addr);
+    pair.server.drive(pair.time);
     pair.server.delay_outbound(); // Delay it
 
     pair.client_send(client_ch, s).finish().unwrap();
     pair.drive_client(); // Send FIN
-    pair.server.drive(pair.time, pair.client.addr); // Acknowledge
+    pair.server.drive(pair.time); // Acknowledge
     pair.server.finish_delay(); // Add flow control packets after
     pair.drive();
 
@@ -1392,7 +2439,7 @@ fn handshake_1rtt_handling() {
     let server_ch = pair.server.assert_accept();
     // Server now has 1-RTT keys, but remains in Handshake state until the TLS CFIN has
     // authenticated the client. Delay the final client handshake flight so that doesn't happen yet.
-    pair.client.drive(pair.time, pair.server.addr);
+    pair.client.drive(pair.time);
     pair.client.delay_outbound();
 
     // Send some 1-RTT data which will be received first.
@@ -1400,7 +2447,7 @@ fn handshake_1rtt_handling() {
     const MSG: &[u8] = b"hello";
     pair.client_send(client_ch, s).write(MSG).unwrap();
     pair.client_send(client_ch, s).finish().unwrap();
-    pair.client.drive(pair.time, pair.server.addr);
+    pair.client.drive(pair.time);
 
     // Add the handshake flight back on.
     pair.client.finish_delay();
@@ -1575,15 +2622,13 @@ fn large_initial() {
     let mut server_config = server_config();
     Arc::get_mut(&mut server_config.crypto)
         .unwrap()
-        .set_protocols(&[vec![0, 0, 0, 42]]);
+        .alpn_protocols = vec![vec![0, 0, 0, 42]];
     let mut pair = Pair::new(Arc::new(EndpointConfig::default()), server_config);
     let mut cfg = client_config();
     let protocols = (0..1000u32)
         .map(|x| x.to_be_bytes().to_vec())
         .collect::<Vec<_>>();
-    Arc::get_mut(&mut cfg.crypto)
-        .unwrap()
-        .set_protocols(&protocols);
+    Arc::get_mut(&mut cfg.crypto).unwrap().alpn_protocols = protocols;
     let client_ch = pair.begin_connect(cfg);
     pair.drive();
     let server_ch = pair.server.assert_accept();
@@ -1842,3 +2887,419 @@ fn big_cert_and_key() -> (Certificate, PrivateKey) {
     let cert = Certificate::from_der(&cert.serialize_der().unwrap()).unwrap();
     (cert, key)
 }
+
+/// Set up a pair whose connections search for the path MTU, returning the client's configuration
+fn mtu_discovery_pair() -> (Pair, ClientConfig) {
+    let mut transport = TransportConfig::default();
+    transport.mtu_discovery_config(Some(MtuDiscoveryConfig::default()));
+    let transport = Arc::new(transport);
+    let pair = Pair::new(
+        Default::default(),
+        ServerConfig {
+            transport: transport.clone(),
+            ..server_config()
+        },
+    );
+    let client_config = ClientConfig {
+        transport,
+        ..client_config()
+    };
+    (pair, client_config)
+}
+
+#[test]
+fn mtu_discovery() {
+    let _guard = subscribe();
+    let (mut pair, client_config) = mtu_discovery_pair();
+    let (client_ch, server_ch) = pair.connect_with(client_config);
+    pair.drive();
+
+    let upper_bound = 1452;
+    for stats in [
+        pair.client_conn_mut(client_ch).stats(),
+        pair.server_conn_mut(server_ch).stats(),
+    ] {
+        assert_eq!(stats.path.current_mtu, upper_bound);
+        assert_eq!(stats.path.sent_plpmtud_probes, 1);
+        assert_eq!(stats.path.lost_plpmtud_probes, 0);
+    }
+}
+
+#[test]
+fn mtu_discovery_restricted_path() {
+    let _guard = subscribe();
+    let (mut pair, client_config) = mtu_discovery_pair();
+    pair.mtu = 1400;
+    let (client_ch, server_ch) = pair.connect_with(client_config);
+    pair.drive();
+
+    for stats in [
+        pair.client_conn_mut(client_ch).stats(),
+        pair.server_conn_mut(server_ch).stats(),
+    ] {
+        assert!(stats.path.current_mtu <= 1400);
+        assert!(stats.path.current_mtu > 1400 - 20);
+        assert!(stats.path.lost_plpmtud_probes >= 3);
+        // Lost probes aren't treated as congestion
+        assert_eq!(stats.path.congestion_events, 0);
+    }
+}
+
+#[test]
+fn mtu_discovery_disabled() {
+    let _guard = subscribe();
+    // Disabled by default
+    let mut pair = Pair::default();
+    let (client_ch, server_ch) = pair.connect();
+    pair.drive();
+
+    for stats in [
+        pair.client_conn_mut(client_ch).stats(),
+        pair.server_conn_mut(server_ch).stats(),
+    ] {
+        assert_eq!(stats.path.current_mtu, 1200);
+        assert_eq!(stats.path.sent_plpmtud_probes, 0);
+    }
+}
+
+#[test]
+fn mtu_black_hole() {
+    let _guard = subscribe();
+    let (mut pair, client_config) = mtu_discovery_pair();
+    let (client_ch, server_ch) = pair.connect_with(client_config);
+    pair.drive();
+    assert_eq!(
+        pair.client_conn_mut(client_ch).stats().path.current_mtu,
+        1452
+    );
+
+    // The path stops delivering large datagrams
+    pair.mtu = 1300;
+    const LEN: usize = 128 * 1024;
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    assert_eq!(
+        pair.client_send(client_ch, s).write(&[0; LEN]).unwrap(),
+        LEN
+    );
+    pair.client_send(client_ch, s).finish().unwrap();
+    pair.drive();
+
+    let stats = pair.client_conn_mut(client_ch).stats();
+    assert_eq!(stats.path.black_holes_detected, 1);
+    assert_eq!(stats.path.current_mtu, 1200);
+
+    assert_matches!(pair.server_streams(server_ch).accept(Dir::Uni), Some(stream) if stream == s);
+    let mut recv = pair.server_recv(server_ch, s);
+    let mut chunks = recv.read(false).unwrap();
+    let mut received = 0;
+    while let Ok(Some(chunk)) = chunks.next(usize::MAX) {
+        received += chunk.bytes.len();
+    }
+    let _ = chunks.finalize();
+    assert_eq!(received, LEN);
+}
+
+#[test]
+fn mtu_kept_while_peer_silent() {
+    let _guard = subscribe();
+    let (mut pair, client_config) = mtu_discovery_pair();
+    let (client_ch, _) = pair.connect_with(client_config);
+    pair.drive();
+    assert_eq!(
+        pair.client_conn_mut(client_ch).stats().path.current_mtu,
+        1452
+    );
+
+    // Send full-sized packets that the peer never answers
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    pair.client_send(client_ch, s).write(&[0; 4096]).unwrap();
+    pair.client.drive(pair.time);
+    assert!(!pair.client.outbound.is_empty());
+    pair.client.outbound.clear();
+
+    // A silent peer isn't evidence of a black hole, however many PTOs expire
+    let mut ptos = 0;
+    while ptos < 3 {
+        pair.time = pair.client.next_wakeup().unwrap();
+        pair.client.drive(pair.time);
+        if !pair.client.outbound.is_empty() {
+            ptos += 1;
+            pair.client.outbound.clear();
+        }
+    }
+
+    let stats = pair.client_conn_mut(client_ch).stats();
+    assert_eq!(stats.path.black_holes_detected, 0);
+    assert_eq!(stats.path.current_mtu, 1452);
+}
+
+/// Upload `size` bytes to the server on a new stream, stepping `pair` until it's all been read
+fn upload(pair: &mut Pair, client_ch: ConnectionHandle, server_ch: ConnectionHandle, size: usize) {
+    let s = pair.client_streams(client_ch).open(Dir::Uni).unwrap();
+    let msg = vec![0xAB; size];
+    let mut written = 0;
+    let mut read = 0;
+    while read < size {
+        if written < size {
+            written += pair
+                .client_send(client_ch, s)
+                .write(&msg[written..])
+                .unwrap_or(0);
+        }
+        assert!(pair.step(), "connection went idle");
+        let mut recv = pair.server_recv(server_ch, s);
+        if let Ok(mut chunks) = recv.read(true) {
+            while let Ok(Some(chunk)) = chunks.next(usize::MAX) {
+                read += chunk.bytes.len();
+            }
+            let _ = chunks.finalize();
+        };
+    }
+}
+
+/// Time taken to upload `size` bytes to the server over a path with 100ms RTT, 10MB/s bandwidth
+/// and 1% random loss
+fn lossy_upload_time(
+    congestion: impl congestion::ControllerFactory + Send + Sync + 'static,
+    size: usize,
+) -> Duration {
+    let _guard = subscribe();
+    let mut transport = TransportConfig::default();
+    transport.congestion_controller_factory(congestion);
+    let mut pair = Pair::new(Default::default(), server_config());
+    pair.latency = Duration::from_millis(50);
+    pair.bandwidth = Some(10_000_000);
+    let (client_ch, server_ch) = pair.connect_with(ClientConfig {
+        transport: Arc::new(transport),
+        ..client_config()
+    });
+    pair.loss = 0.01;
+
+    let start = pair.time;
+    upload(&mut pair, client_ch, server_ch, size);
+    pair.time - start
+}
+
+#[test]
+fn bbr_tolerates_random_loss() {
+    const SIZE: usize = 5 * 1000 * 1000;
+    let bbr = lossy_upload_time(Arc::new(congestion::BbrConfig::default()), SIZE);
+    let cubic = lossy_upload_time(Arc::new(congestion::CubicConfig::default()), SIZE);
+    info!(?bbr, ?cubic, "upload times");
+    // At the link rate, the upload would take half a second
+    assert!(bbr < Duration::from_secs(3));
+    assert!(bbr * 4 < cubic);
+}
+
+/// Congestion events while uploading 2MB over a path with 40ms RTT, 1MB/s bandwidth and a
+/// bottleneck buffer holding ten times the bandwidth-delay product
+fn deep_buffer_congestion_events(
+    congestion: impl congestion::ControllerFactory + Send + Sync + 'static,
+) -> u64 {
+    let _guard = subscribe();
+    let mut transport = TransportConfig::default();
+    transport.congestion_controller_factory(congestion);
+    let mut pair = Pair::new(Default::default(), server_config());
+    pair.latency = Duration::from_millis(20);
+    pair.bandwidth = Some(1_000_000);
+    pair.buffer_size = Some(400_000);
+    let (client_ch, server_ch) = pair.connect_with(ClientConfig {
+        transport: Arc::new(transport),
+        ..client_config()
+    });
+
+    upload(&mut pair, client_ch, server_ch, 2_000_000);
+    pair.client_conn_mut(client_ch)
+        .stats()
+        .path
+        .congestion_events
+}
+
+#[test]
+fn hystart_avoids_slow_start_overshoot() {
+    let mut new_reno = congestion::NewRenoConfig::default();
+    new_reno.hystart(true);
+    let mut cubic = congestion::CubicConfig::default();
+    cubic.hystart(true);
+    // Slow start would otherwise overshoot the path's capacity by far, causing many losses
+    let plain = deep_buffer_congestion_events(Arc::new(congestion::NewRenoConfig::default()));
+    assert!(deep_buffer_congestion_events(Arc::new(new_reno)) * 10 < plain);
+    let plain = deep_buffer_congestion_events(Arc::new(congestion::CubicConfig::default()));
+    assert!(deep_buffer_congestion_events(Arc::new(cubic)) * 10 < plain);
+}
+
+/// Bytes uploaded by a connection using Cubic and one using `background` as they compete for 10
+/// seconds over a path with 40ms RTT, 1MB/s bandwidth and a 200KB buffer
+fn competing_uploads(
+    background: impl congestion::ControllerFactory + Send + Sync + 'static,
+) -> (usize, usize) {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    pair.latency = Duration::from_millis(20);
+    pair.bandwidth = Some(1_000_000);
+    pair.buffer_size = Some(200_000);
+    let (foreground_client, foreground_server) = pair.connect();
+    let mut transport = TransportConfig::default();
+    transport.congestion_controller_factory(background);
+    let (background_client, background_server) = pair.connect_with(ClientConfig {
+        transport: Arc::new(transport),
+        ..client_config()
+    });
+
+    let mut connections = [
+        (foreground_client, foreground_server, 0),
+        (background_client, background_server, 0),
+    ];
+    let streams = connections
+        .iter()
+        .map(|&(client_ch, _, _)| pair.client_streams(client_ch).open(Dir::Uni).unwrap())
+        .collect::<Vec<_>>();
+    let msg = [0xAB; 64 * 1024];
+    let start = pair.time;
+    while pair.time - start < Duration::from_secs(10) {
+        for (&mut (client_ch, server_ch, ref mut read), &s) in connections.iter_mut().zip(&streams)
+        {
+            let _ = pair.client_send(client_ch, s).write(&msg);
+            let mut recv = pair.server_recv(server_ch, s);
+            if let Ok(mut chunks) = recv.read(true) {
+                while let Ok(Some(chunk)) = chunks.next(usize::MAX) {
+                    *read += chunk.bytes.len();
+                }
+                let _ = chunks.finalize();
+            };
+        }
+        assert!(pair.step(), "connections went idle");
+    }
+    (connections[0].2, connections[1].2)
+}
+
+#[test]
+fn ledbat_yields_to_competing_traffic() {
+    let ledbat = Arc::new(congestion::LedbatConfig::default());
+    let (foreground, background) = competing_uploads(ledbat.clone());
+    info!(foreground, background, "bytes uploaded");
+    // Two Cubic connections would split the path roughly evenly
+    assert!(background * 10 < foreground);
+
+    // Without competition, it uses the spare capacity
+    let _guard = subscribe();
+    let mut transport = TransportConfig::default();
+    transport.congestion_controller_factory(ledbat);
+    let mut pair = Pair::default();
+    pair.latency = Duration::from_millis(20);
+    pair.bandwidth = Some(1_000_000);
+    pair.buffer_size = Some(200_000);
+    let (client_ch, server_ch) = pair.connect_with(ClientConfig {
+        transport: Arc::new(transport),
+        ..client_config()
+    });
+    let start = pair.time;
+    upload(&mut pair, client_ch, server_ch, 2_000_000);
+    let elapsed = pair.time - start;
+    info!(?elapsed, "uncontended upload time");
+    assert!(elapsed < Duration::from_secs(4));
+}
+
+/// Times taken by a connection to upload 1MB with and without resuming the congestion state of an
+/// earlier one, over a path with 100ms RTT and 10MB/s bandwidth
+fn careful_resume_upload_times(
+    congestion: impl congestion::ControllerFactory + Send + Sync + 'static,
+) -> (Duration, Duration) {
+    let _guard = subscribe();
+    let mut transport = TransportConfig::default();
+    transport.congestion_controller_factory(congestion);
+    let client_config = ClientConfig {
+        transport: Arc::new(transport),
+        ..client_config()
+    };
+    let mut pair = Pair::default();
+    pair.latency = Duration::from_millis(50);
+    pair.bandwidth = Some(10_000_000);
+    let (client_ch, server_ch) = pair.connect_with(client_config.clone());
+    upload(&mut pair, client_ch, server_ch, 5_000_000);
+    let snapshot = pair
+        .client_conn_mut(client_ch)
+        .congestion_snapshot()
+        .unwrap();
+    info!(?snapshot, "saved congestion state");
+
+    let mut upload_time = |snapshot: Option<CongestionSnapshot>| {
+        let (client_ch, server_ch) = pair.connect_with(client_config.clone());
+        if let Some(snapshot) = snapshot {
+            assert!(pair.client_conn_mut(client_ch).resume_congestion(snapshot));
+        }
+        let start = pair.time;
+        upload(&mut pair, client_ch, server_ch, 1_000_000);
+        pair.time - start
+    };
+    let cold = upload_time(None);
+    let resumed = upload_time(Some(snapshot));
+    info!(?cold, ?resumed, "upload times");
+    (cold, resumed)
+}
+
+#[test]
+fn careful_resume_speeds_up_repeat_connections() {
+    let (cold, resumed) = careful_resume_upload_times(Arc::new(congestion::CubicConfig::default()));
+    assert!(resumed * 2 < cold);
+}
+
+#[test]
+fn careful_resume_bbr() {
+    let (cold, resumed) = careful_resume_upload_times(Arc::new(congestion::BbrConfig::default()));
+    assert!(resumed * 2 < cold);
+}
+
+#[test]
+fn careful_resume_checks_snapshot() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    let (client_ch, _) = pair.connect();
+    let snapshot = CongestionSnapshot {
+        window: 1_000_000,
+        rtt: Duration::from_millis(100),
+        remote: pair.server.addr,
+        taken: SystemTime::now(),
+    };
+    let conn = pair.client_conn_mut(client_ch);
+    assert!(!conn.resume_congestion(CongestionSnapshot {
+        remote: SocketAddr::new(Ipv6Addr::LOCALHOST.into(), 1),
+        ..snapshot
+    }));
+    assert!(!conn.resume_congestion(CongestionSnapshot {
+        taken: snapshot.taken - CongestionSnapshot::MAX_AGE - Duration::from_secs(1),
+        ..snapshot
+    }));
+    assert!(!conn.resume_congestion(CongestionSnapshot {
+        taken: snapshot.taken + Duration::from_secs(60),
+        ..snapshot
+    }));
+    assert!(conn.resume_congestion(snapshot));
+}
+
+#[test]
+fn careful_resume_retreats_on_congestion() {
+    let _guard = subscribe();
+    let mut pair = Pair::default();
+    pair.latency = Duration::from_millis(50);
+    pair.bandwidth = Some(1_000_000);
+    pair.buffer_size = Some(50_000);
+    let (client_ch, server_ch) = pair.connect();
+    // Saved from a much faster path
+    let snapshot = CongestionSnapshot {
+        window: 10_000_000,
+        rtt: Duration::from_millis(100),
+        remote: pair.server.addr,
+        taken: SystemTime::now(),
+    };
+    assert!(pair.client_conn_mut(client_ch).resume_congestion(snapshot));
+    upload(&mut pair, client_ch, server_ch, 1_000_000);
+    let stats = pair.client_conn_mut(client_ch).stats();
+    info!(
+        cwnd = stats.path.cwnd,
+        events = stats.path.congestion_events,
+        "after upload"
+    );
+    assert!(stats.path.congestion_events > 0);
+    assert!(stats.path.cwnd < snapshot.window / 20);
+}
@@ -4,7 +4,7 @@ use std::{
     env,
     io::{self, Write},
     mem,
-    net::{Ipv6Addr, SocketAddr, UdpSocket},
+    net::{IpAddr, Ipv6Addr, SocketAddr, UdpSocket},
     ops::RangeFrom,
     str,
     sync::{Arc, Mutex},
@@ -13,6 +13,7 @@ use std::{
 
 use assert_matches::assert_matches;
 use lazy_static::lazy_static;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 use rustls::KeyLogFile;
 use tracing::{info_span, trace};
 
@@ -24,9 +25,21 @@ pub struct Pair {
     pub time: Instant,
     // One-way
     pub latency: Duration,
+    /// Largest UDP payload delivered in either direction; larger datagrams are dropped
+    pub mtu: usize,
+    /// Fraction of datagrams dropped at random in either direction
+    pub loss: f64,
+    /// Bytes per second delivered in each direction, if limited; excess datagrams are queued
+    pub bandwidth: Option<u64>,
+    /// Bytes that may be queued by the bandwidth limit in each direction, beyond which datagrams
+    /// are dropped
+    pub buffer_size: Option<u64>,
     /// Number of spin bit flips
     pub spins: u64,
     last_spin: bool,
+    rng: StdRng,
+    /// When datagrams queued towards the server and client respectively finish being sent
+    busy_until: [Instant; 2],
 }
 
 impl Pair {
@@ -46,13 +59,20 @@ impl Pair {
             Ipv6Addr::LOCALHOST.into(),
             CLIENT_PORTS.lock().unwrap().next().unwrap(),
         );
+        let now = Instant::now();
         Self {
             server: TestEndpoint::new(server, server_addr),
             client: TestEndpoint::new(client, client_addr),
-            time: Instant::now(),
+            time: now,
             latency: Duration::new(0, 0),
+            mtu: usize::MAX,
+            loss: 0.0,
+            bandwidth: None,
+            buffer_size: None,
             spins: 0,
             last_spin: false,
+            rng: StdRng::seed_from_u64(0),
+            busy_until: [now; 2],
         }
     }
 
@@ -94,8 +114,8 @@ impl Pair {
     pub fn drive_client(&mut self) {
         let span = info_span!("client");
         let _guard = span.enter();
-        self.client.drive(self.time, self.server.addr);
-        for x in self.client.outbound.drain(..) {
+        self.client.drive(self.time);
+        while let Some(x) = self.client.outbound.pop_front() {
             if x.contents[0] & packet::LONG_HEADER_FORM == 0 {
                 let spin = x.contents[0] & packet::SPIN_BIT != 0;
                 self.spins += (spin == self.last_spin) as u64;
@@ -104,10 +124,24 @@ impl Pair {
             if let Some(ref socket) = self.client.socket {
                 socket.send_to(&x.contents, x.destination).unwrap();
             }
-            if self.server.addr == x.destination {
+            if x.contents.len() > self.mtu {
+                trace!(
+                    "dropping {} byte datagram exceeding path MTU",
+                    x.contents.len()
+                );
+                continue;
+            }
+            let arrival = match self.arrival_time(0, x.contents.len()) {
+                Some(x) => x,
+                None => continue,
+            };
+            let remote = self.client.source_addr(x.src_ip);
+            if let (Some(remote), Some(local_ip)) =
+                (remote, self.server.local_ip_for(x.destination))
+            {
                 self.server
                     .inbound
-                    .push_back((self.time + self.latency, x.ecn, x.contents));
+                    .push_back((arrival, x.ecn, x.contents, remote, local_ip));
             }
         }
     }
@@ -115,22 +149,61 @@ impl Pair {
     pub fn drive_server(&mut self) {
         let span = info_span!("server");
         let _guard = span.enter();
-        self.server.drive(self.time, self.client.addr);
-        for x in self.server.outbound.drain(..) {
+        self.server.drive(self.time);
+        while let Some(x) = self.server.outbound.pop_front() {
             if let Some(ref socket) = self.server.socket {
                 socket.send_to(&x.contents, x.destination).unwrap();
             }
-            if self.client.addr == x.destination {
+            if x.contents.len() > self.mtu {
+                trace!(
+                    "dropping {} byte datagram exceeding path MTU",
+                    x.contents.len()
+                );
+                continue;
+            }
+            let arrival = match self.arrival_time(1, x.contents.len()) {
+                Some(x) => x,
+                None => continue,
+            };
+            let remote = self.server.source_addr(x.src_ip);
+            if let (Some(remote), Some(local_ip)) =
+                (remote, self.client.local_ip_for(x.destination))
+            {
                 self.client
                     .inbound
-                    .push_back((self.time + self.latency, x.ecn, x.contents));
+                    .push_back((arrival, x.ecn, x.contents, remote, local_ip));
+            }
+        }
+    }
+
+    /// When a `len` byte datagram sent now towards the server (0) or client (1) arrives, or `None`
+    /// if it's lost
+    fn arrival_time(&mut self, direction: usize, len: usize) -> Option<Instant> {
+        if self.loss > 0.0 && self.rng.gen_bool(self.loss) {
+            trace!("dropping {} byte datagram at random", len);
+            return None;
+        }
+        let mut departure = self.time;
+        if let Some(bandwidth) = self.bandwidth {
+            let start = self.busy_until[direction].max(self.time);
+            let queued = (start - self.time).as_nanos() * u128::from(bandwidth) / 1_000_000_000;
+            if matches!(self.buffer_size, Some(x) if queued + len as u128 > x.into()) {
+                trace!("dropping {} byte datagram exceeding buffer", len);
+                return None;
             }
+            departure = start + Duration::from_nanos(len as u64 * 1_000_000_000 / bandwidth);
+            self.busy_until[direction] = departure;
         }
+        Some(departure + self.latency)
     }
 
     pub fn connect(&mut self) -> (ConnectionHandle, ConnectionHandle) {
+        self.connect_with(client_config())
+    }
+
+    pub fn connect_with(&mut self, config: ClientConfig) -> (ConnectionHandle, ConnectionHandle) {
         info!("connecting");
-        let client_ch = self.begin_connect(client_config());
+        let client_ch = self.begin_connect(config);
         self.drive();
         let server_ch = self.server.assert_accept();
         assert_matches!(
@@ -158,7 +231,7 @@ impl Pair {
         let _guard = span.enter();
         let (client_ch, client_conn) = self
             .client
-            .connect(config, self.server.addr, "localhost")
+            .connect_at(self.time, config, self.server.addr, "localhost")
             .unwrap();
         self.client.connections.insert(client_ch, client_conn);
         client_ch
@@ -211,17 +284,40 @@ impl Default for Pair {
     }
 }
 
+/// A received datagram with its arrival time, remote address and local IP, if not the
+/// endpoint's primary address
+pub type Inbound = (
+    Instant,
+    Option<EcnCodepoint>,
+    Vec<u8>,
+    SocketAddr,
+    Option<IpAddr>,
+);
+
 pub struct TestEndpoint {
     pub endpoint: Endpoint,
     pub addr: SocketAddr,
+    /// Additional address the endpoint receives datagrams on, e.g. a server's preferred address
+    pub alt_addr: Option<SocketAddr>,
     socket: Option<UdpSocket>,
     timeout: Option<Instant>,
     pub outbound: VecDeque<Transmit>,
     delayed: VecDeque<Transmit>,
-    pub inbound: VecDeque<(Instant, Option<EcnCodepoint>, Vec<u8>)>,
+    pub inbound: VecDeque<Inbound>,
     accepted: Option<ConnectionHandle>,
     pub connections: HashMap<ConnectionHandle, Connection>,
     conn_events: HashMap<ConnectionHandle, VecDeque<ConnectionEvent>>,
+    /// Decides what to do with incoming connection attempts; accepts them by default
+    pub incoming_decision: Box<dyn FnMut(&IncomingConnection) -> IncomingDecision>,
+}
+
+pub enum IncomingDecision {
+    Accept,
+    Refuse(TransportError),
+    Retry,
+    Ignore,
+    /// Drop the `IncomingConnection` without passing it back to the endpoint
+    Forget,
 }
 
 impl TestEndpoint {
@@ -238,6 +334,7 @@ impl TestEndpoint {
         Self {
             endpoint,
             addr,
+            alt_addr: None,
             socket,
             timeout: None,
             outbound: VecDeque::new(),
@@ -246,10 +343,37 @@ impl TestEndpoint {
             accepted: None,
             connections: HashMap::default(),
             conn_events: HashMap::default(),
+            incoming_decision: Box::new(|_| IncomingDecision::Accept),
         }
     }
 
-    pub fn drive(&mut self, now: Instant, remote: SocketAddr) {
+    /// The address a datagram sent by this endpoint from `src_ip` appears to come from
+    ///
+    /// Returns `None` if the endpoint can't send from `src_ip`.
+    fn source_addr(&self, src_ip: Option<IpAddr>) -> Option<SocketAddr> {
+        match (src_ip, self.alt_addr) {
+            (None, _) => Some(self.addr),
+            (Some(ip), _) if ip == self.addr.ip() => Some(self.addr),
+            (Some(ip), Some(alt)) if ip == alt.ip() => Some(alt),
+            _ => None,
+        }
+    }
+
+    /// The local IP to report for a datagram sent to `destination`
+    ///
+    /// Returns `None` if the datagram doesn't reach this endpoint, and `Some(None)` for datagrams
+    /// sent to its primary address.
+    fn local_ip_for(&self, destination: SocketAddr) -> Option<Option<IpAddr>> {
+        if destination == self.addr {
+            Some(None)
+        } else if self.alt_addr == Some(destination) {
+            Some(Some(destination.ip()))
+        } else {
+            None
+        }
+    }
+
+    pub fn drive(&mut self, now: Instant) {
         if let Some(ref socket) = self.socket {
             loop {
                 let mut buf = [0; 8192];
@@ -260,23 +384,32 @@ impl TestEndpoint {
         }
 
         while self.inbound.front().map_or(false, |x| x.0 <= now) {
-            let (recv_time, ecn, packet) = self.inbound.pop_front().unwrap();
-            if let Some((ch, event)) =
-                self.endpoint
-                    .handle(recv_time, remote, None, ecn, packet.as_slice().into())
+            let (recv_time, ecn, packet, remote, local_ip) = self.inbound.pop_front().unwrap();
+            match self
+                .endpoint
+                .handle(recv_time, remote, local_ip, ecn, packet.as_slice().into())
             {
-                match event {
-                    DatagramEvent::NewConnection(conn) => {
-                        self.connections.insert(ch, conn);
-                        self.accepted = Some(ch);
-                    }
-                    DatagramEvent::ConnectionEvent(event) => {
-                        self.conn_events
-                            .entry(ch)
-                            .or_insert_with(VecDeque::new)
-                            .push_back(event);
+                Some(DatagramEvent::NewConnection(incoming)) => {
+                    match (self.incoming_decision)(&incoming) {
+                        IncomingDecision::Accept => {
+                            if let Ok((ch, conn)) = self.endpoint.accept(incoming, now) {
+                                self.connections.insert(ch, conn);
+                                self.accepted = Some(ch);
+                            }
+                        }
+                        IncomingDecision::Refuse(reason) => self.endpoint.refuse(incoming, reason),
+                        IncomingDecision::Retry => self.endpoint.retry(incoming).unwrap(),
+                        IncomingDecision::Ignore => self.endpoint.ignore(incoming),
+                        IncomingDecision::Forget => drop(incoming),
                     }
                 }
+                Some(DatagramEvent::ConnectionEvent(ch, event)) => {
+                    self.conn_events
+                        .entry(ch)
+                        .or_insert_with(VecDeque::new)
+                        .push_back(event);
+                }
+                None => {}
             }
         }
 
@@ -285,14 +418,15 @@ impl TestEndpoint {
         }
 
         let mut endpoint_events: Vec<(ConnectionHandle, EndpointEvent)> = vec![];
+        let timeout_expired = self.timeout.map_or(false, |x| x <= now);
+        self.timeout = None;
         for (ch, conn) in self.connections.iter_mut() {
-            if self.timeout.map_or(false, |x| x <= now) {
-                self.timeout = None;
+            if timeout_expired {
                 conn.handle_timeout(now);
             }
 
-            for (_, mut events) in self.conn_events.drain() {
-                for event in events.drain(..) {
+            if let Some(events) = self.conn_events.remove(ch) {
+                for event in events {
                     conn.handle_event(event);
                 }
             }
@@ -304,7 +438,7 @@ impl TestEndpoint {
             while let Some(x) = conn.poll_transmit(now, MAX_DATAGRAMS) {
                 self.outbound.extend(split_transmit(x));
             }
-            self.timeout = conn.poll_timeout();
+            self.timeout = min_opt(self.timeout, conn.poll_timeout());
         }
 
         for (ch, event) in endpoint_events {
@@ -379,34 +513,26 @@ pub fn server_config() -> ServerConfig {
     let key = CERTIFICATE.serialize_private_key_der();
     let cert = CERTIFICATE.serialize_pem().unwrap();
 
-    let mut crypto = crypto::ServerConfig::new();
-    Arc::make_mut(&mut crypto)
-        .set_single_cert(
-            rustls::internal::pemfile::certs(&mut cert.as_bytes()).unwrap(),
-            rustls::PrivateKey(key.to_vec()),
+    let mut config = ServerConfig::default();
+    config
+        .certificate(
+            CertificateChain::from_pem(cert.as_bytes()).unwrap(),
+            PrivateKey::from_der(&key).unwrap(),
         )
         .unwrap();
-    ServerConfig {
-        crypto,
-        ..Default::default()
-    }
+    config
 }
 
 pub fn client_config() -> ClientConfig {
     let cert = CERTIFICATE.serialize_der().unwrap();
-    let anchor = webpki::trust_anchor_util::cert_der_as_trust_anchor(&cert).unwrap();
-    let anchor_vec = vec![anchor];
-
-    let mut crypto = crypto::ClientConfig::new();
-    Arc::make_mut(&mut crypto)
-        .root_store
-        .add_server_trust_anchors(&webpki::TLSServerTrustAnchors(&anchor_vec));
-    Arc::make_mut(&mut crypto).key_log = Arc::new(KeyLogFile::new());
-    Arc::make_mut(&mut crypto).enable_early_data = true;
-    ClientConfig {
-        transport: Default::default(),
-        crypto,
-    }
+
+    let mut config = ClientConfig::default();
+    config
+        .add_certificate_authority(Certificate::from_der(&cert).unwrap())
+        .unwrap();
+    Arc::make_mut(&mut config.crypto).key_log = Arc::new(KeyLogFile::new());
+    Arc::make_mut(&mut config.crypto).enable_early_data = true;
+    config
 }
 
 pub fn min_opt<T: Ord>(x: Option<T>, y: Option<T>) -> Option<T> {
@@ -450,6 +576,6 @@ fn split_transmit(transmit: Transmit) -> Vec<Transmit> {
 lazy_static! {
     pub static ref SERVER_PORTS: Mutex<RangeFrom<u16>> = Mutex::new(4433..);
     pub static ref CLIENT_PORTS: Mutex<RangeFrom<u16>> = Mutex::new(44433..);
-    static ref CERTIFICATE: rcgen::Certificate =
+    pub static ref CERTIFICATE: rcgen::Certificate =
         rcgen::generate_simple_self_signed(vec!["localhost".into()]).unwrap();
 }
@@ -1,6 +1,7 @@
 use std::{
     fmt, io,
     net::{IpAddr, SocketAddr},
+    sync::Arc,
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
@@ -10,9 +11,32 @@ use crate::{
     coding::{BufExt, BufMutExt},
     crypto::{AeadKey, CryptoError, HandshakeTokenKey, HmacKey},
     shared::ConnectionId,
-    RESET_TOKEN_SIZE,
+    KeyRing, RESET_TOKEN_SIZE,
 };
 
+/// Kind of address validation token, stored as the first byte of every token
+///
+/// Keeps a token minted for one purpose from being accepted for the other. The second byte
+/// identifies the key in the server's `KeyRing` the token was sealed with.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TokenType {
+    /// Sent in a Retry packet, valid only for the connection attempt it was issued for
+    Retry = 0,
+    /// Sent in a NEW_TOKEN frame, valid for future connections from the same address
+    Validation = 1,
+}
+
+impl TokenType {
+    /// Determine the type of a token received from a client
+    pub fn of(token: &[u8]) -> Option<Self> {
+        match token.first() {
+            Some(0) => Some(TokenType::Retry),
+            Some(1) => Some(TokenType::Validation),
+            _ => None,
+        }
+    }
+}
+
 pub struct RetryToken<'a> {
     /// The destination connection ID set in the very first packet from the client
     pub orig_dst_cid: ConnectionId,
@@ -25,10 +49,11 @@ pub struct RetryToken<'a> {
 impl<'a> RetryToken<'a> {
     pub fn encode(
         &self,
-        key: &impl HandshakeTokenKey,
+        keys: &KeyRing<impl HandshakeTokenKey>,
         address: &SocketAddr,
         retry_src_cid: &ConnectionId,
     ) -> Vec<u8> {
+        let (key_id, key) = keys.current();
         let aead_key = key.aead_from_hkdf(self.random_bytes);
 
         let mut buf = Vec::new();
@@ -46,17 +71,23 @@ impl<'a> RetryToken<'a> {
         aead_key.seal(&mut buf, additional_data).unwrap();
 
         let mut token = Vec::new();
+        token.put_u8(TokenType::Retry as u8);
+        token.put_u8(key_id);
         token.put_slice(self.random_bytes);
         token.put_slice(&buf);
         token
     }
 
     pub fn from_bytes(
-        key: &impl HandshakeTokenKey,
+        keys: &KeyRing<impl HandshakeTokenKey>,
         address: &SocketAddr,
         retry_src_cid: &ConnectionId,
         raw_token_bytes: &'a [u8],
     ) -> Result<Self, CryptoError> {
+        if TokenType::of(raw_token_bytes) != Some(TokenType::Retry) {
+            return Err(CryptoError);
+        }
+        let (key, raw_token_bytes) = token_key(keys, raw_token_bytes)?;
         if raw_token_bytes.len() < Self::RANDOM_BYTES_LEN {
             // Invalid length
             return Err(CryptoError);
@@ -103,6 +134,98 @@ impl<'a> RetryToken<'a> {
     pub const RANDOM_BYTES_LEN: usize = 32;
 }
 
+/// Token sent in a NEW_TOKEN frame, allowing a client to skip address validation when it
+/// reconnects from the same IP address
+///
+/// Unlike a `RetryToken`, it isn't bound to a port or connection ID, since those are expected to
+/// differ on a later connection.
+pub struct ValidationToken<'a> {
+    /// The time at which this token was issued
+    pub issued: SystemTime,
+    /// Random bytes for deriving AEAD key
+    pub random_bytes: &'a [u8],
+}
+
+impl<'a> ValidationToken<'a> {
+    pub fn encode(&self, keys: &KeyRing<impl HandshakeTokenKey>, address: &IpAddr) -> Vec<u8> {
+        let (key_id, key) = keys.current();
+        let aead_key = key.aead_from_hkdf(self.random_bytes);
+
+        let mut buf = Vec::new();
+        buf.write::<u64>(
+            self.issued
+                .duration_since(UNIX_EPOCH)
+                .map(|x| x.as_secs())
+                .unwrap_or(0),
+        );
+
+        let mut additional_data = [0u8; Self::MAX_ADDITIONAL_DATA_SIZE];
+        let additional_data = Self::put_additional_data(address, &mut additional_data);
+        aead_key.seal(&mut buf, additional_data).unwrap();
+
+        let mut token = Vec::new();
+        token.put_u8(TokenType::Validation as u8);
+        token.put_u8(key_id);
+        token.put_slice(self.random_bytes);
+        token.put_slice(&buf);
+        token
+    }
+
+    pub fn from_bytes(
+        keys: &KeyRing<impl HandshakeTokenKey>,
+        address: &IpAddr,
+        raw_token_bytes: &'a [u8],
+    ) -> Result<Self, CryptoError> {
+        if TokenType::of(raw_token_bytes) != Some(TokenType::Validation) {
+            return Err(CryptoError);
+        }
+        let (key, raw_token_bytes) = token_key(keys, raw_token_bytes)?;
+        if raw_token_bytes.len() < Self::RANDOM_BYTES_LEN {
+            // Invalid length
+            return Err(CryptoError);
+        }
+
+        let random_bytes = &raw_token_bytes[..Self::RANDOM_BYTES_LEN];
+        let aead_key = key.aead_from_hkdf(random_bytes);
+        let mut sealed_token = raw_token_bytes[Self::RANDOM_BYTES_LEN..].to_vec();
+
+        let mut additional_data = [0u8; Self::MAX_ADDITIONAL_DATA_SIZE];
+        let additional_data = Self::put_additional_data(address, &mut additional_data);
+        let data = aead_key.open(&mut sealed_token, additional_data)?;
+
+        let mut reader = io::Cursor::new(data);
+        let issued = UNIX_EPOCH + Duration::new(reader.get::<u64>().map_err(|_| CryptoError)?, 0);
+
+        Ok(Self {
+            issued,
+            random_bytes,
+        })
+    }
+
+    fn put_additional_data<'b>(address: &IpAddr, additional_data: &'b mut [u8]) -> &'b [u8] {
+        let mut cursor = &mut *additional_data;
+        match address {
+            IpAddr::V4(x) => cursor.put_slice(&x.octets()),
+            IpAddr::V6(x) => cursor.put_slice(&x.octets()),
+        }
+
+        let size = Self::MAX_ADDITIONAL_DATA_SIZE - cursor.len();
+        &additional_data[..size]
+    }
+
+    const MAX_ADDITIONAL_DATA_SIZE: usize = 16; // max(ipv4, ipv6)
+    pub const RANDOM_BYTES_LEN: usize = 32;
+}
+
+/// Look up the key a token identifies, returning it along with the rest of the token following
+/// the type and key ID
+fn token_key<'a, K>(keys: &KeyRing<K>, token: &'a [u8]) -> Result<(Arc<K>, &'a [u8]), CryptoError> {
+    let key_id = *token.get(1).ok_or(CryptoError)?;
+    // Tokens sealed with keys that have since been removed are treated like any invalid token
+    let key = keys.get(key_id).ok_or(CryptoError)?;
+    Ok((key, &token[2..]))
+}
+
 /// Stateless reset token
 ///
 /// Used for an endpoint to securely communicate that it has lost state for a connection.
@@ -177,6 +300,7 @@ mod test {
         rng.fill_bytes(&mut master_key);
 
         let prk: ring::hkdf::Prk = crypto::HandshakeTokenKey::from_secret(&master_key);
+        let keys = KeyRing::new(0, prk);
 
         let addr = SocketAddr::new(Ipv6Addr::LOCALHOST.into(), 4433);
         let retry_src_cid = RandomConnectionIdGenerator::new(MAX_CID_SIZE).generate_cid();
@@ -185,14 +309,65 @@ mod test {
             issued: UNIX_EPOCH + Duration::new(42, 0), // Fractional seconds would be lost
             random_bytes: &random_bytes,
         };
-        let encoded = token.encode(&prk, &addr, &retry_src_cid);
+        let encoded = token.encode(&keys, &addr, &retry_src_cid);
 
-        let decoded = RetryToken::from_bytes(&prk, &addr, &retry_src_cid, &encoded)
+        let decoded = RetryToken::from_bytes(&keys, &addr, &retry_src_cid, &encoded)
             .expect("token didn't validate");
         assert_eq!(token.orig_dst_cid, decoded.orig_dst_cid);
         assert_eq!(token.issued, decoded.issued);
     }
 
+    #[cfg(feature = "ring")]
+    #[test]
+    fn validation_token_sanity() {
+        use super::*;
+        use crate::cid_generator::{ConnectionIdGenerator, RandomConnectionIdGenerator};
+        use crate::{crypto, MAX_CID_SIZE};
+
+        use rand::RngCore;
+        use std::net::Ipv6Addr;
+
+        let rng = &mut rand::thread_rng();
+
+        let mut master_key = [0; 64];
+        rng.fill_bytes(&mut master_key);
+
+        let mut random_bytes = [0; 32];
+        rng.fill_bytes(&mut random_bytes);
+
+        let prk: ring::hkdf::Prk = crypto::HandshakeTokenKey::from_secret(&master_key);
+        let keys = KeyRing::new(0, prk);
+
+        let addr = IpAddr::from(Ipv6Addr::LOCALHOST);
+        let token = ValidationToken {
+            issued: UNIX_EPOCH + Duration::new(42, 0),
+            random_bytes: &random_bytes,
+        };
+        let encoded = token.encode(&keys, &addr);
+        assert_eq!(TokenType::of(&encoded), Some(TokenType::Validation));
+
+        let decoded =
+            ValidationToken::from_bytes(&keys, &addr, &encoded).expect("token didn't validate");
+        assert_eq!(token.issued, decoded.issued);
+
+        // Bound to the client's address
+        let other_addr = IpAddr::from(Ipv6Addr::new(0, 0, 0, 0, 0, 0, 0, 2));
+        assert!(ValidationToken::from_bytes(&keys, &other_addr, &encoded).is_err());
+
+        // Not interchangeable with retry tokens
+        let retry_src_cid = RandomConnectionIdGenerator::new(MAX_CID_SIZE).generate_cid();
+        let socket_addr = SocketAddr::new(addr, 4433);
+        assert!(RetryToken::from_bytes(&keys, &socket_addr, &retry_src_cid, &encoded).is_err());
+        let retry = RetryToken {
+            orig_dst_cid: retry_src_cid,
+            issued: UNIX_EPOCH + Duration::new(42, 0),
+            random_bytes: &random_bytes,
+        }
+        .encode(&keys, &socket_addr, &retry_src_cid);
+        assert_eq!(TokenType::of(&retry), Some(TokenType::Retry));
+        assert!(ValidationToken::from_bytes(&keys, &addr, &retry).is_err());
+    }
+
     #[cfg(feature = "ring")]
     #[test]
     fn invalid_token_returns_err() {
@@ -211,11 +386,14 @@ mod test {
         rng.fill_bytes(&mut random_bytes);
 
         let prk: ring::hkdf::Prk = crypto::HandshakeTokenKey::from_secret(&master_key);
+        let keys = KeyRing::new(0, prk);
 
         let addr = SocketAddr::new(Ipv6Addr::LOCALHOST.into(), 4433);
         let retry_src_cid = RandomConnectionIdGenerator::new(MAX_CID_SIZE).generate_cid();
 
         let mut invalid_token = Vec::new();
+        invalid_token.put_u8(TokenType::Retry as u8);
+        invalid_token.put_u8(0);
         invalid_token.put_slice(&random_bytes);
 
         let mut random_data = [0; 32];
@@ -223,12 +401,44 @@ mod test {
         invalid_token.put_slice(&random_data);
 
         // Assert: garbage sealed data with valid random bytes returns err
-        assert!(RetryToken::from_bytes(&prk, &addr, &retry_src_cid, &invalid_token).is_err());
+        assert!(RetryToken::from_bytes(&keys, &addr, &retry_src_cid, &invalid_token).is_err());
 
         let invalid_token = [0; 31];
         rand::thread_rng().fill_bytes(&mut random_bytes);
 
         // Assert: completely invalid retry token returns error
-        assert!(RetryToken::from_bytes(&prk, &addr, &retry_src_cid, &invalid_token).is_err());
+        assert!(RetryToken::from_bytes(&keys, &addr, &retry_src_cid, &invalid_token).is_err());
+    }
+
+    #[cfg(feature = "ring")]
+    #[test]
+    fn key_rotation() {
+        use super::*;
+        use crate::crypto;
+        use std::net::Ipv6Addr;
+
+        let key = |x: u8| -> ring::hkdf::Prk { crypto::HandshakeTokenKey::from_secret(&[x; 64]) };
+        let random_bytes = [0; 32];
+        let addr = IpAddr::from(Ipv6Addr::LOCALHOST);
+        let token = ValidationToken {
+            issued: UNIX_EPOCH + Duration::new(42, 0),
+            random_bytes: &random_bytes,
+        };
+
+        let keys = KeyRing::new(1, key(1));
+        let old = token.encode(&keys, &addr);
+        keys.rotate(2, key(2));
+        let new = token.encode(&keys, &addr);
+        assert!(ValidationToken::from_bytes(&keys, &addr, &old).is_ok());
+        assert!(ValidationToken::from_bytes(&keys, &addr, &new).is_ok());
+
+        // Another server sharing only the new key accepts only the new token
+        let other = KeyRing::new(2, key(2));
+        assert!(ValidationToken::from_bytes(&other, &addr, &old).is_err());
+        assert!(ValidationToken::from_bytes(&other, &addr, &new).is_ok());
+
+        keys.remove(1);
+        assert!(ValidationToken::from_bytes(&keys, &addr, &old).is_err());
+        assert!(ValidationToken::from_bytes(&keys, &addr, &new).is_ok());
     }
 }
@@ -0,0 +1,88 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::Mutex,
+};
+
+use bytes::Bytes;
+
+/// Storage for the address validation tokens servers send in NEW_TOKEN frames
+///
+/// A client presents a stored token in the Initial packets of a later connection to the same
+/// server, allowing the server to skip the round-trip of a stateless retry and to send more data
+/// before the client's address is validated. Tokens should only be used once.
+pub trait TokenStore: Send + Sync {
+    /// Record a token received from the server named `server_name`
+    fn insert(&self, server_name: &str, token: Bytes);
+
+    /// Remove and return a token to use for a new connection to the server named `server_name`
+    fn take(&self, server_name: &str) -> Option<Bytes>;
+}
+
+/// A `TokenStore` keeping a bounded number of tokens in memory
+///
+/// Keeps the most recent tokens for each server, forgetting the least recently inserted server
+/// when too many are known.
+pub struct TokenMemoryCache {
+    max_server_names: usize,
+    max_tokens_per_server: usize,
+    state: Mutex<CacheState>,
+}
+
+#[derive(Default)]
+struct CacheState {
+    tokens: HashMap<String, VecDeque<Bytes>>,
+    /// Server names in the order they were added to `tokens`
+    order: VecDeque<String>,
+}
+
+impl TokenMemoryCache {
+    /// Construct a cache holding up to `max_tokens_per_server` tokens for each of up to
+    /// `max_server_names` servers
+    pub fn new(max_server_names: usize, max_tokens_per_server: usize) -> Self {
+        Self {
+            max_server_names,
+            max_tokens_per_server,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+}
+
+impl Default for TokenMemoryCache {
+    fn default() -> Self {
+        Self::new(256, 2)
+    }
+}
+
+impl TokenStore for TokenMemoryCache {
+    fn insert(&self, server_name: &str, token: Bytes) {
+        if self.max_server_names == 0 || self.max_tokens_per_server == 0 {
+            return;
+        }
+        let mut state = self.state.lock().unwrap();
+        let state = &mut *state;
+        if !state.tokens.contains_key(server_name) {
+            if state.order.len() >= self.max_server_names {
+                if let Some(oldest) = state.order.pop_front() {
+                    state.tokens.remove(&oldest);
+                }
+            }
+            state.order.push_back(server_name.into());
+        }
+        let tokens = state.tokens.entry(server_name.into()).or_default();
+        if tokens.len() >= self.max_tokens_per_server {
+            tokens.pop_front();
+        }
+        tokens.push_back(token);
+    }
+
+    fn take(&self, server_name: &str) -> Option<Bytes> {
+        let mut state = self.state.lock().unwrap();
+        let tokens = state.tokens.get_mut(server_name)?;
+        let token = tokens.pop_back();
+        if tokens.is_empty() {
+            state.tokens.remove(server_name);
+            state.order.retain(|x| x != server_name);
+        }
+        token
+    }
+}
@@ -67,17 +67,22 @@ macro_rules! apply_params {
 macro_rules! make_struct {
     {$($(#[$doc:meta])* $name:ident ($code:expr) = $default:expr,)*} => {
         /// Transport parameters used to negotiate connection-level preferences between peers
-        #[derive(Debug, Copy, Clone, Eq, PartialEq)]
+        #[derive(Debug, Clone, Eq, PartialEq)]
         pub struct TransportParameters {
             $($(#[$doc])* pub(crate) $name : VarInt,)*
 
             /// Does the endpoint support active connection migration
             pub(crate) disable_active_migration: bool,
+            /// Does the endpoint support the multipath extension
+            pub(crate) enable_multipath: bool,
             /// Maximum size for datagram frames
             pub(crate) max_datagram_frame_size: Option<VarInt>,
             /// The value that the endpoint included in the Source Connection ID field of the first
             /// Initial packet it sends for the connection
             pub(crate) initial_src_cid: Option<ConnectionId>,
+            /// The version in use and the versions the endpoint supports, for compatible version
+            /// negotiation
+            pub(crate) version_information: Option<VersionInformation>,
 
             // Server-only
             /// The value of the Destination Connection ID field from the first Initial packet sent
@@ -99,8 +104,10 @@ macro_rules! make_struct {
                     $($name: VarInt::from_u32($default),)*
 
                     disable_active_migration: false,
+                    enable_multipath: false,
                     max_datagram_frame_size: None,
                     initial_src_cid: None,
+                    version_information: None,
 
                     original_dst_cid: None,
                     retry_src_cid: None,
@@ -118,6 +125,7 @@ impl TransportParameters {
     pub(crate) fn new<S>(
         config: &TransportConfig,
         endpoint_config: &EndpointConfig<S>,
+        version: u32,
         cid_gen: &dyn ConnectionIdGenerator,
         initial_src_cid: ConnectionId,
         server_config: Option<&ServerConfig<S>>,
@@ -140,6 +148,11 @@ impl TransportParameters {
                     .expect("setter guarantees this is in-bounds")
             }),
             disable_active_migration: server_config.map_or(false, |c| !c.migration),
+            // Paths are told apart by connection ID, so they can't be used without one, and
+            // packets on each path need their own nonces
+            enable_multipath: config.enable_multipath
+                && cid_gen.cid_len() != 0
+                && <S::PacketKey as crypto::PacketKey>::MULTIPATH,
             active_connection_id_limit: if cid_gen.cid_len() == 0 {
                 2 // i.e. default, i.e. unsent
             } else {
@@ -149,6 +162,10 @@ impl TransportParameters {
             max_datagram_frame_size: config
                 .datagram_receive_buffer_size
                 .map(|x| (x.min(u16::max_value().into()) as u16).into()),
+            version_information: Some(VersionInformation {
+                chosen_version: version,
+                available_versions: endpoint_config.supported_versions.clone(),
+            }),
             ..Self::default()
         }
     }
@@ -184,6 +201,47 @@ impl TransportParameters {
     }
 }
 
+/// Contents of the `version_information` transport parameter (RFC 9368 §3)
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub(crate) struct VersionInformation {
+    /// The version of the packets that carry these transport parameters
+    pub chosen_version: u32,
+    /// Versions the endpoint supports, in order of preference
+    pub available_versions: Vec<u32>,
+}
+
+impl VersionInformation {
+    fn wire_size(&self) -> usize {
+        4 * (1 + self.available_versions.len())
+    }
+
+    fn write<W: BufMut>(&self, w: &mut W) {
+        w.write(self.chosen_version);
+        for &version in &self.available_versions {
+            w.write(version);
+        }
+    }
+
+    fn read<R: Buf>(r: &mut R) -> Result<Self, Error> {
+        let count = r.remaining() / 4;
+        if count == 0 || count * 4 != r.remaining() {
+            return Err(Error::Malformed);
+        }
+        let chosen_version = r.get::<u32>()?;
+        let mut available_versions = Vec::with_capacity(count - 1);
+        while r.has_remaining() {
+            available_versions.push(r.get::<u32>()?);
+        }
+        if chosen_version == 0 || available_versions.contains(&0) {
+            return Err(Error::IllegalValue);
+        }
+        Ok(Self {
+            chosen_version,
+            available_versions,
+        })
+    }
+}
+
 /// A server's preferred address
 ///
 /// This is communicated as a transport parameter during TLS session establishment.
@@ -306,6 +364,11 @@ impl TransportParameters {
             w.write_var(0);
         }
 
+        if self.enable_multipath {
+            w.write_var(ENABLE_MULTIPATH);
+            w.write_var(0);
+        }
+
         if let Some(x) = self.max_datagram_frame_size {
             w.write_var(0x20);
             w.write_var(x.size() as u64);
@@ -318,6 +381,12 @@ impl TransportParameters {
             x.write(w);
         }
 
+        if let Some(ref x) = self.version_information {
+            w.write_var(0x11);
+            w.write_var(x.wire_size() as u64);
+            x.write(w);
+        }
+
         for &(tag, cid) in &[
             (0x00, &self.original_dst_cid),
             (0x0f, &self.initial_src_cid),
@@ -383,6 +452,18 @@ impl TransportParameters {
                 }
                 0x0f => decode_cid(len, &mut params.initial_src_cid, r)?,
                 0x10 => decode_cid(len, &mut params.retry_src_cid, r)?,
+                0x11 => {
+                    if params.version_information.is_some() {
+                        return Err(Error::Malformed);
+                    }
+                    params.version_information = Some(VersionInformation::read(&mut r.take(len))?);
+                }
+                ENABLE_MULTIPATH => {
+                    if len != 0 || params.enable_multipath {
+                        return Err(Error::Malformed);
+                    }
+                    params.enable_multipath = true;
+                }
                 0x20 => {
                     if len > 8 || params.max_datagram_frame_size.is_some() {
                         return Err(Error::Malformed);
@@ -425,6 +506,9 @@ impl TransportParameters {
     }
 }
 
+/// Codepoint of the `enable_multipath` transport parameter from the multipath extension draft
+const ENABLE_MULTIPATH: u64 = 0x0f73_9bbc_1b66_6d05;
+
 fn decode_cid(len: usize, value: &mut Option<ConnectionId>, r: &mut impl Buf) -> Result<(), Error> {
     if len > MAX_CID_SIZE || value.is_some() || r.remaining() < len {
         return Err(Error::Malformed);
@@ -448,12 +532,17 @@ mod test {
             initial_max_streams_uni: 16u32.into(),
             ack_delay_exponent: 2u32.into(),
             max_udp_payload_size: 1200u32.into(),
+            enable_multipath: true,
             preferred_address: Some(PreferredAddress {
                 address_v4: Some(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 42)),
                 address_v6: None,
                 connection_id: ConnectionId::new(&[]),
                 stateless_reset_token: [0xab; RESET_TOKEN_SIZE].into(),
             }),
+            version_information: Some(VersionInformation {
+                chosen_version: 1,
+                available_versions: vec![1, crate::QUIC_VERSION_2],
+            }),
             ..TransportParameters::default()
         };
         params.write(&mut buf);
@@ -476,4 +565,31 @@ mod test {
         high_limit.validate_resumption_from(&low_limit).unwrap();
         low_limit.validate_resumption_from(&high_limit).unwrap_err();
     }
+
+    #[test]
+    fn version_information_validation() {
+        let mut buf = Vec::new();
+        TransportParameters {
+            version_information: Some(VersionInformation {
+                chosen_version: 0,
+                available_versions: vec![1],
+            }),
+            ..TransportParameters::default()
+        }
+        .write(&mut buf);
+        assert_eq!(
+            TransportParameters::read(Side::Server, &mut buf.as_slice()),
+            Err(Error::IllegalValue)
+        );
+
+        // Length must be a nonzero multiple of 4
+        let mut buf = Vec::new();
+        buf.write_var(0x11);
+        buf.write_var(3);
+        buf.put_slice(&[0, 0, 1]);
+        assert_eq!(
+            TransportParameters::read(Side::Server, &mut buf.as_slice()),
+            Err(Error::Malformed)
+        );
+    }
 }
@@ -0,0 +1,46 @@
+use std::net::SocketAddr;
+
+/// Decides whether a server accepts 0-RTT data on each resumed connection
+///
+/// Consulted before the handshake begins for every connection attempt that offers 0-RTT with a
+/// session ticket the server recognizes. Rejecting 0-RTT doesn't prevent the session from being
+/// resumed; the client is told its early data was discarded, and may send it again once the
+/// handshake completes.
+pub trait ZeroRttPolicy: Send + Sync {
+    /// Whether to accept the 0-RTT data of the connection attempt described by `request`
+    fn accept(&self, request: &ZeroRttRequest<'_>) -> bool;
+}
+
+/// A resuming client's attempt to send 0-RTT data, as seen by a [`ZeroRttPolicy`]
+#[derive(Debug)]
+pub struct ZeroRttRequest<'a> {
+    pub(crate) remote: SocketAddr,
+    pub(crate) resumption_data: &'a [u8],
+    pub(crate) server_name: Option<&'a str>,
+    pub(crate) alpn_protocols: Option<&'a [Vec<u8>]>,
+}
+
+impl<'a> ZeroRttRequest<'a> {
+    /// The peer's UDP address
+    pub fn remote(&self) -> SocketAddr {
+        self.remote
+    }
+
+    /// Application data associated with the session ticket being resumed
+    ///
+    /// Set by `Connection::set_resumption_data()` on the connection the ticket was issued by, and
+    /// empty if it wasn't called. The crypto layer guarantees its integrity.
+    pub fn resumption_data(&self) -> &'a [u8] {
+        self.resumption_data
+    }
+
+    /// The server name the client is connecting to now, from the TLS SNI extension
+    pub fn server_name(&self) -> Option<&'a str> {
+        self.server_name
+    }
+
+    /// The application protocols the client is now offering, in its preference order
+    pub fn alpn_protocols(&self) -> Option<&'a [Vec<u8>]> {
+        self.alpn_protocols
+    }
+}
@@ -118,6 +118,8 @@ impl Context {
                         .next()
                         .await
                         .expect("accept")
+                        .accept()
+                        .unwrap()
                         .await
                         .expect("connect");
 
@@ -7,6 +7,7 @@ use std::{
     io::{self, Write},
     net::ToSocketAddrs,
     path::PathBuf,
+    sync::Arc,
     time::{Duration, Instant},
 };
 
@@ -38,6 +39,10 @@ struct Opt {
     /// Simulate NAT rebinding after connecting
     #[structopt(long = "rebind")]
     rebind: bool,
+
+    /// File to keep TLS session tickets in, allowing later runs to resume the session
+    #[structopt(parse(from_os_str), long = "session-file")]
+    session_file: Option<PathBuf>,
 }
 
 fn main() {
@@ -91,6 +96,10 @@ async fn run(options: Opt) -> Result<()> {
         }
     }
 
+    if let Some(path) = options.session_file {
+        client_config.session_store(Arc::new(quinn::SessionFileStore::open(path)?));
+    }
+
     endpoint.default_client_config(client_config.build());
 
     let (endpoint, _) = endpoint.bind(&"[::]:0".parse().unwrap())?;
@@ -15,7 +15,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // accept a single connection
     tokio::spawn(async move {
         let incoming_conn = incoming.next().await.unwrap();
-        let new_conn = incoming_conn.await.unwrap();
+        let new_conn = incoming_conn.accept().unwrap().await.unwrap();
         println!(
             "[server] connection accepted: addr={}",
             new_conn.connection.remote_address()
@@ -143,12 +143,12 @@ async fn run(options: Opt) -> Result<()> {
     Ok(())
 }
 
-async fn handle_connection(root: Arc<Path>, conn: quinn::Connecting) -> Result<()> {
+async fn handle_connection(root: Arc<Path>, conn: quinn::IncomingConnection) -> Result<()> {
     let quinn::NewConnection {
         connection,
         mut bi_streams,
         ..
-    } = conn.await?;
+    } = conn.accept()?.await?;
     let span = info_span!(
         "connection",
         remote = %connection.remote_address(),
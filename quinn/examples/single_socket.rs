@@ -43,7 +43,14 @@ fn run_server(addr: SocketAddr) -> Result<Vec<u8>, Box<dyn Error>> {
     let (mut incoming, server_cert) = make_server_endpoint(addr)?;
     // accept a single connection
     tokio::spawn(async move {
-        let quinn::NewConnection { connection, .. } = incoming.next().await.unwrap().await.unwrap();
+        let quinn::NewConnection { connection, .. } = incoming
+            .next()
+            .await
+            .unwrap()
+            .accept()
+            .unwrap()
+            .await
+            .unwrap();
         println!(
             "[server] incoming connection: addr={}",
             connection.remote_address()
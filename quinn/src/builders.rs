@@ -3,17 +3,18 @@ use std::{io, net::SocketAddr, sync::Arc};
 use once_cell::sync::OnceCell;
 use proto::{
     generic::{ClientConfig, EndpointConfig, ServerConfig},
-    ConnectionIdGenerator,
+    quic_lb, ConnectionIdGenerator,
 };
 use thiserror::Error;
 use tracing::error;
 
 use crate::{
-    endpoint::{Endpoint, EndpointDriver, EndpointRef, Incoming},
-    platform::UdpSocket,
+    endpoint::{Endpoint, EndpointDriver, EndpointRef, Incoming, Shard},
+    platform::{self, AsyncUdpSocket, UdpSocket},
+    runtime::{default_runtime, Runtime},
 };
 #[cfg(feature = "rustls")]
-use crate::{Certificate, CertificateChain, PrivateKey};
+use crate::{Certificate, CertificateChain, PrivateKey, SessionStore};
 
 /// A helper for constructing an [`Endpoint`].
 ///
@@ -29,6 +30,10 @@ where
     server_config: Option<ServerConfig<S>>,
     config: EndpointConfig<S>,
     default_client_config: Option<ClientConfig<S>>,
+    /// Whether `connection_id_generator` was called
+    custom_cid_generator: bool,
+    steer_by_cid: bool,
+    runtime: Option<Arc<dyn Runtime>>,
 }
 
 #[allow(missing_docs)]
@@ -42,18 +47,24 @@ where
             server_config: None,
             config,
             default_client_config: Some(default_client_config),
+            custom_cid_generator: false,
+            steer_by_cid: false,
+            runtime: None,
         }
     }
 
     /// Build an endpoint bound to `addr`
     ///
-    /// Must be called from within a tokio runtime context. To avoid consuming the
-    /// `EndpointBuilder`, call `clone()` first.
+    /// Fails if no runtime was set with [`runtime()`] and none could be selected automatically; see
+    /// [`default_runtime()`]. To avoid consuming the `EndpointBuilder`, call `clone()` first.
     ///
     /// Platform defaults for dual-stack sockets vary. For example, any socket bound to a wildcard
     /// IPv6 address on Windows will not by default be able to communicate with IPv4
     /// addresses. Portable applications should bind an address that matches the family they wish to
     /// communicate within.
+    ///
+    /// [`runtime()`]: EndpointBuilder::runtime
+    /// [`default_runtime()`]: crate::default_runtime
     pub fn bind(self, addr: &SocketAddr) -> Result<(Endpoint<S>, Incoming<S>), EndpointError> {
         let socket = std::net::UdpSocket::bind(addr).map_err(EndpointError::Socket)?;
         self.with_socket(socket)
@@ -61,37 +72,127 @@ where
 
     /// Build an endpoint around a pre-configured socket
     ///
-    /// Must be called from within a tokio runtime context. To avoid consuming the
+    /// Fails if no runtime is available, like [`bind()`]. To avoid consuming the
     /// `EndpointBuilder`, call `clone()` first.
+    ///
+    /// [`bind()`]: EndpointBuilder::bind
     pub fn with_socket(
         self,
         socket: std::net::UdpSocket,
     ) -> Result<(Endpoint<S>, Incoming<S>), EndpointError> {
-        let addr = socket.local_addr().map_err(EndpointError::Socket)?;
-        let socket = UdpSocket::from_std(socket).map_err(EndpointError::Socket)?;
-        let rc = EndpointRef::new(
-            socket,
-            proto::generic::Endpoint::new(Arc::new(self.config), self.server_config.map(Arc::new)),
-            addr.is_ipv6(),
-        );
-        let driver = EndpointDriver(rc.clone());
-        tokio::spawn(async {
-            if let Err(e) = driver.await {
-                error!("I/O error: {}", e);
+        let runtime = self
+            .runtime
+            .clone()
+            .or_else(default_runtime)
+            .ok_or(EndpointError::NoRuntime)?;
+        let socket = UdpSocket::new(&*runtime, socket).map_err(EndpointError::Socket)?;
+        self.with_async_socket(socket)
+    }
+
+    /// Build an endpoint that communicates through `socket`, which need not be a UDP socket
+    ///
+    /// Fails if no runtime is available, like [`bind()`]. To avoid consuming the
+    /// `EndpointBuilder`, call `clone()` first.
+    ///
+    /// [`bind()`]: EndpointBuilder::bind
+    pub fn with_async_socket(
+        self,
+        socket: impl AsyncUdpSocket,
+    ) -> Result<(Endpoint<S>, Incoming<S>), EndpointError> {
+        let runtime = self
+            .runtime
+            .or_else(default_runtime)
+            .ok_or(EndpointError::NoRuntime)?;
+        let endpoint =
+            proto::generic::Endpoint::new(Arc::new(self.config), self.server_config.map(Arc::new));
+        spawn(
+            runtime,
+            Box::new(socket),
+            endpoint,
+            self.default_client_config,
+            None,
+        )
+    }
+
+    /// Build `shards` endpoints sharing `addr`, each with its own socket and driver
+    ///
+    /// Lets a busy server handle I/O on as many threads of a multi-threaded runtime, where a single
+    /// endpoint is limited to one. The sockets are bound with `SO_REUSEPORT`, so the kernel spreads
+    /// incoming datagrams between them by address. Each shard issues connection IDs identifying it,
+    /// in the [`quic_lb`] plaintext format with a one-byte server ID, and forwards datagrams for
+    /// other shards' connection IDs to them, so connections survive changes of address. The
+    /// connection ID generator of an `EndpointConfig` passed to [`new()`] is therefore replaced.
+    ///
+    /// Connections are accepted and initiated through each shard separately. The shards enforce
+    /// handshake rate limits and detect 0-RTT replays together, as if they were one endpoint.
+    ///
+    /// Fails if `shards` is 0 or more than 256, if a generator was set with
+    /// [`connection_id_generator()`], on platforms without `SO_REUSEPORT`, or if no runtime is
+    /// available, like [`bind()`].
+    ///
+    /// [`new()`]: EndpointBuilder::new
+    /// [`bind()`]: EndpointBuilder::bind
+    /// [`connection_id_generator()`]: EndpointBuilder::connection_id_generator
+    #[allow(clippy::type_complexity)]
+    pub fn bind_sharded(
+        self,
+        addr: &SocketAddr,
+        shards: usize,
+    ) -> Result<Vec<(Endpoint<S>, Incoming<S>)>, EndpointError> {
+        if shards == 0 || shards > 256 {
+            return Err(EndpointError::ShardCount);
+        }
+        if self.custom_cid_generator {
+            return Err(EndpointError::ShardedCidGenerator);
+        }
+        let runtime = self
+            .runtime
+            .or_else(default_runtime)
+            .ok_or(EndpointError::NoRuntime)?;
+        let cid_config = quic_lb::Config::plaintext(0, 1, SHARD_CID_LEN).unwrap();
+        let server_config = self.server_config.map(Arc::new);
+        let mut shard_endpoints = Vec::with_capacity(shards);
+        for index in 0..shards {
+            let generator =
+                quic_lb::RoutableConnectionIdGenerator::new(cid_config.clone(), &[index as u8])
+                    .unwrap();
+            let mut config = self.config.clone();
+            config.cid_generator(move || Box::new(generator.clone()));
+            let mut endpoint =
+                proto::generic::Endpoint::new(Arc::new(config), server_config.clone());
+            if let Some(first) = shard_endpoints.first() {
+                endpoint.share_server_state(first);
             }
-        });
-        Ok((
-            Endpoint {
-                inner: rc.clone(),
-                // If a default client config hasn't been specified explicitly, leave the OnceCell
-                // empty so `Endpoint` can initialize it iff needed.
-                default_client_config: self
-                    .default_client_config
-                    .map(OnceCell::from)
-                    .unwrap_or_default(),
-            },
-            Incoming::new(rc),
-        ))
+            shard_endpoints.push(endpoint);
+        }
+
+        let mut addr = *addr;
+        let mut endpoints = Vec::with_capacity(shards);
+        for ((index, shard), endpoint) in Shard::group(shards, cid_config)
+            .into_iter()
+            .enumerate()
+            .zip(shard_endpoints)
+        {
+            let socket = platform::bind_reuse_port(&addr).map_err(EndpointError::Socket)?;
+            if index == 0 {
+                // Later shards must bind the same port if it was left for the OS to choose
+                addr = socket.local_addr().map_err(EndpointError::Socket)?;
+                if self.steer_by_cid {
+                    platform::steer_by_cid(&socket, SHARD_CID_LEN as u8)
+                        .map_err(EndpointError::Socket)?;
+                }
+            }
+
+            let socket = UdpSocket::new(&*runtime, socket).map_err(EndpointError::Socket)?;
+            endpoints.push(spawn(
+                runtime.clone(),
+                Box::new(socket),
+                endpoint,
+                self.default_client_config.clone(),
+                Some(shard),
+            )?);
+        }
+        Ok(endpoints)
     }
 
     /// Accept incoming connections.
@@ -118,10 +219,68 @@ where
         factory: F,
     ) -> &mut Self {
         self.config.cid_generator(factory);
+        self.custom_cid_generator = true;
+        self
+    }
+
+    /// Whether [`bind_sharded()`] should ask the kernel to deliver datagrams to the shards their
+    /// connection IDs belong to, sparing the work of forwarding them
+    ///
+    /// Only supported on Linux, where a BPF program is attached to the sockets. It assumes the
+    /// shards' sockets are the only ones bound to the address. Defaults to `false`.
+    ///
+    /// [`bind_sharded()`]: EndpointBuilder::bind_sharded
+    pub fn steer_by_cid(&mut self, enabled: bool) -> &mut Self {
+        self.steer_by_cid = enabled;
+        self
+    }
+
+    /// Use `runtime` to drive the endpoint and its connections
+    ///
+    /// Defaults to the runtime selected by [`default_runtime()`] when the endpoint is built.
+    ///
+    /// [`default_runtime()`]: crate::default_runtime
+    pub fn runtime(&mut self, runtime: Arc<dyn Runtime>) -> &mut Self {
+        self.runtime = Some(runtime);
         self
     }
 }
 
+/// Start driving an endpoint on `socket`, returning handles to it
+fn spawn<S>(
+    runtime: Arc<dyn Runtime>,
+    socket: Box<dyn AsyncUdpSocket>,
+    endpoint: proto::generic::Endpoint<S>,
+    default_client_config: Option<ClientConfig<S>>,
+    shard: Option<Shard>,
+) -> Result<(Endpoint<S>, Incoming<S>), EndpointError>
+where
+    S: proto::crypto::Session + Send + 'static,
+{
+    let addr = socket.local_addr().map_err(EndpointError::Socket)?;
+    let rc = EndpointRef::new(socket, endpoint, addr.is_ipv6(), shard, runtime.clone());
+    let driver = EndpointDriver(rc.clone());
+    runtime.spawn(Box::pin(async {
+        if let Err(e) = driver.await {
+            error!("I/O error: {}", e);
+        }
+    }));
+    Ok((
+        Endpoint {
+            inner: rc.clone(),
+            // If a default client config hasn't been specified explicitly, leave the OnceCell
+            // empty so `Endpoint` can initialize it iff needed.
+            default_client_config: default_client_config
+                .map(OnceCell::from)
+                .unwrap_or_default(),
+        },
+        Incoming::new(rc),
+    ))
+}
+
+/// Length of the connection IDs issued by sharded endpoints
+const SHARD_CID_LEN: usize = 8;
+
 impl<S> Default for EndpointBuilder<S>
 where
     S: proto::crypto::Session,
@@ -131,6 +290,9 @@ where
             server_config: None,
             config: EndpointConfig::default(),
             default_client_config: None,
+            custom_cid_generator: false,
+            steer_by_cid: false,
+            runtime: None,
         }
     }
 }
@@ -141,6 +303,16 @@ pub enum EndpointError {
     /// An error during setup of the underlying UDP socket.
     #[error("failed to set up UDP socket: {0}")]
     Socket(io::Error),
+    /// No runtime was configured, and none could be selected automatically.
+    #[error("no async runtime found")]
+    NoRuntime,
+    /// [`EndpointBuilder::bind_sharded()`] was asked for 0 or more than 256 shards.
+    #[error("shard count must be between 1 and 256")]
+    ShardCount,
+    /// [`EndpointBuilder::bind_sharded()`] was called after
+    /// [`EndpointBuilder::connection_id_generator()`], whose generator it would have to replace.
+    #[error("sharded endpoints can't use a custom connection ID generator")]
+    ShardedCidGenerator,
 }
 
 /// Helper for constructing a [`ServerConfig`] to be passed to [`EndpointBuilder::listen()`] to
@@ -191,7 +363,7 @@ impl ServerConfigBuilder<proto::crypto::rustls::TlsSession> {
         &mut self,
         cert_chain: CertificateChain,
         key: PrivateKey,
-    ) -> Result<&mut Self, rustls::TLSError> {
+    ) -> Result<&mut Self, rustls::Error> {
         self.config.certificate(cert_chain, key)?;
         Ok(self)
     }
@@ -234,9 +406,8 @@ where
 
 /// Helper for creating new outgoing connections.
 ///
-/// If the `native-certs` and `ct-logs` features are enabled, [`ClientConfigBuilder::default()`] will
-/// construct a configuration that trusts the host OS certificate store and uses built-in
-/// certificate transparency logs respectively. These features are both enabled by default.
+/// If the `native-certs` feature is enabled, [`ClientConfigBuilder::default()`] will construct a
+/// configuration that trusts the host OS certificate store. This feature is enabled by default.
 ///
 /// [`ClientConfigBuilder::default()`]: #method.default
 pub struct ClientConfigBuilder<S>
@@ -253,8 +424,7 @@ where
     /// Construct a builder using `config` as the initial state.
     ///
     /// If you want to trust the usual certificate authorities trusted by the system, use
-    /// [`ClientConfigBuilder::default()`] with the `native-certs` and `ct-logs` features enabled
-    /// instead.
+    /// [`ClientConfigBuilder::default()`] with the `native-certs` feature enabled instead.
     ///
     /// The `ClientConfigBuilder` provides a number of shortcuts to customize the TLS client
     /// behavior. However, if you want to take full control over the client's behavior (such as
@@ -269,6 +439,14 @@ where
         Self { config }
     }
 
+    /// Use QUIC `version` rather than the endpoint's initial version
+    ///
+    /// Must be one of the versions supported by the endpoint.
+    pub fn version(&mut self, version: u32) -> &mut Self {
+        self.config.version(version);
+        self
+    }
+
     /// Consume the builder and return the [`ClientConfig`], which can then be used to configure
     /// outgoing connections from an [`Endpoint`].
     ///
@@ -322,6 +500,15 @@ impl ClientConfigBuilder<proto::crypto::rustls::TlsSession> {
         Arc::make_mut(&mut self.config.crypto).enable_early_data = true;
         self
     }
+
+    /// Keep TLS session tickets in `store`, e.g. a [`SessionFileStore`] to resume sessions and
+    /// send 0-RTT data across restarts.
+    ///
+    /// [`SessionFileStore`]: crate::SessionFileStore
+    pub fn session_store(&mut self, store: Arc<dyn SessionStore>) -> &mut Self {
+        self.config.session_store(store);
+        self
+    }
 }
 
 impl<S> Clone for ClientConfigBuilder<S>
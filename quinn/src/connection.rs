@@ -13,16 +13,19 @@ use bytes::Bytes;
 use futures_channel::{mpsc, oneshot};
 use futures_util::{FutureExt, StreamExt};
 use fxhash::FxHashMap;
-use proto::{ConnectionError, ConnectionHandle, ConnectionStats, Dir, StreamEvent, StreamId};
+use proto::{
+    CongestionSnapshot, ConnectionError, ConnectionHandle, ConnectionStats, Dir, PathId, PathInfo,
+    PathStatus, StreamEvent, StreamId,
+};
 use thiserror::Error;
-use tokio::time::{sleep_until, Instant as TokioInstant, Sleep};
 use tracing::info_span;
 
 use crate::{
     broadcast::{self, Broadcast},
     mutex::Mutex,
-    platform::caps,
+    platform::UdpCapabilities,
     recv_stream::RecvStream,
+    runtime::{AsyncTimer, Runtime},
     send_stream::{SendStream, WriteError},
     ConnectionEvent, EndpointEvent, VarInt,
 };
@@ -48,6 +51,8 @@ where
         conn: proto::generic::Connection<S>,
         endpoint_events: mpsc::UnboundedSender<(ConnectionHandle, EndpointEvent)>,
         conn_events: mpsc::UnboundedReceiver<ConnectionEvent>,
+        udp_caps: UdpCapabilities,
+        runtime: Arc<dyn Runtime>,
     ) -> Connecting<S> {
         let (on_handshake_data_send, on_handshake_data_recv) = oneshot::channel();
         let (on_connected_send, on_connected_recv) = oneshot::channel();
@@ -58,9 +63,11 @@ where
             conn_events,
             on_handshake_data_send,
             on_connected_send,
+            udp_caps,
+            runtime.clone(),
         );
 
-        tokio::spawn(ConnectionDriver(conn.clone()));
+        runtime.spawn(Box::pin(ConnectionDriver(conn.clone())));
 
         Connecting {
             conn: Some(conn),
@@ -187,6 +194,33 @@ where
         let conn_ref: &ConnectionRef<S> = self.conn.as_ref().expect("used after yielding Ready");
         conn_ref.lock("remote_address").inner.remote_address()
     }
+
+    /// Whether 0-RTT data received on this connection was checked not to be a replay
+    ///
+    /// See [`Connection::is_0rtt_replay_protected()`]. Will panic if called after `poll` has
+    /// returned `Ready`.
+    pub fn is_0rtt_replay_protected(&self) -> bool {
+        let conn_ref: &ConnectionRef<S> = self.conn.as_ref().expect("used after yielding Ready");
+        conn_ref
+            .lock("is_0rtt_replay_protected")
+            .inner
+            .is_0rtt_replay_protected()
+    }
+
+    /// Set application data to associate with the session tickets issued to the client
+    ///
+    /// When the client later resumes the session and attempts 0-RTT, the data is passed to the
+    /// server's [`ZeroRttPolicy`](crate::ZeroRttPolicy). It's kept on the server, out of the
+    /// client's reach.
+    ///
+    /// Will panic if called after `poll` has returned `Ready`.
+    pub fn set_resumption_data(&self, data: &[u8]) {
+        let conn_ref: &ConnectionRef<S> = self.conn.as_ref().expect("used after yielding Ready");
+        conn_ref
+            .lock("set_resumption_data")
+            .inner
+            .set_resumption_data(data);
+    }
 }
 
 /// Future that completes when a connection is fully established
@@ -203,6 +237,40 @@ impl Future for ZeroRttAccepted {
     }
 }
 
+/// Future that completes when a migration started by [`Connection::migrate`] finishes
+///
+/// [`Connection::migrate`]: crate::generic::Connection::migrate
+#[must_use = "futures/streams/sinks do nothing unless you `.await` or poll them"]
+pub struct Migrating(oneshot::Receiver<Result<(), MigrationError>>);
+
+impl Future for Migrating {
+    type Output = Result<(), MigrationError>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        self.0.poll_unpin(cx).map(|x| {
+            x.unwrap_or(Err(MigrationError::ConnectionClosed(
+                ConnectionError::LocallyClosed,
+            )))
+        })
+    }
+}
+
+/// Future that completes when a path opened by [`Connection::open_path`] is validated
+///
+/// [`Connection::open_path`]: crate::generic::Connection::open_path
+#[must_use = "futures/streams/sinks do nothing unless you `.await` or poll them"]
+pub struct OpeningPath(oneshot::Receiver<Result<PathId, PathError>>);
+
+impl Future for OpeningPath {
+    type Output = Result<PathId, PathError>;
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        self.0.poll_unpin(cx).map(|x| {
+            x.unwrap_or(Err(PathError::ConnectionClosed(
+                ConnectionError::LocallyClosed,
+            )))
+        })
+    }
+}
+
 /// Components of a newly established connection
 ///
 /// All fields of this struct, in addition to any other handles constructed later, must be dropped
@@ -428,6 +496,18 @@ where
         self.0.lock("remote_address").inner.remote_address()
     }
 
+    /// Whether 0-RTT data received on this connection was checked not to be a replay
+    ///
+    /// Only ever `true` for servers configured with `ServerConfig::anti_replay`, when the client's
+    /// session ticket hadn't been used for 0-RTT before. Otherwise, 0-RTT data may have been
+    /// replayed by an attacker.
+    pub fn is_0rtt_replay_protected(&self) -> bool {
+        self.0
+            .lock("is_0rtt_replay_protected")
+            .inner
+            .is_0rtt_replay_protected()
+    }
+
     /// The local IP address which was used when the peer established
     /// the connection
     ///
@@ -446,16 +526,143 @@ where
         self.0.lock("local_ip").inner.local_ip()
     }
 
+    /// Move the connection to a new local IP address
+    ///
+    /// Only supported by clients. The new address is validated with a PATH_CHALLENGE while the
+    /// connection continues to use its current path, which is kept if validation fails. The
+    /// endpoint's socket must be able to send from `local_ip`, e.g. because it is bound to a
+    /// wildcard address on a host with multiple network interfaces, and the platform must let
+    /// quinn choose the source address of outgoing datagrams, which is currently only the case on
+    /// Linux.
+    ///
+    /// The returned future resolves once the connection is using the new address, or the
+    /// migration has failed.
+    pub fn migrate(&self, local_ip: IpAddr) -> Migrating {
+        let (send, recv) = oneshot::channel();
+        let conn = &mut *self.0.lock("migrate");
+        if let Some(ref x) = conn.error {
+            let _ = send.send(Err(MigrationError::ConnectionClosed(x.clone())));
+            return Migrating(recv);
+        }
+        if !conn.udp_caps.src_ip {
+            let _ = send.send(Err(MigrationError::Unsupported));
+            return Migrating(recv);
+        }
+        match conn.inner.initiate_migration(conn.runtime.now(), local_ip) {
+            Ok(()) => {
+                conn.on_migrated = Some(send);
+                conn.wake();
+            }
+            Err(e) => {
+                let _ = send.send(Err(e.into()));
+            }
+        }
+        Migrating(recv)
+    }
+
+    /// Open an additional path to `remote`, sending from `local_ip` if specified
+    ///
+    /// Requires both peers to enable the multipath extension with
+    /// [`TransportConfig::enable_multipath()`], and is only supported by clients. The endpoint's
+    /// socket must be able to send from `local_ip`, e.g. because it is bound to a wildcard address
+    /// on a host with multiple network interfaces.
+    ///
+    /// The returned future resolves once the path has been validated and can carry application
+    /// data, or opening it has failed.
+    ///
+    /// [`TransportConfig::enable_multipath()`]: crate::TransportConfig::enable_multipath
+    pub fn open_path(&self, remote: SocketAddr, local_ip: Option<IpAddr>) -> OpeningPath {
+        let (send, recv) = oneshot::channel();
+        let conn = &mut *self.0.lock("open_path");
+        if let Some(ref x) = conn.error {
+            let _ = send.send(Err(PathError::ConnectionClosed(x.clone())));
+            return OpeningPath(recv);
+        }
+        match conn.inner.open_path(conn.runtime.now(), remote, local_ip) {
+            Ok(id) => {
+                conn.on_path_opened.insert(id, send);
+                conn.wake();
+            }
+            Err(e) => {
+                let _ = send.send(Err(e.into()));
+            }
+        }
+        OpeningPath(recv)
+    }
+
+    /// Stop using path `id`, telling the peer why with `error_code`
+    ///
+    /// Data in flight on the path is retransmitted on the remaining paths. The last open path
+    /// can't be closed; close the connection instead.
+    pub fn close_path(&self, id: PathId, error_code: VarInt) -> Result<(), PathError> {
+        let conn = &mut *self.0.lock("close_path");
+        if let Some(ref x) = conn.error {
+            return Err(PathError::ConnectionClosed(x.clone()));
+        }
+        conn.inner.close_path(id, error_code)?;
+        conn.wake();
+        Ok(())
+    }
+
+    /// Ask the peer to only send application data on path `id` if no other path is available, or
+    /// to use it freely again
+    pub fn set_path_status(&self, id: PathId, status: PathStatus) -> Result<(), PathError> {
+        let conn = &mut *self.0.lock("set_path_status");
+        if let Some(ref x) = conn.error {
+            return Err(PathError::ConnectionClosed(x.clone()));
+        }
+        conn.inner.set_path_status(id, status)?;
+        conn.wake();
+        Ok(())
+    }
+
+    /// The paths of the connection that haven't been closed
+    ///
+    /// Only ever contains a single path unless the multipath extension was negotiated.
+    pub fn paths(&self) -> Vec<PathInfo> {
+        self.0.lock("paths").inner.paths()
+    }
+
     /// Current best estimate of this connection's latency (round-trip-time)
     pub fn rtt(&self) -> Duration {
         self.0.lock("rtt").inner.rtt()
     }
 
+    /// Congestion control state of the current path, to speed up later connections to the peer
+    ///
+    /// Typically taken once the connection is closed, and passed to
+    /// [`resume_congestion()`](Self::resume_congestion) on a later connection to the same peer
+    /// address. `None` if no RTT has been measured yet, or while a resumption is in progress.
+    pub fn congestion_snapshot(&self) -> Option<CongestionSnapshot> {
+        self.0
+            .lock("congestion_snapshot")
+            .inner
+            .congestion_snapshot()
+    }
+
+    /// Carefully resume the congestion control state saved from an earlier connection to the peer
+    ///
+    /// Rather than slow starting from the initial window, the congestion window jumps to half of
+    /// `snapshot`'s once it fills, provided the path's RTT is consistent with the saved one. The
+    /// window is reduced again if the jump causes congestion. Returns `false` if `snapshot` was
+    /// taken for another peer address or is older than [`CongestionSnapshot::MAX_AGE`].
+    pub fn resume_congestion(&self, snapshot: CongestionSnapshot) -> bool {
+        let conn = &mut *self.0.lock("resume_congestion");
+        let resumed = conn.inner.resume_congestion(snapshot);
+        conn.wake();
+        resumed
+    }
+
     /// Returns connection statistics
     pub fn stats(&self) -> ConnectionStats {
         self.0.lock("stats").inner.stats()
     }
 
+    /// The QUIC version negotiated for this connection
+    pub fn version(&self) -> u32 {
+        self.0.lock("version").inner.version()
+    }
+
     /// Parameters negotiated during the handshake
     ///
     /// Guaranteed to return `Some` on fully established connections or after
@@ -692,6 +899,7 @@ impl<S> ConnectionRef<S>
 where
     S: proto::crypto::Session,
 {
+    #[allow(clippy::too_many_arguments)]
     fn new(
         handle: ConnectionHandle,
         conn: proto::generic::Connection<S>,
@@ -699,6 +907,8 @@ where
         conn_events: mpsc::UnboundedReceiver<ConnectionEvent>,
         on_handshake_data: oneshot::Sender<()>,
         on_connected: oneshot::Sender<bool>,
+        udp_caps: UdpCapabilities,
+        runtime: Arc<dyn Runtime>,
     ) -> Self {
         Self(Arc::new(Mutex::new(ConnectionInner {
             inner: conn,
@@ -720,8 +930,12 @@ where
             datagram_reader: None,
             finishing: FxHashMap::default(),
             stopped: FxHashMap::default(),
+            on_migrated: None,
+            on_path_opened: FxHashMap::default(),
             error: None,
             ref_count: 0,
+            udp_caps,
+            runtime,
         })))
     }
 
@@ -779,8 +993,8 @@ where
     on_handshake_data: Option<oneshot::Sender<()>>,
     on_connected: Option<oneshot::Sender<bool>>,
     connected: bool,
-    timer: Option<Pin<Box<Sleep>>>,
-    timer_deadline: Option<TokioInstant>,
+    timer: Option<Pin<Box<dyn AsyncTimer>>>,
+    timer_deadline: Option<Instant>,
     conn_events: mpsc::UnboundedReceiver<ConnectionEvent>,
     endpoint_events: mpsc::UnboundedSender<(ConnectionHandle, EndpointEvent)>,
     pub(crate) blocked_writers: FxHashMap<StreamId, Waker>,
@@ -792,10 +1006,15 @@ where
     datagram_reader: Option<Waker>,
     pub(crate) finishing: FxHashMap<StreamId, oneshot::Sender<Option<WriteError>>>,
     pub(crate) stopped: FxHashMap<StreamId, Waker>,
+    on_migrated: Option<oneshot::Sender<Result<(), MigrationError>>>,
+    on_path_opened: FxHashMap<PathId, oneshot::Sender<Result<PathId, PathError>>>,
     /// Always set to Some before the connection becomes drained
     pub(crate) error: Option<ConnectionError>,
     /// Number of live handles that can be used to initiate or handle I/O; excludes the driver
     ref_count: usize,
+    /// Capabilities of the endpoint's socket at the time the connection was created
+    udp_caps: UdpCapabilities,
+    runtime: Arc<dyn Runtime>,
 }
 
 impl<S> ConnectionInner<S>
@@ -803,10 +1022,10 @@ where
     S: proto::crypto::Session,
 {
     fn drive_transmit(&mut self) -> bool {
-        let now = Instant::now();
+        let now = self.runtime.now();
         let mut transmits = 0;
 
-        let max_datagrams = caps().max_gso_segments;
+        let max_datagrams = self.udp_caps.max_gso_segments;
 
         while let Some(t) = self.inner.poll_transmit(now, max_datagrams) {
             transmits += match t.segment_size {
@@ -902,6 +1121,26 @@ where
                         x.wake();
                     }
                 }
+                Migrated => {
+                    if let Some(x) = self.on_migrated.take() {
+                        let _ = x.send(Ok(()));
+                    }
+                }
+                MigrationFailed { reason } => {
+                    if let Some(x) = self.on_migrated.take() {
+                        let _ = x.send(Err(reason.into()));
+                    }
+                }
+                PathOpened { id } => {
+                    if let Some(x) = self.on_path_opened.remove(&id) {
+                        let _ = x.send(Ok(id));
+                    }
+                }
+                PathClosed { id, reason } => {
+                    if let Some(x) = self.on_path_opened.remove(&id) {
+                        let _ = x.send(Err(reason.into()));
+                    }
+                }
                 Stream(StreamEvent::Readable { id }) => {
                     if let Some(reader) = self.blocked_readers.remove(&id) {
                         reader.wake();
@@ -939,10 +1178,10 @@ where
         // Check whether we need to (re)set the timer. If so, we must poll again to ensure the
         // timer is registered with the runtime (and check whether it's already
         // expired).
-        match self.inner.poll_timeout().map(TokioInstant::from_std) {
+        match self.inner.poll_timeout() {
             Some(deadline) => {
                 if let Some(delay) = &mut self.timer {
-                    // There is no need to reset the tokio timer if the deadline
+                    // There is no need to reset the timer if the deadline
                     // did not change
                     if self
                         .timer_deadline
@@ -952,7 +1191,7 @@ where
                         delay.as_mut().reset(deadline);
                     }
                 } else {
-                    self.timer = Some(Box::pin(sleep_until(deadline)));
+                    self.timer = Some(self.runtime.new_timer(deadline));
                 }
                 // Store the actual expiration time of the timer
                 self.timer_deadline = Some(deadline);
@@ -980,7 +1219,7 @@ where
 
         // A timer expired, so the caller needs to check for
         // new transmits, which might cause new timers to be set.
-        self.inner.handle_timeout(Instant::now());
+        self.inner.handle_timeout(self.runtime.now());
         self.timer_deadline = None;
         true
     }
@@ -1021,13 +1260,19 @@ where
         if let Some(x) = self.on_connected.take() {
             let _ = x.send(false);
         }
+        if let Some(x) = self.on_migrated.take() {
+            let _ = x.send(Err(MigrationError::ConnectionClosed(reason.clone())));
+        }
+        for (_, x) in self.on_path_opened.drain() {
+            let _ = x.send(Err(PathError::ConnectionClosed(reason.clone())));
+        }
         for (_, waker) in self.stopped.drain() {
             waker.wake();
         }
     }
 
     fn close(&mut self, error_code: VarInt, reason: Bytes) {
-        self.inner.close(Instant::now(), error_code, reason);
+        self.inner.close(self.runtime.now(), error_code, reason);
         self.terminate(ConnectionError::LocallyClosed);
         self.wake();
     }
@@ -1095,6 +1340,105 @@ pub enum SendDatagramError {
     ConnectionClosed(#[source] ConnectionError),
 }
 
+/// Errors that can arise when migrating a connection
+#[derive(Debug, Error, Clone, Eq, PartialEq)]
+pub enum MigrationError {
+    /// Only clients can initiate migration
+    #[error("only clients can initiate migration")]
+    NotClient,
+    /// The handshake has not been confirmed yet
+    #[error("handshake not confirmed")]
+    HandshakeNotConfirmed,
+    /// Multipath connections change networks by opening and closing paths instead
+    #[error("connection uses multipath")]
+    Multipath,
+    /// The peer's `disable_active_migration` transport parameter forbids migration
+    #[error("migration disabled by peer")]
+    DisabledByPeer,
+    /// Another migration or path validation is in progress
+    #[error("migration already in progress")]
+    InProgress,
+    /// The peer hasn't supplied a spare connection ID for use on a new path
+    #[error("no connection IDs available")]
+    NoConnectionIds,
+    /// The peer didn't respond on the new path in time
+    #[error("path validation failed")]
+    ValidationFailed,
+    /// The socket can't choose the local address datagrams are sent from on this platform
+    #[error("sending from a specific local address is unsupported")]
+    Unsupported,
+    /// The connection was closed
+    #[error("connection closed: {0}")]
+    ConnectionClosed(#[source] ConnectionError),
+}
+
+impl From<proto::MigrationError> for MigrationError {
+    fn from(x: proto::MigrationError) -> Self {
+        use proto::MigrationError::*;
+        match x {
+            NotClient => MigrationError::NotClient,
+            HandshakeNotConfirmed => MigrationError::HandshakeNotConfirmed,
+            Multipath => MigrationError::Multipath,
+            DisabledByPeer => MigrationError::DisabledByPeer,
+            InProgress => MigrationError::InProgress,
+            NoConnectionIds => MigrationError::NoConnectionIds,
+            ValidationFailed => MigrationError::ValidationFailed,
+        }
+    }
+}
+
+/// Reasons why a path of a multipath connection could not be used, or was closed
+#[derive(Debug, Error, Clone, Eq, PartialEq)]
+pub enum PathError {
+    /// The multipath extension is disabled locally or unsupported by the peer
+    #[error("multipath not negotiated")]
+    MultipathNotNegotiated,
+    /// Only clients can open paths
+    #[error("only clients can open paths")]
+    NotClient,
+    /// The handshake has not been confirmed yet
+    #[error("handshake not confirmed")]
+    HandshakeNotConfirmed,
+    /// No connection ID is left to identify a new path with
+    #[error("no connection IDs available")]
+    NoConnectionIds,
+    /// The path doesn't exist or has already been closed
+    #[error("unknown path")]
+    UnknownPath,
+    /// The last open path can't be closed
+    #[error("last open path")]
+    LastPath,
+    /// The peer didn't respond on the new path in time
+    #[error("path validation failed")]
+    ValidationFailed,
+    /// The peer closed the path
+    #[error("abandoned by peer: {0}")]
+    AbandonedByPeer(VarInt),
+    /// The local application closed the path
+    #[error("closed")]
+    LocallyClosed,
+    /// The connection was closed
+    #[error("connection closed: {0}")]
+    ConnectionClosed(#[source] ConnectionError),
+}
+
+impl From<proto::PathError> for PathError {
+    fn from(x: proto::PathError) -> Self {
+        use proto::PathError::*;
+        match x {
+            MultipathNotNegotiated => PathError::MultipathNotNegotiated,
+            NotClient => PathError::NotClient,
+            HandshakeNotConfirmed => PathError::HandshakeNotConfirmed,
+            NoConnectionIds => PathError::NoConnectionIds,
+            UnknownPath => PathError::UnknownPath,
+            LastPath => PathError::LastPath,
+            ValidationFailed => PathError::ValidationFailed,
+            AbandonedByPeer(code) => PathError::AbandonedByPeer(code),
+            LocallyClosed => PathError::LocallyClosed,
+        }
+    }
+}
+
 /// The maximum amount of datagrams which will be produced in a single `drive_transmit` call
 ///
 /// This limits the amount of CPU resources consumed by datagram generation,
@@ -1,10 +1,11 @@
 use std::{
     collections::VecDeque,
+    fmt,
     future::Future,
     io,
     io::IoSliceMut,
     mem::MaybeUninit,
-    net::{SocketAddr, SocketAddrV6},
+    net::{IpAddr, SocketAddr, SocketAddrV6},
     pin::Pin,
     str,
     sync::{Arc, Mutex},
@@ -12,18 +13,23 @@ use std::{
     time::Instant,
 };
 
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
 use futures_channel::mpsc;
 use futures_util::StreamExt;
 use fxhash::FxHashMap;
 use once_cell::sync::OnceCell;
-use proto::{self as proto, generic::ClientConfig, ConnectError, ConnectionHandle, DatagramEvent};
+use proto::{
+    self as proto, generic::ClientConfig, quic_lb, ConnectError, ConnectionError, ConnectionHandle,
+    DatagramEvent, EndpointStats, TransportError, TransportErrorCode,
+};
+use thiserror::Error;
 
 use crate::{
     broadcast::{self, Broadcast},
     builders::EndpointBuilder,
     connection::Connecting,
-    platform::{RecvMeta, UdpSocket, BATCH_SIZE},
+    platform::{AsyncUdpSocket, RecvMeta, UdpCapabilities, UdpSocket, BATCH_SIZE},
+    runtime::Runtime,
     work_limiter::WorkLimiter,
     ConnectionEvent, EndpointEvent, VarInt, IO_LOOP_BOUND, RECV_TIME_BOUND,
 };
@@ -97,8 +103,11 @@ where
         } else {
             *addr
         };
-        let (ch, conn) = endpoint.inner.connect(config, addr, server_name)?;
-        Ok(endpoint.connections.insert(ch, conn))
+        let now = endpoint.runtime.now();
+        let (ch, conn) = endpoint.inner.connect_at(now, config, addr, server_name)?;
+        let udp_caps = endpoint.socket.caps();
+        let runtime = endpoint.runtime.clone();
+        Ok(endpoint.connections.insert(ch, conn, udp_caps, runtime))
     }
 
     /// Switch to a new UDP socket
@@ -108,12 +117,16 @@ where
     ///
     /// On error, the old UDP socket is retained.
     pub fn rebind(&self, socket: std::net::UdpSocket) -> io::Result<()> {
-        let addr = socket.local_addr()?;
-        let socket = UdpSocket::from_std(socket)?;
         let mut inner = self.inner.lock().unwrap();
-        inner.socket = socket;
-        inner.ipv6 = addr.is_ipv6();
-        Ok(())
+        let socket = UdpSocket::new(&*inner.runtime, socket)?;
+        inner.rebind(Box::new(socket))
+    }
+
+    /// Switch to a new socket of any kind
+    ///
+    /// Like [`rebind()`](Self::rebind), but for sockets that aren't platform UDP sockets.
+    pub fn rebind_async_socket(&self, socket: impl AsyncUdpSocket) -> io::Result<()> {
+        self.inner.lock().unwrap().rebind(Box::new(socket))
     }
 
     /// Get the local `SocketAddr` the underlying socket is bound to
@@ -121,6 +134,11 @@ where
         self.inner.lock().unwrap().socket.local_addr()
     }
 
+    /// Returns statistics about incoming connection attempts
+    pub fn stats(&self) -> EndpointStats {
+        self.inner.lock().unwrap().inner.stats()
+    }
+
     /// Close all of this endpoint's connections immediately and cease accepting new connections.
     ///
     /// See [`Connection::close()`] for details.
@@ -207,9 +225,10 @@ where
             endpoint.driver = Some(cx.waker().clone());
         }
 
-        let now = Instant::now();
+        let now = endpoint.runtime.now();
         let mut keep_going = false;
         keep_going |= endpoint.drive_recv(cx, now)?;
+        keep_going |= endpoint.drive_forwarded(cx, now);
         keep_going |= endpoint.handle_events(cx);
         keep_going |= endpoint.drive_send(cx)?;
 
@@ -255,10 +274,10 @@ pub(crate) struct EndpointInner<S>
 where
     S: proto::crypto::Session,
 {
-    socket: UdpSocket,
+    socket: Box<dyn AsyncUdpSocket>,
     inner: proto::generic::Endpoint<S>,
     outgoing: VecDeque<proto::Transmit>,
-    incoming: VecDeque<Connecting<S>>,
+    incoming: VecDeque<proto::IncomingConnection>,
     incoming_reader: Option<Waker>,
     driver: Option<Waker>,
     ipv6: bool,
@@ -270,6 +289,9 @@ where
     recv_limiter: WorkLimiter,
     recv_buf: Box<[u8]>,
     idle: Broadcast,
+    /// Set if this is one of several endpoints sharing a UDP port
+    shard: Option<Shard>,
+    runtime: Arc<dyn Runtime>,
 }
 
 impl<S> EndpointInner<S>
@@ -295,26 +317,17 @@ where
                 Poll::Ready(Ok(msgs)) => {
                     self.recv_limiter.record_work(msgs);
                     for (meta, buf) in metas.iter().zip(iovs.iter()).take(msgs) {
-                        let data = buf[0..meta.len].into();
-                        match self
-                            .inner
-                            .handle(now, meta.addr, meta.dst_ip, meta.ecn, data)
-                        {
-                            Some((handle, DatagramEvent::NewConnection(conn))) => {
-                                let conn = self.connections.insert(handle, conn);
-                                self.incoming.push_back(conn);
-                            }
-                            Some((handle, DatagramEvent::ConnectionEvent(event))) => {
-                                // Ignoring errors from dropped connections that haven't yet been cleaned up
-                                let _ = self
-                                    .connections
-                                    .senders
-                                    .get_mut(&handle)
-                                    .unwrap()
-                                    .unbounded_send(ConnectionEvent::Proto(event));
-                            }
-                            None => {}
+                        let mut data = buf[0..meta.len].into();
+                        if let Some(ref mut shard) = self.shard {
+                            data = match shard.forward(meta, data) {
+                                Some(x) => x,
+                                None => continue,
+                            };
                         }
+                        let event = self
+                            .inner
+                            .handle(now, meta.addr, meta.dst_ip, meta.ecn, data);
+                        deliver(&mut self.incoming, &mut self.connections, event);
                     }
                 }
                 Poll::Pending => {
@@ -339,10 +352,35 @@ where
         Ok(false)
     }
 
+    /// Handle datagrams forwarded by other shards
+    fn drive_forwarded(&mut self, cx: &mut Context, now: Instant) -> bool {
+        let shard = match self.shard {
+            Some(ref mut x) => x,
+            None => return false,
+        };
+        for _ in 0..IO_LOOP_BOUND {
+            match shard.forwarded.poll_next_unpin(cx) {
+                Poll::Ready(Some((meta, data))) => {
+                    let event = self
+                        .inner
+                        .handle(now, meta.addr, meta.dst_ip, meta.ecn, data);
+                    deliver(&mut self.incoming, &mut self.connections, event);
+                }
+                Poll::Ready(None) => unreachable!("Shard owns a sender to itself"),
+                Poll::Pending => {
+                    return false;
+                }
+            }
+        }
+
+        true
+    }
+
     fn drive_send(&mut self, cx: &mut Context) -> Result<bool, io::Error> {
         let mut transmits = 0;
         loop {
             while self.outgoing.len() < BATCH_SIZE {
+                // Never coalesced, so always within the socket's capabilities
                 match self.inner.poll_transmit() {
                     Some(x) => self.outgoing.push_back(x),
                     None => break,
@@ -374,6 +412,7 @@ where
     fn handle_events(&mut self, cx: &mut Context) -> bool {
         use EndpointEvent::*;
 
+        let max_gso_segments = self.socket.caps().max_gso_segments;
         for _ in 0..IO_LOOP_BOUND {
             match self.events.poll_next_unpin(cx) {
                 Poll::Ready(Some((ch, event))) => match event {
@@ -394,7 +433,7 @@ where
                                 .unbounded_send(ConnectionEvent::Proto(event));
                         }
                     }
-                    Transmit(t) => self.outgoing.push_back(t),
+                    Transmit(t) => queue_transmit(&mut self.outgoing, max_gso_segments, t),
                 },
                 Poll::Ready(None) => unreachable!("EndpointInner owns one sender"),
                 Poll::Pending => {
@@ -407,6 +446,135 @@ where
     }
 }
 
+impl<S> EndpointInner<S>
+where
+    S: proto::crypto::Session,
+{
+    /// Ensure transmits queued outside of the driver get sent
+    fn wake_driver(&mut self) {
+        if let Some(task) = self.driver.as_ref() {
+            task.wake_by_ref();
+        }
+    }
+
+    fn rebind(&mut self, socket: Box<dyn AsyncUdpSocket>) -> io::Result<()> {
+        self.ipv6 = socket.local_addr()?.is_ipv6();
+        self.socket = socket;
+        // Wake the driver so it polls the new socket
+        self.wake_driver();
+        Ok(())
+    }
+}
+
+/// Queue `t` to be sent, splitting it if it holds more segments than the socket can take at once
+///
+/// Connections size their transmits for the socket the endpoint had when they were created, which
+/// may since have been replaced.
+fn queue_transmit(
+    outgoing: &mut VecDeque<proto::Transmit>,
+    max_segments: usize,
+    t: proto::Transmit,
+) {
+    let segment_size = match t.segment_size {
+        Some(x) if t.contents.len() > x * max_segments => x,
+        _ => {
+            outgoing.push_back(t);
+            return;
+        }
+    };
+    for contents in t.contents.chunks(segment_size * max_segments) {
+        outgoing.push_back(proto::Transmit {
+            destination: t.destination,
+            ecn: t.ecn,
+            contents: contents.to_vec(),
+            segment_size: if contents.len() > segment_size {
+                Some(segment_size)
+            } else {
+                None
+            },
+            src_ip: t.src_ip,
+        });
+    }
+}
+
+/// Pass the outcome of handling a datagram on to the `Incoming` stream or connection concerned
+fn deliver(
+    incoming: &mut VecDeque<proto::IncomingConnection>,
+    connections: &mut ConnectionSet,
+    event: Option<DatagramEvent>,
+) {
+    match event {
+        Some(DatagramEvent::NewConnection(x)) => {
+            incoming.push_back(x);
+        }
+        Some(DatagramEvent::ConnectionEvent(handle, event)) => {
+            // Ignoring errors from dropped connections that haven't yet been cleaned up
+            let _ = connections
+                .senders
+                .get_mut(&handle)
+                .unwrap()
+                .unbounded_send(ConnectionEvent::Proto(event));
+        }
+        None => {}
+    }
+}
+
+/// Routing state of one of the endpoints created by [`EndpointBuilder::bind_sharded()`]
+///
+/// Each shard issues connection IDs carrying its index as a QUIC-LB server ID, and passes
+/// datagrams its socket receives for other shards' connection IDs on to them.
+#[derive(Debug)]
+pub(crate) struct Shard {
+    index: usize,
+    decoder: quic_lb::Decoder,
+    /// Queues of datagrams to be handled by each shard, this one included
+    peers: Vec<mpsc::Sender<(RecvMeta, BytesMut)>>,
+    /// Datagrams forwarded to this shard
+    forwarded: mpsc::Receiver<(RecvMeta, BytesMut)>,
+}
+
+impl Shard {
+    /// Set up routing between `count` shards issuing connection IDs under `config`
+    pub(crate) fn group(count: usize, config: quic_lb::Config) -> Vec<Self> {
+        let mut decoder = quic_lb::Decoder::new();
+        decoder.insert(config);
+        let (peers, receivers): (Vec<_>, Vec<_>) =
+            (0..count).map(|_| mpsc::channel(FORWARD_QUEUE_LEN)).unzip();
+        receivers
+            .into_iter()
+            .enumerate()
+            .map(|(index, forwarded)| Self {
+                index,
+                decoder: decoder.clone(),
+                peers: peers.clone(),
+                forwarded,
+            })
+            .collect()
+    }
+
+    /// Pass `data` on to the shard its destination connection ID belongs to, if that's another one
+    ///
+    /// Returns `data` if it should be handled by this shard.
+    fn forward(&mut self, meta: &RecvMeta, data: BytesMut) -> Option<BytesMut> {
+        // The connection IDs clients choose for their first packets don't really belong to any
+        // shard, but routing them like any other keeps all of a connection attempt's packets
+        // together.
+        let target = match self.decoder.route(&data) {
+            Some(id) => id[0] as usize,
+            None => return Some(data),
+        };
+        if target == self.index || target >= self.peers.len() {
+            return Some(data);
+        }
+        // Like the socket would, drop datagrams when the receiving shard can't keep up
+        let _ = self.peers[target].try_send((*meta, data));
+        None
+    }
+}
+
+/// Number of forwarded datagrams each shard can have waiting to be handled
+const FORWARD_QUEUE_LEN: usize = 1024;
+
 #[derive(Debug)]
 struct ConnectionSet {
     /// Senders for communicating with the endpoint's connections
@@ -422,6 +590,8 @@ impl ConnectionSet {
         &mut self,
         handle: ConnectionHandle,
         conn: proto::generic::Connection<S>,
+        udp_caps: UdpCapabilities,
+        runtime: Arc<dyn Runtime>,
     ) -> Connecting<S> {
         let (send, recv) = mpsc::unbounded();
         if let Some((error_code, ref reason)) = self.close {
@@ -432,7 +602,7 @@ impl ConnectionSet {
             .unwrap();
         }
         self.senders.insert(handle, send);
-        Connecting::new(handle, conn, self.sender.clone(), recv)
+        Connecting::new(handle, conn, self.sender.clone(), recv, udp_caps, runtime)
     }
 
     fn is_empty(&self) -> bool {
@@ -464,15 +634,19 @@ impl<S> futures_util::stream::Stream for Incoming<S>
 where
     S: proto::crypto::Session,
 {
-    type Item = Connecting<S>;
+    type Item = IncomingConnection<S>;
 
     #[allow(unused_mut)] // MSRV
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
         let endpoint = &mut *self.0.lock().unwrap();
         if endpoint.driver_lost {
             Poll::Ready(None)
-        } else if let Some(conn) = endpoint.incoming.pop_front() {
-            Poll::Ready(Some(conn))
+        } else if let Some(incoming) = endpoint.incoming.pop_front() {
+            endpoint.ref_count += 1;
+            Poll::Ready(Some(IncomingConnection {
+                inner: Some(incoming),
+                endpoint: EndpointRef(self.0 .0.clone()),
+            }))
         } else if endpoint.connections.close.is_some() {
             Poll::Ready(None)
         } else {
@@ -490,6 +664,159 @@ where
         let endpoint = &mut *self.0.lock().unwrap();
         endpoint.inner.reject_new_connections();
         endpoint.incoming_reader = None;
+        while let Some(incoming) = endpoint.incoming.pop_front() {
+            endpoint
+                .inner
+                .refuse(incoming, TransportErrorCode::CONNECTION_REFUSED.into());
+        }
+        endpoint.wake_driver();
+    }
+}
+
+/// A connection attempt that hasn't been accepted or refused yet
+///
+/// Yielded by [`Incoming`]. Inspect it to decide whether to [`accept()`], [`refuse()`],
+/// [`retry()`] or [`ignore()`] it. Dropping it refuses the attempt.
+///
+/// [`Incoming`]: crate::generic::Incoming
+/// [`accept()`]: IncomingConnection::accept
+/// [`refuse()`]: IncomingConnection::refuse
+/// [`retry()`]: IncomingConnection::retry
+/// [`ignore()`]: IncomingConnection::ignore
+#[derive(Debug)]
+pub struct IncomingConnection<S: proto::crypto::Session> {
+    inner: Option<proto::IncomingConnection>,
+    endpoint: EndpointRef<S>,
+}
+
+impl<S> IncomingConnection<S>
+where
+    S: proto::crypto::Session + 'static,
+{
+    /// Begin the handshake
+    ///
+    /// Fails if the endpoint can no longer take on connections, or the client's first packet was
+    /// invalid.
+    pub fn accept(mut self) -> Result<Connecting<S>, ConnectionError> {
+        let incoming = self.inner.take().unwrap();
+        let endpoint = &mut *self.endpoint.lock().unwrap();
+        let now = endpoint.runtime.now();
+        let result = endpoint.inner.accept(incoming, now);
+        endpoint.wake_driver();
+        let (ch, conn) = result?;
+        let udp_caps = endpoint.socket.caps();
+        let runtime = endpoint.runtime.clone();
+        Ok(endpoint.connections.insert(ch, conn, udp_caps, runtime))
+    }
+
+    /// Refuse the connection attempt, informing the client with `reason`
+    ///
+    /// Use [`TransportErrorCode::CONNECTION_REFUSED`] for the usual case, or
+    /// [`TransportErrorCode::APPLICATION_ERROR`] for an application-specific reason.
+    pub fn refuse(mut self, reason: TransportError) {
+        let incoming = self.inner.take().unwrap();
+        let endpoint = &mut *self.endpoint.lock().unwrap();
+        endpoint.inner.refuse(incoming, reason);
+        endpoint.wake_driver();
+    }
+
+    /// Ask the client to prove ownership of its address by sending a Retry packet
+    ///
+    /// The client's next attempt shows up as a new `IncomingConnection` with
+    /// [`remote_address_validated()`] set. Fails if the address was already validated.
+    ///
+    /// [`remote_address_validated()`]: IncomingConnection::remote_address_validated
+    pub fn retry(mut self) -> Result<(), RetryError<S>> {
+        let incoming = self.inner.take().unwrap();
+        let result = {
+            let endpoint = &mut *self.endpoint.lock().unwrap();
+            endpoint.wake_driver();
+            endpoint.inner.retry(incoming)
+        };
+        match result {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                self.inner = Some(e.into_incoming());
+                Err(RetryError(Box::new(self)))
+            }
+        }
+    }
+
+    /// Drop the connection attempt without telling the client
+    pub fn ignore(mut self) {
+        let incoming = self.inner.take().unwrap();
+        self.endpoint.lock().unwrap().inner.ignore(incoming);
+    }
+}
+
+impl<S> IncomingConnection<S>
+where
+    S: proto::crypto::Session,
+{
+    /// The peer's UDP address
+    pub fn remote_address(&self) -> SocketAddr {
+        self.inner.as_ref().unwrap().remote_address()
+    }
+
+    /// The local IP address the attempt was received on, if known
+    pub fn local_ip(&self) -> Option<IpAddr> {
+        self.inner.as_ref().unwrap().local_ip()
+    }
+
+    /// Whether the client proved ownership of its address with a token
+    pub fn remote_address_validated(&self) -> bool {
+        self.inner.as_ref().unwrap().remote_address_validated()
+    }
+
+    /// The server name the client asked for with TLS SNI, if known
+    pub fn server_name(&self) -> Option<&str> {
+        self.inner.as_ref().unwrap().server_name()
+    }
+
+    /// The application protocols the client offered with TLS ALPN, if known
+    pub fn alpn_protocols(&self) -> Option<&[Vec<u8>]> {
+        self.inner.as_ref().unwrap().alpn_protocols()
+    }
+}
+
+impl<S> Drop for IncomingConnection<S>
+where
+    S: proto::crypto::Session,
+{
+    fn drop(&mut self) {
+        if let Some(incoming) = self.inner.take() {
+            let endpoint = &mut *self.endpoint.lock().unwrap();
+            endpoint
+                .inner
+                .refuse(incoming, TransportErrorCode::CONNECTION_REFUSED.into());
+            endpoint.wake_driver();
+        }
+    }
+}
+
+/// The client's address was already validated, so a connection attempt can't be retried
+///
+/// Contains the attempt so it can be accepted or refused instead.
+#[derive(Error)]
+#[error("retry of a validated connection attempt")]
+pub struct RetryError<S: proto::crypto::Session>(Box<IncomingConnection<S>>);
+
+impl<S> RetryError<S>
+where
+    S: proto::crypto::Session,
+{
+    /// Recover the connection attempt
+    pub fn into_incoming(self) -> IncomingConnection<S> {
+        *self.0
+    }
+}
+
+impl<S> fmt::Debug for RetryError<S>
+where
+    S: proto::crypto::Session,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("RetryError").finish()
     }
 }
 
@@ -500,7 +827,13 @@ impl<S> EndpointRef<S>
 where
     S: proto::crypto::Session,
 {
-    pub(crate) fn new(socket: UdpSocket, inner: proto::generic::Endpoint<S>, ipv6: bool) -> Self {
+    pub(crate) fn new(
+        socket: Box<dyn AsyncUdpSocket>,
+        inner: proto::generic::Endpoint<S>,
+        ipv6: bool,
+        shard: Option<Shard>,
+        runtime: Arc<dyn Runtime>,
+    ) -> Self {
         let recv_buf =
             vec![0; inner.config().get_max_udp_payload_size().min(64 * 1024) as usize * BATCH_SIZE];
         let (sender, events) = mpsc::unbounded();
@@ -523,6 +856,8 @@ where
             recv_buf: recv_buf.into(),
             recv_limiter: WorkLimiter::new(RECV_TIME_BOUND),
             idle: Broadcast::new(),
+            shard,
+            runtime,
         })))
     }
 }
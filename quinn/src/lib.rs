@@ -8,6 +8,9 @@
 //!
 //! The entry point of this crate is the [`Endpoint`](generic/struct.Endpoint.html).
 //!
+//! Endpoints and connections run on tokio by default. Other async runtimes can be used by
+//! enabling the `runtime-async-std` or `runtime-smol` features, or by implementing [`Runtime`].
+//!
 #![cfg_attr(
     feature = "rustls",
     doc = "```no_run
@@ -59,19 +62,37 @@ mod endpoint;
 mod mutex;
 mod platform;
 mod recv_stream;
+mod runtime;
 mod send_stream;
+mod session_store;
+#[cfg(feature = "runtime-tokio")]
+pub mod testing;
 mod work_limiter;
 
 pub use proto::{
-    crypto, ApplicationClose, Certificate, CertificateChain, Chunk, ConfigError, ConnectError,
-    ConnectionClose, ConnectionError, ParseError, PrivateKey, StreamId, Transmit, TransportConfig,
-    VarInt,
+    crypto, multipath, quic_lb, AntiReplayConfig, ApplicationClose, Certificate, CertificateChain,
+    Chunk, ConfigError, CongestionSnapshot, ConnectError, ConnectionClose, ConnectionError,
+    EcnCodepoint, EndpointStats, HandshakeLimitConfig, KeyRing, MtuDiscoveryConfig, ParseError,
+    PathId, PathInfo, PathStatus, PrivateKey, SessionStore, StreamId, TokenMemoryCache, TokenStore,
+    Transmit, TransportConfig, TransportError, TransportErrorCode, VarInt, ZeroRttPolicy,
+    ZeroRttRequest,
 };
 
 pub use crate::builders::EndpointError;
-pub use crate::connection::{SendDatagramError, ZeroRttAccepted};
+pub use crate::connection::{
+    Migrating, MigrationError, OpeningPath, PathError, SendDatagramError, ZeroRttAccepted,
+};
+pub use crate::platform::{AsyncUdpSocket, RecvMeta, UdpCapabilities};
 pub use crate::recv_stream::{ReadError, ReadExactError, ReadToEndError};
+#[cfg(feature = "runtime-async-std")]
+pub use crate::runtime::AsyncStdRuntime;
+#[cfg(feature = "runtime-smol")]
+pub use crate::runtime::SmolRuntime;
+#[cfg(feature = "runtime-tokio")]
+pub use crate::runtime::TokioRuntime;
+pub use crate::runtime::{default_runtime, AsyncTimer, RegisteredUdpSocket, Runtime};
 pub use crate::send_stream::{StoppedError, WriteError};
+pub use crate::session_store::SessionFileStore;
 
 /// Types that are generic over the crypto protocol implementation
 pub mod generic {
@@ -80,7 +101,7 @@ pub mod generic {
         Connecting, Connection, Datagrams, IncomingBiStreams, IncomingUniStreams, NewConnection,
         OpenBi, OpenUni,
     };
-    pub use crate::endpoint::{Endpoint, Incoming};
+    pub use crate::endpoint::{Endpoint, Incoming, IncomingConnection, RetryError};
     pub use crate::recv_stream::{Read, ReadChunk, ReadChunks, ReadExact, ReadToEnd, RecvStream};
     pub use crate::send_stream::SendStream;
     pub use proto::generic::{ClientConfig, ServerConfig};
@@ -124,6 +145,10 @@ mod rustls_impls {
     pub type Endpoint = generic::Endpoint<TlsSession>;
     /// An `Incoming` using rustls for the cryptography protocol
     pub type Incoming = generic::Incoming<TlsSession>;
+    /// An `IncomingConnection` using rustls for the cryptography protocol
+    pub type IncomingConnection = generic::IncomingConnection<TlsSession>;
+    /// A `RetryError` using rustls for the cryptography protocol
+    pub type RetryError = generic::RetryError<TlsSession>;
 
     /// A `Read` using rustls for the cryptography protocol
     pub type Read<'a> = generic::Read<'a, TlsSession>;
@@ -5,11 +5,10 @@ use std::{
     time::Instant,
 };
 
-use futures_util::ready;
 use proto::Transmit;
-use tokio::io::ReadBuf;
 
-use super::{log_sendmsg_error, RecvMeta, IO_ERROR_LOG_INTERVAL};
+use super::{log_sendmsg_error, AsyncUdpSocket, RecvMeta, UdpCapabilities, IO_ERROR_LOG_INTERVAL};
+use crate::runtime::{RegisteredUdpSocket, Runtime};
 
 /// Tokio-compatible UDP socket with some useful specializations.
 ///
@@ -17,31 +16,31 @@ use super::{log_sendmsg_error, RecvMeta, IO_ERROR_LOG_INTERVAL};
 /// platforms.
 #[derive(Debug)]
 pub struct UdpSocket {
-    io: tokio::net::UdpSocket,
+    io: Box<dyn RegisteredUdpSocket>,
     last_send_error: Instant,
 }
 
 impl UdpSocket {
-    pub fn from_std(socket: std::net::UdpSocket) -> io::Result<UdpSocket> {
-        socket.set_nonblocking(true)?;
+    pub fn new(runtime: &dyn Runtime, socket: std::net::UdpSocket) -> io::Result<UdpSocket> {
         let now = Instant::now();
         Ok(UdpSocket {
-            io: tokio::net::UdpSocket::from_std(socket)?,
+            io: runtime.wrap_udp_socket(socket)?,
             last_send_error: now.checked_sub(2 * IO_ERROR_LOG_INTERVAL).unwrap_or(now),
         })
     }
+}
 
-    pub fn poll_send(
+impl AsyncUdpSocket for UdpSocket {
+    fn poll_send(
         &mut self,
         cx: &mut Context,
         transmits: &[Transmit],
     ) -> Poll<Result<usize, io::Error>> {
         let mut sent = 0;
         for transmit in transmits {
-            match self
-                .io
-                .poll_send_to(cx, &transmit.contents, transmit.destination)
-            {
+            match self.io.poll_write(cx, &mut |io| {
+                io.send_to(&transmit.contents, transmit.destination)
+            }) {
                 Poll::Ready(Ok(_)) => {
                     sent += 1;
                 }
@@ -68,34 +67,56 @@ impl UdpSocket {
         Poll::Ready(Ok(sent))
     }
 
-    pub fn poll_recv(
-        &self,
+    fn poll_recv(
+        &mut self,
         cx: &mut Context,
         bufs: &mut [IoSliceMut<'_>],
         meta: &mut [RecvMeta],
     ) -> Poll<io::Result<usize>> {
         debug_assert!(!bufs.is_empty());
-        let mut buf = ReadBuf::new(&mut bufs[0]);
-        let addr = ready!(self.io.poll_recv_from(cx, &mut buf))?;
-        meta[0] = RecvMeta {
-            len: buf.filled().len(),
-            addr,
-            ecn: None,
-            dst_ip: None,
-        };
-        Poll::Ready(Ok(1))
+        let buf = &mut bufs[0];
+        let meta = &mut meta[0];
+        self.io.poll_read(cx, &mut |io| {
+            let (len, addr) = io.recv_from(buf)?;
+            *meta = RecvMeta {
+                len,
+                addr,
+                ecn: None,
+                dst_ip: None,
+            };
+            Ok(1)
+        })
     }
 
-    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+    fn local_addr(&self) -> io::Result<SocketAddr> {
         self.io.local_addr()
     }
+
+    fn caps(&self) -> UdpCapabilities {
+        caps()
+    }
 }
 
 /// Returns the platforms UDP socket capabilities
-pub fn caps() -> super::UdpCapabilities {
-    super::UdpCapabilities {
+pub fn caps() -> UdpCapabilities {
+    UdpCapabilities {
         max_gso_segments: 1,
+        src_ip: false,
     }
 }
 
 pub const BATCH_SIZE: usize = 1;
+
+pub fn bind_reuse_port(_addr: &SocketAddr) -> io::Result<std::net::UdpSocket> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "SO_REUSEPORT is not supported on this platform",
+    ))
+}
+
+pub fn steer_by_cid(_socket: &std::net::UdpSocket, _cid_len: u8) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "steering by connection ID is not supported on this platform",
+    ))
+}
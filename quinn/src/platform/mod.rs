@@ -1,6 +1,9 @@
 //! Uniform interface to send/recv UDP packets with ECN information.
 use std::{
+    fmt::Debug,
+    io::{self, IoSliceMut},
     net::{IpAddr, Ipv6Addr, SocketAddr},
+    task::{Context, Poll},
     time::{Duration, Instant},
 };
 
@@ -18,16 +21,52 @@ mod imp;
 #[path = "fallback.rs"]
 mod imp;
 
-pub use imp::UdpSocket;
-
-/// Returns the platforms UDP socket capabilities
-pub fn caps() -> UdpCapabilities {
-    imp::caps()
-}
+pub use imp::{bind_reuse_port, steer_by_cid, UdpSocket};
 
 /// Number of UDP packets to send/receive at a time
 pub const BATCH_SIZE: usize = imp::BATCH_SIZE;
 
+/// A UDP socket, or anything else able to carry datagrams, that an endpoint communicates through
+///
+/// Endpoints use the platform's UDP sockets unless built with
+/// [`EndpointBuilder::with_async_socket()`], so implementing this allows QUIC to run over other
+/// transports, such as a userspace network stack, a relay or an in-memory pipe. Errors returned by
+/// either polling method, except `ConnectionReset` when receiving, stop the endpoint.
+///
+/// [`EndpointBuilder::with_async_socket()`]: crate::generic::EndpointBuilder::with_async_socket
+pub trait AsyncUdpSocket: Send + Debug + 'static {
+    /// Send as many of `transmits` as possible, in order, returning how many were sent
+    ///
+    /// A transmit with a `segment_size` holds a sequence of datagrams of that size, of which the
+    /// last may be shorter, and at most `caps().max_gso_segments` of them. Failures affecting only
+    /// individual datagrams are best handled by dropping them, as QUIC recovers from loss.
+    fn poll_send(&mut self, cx: &mut Context, transmits: &[Transmit]) -> Poll<io::Result<usize>>;
+
+    /// Receive up to `bufs.len()` datagrams, returning how many were received
+    ///
+    /// The `i`th datagram is written to `bufs[i]` and described by `meta[i]`. `bufs` and `meta`
+    /// are the same length.
+    fn poll_recv(
+        &mut self,
+        cx: &mut Context,
+        bufs: &mut [IoSliceMut<'_>],
+        meta: &mut [RecvMeta],
+    ) -> Poll<io::Result<usize>>;
+
+    /// The local address the socket is bound to
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+
+    /// What the socket supports beyond sending and receiving single datagrams
+    ///
+    /// Defaults to nothing.
+    fn caps(&self) -> UdpCapabilities {
+        UdpCapabilities {
+            max_gso_segments: 1,
+            src_ip: false,
+        }
+    }
+}
+
 /// The capabilities a UDP socket suppports on a certain platform
 #[derive(Debug, Clone, Copy)]
 pub struct UdpCapabilities {
@@ -35,12 +74,19 @@ pub struct UdpCapabilities {
     /// supports Generic Send Offload (GSO).
     /// This is 1 if the platform doesn't support GSO.
     pub max_gso_segments: usize,
+    /// Whether datagrams are sent from the local IP address their `Transmit` specifies, rather
+    /// than one the operating system picks
+    pub src_ip: bool,
 }
 
+/// Describes a datagram received by an [`AsyncUdpSocket`]
 #[derive(Debug, Copy, Clone)]
 pub struct RecvMeta {
+    /// The address the datagram was sent from
     pub addr: SocketAddr,
+    /// The length of the datagram
     pub len: usize,
+    /// The explicit congestion notification bits the datagram was received with, if known
     pub ecn: Option<EcnCodepoint>,
     /// The destination IP address which was encoded in this datagram
     pub dst_ip: Option<IpAddr>,
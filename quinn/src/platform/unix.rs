@@ -9,12 +9,13 @@ use std::{
     time::Instant,
 };
 
-use futures_util::ready;
 use lazy_static::lazy_static;
 use proto::{EcnCodepoint, Transmit};
-use tokio::io::unix::AsyncFd;
 
-use super::{cmsg, log_sendmsg_error, RecvMeta, UdpCapabilities, IO_ERROR_LOG_INTERVAL};
+use super::{
+    cmsg, log_sendmsg_error, AsyncUdpSocket, RecvMeta, UdpCapabilities, IO_ERROR_LOG_INTERVAL,
+};
+use crate::runtime::{RegisteredUdpSocket, Runtime};
 
 #[cfg(target_os = "freebsd")]
 type IpTosTy = libc::c_uchar;
@@ -27,57 +28,154 @@ type IpTosTy = libc::c_int;
 /// platforms.
 #[derive(Debug)]
 pub struct UdpSocket {
-    io: AsyncFd<mio::net::UdpSocket>,
+    io: Box<dyn RegisteredUdpSocket>,
     last_send_error: Instant,
 }
 
 impl UdpSocket {
-    pub fn from_std(socket: std::net::UdpSocket) -> io::Result<UdpSocket> {
-        socket.set_nonblocking(true)?;
-        let io = mio::net::UdpSocket::from_std(socket);
-        init(&io)?;
+    pub fn new(runtime: &dyn Runtime, socket: std::net::UdpSocket) -> io::Result<UdpSocket> {
+        init(&socket)?;
         let now = Instant::now();
         Ok(UdpSocket {
-            io: AsyncFd::new(io)?,
+            io: runtime.wrap_udp_socket(socket)?,
             last_send_error: now.checked_sub(2 * IO_ERROR_LOG_INTERVAL).unwrap_or(now),
         })
     }
+}
 
-    pub fn poll_send(
+impl AsyncUdpSocket for UdpSocket {
+    fn poll_send(
         &mut self,
         cx: &mut Context,
         transmits: &[Transmit],
     ) -> Poll<Result<usize, io::Error>> {
-        loop {
-            let last_send_error = &mut self.last_send_error;
-            let mut guard = ready!(self.io.poll_write_ready(cx))?;
-            if let Ok(res) = guard.try_io(|io| send(io.get_ref(), last_send_error, transmits)) {
-                return Poll::Ready(res);
-            }
-        }
+        let last_send_error = &mut self.last_send_error;
+        self.io
+            .poll_write(cx, &mut |io| send(io, last_send_error, transmits))
     }
 
-    pub fn poll_recv(
-        &self,
+    fn poll_recv(
+        &mut self,
         cx: &mut Context,
         bufs: &mut [IoSliceMut<'_>],
         meta: &mut [RecvMeta],
     ) -> Poll<io::Result<usize>> {
         debug_assert!(!bufs.is_empty());
-        loop {
-            let mut guard = ready!(self.io.poll_read_ready(cx))?;
-            if let Ok(res) = guard.try_io(|io| recv(io.get_ref(), bufs, meta)) {
-                return Poll::Ready(res);
-            }
-        }
+        self.io.poll_read(cx, &mut |io| recv(io, bufs, meta))
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.io.local_addr()
+    }
+
+    fn caps(&self) -> UdpCapabilities {
+        caps()
+    }
+}
+
+/// Bind a socket to `addr` with `SO_REUSEPORT`, so that it can share the address with other such
+/// sockets, each receiving a portion of incoming datagrams
+pub fn bind_reuse_port(addr: &SocketAddr) -> io::Result<std::net::UdpSocket> {
+    let socket = socket2::Socket::new(
+        socket2::Domain::for_address(*addr),
+        socket2::Type::DGRAM,
+        Some(socket2::Protocol::UDP),
+    )?;
+    let on: libc::c_int = 1;
+    let rc = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_REUSEPORT,
+            &on as *const _ as _,
+            mem::size_of_val(&on) as _,
+        )
+    };
+    if rc == -1 {
+        return Err(io::Error::last_os_error());
     }
+    socket.bind(&(*addr).into())?;
+    Ok(socket.into())
+}
 
-    pub fn local_addr(&self) -> io::Result<SocketAddr> {
-        self.io.get_ref().local_addr()
+/// Make the kernel deliver datagrams to the socket of `socket`'s `SO_REUSEPORT` group whose
+/// index is found in the second byte of their destination connection ID
+///
+/// Matches connection IDs of `cid_len` bytes in the QUIC-LB plaintext format with config ID 0 and
+/// a one-byte server ID. Other datagrams, and those naming a socket that doesn't exist, are
+/// distributed by address as usual.
+#[cfg(target_os = "linux")]
+pub fn steer_by_cid(socket: &std::net::UdpSocket, cid_len: u8) -> io::Result<()> {
+    // Not exported by libc for Linux
+    const SO_ATTACH_REUSEPORT_CBPF: libc::c_int = 51;
+    // Offsets into the UDP payload
+    const LONG_CID_LEN: u32 = 5;
+    const LONG_CID: u32 = 6;
+    const SHORT_CID: u32 = 1;
+
+    fn stmt(code: u32, k: u32) -> libc::sock_filter {
+        jump(code, k, 0, 0)
+    }
+    fn jump(code: u32, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+        libc::sock_filter {
+            code: code as u16,
+            jt,
+            jf,
+            k,
+        }
+    }
+    let load = libc::BPF_LD | libc::BPF_B | libc::BPF_ABS;
+    let mut program = [
+        stmt(load, 0),
+        // Long header packets
+        jump(libc::BPF_JMP | libc::BPF_JSET | libc::BPF_K, 0x80, 0, 6),
+        stmt(load, LONG_CID_LEN),
+        jump(
+            libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K,
+            cid_len.into(),
+            0,
+            8,
+        ),
+        stmt(load, LONG_CID),
+        jump(libc::BPF_JMP | libc::BPF_JSET | libc::BPF_K, 0xc0, 6, 0),
+        stmt(load, LONG_CID + 1),
+        stmt(libc::BPF_RET | libc::BPF_A, 0),
+        // Short header packets
+        stmt(load, SHORT_CID),
+        jump(libc::BPF_JMP | libc::BPF_JSET | libc::BPF_K, 0xc0, 2, 0),
+        stmt(load, SHORT_CID + 1),
+        stmt(libc::BPF_RET | libc::BPF_A, 0),
+        // Out of range, so the kernel falls back to hashing addresses
+        stmt(libc::BPF_RET | libc::BPF_K, u32::MAX),
+    ];
+    let prog = libc::sock_fprog {
+        len: program.len() as u16,
+        filter: program.as_mut_ptr(),
+    };
+    let rc = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            SO_ATTACH_REUSEPORT_CBPF,
+            &prog as *const _ as _,
+            mem::size_of_val(&prog) as _,
+        )
+    };
+    if rc == -1 {
+        return Err(io::Error::last_os_error());
     }
+    Ok(())
 }
 
-fn init(io: &mio::net::UdpSocket) -> io::Result<()> {
+#[cfg(not(target_os = "linux"))]
+pub fn steer_by_cid(_socket: &std::net::UdpSocket, _cid_len: u8) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "steering by connection ID is not supported on this platform",
+    ))
+}
+
+fn init(io: &std::net::UdpSocket) -> io::Result<()> {
     let mut cmsg_platform_space = 0;
     if cfg!(target_os = "linux") {
         cmsg_platform_space +=
@@ -97,7 +195,10 @@ fn init(io: &mio::net::UdpSocket) -> io::Result<()> {
     let addr = io.local_addr()?;
 
     // macos and ios do not support IP_RECVTOS on dual-stack sockets :(
-    if addr.is_ipv4() || ((!cfg!(any(target_os = "macos", target_os = "ios"))) && !io.only_v6()?) {
+    if addr.is_ipv4()
+        || ((!cfg!(any(target_os = "macos", target_os = "ios")))
+            && !socket2::SockRef::from(io).only_v6()?)
+    {
         let on: libc::c_int = 1;
         let rc = unsafe {
             libc::setsockopt(
@@ -190,7 +291,7 @@ fn init(io: &mio::net::UdpSocket) -> io::Result<()> {
 
 #[cfg(not(any(target_os = "macos", target_os = "ios")))]
 fn send(
-    io: &mio::net::UdpSocket,
+    io: &std::net::UdpSocket,
     last_send_error: &mut Instant,
     transmits: &[Transmit],
 ) -> io::Result<usize> {
@@ -257,7 +358,7 @@ fn send(
 
 #[cfg(any(target_os = "macos", target_os = "ios"))]
 fn send(
-    io: &mio::net::UdpSocket,
+    io: &std::net::UdpSocket,
     last_send_error: &mut Instant,
     transmits: &[Transmit],
 ) -> io::Result<usize> {
@@ -297,7 +398,7 @@ fn send(
 
 #[cfg(not(any(target_os = "macos", target_os = "ios")))]
 fn recv(
-    io: &mio::net::UdpSocket,
+    io: &std::net::UdpSocket,
     bufs: &mut [IoSliceMut<'_>],
     meta: &mut [RecvMeta],
 ) -> io::Result<usize> {
@@ -340,7 +441,7 @@ fn recv(
 
 #[cfg(any(target_os = "macos", target_os = "ios"))]
 fn recv(
-    io: &mio::net::UdpSocket,
+    io: &std::net::UdpSocket,
     bufs: &mut [IoSliceMut<'_>],
     meta: &mut [RecvMeta],
 ) -> io::Result<usize> {
@@ -573,6 +674,8 @@ lazy_static! {
     static ref CAPABILITIES: UdpCapabilities = {
         UdpCapabilities {
             max_gso_segments: gso::max_gso_segments(),
+            // Only Linux is passed the source address with the datagram
+            src_ip: cfg!(target_os = "linux"),
         }
     };
 }
@@ -214,6 +214,19 @@ where
         self.is_0rtt
     }
 
+    /// Check if this stream was opened during 0-RTT with protection against replay in effect
+    ///
+    /// Such data isn't a replay of an earlier connection attempt, so non-idempotent requests are
+    /// safe to act on. See `Connection::is_0rtt_replay_protected()`.
+    pub fn is_0rtt_replay_protected(&self) -> bool {
+        self.is_0rtt
+            && self
+                .conn
+                .lock("RecvStream::is_0rtt_replay_protected")
+                .inner
+                .is_0rtt_replay_protected()
+    }
+
     /// Get the identity of this stream
     pub fn id(&self) -> StreamId {
         self.stream
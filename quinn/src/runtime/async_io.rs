@@ -0,0 +1,107 @@
+//! Runtimes built on the `async-io` reactor, which async-std and smol share
+use std::{
+    future::Future,
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use async_io::{Async, Timer};
+use futures_util::ready;
+
+use super::{AsyncTimer, RegisteredUdpSocket, Runtime};
+
+/// A [`Runtime`] for async-std
+#[cfg(feature = "runtime-async-std")]
+#[derive(Debug)]
+pub struct AsyncStdRuntime;
+
+#[cfg(feature = "runtime-async-std")]
+impl Runtime for AsyncStdRuntime {
+    fn new_timer(&self, deadline: Instant) -> Pin<Box<dyn AsyncTimer>> {
+        Box::pin(Timer::at(deadline))
+    }
+
+    fn spawn(&self, future: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        async_std::task::spawn(future);
+    }
+
+    fn wrap_udp_socket(
+        &self,
+        socket: std::net::UdpSocket,
+    ) -> io::Result<Box<dyn RegisteredUdpSocket>> {
+        Ok(Box::new(UdpSocket(Async::new(socket)?)))
+    }
+}
+
+/// A [`Runtime`] for smol
+#[cfg(feature = "runtime-smol")]
+#[derive(Debug)]
+pub struct SmolRuntime;
+
+#[cfg(feature = "runtime-smol")]
+impl Runtime for SmolRuntime {
+    fn new_timer(&self, deadline: Instant) -> Pin<Box<dyn AsyncTimer>> {
+        Box::pin(Timer::at(deadline))
+    }
+
+    fn spawn(&self, future: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        smol::spawn(future).detach();
+    }
+
+    fn wrap_udp_socket(
+        &self,
+        socket: std::net::UdpSocket,
+    ) -> io::Result<Box<dyn RegisteredUdpSocket>> {
+        Ok(Box::new(UdpSocket(Async::new(socket)?)))
+    }
+}
+
+impl AsyncTimer for Timer {
+    fn reset(mut self: Pin<&mut Self>, deadline: Instant) {
+        self.set_at(deadline)
+    }
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        Future::poll(self.as_mut(), cx).map(|_| ())
+    }
+}
+
+#[derive(Debug)]
+struct UdpSocket(Async<std::net::UdpSocket>);
+
+impl RegisteredUdpSocket for UdpSocket {
+    fn poll_read(
+        &self,
+        cx: &mut Context,
+        op: &mut dyn FnMut(&std::net::UdpSocket) -> io::Result<usize>,
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            ready!(self.0.poll_readable(cx))?;
+            match op(self.0.get_ref()) {
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                res => return Poll::Ready(res),
+            }
+        }
+    }
+
+    fn poll_write(
+        &self,
+        cx: &mut Context,
+        op: &mut dyn FnMut(&std::net::UdpSocket) -> io::Result<usize>,
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            ready!(self.0.poll_writable(cx))?;
+            match op(self.0.get_ref()) {
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                res => return Poll::Ready(res),
+            }
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.0.get_ref().local_addr()
+    }
+}
@@ -0,0 +1,96 @@
+//! Abstraction over the async runtime driving endpoints and connections
+use std::{
+    fmt::Debug,
+    future::Future,
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+#[cfg(feature = "runtime-tokio")]
+mod tokio;
+#[cfg(feature = "runtime-tokio")]
+pub use self::tokio::TokioRuntime;
+
+#[cfg(any(feature = "runtime-async-std", feature = "runtime-smol"))]
+mod async_io;
+#[cfg(feature = "runtime-async-std")]
+pub use self::async_io::AsyncStdRuntime;
+#[cfg(feature = "runtime-smol")]
+pub use self::async_io::SmolRuntime;
+
+/// Timers, task spawning and socket readiness, as provided by an async runtime
+///
+/// Implementations for tokio, async-std and smol are available with the `runtime-tokio`,
+/// `runtime-async-std` and `runtime-smol` features respectively.
+pub trait Runtime: Send + Sync + Debug + 'static {
+    /// Construct a timer that expires at `deadline`
+    fn new_timer(&self, deadline: Instant) -> Pin<Box<dyn AsyncTimer>>;
+    /// Drive `future` to completion in the background
+    fn spawn(&self, future: Pin<Box<dyn Future<Output = ()> + Send>>);
+    /// Register `socket` with the runtime's reactor, making it nonblocking
+    fn wrap_udp_socket(
+        &self,
+        socket: std::net::UdpSocket,
+    ) -> io::Result<Box<dyn RegisteredUdpSocket>>;
+    /// The current time, against which timer deadlines are measured
+    ///
+    /// Defaults to the system clock.
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A timer created by a [`Runtime`]
+pub trait AsyncTimer: Send + Debug + 'static {
+    /// Change the time the timer expires at to `deadline`
+    fn reset(self: Pin<&mut Self>, deadline: Instant);
+    /// Check whether the timer has expired, arranging for the current task to be woken when it
+    /// does if not
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()>;
+}
+
+/// A nonblocking UDP socket registered with a [`Runtime`]'s reactor
+///
+/// Quinn performs I/O on the socket itself, and relies on the reactor to learn when operations
+/// that couldn't complete immediately are worth retrying.
+pub trait RegisteredUdpSocket: Send + Sync + Debug + 'static {
+    /// Perform `op` once the socket is readable, retrying whenever it fails with `WouldBlock`
+    fn poll_read(
+        &self,
+        cx: &mut Context,
+        op: &mut dyn FnMut(&std::net::UdpSocket) -> io::Result<usize>,
+    ) -> Poll<io::Result<usize>>;
+    /// Perform `op` once the socket is writable, retrying whenever it fails with `WouldBlock`
+    fn poll_write(
+        &self,
+        cx: &mut Context,
+        op: &mut dyn FnMut(&std::net::UdpSocket) -> io::Result<usize>,
+    ) -> Poll<io::Result<usize>>;
+    /// The local address the socket is bound to
+    fn local_addr(&self) -> io::Result<SocketAddr>;
+}
+
+/// Select a runtime from those enabled at compile time
+///
+/// Prefers tokio if called from within a tokio runtime context, then async-std, then smol.
+/// Returns `None` if none of them is suitable.
+pub fn default_runtime() -> Option<Arc<dyn Runtime>> {
+    #[cfg(feature = "runtime-tokio")]
+    {
+        if ::tokio::runtime::Handle::try_current().is_ok() {
+            return Some(Arc::new(TokioRuntime));
+        }
+    }
+
+    #[cfg(feature = "runtime-async-std")]
+    let fallback: Option<Arc<dyn Runtime>> = Some(Arc::new(AsyncStdRuntime));
+    #[cfg(all(feature = "runtime-smol", not(feature = "runtime-async-std")))]
+    let fallback: Option<Arc<dyn Runtime>> = Some(Arc::new(SmolRuntime));
+    #[cfg(not(any(feature = "runtime-async-std", feature = "runtime-smol")))]
+    let fallback = None;
+    fallback
+}
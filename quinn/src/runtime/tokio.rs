@@ -0,0 +1,156 @@
+use std::{
+    future::Future,
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Instant,
+};
+
+use futures_util::ready;
+use tokio::time::{sleep_until, Sleep};
+
+use super::{AsyncTimer, RegisteredUdpSocket, Runtime};
+
+/// A [`Runtime`] for tokio
+///
+/// Must be used from within a tokio runtime context.
+#[derive(Debug)]
+pub struct TokioRuntime;
+
+impl Runtime for TokioRuntime {
+    fn new_timer(&self, deadline: Instant) -> Pin<Box<dyn AsyncTimer>> {
+        Box::pin(sleep_until(deadline.into()))
+    }
+
+    fn spawn(&self, future: Pin<Box<dyn Future<Output = ()> + Send>>) {
+        tokio::spawn(future);
+    }
+
+    fn wrap_udp_socket(
+        &self,
+        socket: std::net::UdpSocket,
+    ) -> io::Result<Box<dyn RegisteredUdpSocket>> {
+        socket.set_nonblocking(true)?;
+        Ok(Box::new(UdpSocket::new(socket)?))
+    }
+
+    /// Follows tokio's clock, so that pausing it in tests also pauses endpoints and connections
+    fn now(&self) -> Instant {
+        tokio::time::Instant::now().into_std()
+    }
+}
+
+impl AsyncTimer for Sleep {
+    fn reset(self: Pin<&mut Self>, deadline: Instant) {
+        Sleep::reset(self, deadline.into())
+    }
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<()> {
+        Future::poll(self, cx)
+    }
+}
+
+#[cfg(unix)]
+#[derive(Debug)]
+struct UdpSocket(tokio::io::unix::AsyncFd<std::net::UdpSocket>);
+
+#[cfg(unix)]
+impl UdpSocket {
+    fn new(socket: std::net::UdpSocket) -> io::Result<Self> {
+        Ok(Self(tokio::io::unix::AsyncFd::new(socket)?))
+    }
+}
+
+#[cfg(unix)]
+impl RegisteredUdpSocket for UdpSocket {
+    fn poll_read(
+        &self,
+        cx: &mut Context,
+        op: &mut dyn FnMut(&std::net::UdpSocket) -> io::Result<usize>,
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            let mut guard = ready!(self.0.poll_read_ready(cx))?;
+            if let Ok(res) = guard.try_io(|io| op(io.get_ref())) {
+                return Poll::Ready(res);
+            }
+        }
+    }
+
+    fn poll_write(
+        &self,
+        cx: &mut Context,
+        op: &mut dyn FnMut(&std::net::UdpSocket) -> io::Result<usize>,
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            let mut guard = ready!(self.0.poll_write_ready(cx))?;
+            if let Ok(res) = guard.try_io(|io| op(io.get_ref())) {
+                return Poll::Ready(res);
+            }
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.0.get_ref().local_addr()
+    }
+}
+
+/// tokio only exposes readiness through its own socket type on this platform, so I/O is performed
+/// on a second handle to the same socket
+#[cfg(not(unix))]
+#[derive(Debug)]
+struct UdpSocket {
+    io: tokio::net::UdpSocket,
+    handle: std::net::UdpSocket,
+}
+
+#[cfg(not(unix))]
+impl UdpSocket {
+    fn new(socket: std::net::UdpSocket) -> io::Result<Self> {
+        Ok(Self {
+            handle: socket.try_clone()?,
+            io: tokio::net::UdpSocket::from_std(socket)?,
+        })
+    }
+}
+
+#[cfg(not(unix))]
+impl RegisteredUdpSocket for UdpSocket {
+    fn poll_read(
+        &self,
+        cx: &mut Context,
+        op: &mut dyn FnMut(&std::net::UdpSocket) -> io::Result<usize>,
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            ready!(self.io.poll_recv_ready(cx))?;
+            match self
+                .io
+                .try_io(tokio::io::Interest::READABLE, || op(&self.handle))
+            {
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                res => return Poll::Ready(res),
+            }
+        }
+    }
+
+    fn poll_write(
+        &self,
+        cx: &mut Context,
+        op: &mut dyn FnMut(&std::net::UdpSocket) -> io::Result<usize>,
+    ) -> Poll<io::Result<usize>> {
+        loop {
+            ready!(self.io.poll_send_ready(cx))?;
+            match self
+                .io
+                .try_io(tokio::io::Interest::WRITABLE, || op(&self.handle))
+            {
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {}
+                res => return Poll::Ready(res),
+            }
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.io.local_addr()
+    }
+}
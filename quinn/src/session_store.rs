@@ -0,0 +1,343 @@
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::{mpsc, Mutex},
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use bytes::{Buf, BufMut, Bytes};
+use proto::SessionStore;
+use tracing::warn;
+
+/// A [`SessionStore`] that keeps TLS session tickets in a file
+///
+/// Lets clients resume sessions and send 0-RTT data after restarting. The file is read once when
+/// the store is opened and rewritten by a background thread whenever a ticket is stored, so it
+/// shouldn't be shared by concurrently running processes. Dropping the store waits for pending
+/// writes to finish. Tickets are forgotten once they're older than
+/// [`max_age()`](Self::max_age), and the oldest are evicted when more than
+/// [`max_entries()`](Self::max_entries) are stored.
+///
+/// The file grants the ability to resume sessions as this client, so it should be kept private.
+/// On unix, it is created readable and writable by its owner only.
+pub struct SessionFileStore {
+    path: PathBuf,
+    max_age: Duration,
+    max_entries: usize,
+    entries: Mutex<Entries>,
+    /// Passes the contents of the store to `writer`, or `None` once dropped
+    writes: Option<Mutex<mpsc::Sender<Entries>>>,
+    writer: Option<thread::JoinHandle<()>>,
+}
+
+impl SessionFileStore {
+    /// Open the store kept at `path`, creating it when the first ticket is stored if necessary
+    ///
+    /// A file that can't be parsed is ignored and overwritten, since its contents are only a
+    /// cache.
+    pub fn open(path: impl Into<PathBuf>) -> io::Result<Self> {
+        let path = path.into();
+        let entries = match fs::read(&path) {
+            Ok(data) => decode(&data).unwrap_or_else(|| {
+                warn!("ignoring malformed session store {}", path.display());
+                HashMap::new()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e),
+        };
+        let (send, recv) = mpsc::channel();
+        let writer = {
+            let path = path.clone();
+            thread::Builder::new()
+                .name("quinn-session-store".into())
+                .spawn(move || write_loop(&path, recv))?
+        };
+        Ok(Self {
+            path,
+            max_age: Duration::from_secs(7 * 24 * 60 * 60),
+            max_entries: 256,
+            entries: Mutex::new(entries),
+            writes: Some(Mutex::new(send)),
+            writer: Some(writer),
+        })
+    }
+
+    /// Duration after which stored tickets are discarded
+    ///
+    /// TLS 1.3 servers never accept tickets older than 7 days, the default. Servers commonly
+    /// issue tickets with shorter lifetimes; rustls ignores tickets it knows to have expired, but
+    /// they occupy space until they reach this age.
+    pub fn max_age(&mut self, value: Duration) -> &mut Self {
+        self.max_age = value;
+        self
+    }
+
+    /// Maximum number of tickets to keep, one per server name
+    ///
+    /// Defaults to 256.
+    pub fn max_entries(&mut self, value: usize) -> &mut Self {
+        self.max_entries = value;
+        self
+    }
+
+    /// Path of the file the store is kept in
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Store `value` as if at time `now`
+    fn insert_at(&self, now: SystemTime, key: &[u8], value: Vec<u8>) {
+        let now = unix_time(now);
+        let mut entries = self.entries.lock().unwrap();
+        let max_age = self.max_age.as_secs();
+        entries.retain(|_, entry| now.saturating_sub(entry.stored) < max_age);
+        entries.insert(
+            Bytes::copy_from_slice(key),
+            Entry {
+                value: value.into(),
+                stored: now,
+            },
+        );
+        while entries.len() > self.max_entries {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.stored)
+                .map(|(key, _)| key.clone())
+                .unwrap();
+            entries.remove(&oldest);
+        }
+        // rustls calls this during the handshake, so leave encoding and file I/O to the writer
+        // thread. Cloning the entries only copies references to their contents.
+        let _ = self
+            .writes
+            .as_ref()
+            .unwrap()
+            .lock()
+            .unwrap()
+            .send(entries.clone());
+    }
+}
+
+impl SessionStore for SessionFileStore {
+    fn insert(&self, key: &[u8], value: Vec<u8>) {
+        self.insert_at(SystemTime::now(), key, value);
+    }
+
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        let now = unix_time(SystemTime::now());
+        let entries = self.entries.lock().unwrap();
+        let entry = entries.get(key)?;
+        if now.saturating_sub(entry.stored) >= self.max_age.as_secs() {
+            return None;
+        }
+        Some(entry.value.to_vec())
+    }
+}
+
+impl Drop for SessionFileStore {
+    fn drop(&mut self) {
+        // Closing the channel stops the writer once it has written everything sent to it
+        self.writes = None;
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
+
+/// Write each version of the store received from `recv` to `path`, until the store is dropped
+///
+/// Versions that were superseded while an earlier one was being written are skipped.
+fn write_loop(path: &Path, recv: mpsc::Receiver<Entries>) {
+    while let Ok(mut entries) = recv.recv() {
+        while let Ok(newer) = recv.try_recv() {
+            entries = newer;
+        }
+        if let Err(e) = write(path, &encode(&entries)) {
+            warn!("failed to write session store {}: {}", path.display(), e);
+        }
+    }
+}
+
+fn write(path: &Path, data: &[u8]) -> io::Result<()> {
+    // Write to a temporary file first so that a crash can't leave a truncated store behind
+    let tmp = path.with_extension("tmp");
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        // Permissions only apply to newly created files, so don't reuse one left behind by a crash
+        let _ = fs::remove_file(&tmp);
+        options.mode(0o600);
+    }
+    options.open(&tmp)?.write_all(data)?;
+    fs::rename(&tmp, path)
+}
+
+type Entries = HashMap<Bytes, Entry>;
+
+#[derive(Clone)]
+struct Entry {
+    value: Bytes,
+    /// Seconds since the UNIX epoch at which the value was stored
+    stored: u64,
+}
+
+/// Identifies the file format, in case it ever needs to change
+const FORMAT_VERSION: u8 = 1;
+
+fn encode(entries: &Entries) -> Vec<u8> {
+    let mut buf = vec![FORMAT_VERSION];
+    for (key, entry) in entries {
+        // Entries too large for the format can't be persisted, but remain usable until restart
+        let (key_len, value_len) =
+            match (u16::try_from(key.len()), u32::try_from(entry.value.len())) {
+                (Ok(key_len), Ok(value_len)) => (key_len, value_len),
+                _ => continue,
+            };
+        buf.put_u64(entry.stored);
+        buf.put_u16(key_len);
+        buf.put_slice(key);
+        buf.put_u32(value_len);
+        buf.put_slice(&entry.value);
+    }
+    buf
+}
+
+fn decode(mut buf: &[u8]) -> Option<Entries> {
+    if buf.is_empty() || buf.get_u8() != FORMAT_VERSION {
+        return None;
+    }
+    let mut entries = HashMap::new();
+    while buf.has_remaining() {
+        if buf.remaining() < 8 + 2 {
+            return None;
+        }
+        let stored = buf.get_u64();
+        let key_len = buf.get_u16() as usize;
+        if buf.remaining() < key_len + 4 {
+            return None;
+        }
+        let key = Bytes::copy_from_slice(&buf[..key_len]);
+        buf.advance(key_len);
+        let value_len = buf.get_u32() as usize;
+        if buf.remaining() < value_len {
+            return None;
+        }
+        let value = Bytes::copy_from_slice(&buf[..value_len]);
+        buf.advance(value_len);
+        entries.insert(key, Entry { value, stored });
+    }
+    Some(entries)
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH).map_or(0, |x| x.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A path unique to this test process
+    fn temp_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "quinn-session-store-{}-{}",
+            std::process::id(),
+            name
+        ));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn persists_across_reopen() {
+        let path = temp_path("reopen");
+        let store = SessionFileStore::open(&path).unwrap();
+        assert_eq!(store.get(b"a"), None);
+        store.insert(b"a", b"first".to_vec());
+        store.insert(b"b", b"second".to_vec());
+        store.insert(b"a", b"third".to_vec());
+        drop(store);
+
+        let store = SessionFileStore::open(&path).unwrap();
+        assert_eq!(store.get(b"a").as_deref(), Some(&b"third"[..]));
+        assert_eq!(store.get(b"b").as_deref(), Some(&b"second"[..]));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn expiry() {
+        let path = temp_path("expiry");
+        let mut store = SessionFileStore::open(&path).unwrap();
+        store.max_age(Duration::from_secs(0));
+        store.insert(b"a", b"value".to_vec());
+        assert_eq!(store.get(b"a"), None);
+        drop(store);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn eviction() {
+        let path = temp_path("eviction");
+        let mut store = SessionFileStore::open(&path).unwrap();
+        store.max_entries(2);
+        let now = SystemTime::now();
+        for (i, key) in [b"a", b"b", b"c"].iter().enumerate() {
+            store.insert_at(now + Duration::from_secs(i as u64), &key[..], key.to_vec());
+        }
+        assert_eq!(store.entries.lock().unwrap().len(), 2);
+        assert_eq!(store.get(b"a"), None);
+        assert_eq!(store.get(b"b").as_deref(), Some(&b"b"[..]));
+        assert_eq!(store.get(b"c").as_deref(), Some(&b"c"[..]));
+        drop(store);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn private_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = temp_path("private");
+        let store = SessionFileStore::open(&path).unwrap();
+        store.insert(b"a", b"value".to_vec());
+        drop(store);
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn oversized_key() {
+        let path = temp_path("oversized");
+        let store = SessionFileStore::open(&path).unwrap();
+        let key = vec![0; usize::from(u16::MAX) + 1];
+        store.insert(&key, b"value".to_vec());
+        store.insert(b"a", b"value".to_vec());
+        assert_eq!(store.get(&key).as_deref(), Some(&b"value"[..]));
+        drop(store);
+
+        let store = SessionFileStore::open(&path).unwrap();
+        assert_eq!(store.get(&key), None);
+        assert_eq!(store.get(b"a").as_deref(), Some(&b"value"[..]));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn malformed_file() {
+        let path = temp_path("malformed");
+        fs::write(&path, [FORMAT_VERSION, 0, 0, 0]).unwrap();
+        let store = SessionFileStore::open(&path).unwrap();
+        assert_eq!(store.get(b"a"), None);
+        store.insert(b"a", b"value".to_vec());
+        drop(store);
+        let store = SessionFileStore::open(&path).unwrap();
+        assert_eq!(store.get(b"a").as_deref(), Some(&b"value"[..]));
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,368 @@
+//! In-memory networks for testing applications deterministically
+//!
+//! A [`Network`] carries datagrams between the [`Socket`]s bound to it, subjecting them to the
+//! latency, loss, reordering and bandwidth limit configured for each [`LinkConfig`]. Endpoints use
+//! the sockets through [`EndpointBuilder::with_async_socket()`].
+//!
+//! Time is measured by tokio's clock, which endpoints on a [`TokioRuntime`] also follow. Tests
+//! that pause it, using `#[tokio::test(start_paused = true)]` or [`tokio::time::pause()`] with
+//! tokio's `test-util` feature, skip straight over periods in which everything is waiting for a
+//! timer or a datagram in flight. They therefore run as fast as the CPU allows, and their outcome
+//! doesn't depend on how fast that is. Together with a fixed seed for the network's random
+//! choices, that makes them reproducible.
+//!
+//! [`EndpointBuilder::with_async_socket()`]: crate::generic::EndpointBuilder::with_async_socket
+//! [`TokioRuntime`]: crate::TokioRuntime
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::BinaryHeap,
+    future::Future,
+    io::{self, IoSliceMut},
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll, Waker},
+    time::Duration,
+};
+
+use fxhash::FxHashMap;
+use proto::{EcnCodepoint, Transmit};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use tokio::time::{sleep_until, Instant, Sleep};
+
+use crate::platform::{AsyncUdpSocket, RecvMeta};
+
+/// A simulated network connecting [`Socket`]s
+///
+/// May be cloned to obtain another handle to the same network.
+#[derive(Debug, Clone)]
+pub struct Network(Arc<Mutex<NetworkState>>);
+
+impl Network {
+    /// Create a network whose random choices are derived from `seed`
+    ///
+    /// Links are perfect until configured otherwise.
+    pub fn new(seed: u64) -> Self {
+        Self(Arc::new(Mutex::new(NetworkState {
+            rng: StdRng::seed_from_u64(seed),
+            default_link: LinkConfig::default(),
+            links: FxHashMap::default(),
+            busy_until: FxHashMap::default(),
+            sockets: FxHashMap::default(),
+            next_seq: 0,
+        })))
+    }
+
+    /// Create a socket bound to `addr`
+    ///
+    /// If the port is 0, an unused one is chosen. Fails if `addr` is already bound.
+    pub fn bind(&self, mut addr: SocketAddr) -> io::Result<Socket> {
+        let mut state = self.0.lock().unwrap();
+        if addr.port() == 0 {
+            let port = (EPHEMERAL_PORTS..=u16::MAX)
+                .find(|&port| {
+                    !state
+                        .sockets
+                        .contains_key(&SocketAddr::new(addr.ip(), port))
+                })
+                .ok_or_else(|| io::Error::from(io::ErrorKind::AddrNotAvailable))?;
+            addr.set_port(port);
+        }
+        if state.sockets.contains_key(&addr) {
+            return Err(io::ErrorKind::AddrInUse.into());
+        }
+        state.sockets.insert(addr, Inbox::default());
+        Ok(Socket {
+            network: self.clone(),
+            addr,
+            timer: None,
+        })
+    }
+
+    /// Set the conditions datagrams sent from `from` to `to` are subjected to
+    ///
+    /// Applies in one direction only, so that asymmetric paths can be modeled.
+    pub fn set_link(&self, from: SocketAddr, to: SocketAddr, config: LinkConfig) {
+        self.0.lock().unwrap().links.insert((from, to), config);
+    }
+
+    /// Set the conditions datagrams are subjected to on links not configured with
+    /// [`set_link()`](Self::set_link)
+    pub fn set_default_link(&self, config: LinkConfig) {
+        self.0.lock().unwrap().default_link = config;
+    }
+}
+
+#[derive(Debug)]
+struct NetworkState {
+    rng: StdRng,
+    default_link: LinkConfig,
+    links: FxHashMap<(SocketAddr, SocketAddr), LinkConfig>,
+    /// When each bandwidth-limited link finishes sending the datagrams queued on it
+    busy_until: FxHashMap<(SocketAddr, SocketAddr), Instant>,
+    sockets: FxHashMap<SocketAddr, Inbox>,
+    /// Orders datagrams arriving at the same time by when they were sent
+    next_seq: u64,
+}
+
+impl NetworkState {
+    fn send(&mut self, now: Instant, from: SocketAddr, transmit: &Transmit) {
+        let size = transmit.segment_size.unwrap_or(transmit.contents.len());
+        for datagram in transmit.contents.chunks(size) {
+            self.send_datagram(now, from, transmit.destination, transmit.ecn, datagram);
+        }
+    }
+
+    fn send_datagram(
+        &mut self,
+        now: Instant,
+        from: SocketAddr,
+        to: SocketAddr,
+        ecn: Option<EcnCodepoint>,
+        data: &[u8],
+    ) {
+        if !self.sockets.contains_key(&to) {
+            return;
+        }
+        let link = self.links.get(&(from, to)).unwrap_or(&self.default_link);
+        if self.rng.gen_bool(link.loss) {
+            return;
+        }
+
+        let mut departure = now;
+        if let Some(bandwidth) = link.bandwidth {
+            let busy_until = self.busy_until.entry((from, to)).or_insert(now);
+            let start = (*busy_until).max(now);
+            if let Some(buffer_size) = link.buffer_size {
+                let queued = (start - now).as_secs_f64() * bandwidth as f64;
+                if queued + data.len() as f64 > buffer_size as f64 {
+                    return;
+                }
+            }
+            departure = start + Duration::from_secs_f64(data.len() as f64 / bandwidth as f64);
+            *busy_until = departure;
+        }
+
+        let mut time = departure + link.latency;
+        if self.rng.gen_bool(link.reorder) {
+            time += link.reorder_delay;
+        }
+
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let inbox = self.sockets.get_mut(&to).unwrap();
+        inbox.queue.push(Reverse(Arrival {
+            time,
+            seq,
+            source: from,
+            ecn,
+            data: data.into(),
+        }));
+        if let Some(waker) = inbox.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+/// Conditions imposed on datagrams traveling between two [`Socket`]s
+///
+/// The default is a perfect link, delivering every datagram instantly.
+#[derive(Debug, Clone)]
+pub struct LinkConfig {
+    latency: Duration,
+    loss: f64,
+    reorder: f64,
+    reorder_delay: Duration,
+    bandwidth: Option<u64>,
+    buffer_size: Option<u64>,
+}
+
+impl LinkConfig {
+    /// Time taken by datagrams to travel the link, excluding time spent queued by the bandwidth
+    /// limit
+    pub fn latency(&mut self, value: Duration) -> &mut Self {
+        self.latency = value;
+        self
+    }
+
+    /// Fraction of datagrams dropped at random, between 0 and 1
+    pub fn loss(&mut self, value: f64) -> &mut Self {
+        assert!((0.0..=1.0).contains(&value), "loss must be between 0 and 1");
+        self.loss = value;
+        self
+    }
+
+    /// Fraction of datagrams delayed by an additional [`reorder_delay()`](Self::reorder_delay),
+    /// between 0 and 1
+    ///
+    /// Datagrams sent shortly after a delayed one overtake it.
+    pub fn reorder(&mut self, value: f64) -> &mut Self {
+        assert!(
+            (0.0..=1.0).contains(&value),
+            "reorder must be between 0 and 1"
+        );
+        self.reorder = value;
+        self
+    }
+
+    /// Extra time taken by datagrams chosen to be reordered
+    pub fn reorder_delay(&mut self, value: Duration) -> &mut Self {
+        self.reorder_delay = value;
+        self
+    }
+
+    /// Rate at which the link can send data, in bytes per second, or `None` for no limit
+    ///
+    /// Datagrams queue up to be sent when it's exceeded.
+    pub fn bandwidth(&mut self, value: Option<u64>) -> &mut Self {
+        assert_ne!(value, Some(0), "bandwidth must be positive");
+        self.bandwidth = value;
+        self
+    }
+
+    /// Maximum number of bytes queued by the bandwidth limit, or `None` for no limit
+    ///
+    /// Datagrams that would exceed it are dropped, like at a router with a full buffer.
+    pub fn buffer_size(&mut self, value: Option<u64>) -> &mut Self {
+        self.buffer_size = value;
+        self
+    }
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Self {
+            latency: Duration::from_millis(0),
+            loss: 0.0,
+            reorder: 0.0,
+            reorder_delay: Duration::from_millis(10),
+            bandwidth: None,
+            buffer_size: None,
+        }
+    }
+}
+
+/// A socket bound to an address on a [`Network`]
+///
+/// Unbinds the address when dropped.
+#[derive(Debug)]
+pub struct Socket {
+    network: Network,
+    addr: SocketAddr,
+    /// Wakes the receiving task when the next datagram in flight arrives
+    timer: Option<Pin<Box<Sleep>>>,
+}
+
+impl AsyncUdpSocket for Socket {
+    fn poll_send(&mut self, _cx: &mut Context, transmits: &[Transmit]) -> Poll<io::Result<usize>> {
+        let now = Instant::now();
+        let mut network = self.network.0.lock().unwrap();
+        for transmit in transmits {
+            network.send(now, self.addr, transmit);
+        }
+        Poll::Ready(Ok(transmits.len()))
+    }
+
+    fn poll_recv(
+        &mut self,
+        cx: &mut Context,
+        bufs: &mut [IoSliceMut<'_>],
+        meta: &mut [RecvMeta],
+    ) -> Poll<io::Result<usize>> {
+        let now = Instant::now();
+        let mut network = self.network.0.lock().unwrap();
+        let inbox = network.sockets.get_mut(&self.addr).unwrap();
+        let mut received = 0;
+        while received < bufs.len() {
+            match inbox.queue.peek() {
+                Some(Reverse(x)) if x.time <= now => {}
+                _ => break,
+            }
+            let Reverse(arrival) = inbox.queue.pop().unwrap();
+            // Like UDP, truncate datagrams too large for the buffer
+            let len = arrival.data.len().min(bufs[received].len());
+            bufs[received][..len].copy_from_slice(&arrival.data[..len]);
+            meta[received] = RecvMeta {
+                addr: arrival.source,
+                len,
+                ecn: arrival.ecn,
+                dst_ip: Some(self.addr.ip()),
+            };
+            received += 1;
+        }
+        if received > 0 {
+            return Poll::Ready(Ok(received));
+        }
+
+        inbox.waker = Some(cx.waker().clone());
+        let next = match inbox.queue.peek() {
+            Some(Reverse(x)) => x.time,
+            None => return Poll::Pending,
+        };
+        drop(network);
+        let timer = match self.timer {
+            Some(ref mut timer) => {
+                timer.as_mut().reset(next);
+                timer
+            }
+            None => self.timer.get_or_insert(Box::pin(sleep_until(next))),
+        };
+        if Future::poll(timer.as_mut(), cx).is_ready() {
+            cx.waker().wake_by_ref();
+        }
+        Poll::Pending
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.addr)
+    }
+}
+
+impl Drop for Socket {
+    fn drop(&mut self) {
+        let mut network = self.network.0.lock().unwrap();
+        network.sockets.remove(&self.addr);
+        network
+            .busy_until
+            .retain(|&(from, to), _| from != self.addr && to != self.addr);
+    }
+}
+
+#[derive(Debug, Default)]
+struct Inbox {
+    /// Datagrams in flight to the socket, earliest arrival first
+    queue: BinaryHeap<Reverse<Arrival>>,
+    waker: Option<Waker>,
+}
+
+#[derive(Debug)]
+struct Arrival {
+    time: Instant,
+    seq: u64,
+    source: SocketAddr,
+    ecn: Option<EcnCodepoint>,
+    data: Box<[u8]>,
+}
+
+impl PartialEq for Arrival {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Arrival {}
+
+impl PartialOrd for Arrival {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Arrival {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.time, self.seq).cmp(&(other.time, other.seq))
+    }
+}
+
+/// First port chosen for sockets bound to port 0
+const EPHEMERAL_PORTS: u16 = 49152;
@@ -1,15 +1,17 @@
 #![cfg(feature = "rustls")]
 
 use std::{
-    io,
+    io::{self, IoSliceMut},
     net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket},
     str,
     sync::Arc,
+    task::{Context, Poll},
 };
 
 use bytes::Bytes;
-use futures_util::future;
+use futures_channel::mpsc;
 use futures_util::StreamExt;
+use futures_util::{future, ready};
 use rand::{rngs::StdRng, RngCore, SeedableRng};
 use tokio::{
     runtime::{Builder, Runtime},
@@ -20,8 +22,9 @@ use tracing_futures::Instrument as _;
 use tracing_subscriber::EnvFilter;
 
 use super::{
-    ClientConfigBuilder, Endpoint, Incoming, NewConnection, RecvStream, SendStream,
-    ServerConfigBuilder, TransportConfig,
+    testing, AsyncUdpSocket, ClientConfigBuilder, Endpoint, EndpointBuilder, EndpointError,
+    Incoming, NewConnection, RecvMeta, RecvStream, SendStream, ServerConfigBuilder, Transmit,
+    TransportConfig,
 };
 
 #[test]
@@ -120,6 +123,8 @@ fn read_after_close() {
             .next()
             .await
             .expect("endpoint")
+            .accept()
+            .unwrap()
             .await
             .expect("connection");
         let mut s = new_conn.connection.open_uni().await.unwrap();
@@ -159,15 +164,10 @@ fn export_keying_material() {
     runtime.block_on(async move {
         let outgoing_conn = endpoint
             .connect(&endpoint.local_addr().unwrap(), "localhost")
-            .unwrap()
-            .await
-            .expect("connect");
-        let incoming_conn = incoming
-            .next()
-            .await
-            .expect("endpoint")
-            .await
-            .expect("connection");
+            .unwrap();
+        let incoming_conn = incoming.next().await.expect("endpoint").accept().unwrap();
+        let outgoing_conn = outgoing_conn.await.expect("connect");
+        let incoming_conn = incoming_conn.await.expect("connection");
         let mut i_buf = [0u8; 64];
         incoming_conn
             .connection
@@ -189,12 +189,12 @@ async fn accept_after_close() {
 
     const MSG: &[u8] = b"goodbye!";
 
-    let sender = endpoint
+    let connecting = endpoint
         .connect(&endpoint.local_addr().unwrap(), "localhost")
-        .unwrap()
-        .await
-        .expect("connect")
-        .connection;
+        .unwrap();
+    // The handshake only proceeds once the server decides to accept the attempt
+    let receiver = incoming.next().await.expect("endpoint").accept().unwrap();
+    let sender = connecting.await.expect("connect").connection;
     let mut s = sender.open_uni().await.unwrap();
     s.write_all(MSG).await.unwrap();
     s.finish().await.unwrap();
@@ -204,12 +204,7 @@ async fn accept_after_close() {
     tokio::time::sleep(Duration::from_millis(100)).await;
 
     // Despite the connection having closed, we should be able to accept it...
-    let mut receiver = incoming
-        .next()
-        .await
-        .expect("endpoint")
-        .await
-        .expect("connection");
+    let mut receiver = receiver.await.expect("connection");
 
     // ...and read what was sent.
     let stream = receiver
@@ -228,8 +223,284 @@ async fn accept_after_close() {
     assert!(receiver.connection.open_uni().await.is_err());
 }
 
+#[tokio::test]
+async fn sharded_endpoint() {
+    let _guard = subscribe();
+    const SHARDS: usize = 4;
+    const MSG: &[u8] = b"hello";
+    let (mut server, cert) = endpoint_builder();
+    server.steer_by_cid(cfg!(target_os = "linux"));
+    let shards = server
+        .bind_sharded(&SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0), SHARDS)
+        .unwrap();
+    assert_eq!(shards.len(), SHARDS);
+    let addr = shards[0].0.local_addr().unwrap();
+    for (endpoint, incoming) in shards {
+        assert_eq!(endpoint.local_addr().unwrap(), addr);
+        tokio::spawn(incoming.for_each(|incoming| async {
+            let new_conn = incoming.accept().unwrap().await.expect("connection");
+            let mut s = new_conn.connection.open_uni().await.unwrap();
+            s.write_all(MSG).await.unwrap();
+            s.finish().await.unwrap();
+        }));
+    }
+
+    let mut client_config = ClientConfigBuilder::default();
+    client_config.add_certificate_authority(cert).unwrap();
+    let mut client = Endpoint::builder();
+    client.default_client_config(client_config.build());
+    let (client, _) = client
+        .bind(&SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0))
+        .unwrap();
+    // Enough connections that some are likely to be forwarded between shards
+    for _ in 0..16 {
+        let mut new_conn = client
+            .connect(&addr, "localhost")
+            .unwrap()
+            .await
+            .expect("connect");
+        let stream = new_conn
+            .uni_streams
+            .next()
+            .await
+            .expect("incoming streams")
+            .expect("missing stream");
+        let msg = stream.read_to_end(MSG.len()).await.expect("read_to_end");
+        assert_eq!(msg, MSG);
+    }
+}
+
+#[test]
+fn sharded_endpoint_count() {
+    let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0);
+    for &shards in [0, 257].iter() {
+        let result = Endpoint::builder().bind_sharded(&addr, shards);
+        assert!(matches!(result, Err(EndpointError::ShardCount)));
+    }
+}
+
+#[test]
+fn sharded_endpoint_custom_cid_generator() {
+    let addr = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0);
+    let mut builder = Endpoint::builder();
+    builder.connection_id_generator(|| Box::new(proto::RandomConnectionIdGenerator::new(8)));
+    let result = builder.bind_sharded(&addr, 2);
+    assert!(matches!(result, Err(EndpointError::ShardedCidGenerator)));
+}
+
+#[cfg(feature = "runtime-async-std")]
+#[test]
+fn async_std_runtime() {
+    let _guard = subscribe();
+    const MSG: &[u8] = b"hello";
+    let (mut endpoint, _) = endpoint_builder();
+    endpoint.runtime(Arc::new(crate::AsyncStdRuntime));
+    async_std::task::block_on(async move {
+        let (endpoint, mut incoming) = endpoint
+            .bind(&SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0))
+            .unwrap();
+        let connecting = endpoint
+            .connect(&endpoint.local_addr().unwrap(), "localhost")
+            .unwrap();
+        let server = incoming.next().await.expect("endpoint").accept().unwrap();
+        async_std::task::spawn(async move {
+            let new_conn = server.await.expect("connection");
+            let mut s = new_conn.connection.open_uni().await.unwrap();
+            s.write_all(MSG).await.unwrap();
+            s.finish().await.unwrap();
+        });
+        let mut new_conn = connecting.await.expect("connect");
+        let stream = new_conn
+            .uni_streams
+            .next()
+            .await
+            .expect("incoming streams")
+            .expect("missing stream");
+        let msg = stream.read_to_end(MSG.len()).await.expect("read_to_end");
+        assert_eq!(msg, MSG);
+    });
+}
+
+#[tokio::test]
+async fn async_socket() {
+    let _guard = subscribe();
+    const MSG: &[u8] = b"hello";
+    let (endpoint, _) = endpoint_builder();
+    let (endpoint, mut incoming) = endpoint.with_async_socket(Loopback::new()).unwrap();
+    let connecting = endpoint
+        .connect(&endpoint.local_addr().unwrap(), "localhost")
+        .unwrap();
+    let server = incoming.next().await.expect("endpoint").accept().unwrap();
+    tokio::spawn(async move {
+        let new_conn = server.await.expect("connection");
+        let mut s = new_conn.connection.open_uni().await.unwrap();
+        s.write_all(MSG).await.unwrap();
+        s.finish().await.unwrap();
+    });
+    let mut new_conn = connecting.await.expect("connect");
+    let stream = new_conn
+        .uni_streams
+        .next()
+        .await
+        .expect("incoming streams")
+        .expect("missing stream");
+    let msg = stream.read_to_end(MSG.len()).await.expect("read_to_end");
+    assert_eq!(msg, MSG);
+}
+
+#[tokio::test(start_paused = true)]
+async fn simulated_network() {
+    let _guard = subscribe();
+    const LATENCY: Duration = Duration::from_millis(50);
+    let network = testing::Network::new(0);
+    let mut link = testing::LinkConfig::default();
+    link.latency(LATENCY).loss(0.05).reorder(0.05);
+    network.set_default_link(link);
+
+    let start = Instant::now();
+    let client = simulated_echo_connection(&network).await;
+    // The client learns that the handshake succeeded after a round trip at the earliest
+    assert!(start.elapsed() >= 2 * LATENCY);
+
+    let data = gen_data(256 * 1024, 0);
+    assert_eq!(simulated_echo(&client, &data).await, data);
+}
+
+#[tokio::test(start_paused = true)]
+async fn simulated_network_bandwidth() {
+    let _guard = subscribe();
+    const BANDWIDTH: u64 = 1_000_000;
+    let network = testing::Network::new(0);
+    let mut link = testing::LinkConfig::default();
+    link.latency(Duration::from_millis(10))
+        .bandwidth(Some(BANDWIDTH))
+        .buffer_size(Some(BANDWIDTH / 10));
+    network.set_default_link(link);
+
+    let client = simulated_echo_connection(&network).await;
+    let data = gen_data(BANDWIDTH as usize, 0);
+    let start = Instant::now();
+    assert_eq!(simulated_echo(&client, &data).await, data);
+    // Sending a second's worth of data in each direction takes at least a second
+    assert!(start.elapsed() >= Duration::from_secs(1));
+}
+
+/// Connect to a server on `network` that echoes bidirectional streams
+async fn simulated_echo_connection(network: &testing::Network) -> crate::Connection {
+    let (server, cert) = endpoint_builder();
+    let socket = network
+        .bind(SocketAddr::new(
+            IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)),
+            4433,
+        ))
+        .unwrap();
+    let server_addr = socket.local_addr().unwrap();
+    let (_, mut incoming) = server.with_async_socket(socket).unwrap();
+    tokio::spawn(async move {
+        let incoming = incoming.next().await.expect("endpoint");
+        let new_conn = incoming.accept().unwrap().await.expect("connection");
+        new_conn
+            .bi_streams
+            .take_while(|x| future::ready(x.is_ok()))
+            .for_each(|stream| async {
+                tokio::spawn(echo(stream.unwrap()));
+            })
+            .await;
+    });
+
+    let mut client_config = ClientConfigBuilder::default();
+    client_config.add_certificate_authority(cert).unwrap();
+    let mut client = Endpoint::builder();
+    client.default_client_config(client_config.build());
+    let socket = network
+        .bind(SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 2)), 0))
+        .unwrap();
+    let (client, _) = client.with_async_socket(socket).unwrap();
+    client
+        .connect(&server_addr, "localhost")
+        .unwrap()
+        .await
+        .expect("connect")
+        .connection
+}
+
+/// Send `data` on a new stream and read back the response
+async fn simulated_echo(connection: &crate::Connection, data: &[u8]) -> Vec<u8> {
+    let (mut send, recv) = connection.open_bi().await.unwrap();
+    let (_, echoed) = future::join(
+        async {
+            send.write_all(data).await.unwrap();
+            send.finish().await.unwrap();
+        },
+        recv.read_to_end(data.len()),
+    )
+    .await;
+    echoed.unwrap()
+}
+
+/// A socket that receives everything sent through it, regardless of destination
+#[derive(Debug)]
+struct Loopback {
+    addr: SocketAddr,
+    send: mpsc::UnboundedSender<Vec<u8>>,
+    recv: mpsc::UnboundedReceiver<Vec<u8>>,
+}
+
+impl Loopback {
+    fn new() -> Self {
+        let (send, recv) = mpsc::unbounded();
+        Self {
+            addr: SocketAddr::new(IpAddr::V4(Ipv4Addr::new(192, 0, 2, 1)), 4433),
+            send,
+            recv,
+        }
+    }
+}
+
+impl AsyncUdpSocket for Loopback {
+    fn poll_send(&mut self, _: &mut Context, transmits: &[Transmit]) -> Poll<io::Result<usize>> {
+        for transmit in transmits {
+            let size = transmit.segment_size.unwrap_or(transmit.contents.len());
+            for datagram in transmit.contents.chunks(size) {
+                self.send.unbounded_send(datagram.into()).unwrap();
+            }
+        }
+        Poll::Ready(Ok(transmits.len()))
+    }
+
+    fn poll_recv(
+        &mut self,
+        cx: &mut Context,
+        bufs: &mut [IoSliceMut<'_>],
+        meta: &mut [RecvMeta],
+    ) -> Poll<io::Result<usize>> {
+        let datagram = ready!(self.recv.poll_next_unpin(cx)).unwrap();
+        bufs[0][..datagram.len()].copy_from_slice(&datagram);
+        meta[0] = RecvMeta {
+            addr: self.addr,
+            len: datagram.len(),
+            ecn: None,
+            dst_ip: None,
+        };
+        Poll::Ready(Ok(1))
+    }
+
+    fn local_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.addr)
+    }
+}
+
 /// Construct an endpoint suitable for connecting to itself
 fn endpoint() -> (Endpoint, Incoming) {
+    let (endpoint, _) = endpoint_builder();
+    let (x, y) = endpoint
+        .bind(&SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0))
+        .unwrap();
+    (x, y)
+}
+
+/// Construct an endpoint builder that trusts its own certificate, which is also returned
+fn endpoint_builder() -> (EndpointBuilder, crate::Certificate) {
     let mut endpoint = Endpoint::builder();
 
     let mut server_config = ServerConfigBuilder::default();
@@ -241,13 +512,12 @@ fn endpoint() -> (Endpoint, Incoming) {
     endpoint.listen(server_config.build());
 
     let mut client_config = ClientConfigBuilder::default();
-    client_config.add_certificate_authority(cert).unwrap();
+    client_config
+        .add_certificate_authority(cert.clone())
+        .unwrap();
     endpoint.default_client_config(client_config.build());
 
-    let (x, y) = endpoint
-        .bind(&SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 0))
-        .unwrap();
-    (x, y)
+    (endpoint, cert)
 }
 
 #[tokio::test]
@@ -261,7 +531,12 @@ async fn zero_rtt() {
             mut uni_streams,
             connection,
             ..
-        } = incoming.into_0rtt().unwrap_or_else(|_| unreachable!()).0;
+        } = incoming
+            .accept()
+            .unwrap()
+            .into_0rtt()
+            .unwrap_or_else(|_| unreachable!())
+            .0;
         tokio::spawn(async move {
             while let Some(Ok(x)) = uni_streams.next().await {
                 let msg = x.read_to_end(usize::max_value()).await.unwrap();
@@ -477,7 +752,12 @@ fn run_echo(args: EchoArgs) {
                 assert_eq!(None, incoming.local_ip());
             }
 
-            let new_conn = incoming.instrument(info_span!("server")).await.unwrap();
+            let new_conn = incoming
+                .accept()
+                .unwrap()
+                .instrument(info_span!("server"))
+                .await
+                .unwrap();
             tokio::spawn(
                 new_conn
                     .bi_streams
@@ -41,7 +41,7 @@ fn connect_n_nodes_to_1_and_send_1mb_data() {
 
     let shared2 = shared.clone();
     let read_incoming_data = incoming_conns
-        .filter_map(|connect| connect.map(|x| x.ok()))
+        .filter_map(|incoming| async { incoming.accept().ok()?.await.ok() })
         .take(expected_messages)
         .for_each(move |new_conn| {
             let conn = new_conn.connection;